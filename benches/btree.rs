@@ -25,7 +25,7 @@ fn fixed_vs_variable(c: &mut Criterion) {
 
         b.iter(|| {
             btree
-                .insert(additional_key.clone(), additional_value.clone())
+                .insert(additional_key, additional_value.clone())
                 .unwrap();
         })
     });
@@ -47,7 +47,7 @@ fn fixed_vs_variable(c: &mut Criterion) {
 
         b.iter(|| {
             btree
-                .insert(additional_key.clone(), additional_value.clone())
+                .insert(additional_key, additional_value.clone())
                 .unwrap();
         })
     });
@@ -142,5 +142,51 @@ fn search(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, insertion, fixed_vs_variable, search);
+fn prefix_compression(c: &mut Criterion) {
+    let mut g = c.benchmark_group("key prefix compression");
+
+    let n_entries = 10_000;
+    let path_faker = StringFaker::with(Vec::from(ASCII), 8..16);
+    let keys: Vec<String> = (0..n_entries)
+        .map(|_| {
+            let path: String = path_faker.fake();
+            format!("https://example.com/some/long/path/{path}")
+        })
+        .collect();
+
+    for compression in [false, true] {
+        let config = BtreeConfig::default()
+            .max_key_size(64)
+            .key_prefix_compression(compression);
+        let mut btree: BtreeIndex<String, u64> =
+            BtreeIndex::with_capacity(config, n_entries).unwrap();
+        for (i, key) in keys.iter().enumerate() {
+            btree.insert(key.clone(), i as u64).unwrap();
+        }
+
+        let name = if compression {
+            "search compressed URL"
+        } else {
+            "search uncompressed URL"
+        };
+        let mut i = 0;
+        g.bench_function(name, |b| {
+            b.iter(|| {
+                // Search different keys, so the decoded keys of the leaves are not cached
+                i = (i + 7919) % keys.len();
+                btree.get(&keys[i]).unwrap().unwrap();
+            })
+        });
+    }
+
+    g.finish()
+}
+
+criterion_group!(
+    benches,
+    insertion,
+    fixed_vs_variable,
+    search,
+    prefix_compression
+);
 criterion_main!(benches);
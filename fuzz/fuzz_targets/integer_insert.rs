@@ -8,7 +8,7 @@ fuzz_target!(|data: (Vec<(u32, u32)>, u8)| {
     let order = data.1.max(2).min(84);
     let mut m = BTreeMap::default();
     let mut fixture =
-        BtreeIndex::with_capacity(BtreeConfig::default().order(order), 1024).unwrap();
+        BtreeIndex::with_capacity(BtreeConfig::default().order(order.into()), 1024).unwrap();
 
     for (key, value) in data.0 {
         m.insert(key, value);
@@ -27,7 +27,7 @@ fuzz_target!(|data: (Vec<(u32, u32)>, u8)| {
 
     // Check that the maps are equal with differnt range queries
     let m: Vec<_> = m.into_iter().collect();
-    let fixture_result: Result<Vec<_>, Error> = fixture.range(..).unwrap().collect();
+    let fixture_result: Result<Vec<_>, Error> = fixture.iter().unwrap().collect();
     let fixture_result = fixture_result.unwrap();
 
     assert_eq!(m, fixture_result);
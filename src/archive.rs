@@ -0,0 +1,173 @@
+use std::{marker::PhantomData, ops::Deref};
+
+use rkyv::{
+    api::high::{HighSerializer, HighValidator},
+    bytecheck::CheckBytes,
+    rancor,
+    ser::allocator::ArenaHandle,
+    util::AlignedVec,
+    Archive, Archived, Portable,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    raw::RawBytes,
+    BtreeConfig, BtreeIndex, ValueCodec,
+};
+
+/// Alignment of the archived values inside the value file, which is the same as for an [`AlignedVec`].
+///
+/// The values are aligned relative to the start of the storage, so they are only aligned in memory
+/// if the storage is, too.
+const ALIGNMENT: usize = 16;
+
+/// Padding between the fixed size length of the serialized value and the archive,
+/// so that the archive starts at an aligned position.
+const PADDING: usize = ALIGNMENT - std::mem::size_of::<u64>();
+
+/// B-tree index that stores the values as [rkyv](https://rkyv.org) archives.
+///
+/// Values are accessed directly inside the value file with [`Self::get_ref`],
+/// without deserializing or copying them, as long as the memory of the storage is aligned
+/// to 16 bytes like for memory mapped files.
+/// The archive is validated on each access, so corrupted data results in an error.
+/// This needs the `rkyv` feature.
+///
+/// To allow accessing the archives directly, the values are never compressed
+/// and always stored with a variable size, regardless of the configuration.
+///
+/// # Example
+///
+/// ```rust
+/// use transient_btree_index::{ArchivedBtreeIndex, BtreeConfig, Error};
+///
+/// #[derive(rkyv::Archive, rkyv::Serialize)]
+/// struct Person {
+///     name: String,
+///     scores: Vec<u32>,
+/// }
+///
+/// fn main() -> std::result::Result<(), Error> {
+///     let mut b = ArchivedBtreeIndex::<u64, Person>::with_capacity(BtreeConfig::default(), 10)?;
+///     let person = Person { name: "Alice".to_string(), scores: vec![10, 20] };
+///     b.insert(1, &person)?;
+///
+///     let archived = b.get_ref(&1)?.unwrap();
+///     assert_eq!("Alice", archived.name.as_str());
+///     assert_eq!(20, archived.scores[1]);
+///     Ok(())
+/// }
+/// ```
+pub struct ArchivedBtreeIndex<K, V>
+where
+    K: Serialize + DeserializeOwned + PartialOrd + Clone,
+{
+    index: BtreeIndex<K, RawBytes>,
+    phantom: PhantomData<fn() -> V>,
+}
+
+impl<K, V> ArchivedBtreeIndex<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: Archive
+        + for<'a> rkyv::Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>>,
+    Archived<V>: Portable + for<'a> CheckBytes<HighValidator<'a, rancor::Error>>,
+{
+    /// Create a new instance with the given configuration and capacity in number of elements.
+    pub fn with_capacity(config: BtreeConfig, capacity: usize) -> Result<ArchivedBtreeIndex<K, V>> {
+        // The fixed integer encoding of bincode always uses 8 bytes for the length of the bytes
+        let config = config
+            .value_codec(ValueCodec::BincodeFixint)
+            .aligned_values(ALIGNMENT);
+        Ok(ArchivedBtreeIndex {
+            index: BtreeIndex::with_capacity(config, capacity)?,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Insert a new element into the index.
+    ///
+    /// Existing values will be overwritten.
+    pub fn insert(&mut self, key: K, value: &V) -> Result<()> {
+        let archive = rkyv::to_bytes::<rancor::Error>(value)?;
+        let mut bytes = Vec::with_capacity(PADDING + archive.len());
+        bytes.resize(PADDING, 0);
+        bytes.extend_from_slice(&archive);
+        self.index.insert(key, RawBytes(bytes))?;
+        Ok(())
+    }
+
+    /// Searches for a key in the index and returns a reference to the archived value if found.
+    ///
+    /// The reference points directly into the storage of the index, unless the archive is not
+    /// aligned in memory, e.g. for a [`MemoryStorage`](crate::MemoryStorage) or custom storage
+    /// backends. In this case, the archive is copied into an aligned buffer.
+    pub fn get_ref(&self, key: &K) -> Result<Option<ArchivedRef<'_, V>>> {
+        if let Some(serialized) = self.index.get_raw_value(key)? {
+            // Skip the length of the bytes and the padding before the archive
+            let archive = serialized
+                .get(std::mem::size_of::<u64>() + PADDING..)
+                .ok_or_else(|| Error::DeserializeBlock("missing archived value".to_string()))?;
+            if archive.as_ptr().align_offset(ALIGNMENT) == 0 {
+                let value = rkyv::access::<Archived<V>, rancor::Error>(archive)?;
+                Ok(Some(ArchivedRef::Borrowed(value)))
+            } else {
+                let mut copy = AlignedVec::<ALIGNMENT>::with_capacity(archive.len());
+                copy.extend_from_slice(archive);
+                rkyv::access::<Archived<V>, rancor::Error>(&copy)?;
+                Ok(Some(ArchivedRef::Copied(copy, PhantomData)))
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns whether the index contains the given key.
+    pub fn contains_key(&self, key: &K) -> Result<bool> {
+        self.index.contains_key(key)
+    }
+
+    /// Returns true if the index does not contain any elements.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the length of the index.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+}
+
+/// Reference to an archived value, which is returned by [`ArchivedBtreeIndex::get_ref`].
+///
+/// It dereferences to the archived value, which has already been validated.
+pub enum ArchivedRef<'a, V>
+where
+    V: Archive,
+{
+    /// The archive inside the storage of the index.
+    Borrowed(&'a Archived<V>),
+    /// A copy of an archive that is not aligned in memory.
+    Copied(AlignedVec<ALIGNMENT>, PhantomData<fn() -> V>),
+}
+
+impl<V> Deref for ArchivedRef<'_, V>
+where
+    V: Archive,
+    Archived<V>: Portable,
+{
+    type Target = Archived<V>;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            ArchivedRef::Borrowed(value) => value,
+            // SAFETY: the archive has been validated before the copy was returned
+            // and the buffer can't be changed afterwards
+            ArchivedRef::Copied(copy, _) => unsafe { rkyv::access_unchecked::<Archived<V>>(copy) },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,150 @@
+use crate::{MemoryStorage, StorageBackend};
+
+use super::*;
+
+#[derive(rkyv::Archive, rkyv::Serialize)]
+struct Entry {
+    id: u64,
+    name: String,
+    children: Vec<Child>,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize)]
+struct Child {
+    weight: f64,
+    tags: Vec<String>,
+}
+
+fn entry(i: u64, tags: usize) -> Entry {
+    Entry {
+        id: i,
+        name: format!("entry {i}"),
+        children: (0..(i % 4))
+            .map(|c| Child {
+                weight: c as f64 / 2.0,
+                tags: (0..tags).map(|t| format!("tag {t}")).collect(),
+            })
+            .collect(),
+    }
+}
+
+fn check_entry(archived: &ArchivedEntry, i: u64, tags: usize) {
+    assert_eq!(i, archived.id);
+    assert_eq!(format!("entry {i}"), archived.name.as_str());
+    assert_eq!((i % 4) as usize, archived.children.len());
+    for (c, child) in archived.children.iter().enumerate() {
+        assert_eq!(c as f64 / 2.0, child.weight);
+        assert_eq!(tags, child.tags.len());
+    }
+}
+
+#[test]
+fn insert_get_ref() {
+    let config = BtreeConfig::default().order(2).max_value_size(64);
+    let mut b = ArchivedBtreeIndex::<u64, Entry>::with_capacity(config, 10).unwrap();
+    assert!(b.is_empty());
+
+    for i in 0..500 {
+        b.insert(i, &entry(i, 1)).unwrap();
+    }
+    assert_eq!(500, b.len());
+    for i in 0..500 {
+        check_entry(&b.get_ref(&i).unwrap().unwrap(), i, 1);
+    }
+    assert!(b.contains_key(&499).unwrap());
+    assert!(b.get_ref(&500).unwrap().is_none());
+
+    // Replace some values with larger ones, which need to be relocated
+    for i in (0..500).step_by(3) {
+        b.insert(i, &entry(i, 20)).unwrap();
+    }
+    assert_eq!(500, b.len());
+    for i in 0..500 {
+        let tags = if i % 3 == 0 { 20 } else { 1 };
+        let archived = b.get_ref(&i).unwrap().unwrap();
+        assert!((&*archived as *const ArchivedEntry).is_aligned());
+        check_entry(&archived, i, tags);
+    }
+}
+
+#[test]
+fn ignores_fixed_value_size() {
+    let config = BtreeConfig::default()
+        .fixed_value_size(3)
+        .storage_backend(|capacity| {
+            let storage: Box<dyn StorageBackend> = Box::new(MemoryStorage::with_capacity(capacity));
+            Ok(storage)
+        });
+    let mut b = ArchivedBtreeIndex::<u64, Entry>::with_capacity(config, 10).unwrap();
+    for i in 0..100 {
+        b.insert(i, &entry(i, 2)).unwrap();
+    }
+    for i in 0..100 {
+        check_entry(&b.get_ref(&i).unwrap().unwrap(), i, 2);
+    }
+}
+
+/// Storage whose content starts one byte after an address that is aligned for the archives.
+struct MisalignedStorage {
+    data: Vec<u8>,
+    len: usize,
+}
+
+impl MisalignedStorage {
+    fn with_capacity(capacity: usize) -> MisalignedStorage {
+        MisalignedStorage {
+            data: vec![0; capacity + ALIGNMENT + 1],
+            len: capacity,
+        }
+    }
+
+    fn offset(&self) -> usize {
+        self.data.as_ptr().align_offset(ALIGNMENT) + 1
+    }
+}
+
+impl StorageBackend for MisalignedStorage {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn grow(&mut self, new_size: usize) -> Result<()> {
+        let old_offset = self.offset();
+        self.data.resize(new_size + ALIGNMENT + 1, 0);
+        // The vector might have been moved to an address with a different alignment
+        let new_offset = self.offset();
+        self.data
+            .copy_within(old_offset..old_offset + self.len, new_offset);
+        self.data[new_offset + self.len..].fill(0);
+        self.len = new_size;
+        Ok(())
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        let offset = self.offset();
+        &self.data[offset..offset + self.len]
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        let offset = self.offset();
+        &mut self.data[offset..offset + self.len]
+    }
+}
+
+#[test]
+fn misaligned_storage() {
+    let config = BtreeConfig::default().order(2).storage_backend(|capacity| {
+        let storage: Box<dyn StorageBackend> = Box::new(MisalignedStorage::with_capacity(capacity));
+        Ok(storage)
+    });
+    let mut b = ArchivedBtreeIndex::<u64, Entry>::with_capacity(config, 10).unwrap();
+    for i in 0..200 {
+        b.insert(i, &entry(i, 3)).unwrap();
+    }
+    for i in 0..200 {
+        let archived = b.get_ref(&i).unwrap().unwrap();
+        assert!(matches!(archived, ArchivedRef::Copied(..)));
+        assert!((&*archived as *const ArchivedEntry).is_aligned());
+        check_entry(&archived, i, 3);
+    }
+}
@@ -1,66 +1,335 @@
 use std::{
+    borrow::{Borrow, Cow},
+    cmp::Ordering,
+    fmt,
+    iter::Peekable,
     marker::PhantomData,
     ops::{Bound, RangeBounds},
+    sync::Arc,
 };
 
 use crate::{
+    cancellation::CancellationToken,
+    codec::ValueCodec,
     error::Result,
-    file::{BlockHeader, FixedSizeTupleFile, TupleFile, VariableSizeTupleFile},
-    Error,
+    file::{
+        default_eviction_policy, BlockHeader, EvictionPolicy, EvictionPolicyFactory,
+        FixedSizeTupleFile, RelocationPolicy, RelocationStats, VariableSizeTupleFile,
+        DEFAULT_BLOCK_ALIGNMENT,
+    },
+    fixed::FixedSize,
+    metrics::{BtreeMetricsObserver, Metrics},
+    ordered::OrderedBytes,
+    prefix::PrefixKey,
+    storage::{
+        create_default_storage, prefault, storage_error, system_page_size, GrowthPolicy,
+        GrowthStrategy, StorageBackend, StorageFactory,
+    },
+    Error, DEFAULT_PAGE_SIZE, HUGE_PAGE_SIZE,
 };
 use serde::{de::DeserializeOwned, Serialize};
 
-use self::node::{NodeFile, SearchResult, StackEntry, MAX_NUMBER_KEYS};
+use self::node::{NodeFile, NodeLayout, SearchResult, StackEntry};
+use self::payload::ValueFile;
 
-mod node;
+pub use self::builder::BtreeBuilder;
+pub use self::frozen::FrozenBtreeIndex;
+pub use self::lookup::LookupContext;
+pub use self::payload::PayloadId;
+#[cfg(feature = "async")]
+pub use self::stream::RangeStream;
+pub use self::transaction::Transaction;
+
+mod bloom;
+mod builder;
+mod frozen;
+mod hash_index;
+mod lookup;
+pub(crate) mod node;
+mod payload;
+#[cfg(feature = "async")]
+mod stream;
+mod transaction;
+#[cfg(feature = "wal")]
+mod wal;
+
+/// Name of the file with the values in storage errors.
+const VALUE_FILE_NAME: &str = "values";
+
+/// Magic bytes at the start of a table written by [`BtreeIndex::write_sstable`].
+#[cfg(feature = "export")]
+const SSTABLE_MAGIC: &[u8; 8] = b"TBTSST01";
+
+/// Escapes the characters that have a special meaning in the label of a Graphviz record node.
+#[cfg(feature = "graphviz")]
+fn escape_dot_record(label: &str) -> String {
+    let mut result = String::with_capacity(label.len());
+    for c in label.chars() {
+        if matches!(c, '"' | '\\' | '{' | '}' | '|' | '<' | '>') {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Create a file for variable sized blocks with the cache settings of the configuration.
+///
+/// The name describes the content of the file in storage errors.
+pub(crate) fn create_variable_size_file<B>(
+    name: &'static str,
+    capacity: usize,
+    config: &BtreeConfig,
+) -> Result<VariableSizeTupleFile<B>>
+where
+    B: Serialize + DeserializeOwned + Clone,
+{
+    let storage = config
+        .create_storage(capacity.max(1))
+        .map_err(|e| storage_error(name, "Creating", e))?;
+    let mut f = VariableSizeTupleFile::with_storage(storage, config.block_cache_size)
+        .with_name(name)
+        .with_growth_policy(config.growth)
+        .with_page_size(config.page_size)
+        .with_block_alignment(config.block_alignment)
+        .with_relocation_policy(config.relocation_policy)
+        .with_metrics(config.metrics.clone());
+    if let Some(max_bytes) = config.block_cache_bytes {
+        f = f.with_cache_byte_budget(max_bytes);
+    }
+    #[cfg(feature = "encryption")]
+    if let Some(key) = &config.encryption_key {
+        f = f.with_encryption(key);
+    }
+    Ok(f.with_eviction_policy(config.eviction_policy.clone()))
+}
+
+/// Create a file for fixed size blocks with the storage of the configuration.
+fn create_fixed_size_file<B>(
+    name: &'static str,
+    capacity: usize,
+    fixed_size: usize,
+    config: &BtreeConfig,
+) -> Result<FixedSizeTupleFile<B>>
+where
+    B: Serialize + DeserializeOwned,
+{
+    let storage = config
+        .create_storage((capacity * fixed_size).max(1))
+        .map_err(|e| storage_error(name, "Creating", e))?;
+    Ok(FixedSizeTupleFile::with_storage(storage, fixed_size)
+        .with_name(name)
+        .with_growth_policy(config.growth)
+        .with_page_size(config.page_size)
+        .with_metrics(config.metrics.clone()))
+}
 
 /// B-tree index backed by temporary memory mapped files.
 ///
 /// Operations similar to the interface of [`std::collections::BTreeMap`] are implemented.
 /// But since the index works with files, most of them return a `Result` to allow error-handling.
-/// Deleting an entry is explicitly not implemented and when memory blocks need to grow fragmentation of the on-disk memory might occur.
+/// Entries can be removed with [`BtreeIndex::remove_range`], but they are only marked as removed and their memory is not reused.
+/// Only the value blocks of entries removed with [`BtreeIndex::drain`] are reused for new values.
+/// Also, when memory blocks need to grow fragmentation of the on-disk memory might occur.
+///
+/// If an operation fails while changing the index, the index might be left in an inconsistent state.
+/// It is marked as poisoned and all further operations return [`Error::Poisoned`],
+/// until it is [cleared](BtreeIndex::clear).
 ///
 /// Since serde is used to serialize the keys and values, the types need to implement the [`Serialize`] and [`DeserializeOwned`] traits.
 /// Also, only keys and values that implement [`Clone`] can be used.
 pub struct BtreeIndex<K, V>
 where
     K: Serialize + DeserializeOwned + PartialOrd + Clone,
-    V: Serialize + DeserializeOwned + Clone + Sync,
+    V: Serialize + DeserializeOwned + Clone,
 {
     nodes: node::NodeFile<K>,
-    values: Box<dyn TupleFile<V>>,
+    values: ValueFile<V>,
     root_id: u64,
-    last_inserted_node_id: u64,
+    /// Nodes from the root to the node of the last insert, with the position of the key
+    /// or the child that was used in each node.
+    insert_path: Vec<(u64, usize)>,
+    /// Range of keys that might not have been removed yet. Popping an entry moves
+    /// the bound behind its key, so the removed entries are not searched again.
+    live_range: (Bound<K>, Bound<K>),
     order: usize,
     nr_elements: usize,
+    value_blocks: Option<payload::ValueBlocks>,
+    bloom_filter: Option<bloom::BloomFilter>,
+    hash_index: Option<hash_index::HashIndex>,
+    instance: u64,
+    /// Set when an operation failed after it might have changed the nodes or files.
+    poisoned: bool,
+    #[cfg(feature = "wal")]
+    wal: Option<wal::WriteAheadLog>,
+    config: BtreeConfig,
 }
 
-#[derive(Clone)]
+/// Number of bytes used by an index, as returned by [`BtreeIndex::memory_usage`].
+///
+/// The sizes of the caches and hash maps are estimations.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Bytes mapped by the file holding the nodes.
+    pub nodes: usize,
+    /// Bytes mapped by the file holding the keys.
+    pub keys: usize,
+    /// Bytes mapped by the file holding the values.
+    pub values: usize,
+    /// Bytes held by the block caches of the key and value files.
+    pub block_cache: usize,
+    /// Bytes held by the hash map of the stored values, when [deduplicating values](BtreeConfig::deduplicate_values).
+    pub value_hashes: usize,
+    /// Bytes held by the [bloom filter](BtreeConfig::bloom_filter_bits_per_key) of the keys.
+    pub bloom_filter: usize,
+    /// Bytes held by the [hash index](BtreeConfig::hash_index) of the keys.
+    pub hash_index: usize,
+}
+
+impl MemoryUsage {
+    /// Sum of all memory mapped and in-memory bytes.
+    pub fn total(&self) -> usize {
+        self.nodes
+            + self.keys
+            + self.values
+            + self.block_cache
+            + self.value_hashes
+            + self.bloom_filter
+            + self.hash_index
+    }
+}
+
+/// Used and allocated bytes of a file backing an index.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct FileCapacity {
+    /// Bytes occupied by the allocated blocks, including blocks whose space has not been
+    /// reclaimed yet.
+    pub used: usize,
+    /// Size of the file, which grows when the used bytes exceed it.
+    pub allocated: usize,
+}
+
+/// Capacities of the files backing an index, as returned by [`BtreeIndex::capacity`].
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Capacity {
+    /// The file holding the nodes.
+    pub nodes: FileCapacity,
+    /// The file holding the keys, which is empty when the keys are stored inside the nodes.
+    pub keys: FileCapacity,
+    /// The file holding the values, which is empty when the values are stored inside the nodes.
+    pub values: FileCapacity,
+}
+
+/// Configured size of the keys or values, as returned by [`BtreeIndex::key_size`]
+/// and [`BtreeIndex::value_size`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TypeSize {
+    /// Estimated maximum size in bytes, e.g. set by [`BtreeConfig::max_key_size`].
     Estimated(usize),
+    /// Exact size in bytes, e.g. set by [`BtreeConfig::fixed_key_size`].
     Fixed(usize),
 }
 
 /// Configuration for a B-tree index.
 #[derive(Clone)]
 pub struct BtreeConfig {
-    order: usize,
+    order: Option<usize>,
     key_size: TypeSize,
     value_size: TypeSize,
     block_cache_size: usize,
+    block_cache_bytes: Option<usize>,
+    eviction_policy: EvictionPolicyFactory,
+    node_key_cache_size: usize,
+    leaf_key_cache_size: usize,
+    key_prefix_compression: bool,
+    deduplicate_values: bool,
+    node_block_size: usize,
+    page_size: usize,
+    order_statistics: bool,
+    bloom_filter_bits_per_key: usize,
+    hash_index: bool,
+    storage: StorageFactory,
+    prefault: bool,
+    huge_pages: bool,
+    growth: GrowthPolicy,
+    metrics: Metrics,
+    value_codec: ValueCodec,
+    block_alignment: usize,
+    relocation_policy: RelocationPolicy,
+    #[cfg(feature = "compression")]
+    value_compression: Option<usize>,
+    #[cfg(feature = "encryption")]
+    encryption_key: Option<[u8; 32]>,
 }
 
 impl Default for BtreeConfig {
     fn default() -> Self {
         Self {
-            order: 84,
+            order: None,
             key_size: TypeSize::Estimated(32),
             value_size: TypeSize::Estimated(32),
             block_cache_size: 16,
+            block_cache_bytes: None,
+            eviction_policy: default_eviction_policy(),
+            node_key_cache_size: 64,
+            leaf_key_cache_size: 16,
+            key_prefix_compression: false,
+            deduplicate_values: false,
+            node_block_size: DEFAULT_PAGE_SIZE,
+            page_size: system_page_size(),
+            order_statistics: false,
+            bloom_filter_bits_per_key: 0,
+            hash_index: false,
+            storage: Arc::new(create_default_storage),
+            prefault: false,
+            huge_pages: false,
+            growth: GrowthPolicy::default(),
+            metrics: Metrics::default(),
+            value_codec: ValueCodec::default(),
+            block_alignment: DEFAULT_BLOCK_ALIGNMENT,
+            relocation_policy: RelocationPolicy::default(),
+            #[cfg(feature = "compression")]
+            value_compression: None,
+            #[cfg(feature = "encryption")]
+            encryption_key: None,
         }
     }
 }
 
+impl fmt::Debug for BtreeConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut result = f.debug_struct("BtreeConfig");
+        result
+            .field("order", &self.order)
+            .field("key_size", &self.key_size)
+            .field("value_size", &self.value_size)
+            .field("block_cache_size", &self.block_cache_size)
+            .field("block_cache_bytes", &self.block_cache_bytes)
+            .field("node_key_cache_size", &self.node_key_cache_size)
+            .field("leaf_key_cache_size", &self.leaf_key_cache_size)
+            .field("key_prefix_compression", &self.key_prefix_compression)
+            .field("deduplicate_values", &self.deduplicate_values)
+            .field("node_block_size", &self.node_block_size)
+            .field("page_size", &self.page_size)
+            .field("block_alignment", &self.block_alignment)
+            .field("relocation_policy", &self.relocation_policy)
+            .field("order_statistics", &self.order_statistics)
+            .field("bloom_filter_bits_per_key", &self.bloom_filter_bits_per_key)
+            .field("hash_index", &self.hash_index)
+            .field("prefault", &self.prefault)
+            .field("huge_pages", &self.huge_pages)
+            .field("growth_strategy", &self.growth.strategy)
+            .field("max_file_size", &self.growth.max_size)
+            .field("value_codec", &self.value_codec);
+        #[cfg(feature = "compression")]
+        result.field("value_compression", &self.value_compression);
+        // The key itself must not be printed
+        #[cfg(feature = "encryption")]
+        result.field("encryption", &self.encryption_key.is_some());
+        result.finish_non_exhaustive()
+    }
+}
+
 impl BtreeConfig {
     /// Set the estimated maximum size in bytes for each key.
     ///
@@ -77,6 +346,10 @@ impl BtreeConfig {
     /// If serializing the key needs a fixed number of bytes
     /// (assuming [bincode](https://crates.io/crates/bincode) is used with a fixed integer encoding),
     /// a more efficient internal implementation will be used.
+    /// Keys with a size of up to 16 bytes, e.g. `u128`, are stored directly inside the nodes.
+    /// For keys larger than 8 bytes, the field for each key in a node is widened to 16 bytes,
+    /// which reduces the maximum [order](Self::order) for the default node block size to 63.
+    /// Larger keys are stored in a separate file like keys with a variable size.
     pub fn fixed_key_size(mut self, key_size: usize) -> Self {
         self.key_size = TypeSize::Fixed(key_size);
         self
@@ -97,274 +370,3326 @@ impl BtreeConfig {
     /// If serializing the value needs a fixed number of bytes
     /// (assuming [bincode](https://crates.io/crates/bincode) is used with a fixed integer encoding),  
     /// a more efficient internal implementation will be used.
+    /// Values with a size of up to 7 bytes are stored directly inside the nodes.
     pub fn fixed_value_size(mut self, value_size: usize) -> Self {
         self.value_size = TypeSize::Fixed(value_size);
         self
     }
 
+    /// Set the estimated maximum key and value sizes from the serialized size of some sample entries.
+    ///
+    /// The largest serialized key and value of the samples are used as estimation,
+    /// which avoids both allocating too much space and re-allocating the blocks of entries
+    /// that are larger than the estimated size.
+    /// Values are measured with the configured [value codec](Self::value_codec),
+    /// so it should be set before calling this function.
+    /// If there are no samples, the configuration is not changed.
+    /// The order of the tree does not depend on the key and value sizes, since a node only stores
+    /// the IDs of the key and value blocks, so the default order already uses the whole node block.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let samples = vec![("first".to_string(), vec![1_u8; 100]), ("second".to_string(), vec![2; 200])];
+    ///     let config = BtreeConfig::default().auto_size_from_samples(&samples)?;
+    ///     let mut b = BtreeIndex::<String, Vec<u8>>::with_capacity(config, samples.len())?;
+    ///     for (key, value) in samples {
+    ///         b.insert(key, value)?;
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn auto_size_from_samples<'a, K, V, I>(mut self, samples: I) -> Result<Self>
+    where
+        K: 'a + Serialize,
+        V: 'a + Serialize,
+        I: IntoIterator<Item = &'a (K, V)>,
+    {
+        use bincode::Options;
+
+        // Measure with the same options the files for variable sized blocks use
+        let serializer = bincode::DefaultOptions::new();
+        let mut max_sizes: Option<(usize, usize)> = None;
+        for (key, value) in samples {
+            let key_size: usize = serializer.serialized_size(key)?.try_into()?;
+            let value_size: usize = self.value_codec.serialized_size(value)?.try_into()?;
+            let (max_key_size, max_value_size) = max_sizes.unwrap_or_default();
+            max_sizes = Some((max_key_size.max(key_size), max_value_size.max(value_size)));
+        }
+        if let Some((max_key_size, max_value_size)) = max_sizes {
+            self.key_size = TypeSize::Estimated(max_key_size);
+            self.value_size = TypeSize::Estimated(max_value_size);
+        }
+        Ok(self)
+    }
+
     /// Sets the order of the tree, which determines how many elements a single node can store.
     ///
     /// A B-tree is balanced, so the number of keys of a node is between the order and the order times two.
-    /// The order must be at least 2 and at most 84 for this implementation, and
-    /// it is guaranteed that the internal structure for a node always fits inside a memory page.
+    /// The order must be at least 2 and at most 84 for the default node block size, and
+    /// it is guaranteed that the internal structure for a node always fits inside a node block.
     /// The default is to use the maximum number of keys, so the memory page is utilized as much as possible.
-    pub fn order(mut self, order: u8) -> Self {
-        self.order = order as usize;
+    /// Larger orders are possible when configuring a larger [node block size](Self::node_block_size).
+    pub fn order(mut self, order: usize) -> Self {
+        self.order = Some(order);
         self
     }
 
-    /// Sets the number of blocks/pages to hold in an internal cache.
-    pub fn block_cache_size(mut self, block_cache_size: usize) -> Self {
-        self.block_cache_size = block_cache_size;
+    /// Enable or disable storing the number of entries in each sub-tree,
+    /// which is needed for the [`BtreeIndex::rank`] and [`BtreeIndex::select`] queries.
+    ///
+    /// The additional counts need space in each node block,
+    /// which reduces the maximum order for the default node block size to 63.
+    pub fn order_statistics(mut self, enabled: bool) -> Self {
+        self.order_statistics = enabled;
         self
     }
-}
-
-impl<'a, K, V> BtreeIndex<K, V>
-where
-    K: 'a + Serialize + DeserializeOwned + PartialOrd + Clone + Ord + Send + Sync,
-    V: 'a + Serialize + DeserializeOwned + Clone + Send + Sync,
-{
-}
-
-impl<K, V> BtreeIndex<K, V>
-where
-    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord + Send + Sync,
-    V: 'static + Serialize + DeserializeOwned + Clone + Send + Sync,
-{
-    /// Create a new instance with the given configuration and capacity in number of elements.
-    pub fn with_capacity(config: BtreeConfig, capacity: usize) -> Result<BtreeIndex<K, V>> {
-        if config.order < 2 {
-            return Err(Error::OrderTooSmall(config.order));
-        } else if config.order > MAX_NUMBER_KEYS / 2 {
-            return Err(Error::OrderTooLarge(config.order));
-        }
-
-        let mut nodes = NodeFile::with_capacity(capacity, &config)?;
-
-        let values: Box<dyn TupleFile<V>> = match config.value_size {
-            TypeSize::Estimated(est_max_value_size) => {
-                let f = VariableSizeTupleFile::with_capacity(
-                    capacity * (est_max_value_size + BlockHeader::size()),
-                    config.block_cache_size,
-                )?;
-                Box::new(f)
-            }
-            TypeSize::Fixed(fixed_value_size) => {
-                let f = FixedSizeTupleFile::with_capacity(
-                    capacity * fixed_value_size,
-                    fixed_value_size,
-                )?;
-                Box::new(f)
-            }
-        };
 
-        // Always add an empty root node
-        let root_id = nodes.allocate_new_node()?;
+    /// Sets the number of bits per key of a bloom filter, which is checked before searching a key.
+    ///
+    /// The bloom filter can tell that a key is not contained in the index without searching
+    /// through the nodes, which makes [`BtreeIndex::get`] and [`BtreeIndex::contains_key`] much faster
+    /// for missing keys. Keys are hashed by their serialized bytes.
+    /// With 10 bits per key, about 1% of the missing keys still need to be searched.
+    /// The filter is kept in main memory and is sized for the capacity of the index.
+    /// When more keys are inserted, it is rebuilt with twice the size from all keys of the index.
+    /// Removed keys stay in the filter until it is rebuilt.
+    /// Per default, no bloom filter is used, which is the same as setting it to 0.
+    pub fn bloom_filter_bits_per_key(mut self, bits_per_key: usize) -> Self {
+        self.bloom_filter_bits_per_key = bits_per_key;
+        self
+    }
 
-        Ok(BtreeIndex {
-            root_id,
-            nodes,
-            values,
-            order: config.order,
-            nr_elements: 0,
-            last_inserted_node_id: root_id,
-        })
+    /// Enable or disable a hash index, which maps the hash of each key to the node containing it.
+    ///
+    /// [`BtreeIndex::get`] and [`BtreeIndex::contains_key`] then only search the node from
+    /// the hash index instead of all nodes from the root node.
+    /// Only if the key is not found in this node, e.g. because of a hash collision,
+    /// the tree is searched as usual. Range queries always use the tree.
+    /// The hash index is kept in main memory and needs about 16 bytes per key.
+    pub fn hash_index(mut self, enabled: bool) -> Self {
+        self.hash_index = enabled;
+        self
     }
 
-    /// Searches for a key in the index and returns the value if found.
-    pub fn get(&self, key: &K) -> Result<Option<V>> {
-        if let Some((node, i)) = self.search(self.root_id, key)? {
-            let payload_id = self.nodes.get_payload(node, i)?;
-            let v = self.values.get_owned(payload_id.try_into()?)?;
-            Ok(Some(v))
+    /// The layout of the node blocks for this configuration.
+    fn node_layout(&self) -> NodeLayout {
+        let layout = if self.order_statistics {
+            NodeLayout::with_child_counts(self.node_block_size)
         } else {
-            Ok(None)
+            NodeLayout::new(self.node_block_size)
+        };
+        match self.key_size {
+            // Wider keys are stored directly in the node, which needs a wider field
+            TypeSize::Fixed(key_size) if key_size > 8 && key_size <= node::MAX_INLINE_KEY_SIZE => {
+                layout.with_key_size(16)
+            }
+            _ => layout,
         }
     }
 
-    /// Returns whether the index contains the given key.
-    pub fn contains_key(&self, key: &K) -> Result<bool> {
-        Ok(self.search(self.root_id, key)?.is_some())
+    /// Sets the format in which the values are serialized.
+    ///
+    /// Per default, the values are serialized with bincode.
+    /// Use [`ValueCodec::BincodeFixint`] if the values contain integers which grow on updates,
+    /// since with the default variable integer encoding the value would need to be relocated.
+    /// The setting is ignored when a fixed value size is configured.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error, ValueCodec};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let config = BtreeConfig::default().value_codec(ValueCodec::BincodeFixint);
+    ///     let mut b = BtreeIndex::<u16, String>::with_capacity(config, 10)?;
+    ///     b.insert(1, "value".to_string())?;
+    ///     assert_eq!(Some("value".to_string()), b.get(&1)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn value_codec(mut self, codec: ValueCodec) -> Self {
+        self.value_codec = codec;
+        self
     }
 
-    /// Insert a new element into the index.
+    /// Compress values with a serialized size of at least `min_size` bytes.
     ///
-    /// Existing values will be overwritten and returned.
-    /// If the operation fails, you should assume that the whole index is corrupted.
-    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
-        // On sorted insert, the last inserted block might the one we need to insert the key into
-        let last_inserted_number_keys = self
-            .nodes
-            .number_of_keys(self.last_inserted_node_id)
-            .unwrap_or(0);
-        if last_inserted_number_keys > 0 {
-            let start = self.nodes.get_key(self.last_inserted_node_id, 0)?;
-            let end = self
-                .nodes
-                .get_key(self.last_inserted_node_id, last_inserted_number_keys - 1)?;
-
-            if &key >= start.as_ref()
-                && &key <= end.as_ref()
-                && last_inserted_number_keys < (2 * self.order) - 1
-            {
-                let expected = self.insert_nonfull(self.last_inserted_node_id, &key, value)?;
-                return Ok(expected);
-            }
-        }
+    /// The values are compressed with [LZ4](https://crates.io/crates/lz4_flex),
+    /// which is fast enough to reduce the time needed for writing and reading the values
+    /// if they are compressible (like text).
+    /// The setting is ignored when a fixed value size is configured.
+    #[cfg(feature = "compression")]
+    pub fn value_compression(mut self, min_size: usize) -> Self {
+        self.value_compression = Some(min_size);
+        self
+    }
 
-        let root_number_of_keys = self.nodes.number_of_keys(self.root_id).unwrap_or(0);
-        if root_number_of_keys == (2 * self.order) - 1 {
-            // Create a new root node, because the current will become full
-            let new_root_id = self.nodes.split_root_node(self.root_id, self.order)?;
+    /// Encrypt the keys and values in the files with AES-256-GCM and the given key,
+    /// so that they are never written to the storage in plaintext.
+    ///
+    /// Small values that would be stored inside the nodes are stored in encrypted blocks, too.
+    /// Keys and values must have an estimated size, because fixed size keys and values
+    /// are stored without encryption, and creating an index fails with
+    /// [`Error::EncryptionUnsupported`] otherwise.
+    /// Keys are not compared by their serialized bytes (see [`BtreeIndex::with_ordered_keys`]),
+    /// and values can't be accessed without copying them, e.g. by an archived index.
+    /// The write-ahead log of the `wal` feature is not encrypted.
+    #[cfg(feature = "encryption")]
+    pub fn encryption_key(mut self, key: [u8; 32]) -> Self {
+        self.encryption_key = Some(key);
+        self
+    }
 
-            let existing = self.insert_nonfull(new_root_id, &key, value)?;
-            self.root_id = new_root_id;
-            Ok(existing)
-        } else {
-            let existing = self.insert_nonfull(self.root_id, &key, value)?;
-            Ok(existing)
-        }
+    /// Sets the size in bytes of a single node block.
+    ///
+    /// The size must be at least 4 KiB, which is also the default, and a multiple or a divisor
+    /// of the [page size](Self::page_size), so that no node block overlaps two memory pages.
+    /// Larger node blocks can hold more keys and allow a larger order of the tree,
+    /// e.g. 64 KiB blocks allow an order of up to 1364.
+    /// A wider and shallower tree needs less node splits when inserting and can improve the locality of range queries.
+    pub fn node_block_size(mut self, node_block_size: usize) -> Self {
+        self.node_block_size = node_block_size;
+        self
     }
 
-    /// Returns true if the index does not contain any elements.
-    pub fn is_empty(&self) -> bool {
-        self.nr_elements == 0
+    /// Align the data of each key and value block to the given number of bytes,
+    /// which must be a power of two.
+    ///
+    /// Per default, blocks are aligned to 8 bytes, so that reading multi-byte values from them
+    /// is aligned. Larger alignments allow to cast the stored bytes to types with a larger
+    /// alignment without copying them, but add more padding between small blocks.
+    /// Keys and values with a fixed size are not stored in blocks and are not affected.
+    pub fn block_alignment(mut self, alignment: usize) -> Self {
+        self.block_alignment = alignment;
+        self
     }
 
-    /// Returns the length of the index.
-    pub fn len(&self) -> usize {
-        self.nr_elements
+    /// Sets how much space is reserved when a key or value block needs more space than allocated
+    /// and is relocated.
+    ///
+    /// Per default, the new block has double the needed size. Values that grow repeatedly,
+    /// e.g. with [`BtreeIndex::merge_insert`], are relocated less often with a larger factor
+    /// for blocks that have already been relocated before.
+    /// The relocations can be checked with [`BtreeIndex::relocation_stats`].
+    pub fn relocation_policy(mut self, policy: RelocationPolicy) -> Self {
+        self.relocation_policy = policy;
+        self
     }
 
-    /// Return an iterator over a range of keys.
+    /// Sets the size in bytes of a memory page, which must be a power of two.
+    ///
+    /// Relocated blocks are aligned to whole pages, and files are shrunk to whole pages.
+    /// Per default, the page size of the system is detected at runtime, e.g. 16 KiB on Apple Silicon.
+    /// A larger size can be configured for storages backed by huge pages.
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Use a custom storage backend for the nodes, keys and values.
     ///
-    /// If you want to iterate over all entries of the index, use the unbounded `..` iterator.
+    /// The given function is called with the initial capacity in bytes for each internal file
+    /// and must return a new and independent storage.
+    /// Per default, temporary memory mapped files are used.
     ///
     /// # Example
     ///
     /// ```rust
-    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error, MemoryStorage, StorageBackend};
     ///
     /// fn main() -> std::result::Result<(), Error> {
-    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
-    ///     b.insert(1,2)?;
-    ///     b.insert(200, 4)?;
-    ///     b.insert(20, 3)?;
-    ///
-    ///     for e in b.range(..)? {
-    ///         let (k, v) = e?;
-    ///         dbg!(k, v);
-    ///     }
+    ///     let config = BtreeConfig::default().storage_backend(|capacity| {
+    ///         let storage: Box<dyn StorageBackend> = Box::new(MemoryStorage::with_capacity(capacity));
+    ///         Ok(storage)
+    ///     });
+    ///     let mut b = BtreeIndex::<u16, u16>::with_capacity(config, 10)?;
+    ///     b.insert(1, 2)?;
+    ///     assert_eq!(Some(2), b.get(&1)?);
     ///     Ok(())
     /// }
     /// ```
-    pub fn range<R>(&self, range: R) -> Result<Range<K, V>>
+    pub fn storage_backend<F>(mut self, create_storage: F) -> Self
     where
-        R: RangeBounds<K>,
+        F: Fn(usize) -> Result<Box<dyn StorageBackend>> + Send + Sync + 'static,
     {
-        // Start to search at the root node
-        let start = range.start_bound().cloned();
-        let end = range.end_bound().cloned();
-        let mut stack = self.nodes.find_range(self.root_id, range);
-        // The range is sorted by smallest first, but popping values from the end of the
-        // stack is more effective
-        stack.reverse();
+        self.storage = Arc::new(create_storage);
+        self
+    }
 
-        let result = Range {
-            stack,
-            start,
-            end,
-            nodes: &self.nodes,
-            values: self.values.as_ref(),
-            phantom: PhantomData,
-        };
-        Ok(result)
+    /// Enable or disable touching all memory pages of the files when they are created.
+    ///
+    /// Memory mapped files are only allocated page by page when they are first accessed,
+    /// which can cause many page faults while inserting the first entries.
+    /// When enabled, the initial capacity of each file is allocated upfront when creating the index,
+    /// which makes creating the index slower, but the first insertions faster.
+    /// Space that is added when a file grows later is not affected.
+    pub fn prefault(mut self, enabled: bool) -> Self {
+        self.prefault = enabled;
+        self
     }
 
-    /// Return an iterator over all entries and consumes the B-tree index.
+    /// Request huge pages for the file holding the nodes.
+    ///
+    /// Nodes are accessed randomly, so for very large indexes, huge pages can avoid many misses
+    /// of the translation lookaside buffer (TLB).
+    /// When enabled, the initial size of the node file is a multiple of the huge page size of 2 MB
+    /// and the storage is asked to [use huge pages](StorageBackend::use_huge_pages).
+    /// For the default storage, this is a hint for transparent huge pages, which only has an effect
+    /// on Linux and depending on the settings of the kernel.
+    pub fn huge_pages(mut self, enabled: bool) -> Self {
+        self.huge_pages = enabled;
+        self
+    }
+
+    /// Sets how much the internal files grow when they need more space.
+    ///
+    /// Per default, the size of a file is doubled, which can reserve a lot of unused space
+    /// for very large indexes.
+    /// A smaller factor (e.g. `GrowthStrategy::Factor(1.25)`) or fixed increments reduce this
+    /// overhead, but the files need to grow more often.
+    pub fn growth_strategy(mut self, strategy: GrowthStrategy) -> Self {
+        self.growth.strategy = strategy;
+        self
+    }
+
+    /// Sets the maximum size in bytes of each internal file.
+    ///
+    /// Inserting an entry fails with [`Error::MaximumFileSizeExceeded`] if a file would need
+    /// to grow beyond this size, instead of allocating more memory or disk space.
+    /// The initial capacity of the files is limited to this size, too.
+    pub fn max_file_size(mut self, max_size: usize) -> Self {
+        self.growth.max_size = Some(max_size);
+        self
+    }
+
+    /// Register an observer that is notified about node splits, growing files,
+    /// relocated blocks and evicted cache entries, e.g. to collect metrics.
     ///
     /// # Example
     ///
     /// ```rust
-    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, BtreeMetricsObserver, Error};
     ///
-    /// fn main() -> std::result::Result<(), Error> {
-    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
-    ///     b.insert(1,2)?;
-    ///     b.insert(200, 4)?;
-    ///     b.insert(20, 3)?;
+    /// #[derive(Default)]
+    /// struct SplitCounter(AtomicUsize);
     ///
-    ///     for e in b.into_iter()? {
-    ///         let (k, v) = e?;
-    ///         dbg!(k, v);
+    /// impl BtreeMetricsObserver for SplitCounter {
+    ///     fn on_split(&self, _node_id: u64, _new_node_id: u64) {
+    ///         self.0.fetch_add(1, Ordering::Relaxed);
+    ///     }
+    /// }
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let splits = Arc::new(SplitCounter::default());
+    ///     let config = BtreeConfig::default().order(2).metrics_observer(splits.clone());
+    ///     let mut b = BtreeIndex::<u16, u16>::with_capacity(config, 10)?;
+    ///     for i in 0..10 {
+    ///         b.insert(i, i)?;
     ///     }
+    ///     assert!(splits.0.load(Ordering::Relaxed) > 0);
     ///     Ok(())
     /// }
     /// ```
-    pub fn into_iter(self) -> Result<BtreeIntoIter<K, V>> {
-        let mut stack = self.nodes.find_range(self.root_id, ..);
-        // The range is sorted by smallest first, but popping values from the end of the
-        // stack is more effective
-        stack.reverse();
+    pub fn metrics_observer(mut self, observer: Arc<dyn BtreeMetricsObserver>) -> Self {
+        self.metrics = Metrics::new(observer);
+        self
+    }
 
-        let result = BtreeIntoIter {
-            stack,
-            nodes: self.nodes,
-            values: self.values,
-            phantom: PhantomData,
-        };
-        Ok(result)
+    /// Sets the number of blocks/pages to hold in an internal cache.
+    pub fn block_cache_size(mut self, block_cache_size: usize) -> Self {
+        self.block_cache_size = block_cache_size;
+        self.block_cache_bytes = None;
+        self
     }
 
-    /// Swaps the values for the given keys.
-    pub fn swap(&mut self, a: &K, b: &K) -> Result<()> {
-        // Get the node ids and position in the node for both keys,
-        // fail when they do not exist
-        let (a_node, a_pos) = self.search(self.root_id, a)?.ok_or(Error::NonExistingKey)?;
-        let (b_node, b_pos) = self.search(self.root_id, b)?.ok_or(Error::NonExistingKey)?;
+    /// Limit the internal caches by the approximate size in bytes of the cached keys and values
+    /// instead of their number.
+    ///
+    /// The size of an entry is estimated by its serialized size.
+    /// This is useful when the size of the values varies a lot.
+    /// The key and the value file each have their own cache with this budget.
+    pub fn block_cache_bytes(mut self, max_bytes: usize) -> Self {
+        self.block_cache_bytes = Some(max_bytes);
+        self
+    }
 
-        // Get the payload IDs for the node positions
-        let a_payload = self.nodes.get_payload(a_node, a_pos)?;
-        let b_payload = self.nodes.get_payload(b_node, b_pos)?;
+    /// Use a custom policy to decide which blocks are removed from a full block cache.
+    ///
+    /// The given function is called for each block cache and must return a new policy.
+    /// Per default, the least recently used block is removed, which performs badly
+    /// when scanning many entries evicts the entries that are accessed repeatedly.
+    /// A [`SegmentedLruPolicy`](crate::SegmentedLruPolicy) protects these entries from scans.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, EvictionPolicy, Error, SegmentedLruPolicy};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let config = BtreeConfig::default().cache_eviction_policy(|| {
+    ///         let policy: Box<dyn EvictionPolicy> = Box::new(SegmentedLruPolicy::default());
+    ///         policy
+    ///     });
+    ///     let mut b = BtreeIndex::<u16, String>::with_capacity(config, 10)?;
+    ///     b.insert(1, "one".to_string())?;
+    ///     assert_eq!(Some("one".to_string()), b.get(&1)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn cache_eviction_policy<F>(mut self, create_policy: F) -> Self
+    where
+        F: Fn() -> Box<dyn EvictionPolicy> + Send + Sync + 'static,
+    {
+        self.eviction_policy = Arc::new(create_policy);
+        self
+    }
 
-        // Swap the payload IDs at these positions
-        self.nodes.set_payload(a_node, a_pos, b_payload)?;
-        self.nodes.set_payload(b_node, b_pos, a_payload)?;
+    /// Sets the number of internal nodes for which the deserialized keys are cached.
+    ///
+    /// Searching a node deserializes some of its keys to compare them with the searched key.
+    /// The keys of the most recently searched nodes are kept, so that searching through the same
+    /// upper nodes of the tree again does not need to deserialize their keys.
+    /// Only the keys needed by a search are deserialized and the entry of a node is removed
+    /// when its keys change. Set it to 0 to disable the cache for internal nodes.
+    /// The cache is not used for keys that are compared by their serialized bytes,
+    /// e.g. for [ordered keys](BtreeIndex::with_ordered_keys) or primitive integers with a fixed size.
+    ///
+    /// Leaf nodes are cached separately (see [`Self::leaf_key_cache_size`]),
+    /// so searching many different leaves does not evict the keys of the internal nodes.
+    pub fn node_key_cache_size(mut self, nodes: usize) -> Self {
+        self.node_key_cache_size = nodes;
+        self
+    }
 
-        Ok(())
+    /// Sets the number of leaf nodes for which the deserialized keys are cached.
+    ///
+    /// Most leaves are only searched once before another leaf is needed, so this cache is
+    /// smaller than the [cache for internal nodes](Self::node_key_cache_size) by default.
+    /// Increase it if the same small set of keys is searched repeatedly, or set it to 0
+    /// to disable caching the keys of leaves.
+    pub fn leaf_key_cache_size(mut self, nodes: usize) -> Self {
+        self.leaf_key_cache_size = nodes;
+        self
     }
 
-    fn search(&self, node_id: u64, key: &K) -> Result<Option<(u64, usize)>> {
-        match self.nodes.binary_search(node_id, key)? {
-            SearchResult::Found(i) => Ok(Some((node_id, i))),
-            SearchResult::NotFound(i) => {
-                if self.nodes.is_leaf(node_id)? {
-                    Ok(None)
-                } else {
-                    // search in the matching child node
-                    let child_node_id = self.nodes.get_child_node(node_id, i)?;
-                    self.search(child_node_id, key)
-                }
-            }
-        }
+    /// Enable or disable the compression of common key prefixes inside each node.
+    ///
+    /// When enabled, each node remembers a prefix shared by its keys and only the remaining suffix
+    /// is stored for each key. This is most useful for byte-string keys like [`String`] or `Vec<u8>`
+    /// with long shared prefixes (e.g. URLs or file paths).
+    /// The setting is ignored when a fixed key size is configured.
+    ///
+    /// When searching [`String`] or `Vec<u8>` keys, the prefix of a node is only compared once
+    /// and then the suffix bytes of its keys are compared without deserializing the keys.
+    /// Searching is still a bit slower than without compression, since the searched keys
+    /// need to be serialized and the decoded keys of the nodes are not cached.
+    pub fn key_prefix_compression(mut self, enabled: bool) -> Self {
+        self.key_prefix_compression = enabled;
+        self
     }
 
-    fn insert_nonfull(&mut self, node_id: u64, key: &K, value: V) -> Result<Option<V>> {
+    /// Enable or disable storing identical values only once.
+    ///
+    /// When enabled, the hash of each serialized value is remembered in memory and
+    /// inserting a value that is already stored for another key reuses its block.
+    /// This saves a lot of space if there are only a few distinct values, but needs
+    /// additional memory for each distinct value.
+    /// Since a block might be shared by several keys, updated values are always stored in
+    /// a new or existing block with the same value instead of replacing the value in the block.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let config = BtreeConfig::default().deduplicate_values(true);
+    ///     let mut b = BtreeIndex::<u32, String>::with_capacity(config, 10)?;
+    ///     for i in 0..10 {
+    ///         b.insert(i, "same category".to_string())?;
+    ///     }
+    ///     assert_eq!(Some("same category".to_string()), b.get(&5)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn deduplicate_values(mut self, enabled: bool) -> Self {
+        self.deduplicate_values = enabled;
+        self
+    }
+
+    /// Create a new storage for a file with the given initial capacity in bytes.
+    pub(crate) fn create_storage(&self, capacity: usize) -> Result<Box<dyn StorageBackend>> {
+        self.create_storage_with_pages(capacity, false)
+    }
+
+    /// Create a new storage for the nodes with the given initial capacity in bytes.
+    pub(crate) fn create_node_storage(&self, capacity: usize) -> Result<Box<dyn StorageBackend>> {
+        if self.huge_pages {
+            self.create_storage_with_pages(capacity.next_multiple_of(HUGE_PAGE_SIZE), true)
+        } else {
+            self.create_storage_with_pages(capacity, false)
+        }
+    }
+
+    fn create_storage_with_pages(
+        &self,
+        capacity: usize,
+        huge_pages: bool,
+    ) -> Result<Box<dyn StorageBackend>> {
+        let mut storage = (self.storage)(self.growth.initial_size(capacity))?;
+        // The pages must be requested before they are allocated by touching them
+        if huge_pages {
+            storage.use_huge_pages()?;
+        }
+        if self.prefault {
+            prefault(storage.as_mut(), self.page_size);
+        }
+        Ok(storage)
+    }
+
+    /// Whether the blocks of files with a variable block size are encrypted.
+    fn is_encrypted(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        return self.encryption_key.is_some();
+        #[cfg(not(feature = "encryption"))]
+        false
+    }
+
+    /// Store the values with a variable size and uncompressed,
+    /// so that their serialized bytes are aligned to (at least) the given number of bytes.
+    #[cfg(feature = "rkyv")]
+    pub(crate) fn aligned_values(mut self, alignment: usize) -> Self {
+        if let TypeSize::Fixed(value_size) = self.value_size {
+            self.value_size = TypeSize::Estimated(value_size);
+        }
+        #[cfg(feature = "compression")]
+        {
+            self.value_compression = None;
+        }
+        self.block_alignment = self.block_alignment.max(alignment);
+        self
+    }
+}
+
+impl<K, V> BtreeIndex<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    /// Create a builder for a new instance, which checks that the configured options fit together.
+    pub fn builder() -> BtreeBuilder<K, V> {
+        BtreeBuilder::new()
+    }
+
+    /// Create a new instance with the given configuration and capacity in number of elements.
+    ///
+    /// Options that have no effect in combination with other options are ignored,
+    /// use the [builder](Self::builder) to reject them.
+    pub fn with_capacity(config: BtreeConfig, capacity: usize) -> Result<BtreeIndex<K, V>> {
+        if !config.page_size.is_power_of_two() {
+            return Err(Error::InvalidPageSize(config.page_size));
+        }
+        if !config.block_alignment.is_power_of_two() {
+            return Err(Error::InvalidBlockAlignment(config.block_alignment));
+        }
+        if config.node_block_size < DEFAULT_PAGE_SIZE
+            || !(config.node_block_size.is_multiple_of(config.page_size)
+                || config.page_size.is_multiple_of(config.node_block_size))
+        {
+            return Err(Error::InvalidNodeBlockSize(config.node_block_size));
+        }
+        #[cfg(feature = "encryption")]
+        if config.encryption_key.is_some()
+            && (matches!(config.key_size, TypeSize::Fixed(_))
+                || matches!(config.value_size, TypeSize::Fixed(_)))
+        {
+            return Err(Error::EncryptionUnsupported("fixed key or value sizes"));
+        }
+        let max_order = config.node_layout().max_keys / 2;
+        let order = config.order.unwrap_or(max_order);
+        if order < 2 {
+            return Err(Error::OrderTooSmall(order));
+        } else if order > max_order {
+            return Err(Error::OrderTooLarge(order));
+        }
+
+        let mut nodes = NodeFile::with_capacity(capacity, &config)?;
+
+        let values = match config.value_size {
+            TypeSize::Estimated(est_max_value_size) => {
+                let f = create_variable_size_file(
+                    VALUE_FILE_NAME,
+                    capacity * (est_max_value_size + BlockHeader::size()),
+                    &config,
+                )?
+                .with_codec(Arc::new(config.value_codec));
+                #[cfg(feature = "compression")]
+                let f = if let Some(min_size) = config.value_compression {
+                    f.with_compression(min_size)
+                } else {
+                    f
+                };
+                ValueFile::Variable(Box::new(f))
+            }
+            TypeSize::Fixed(fixed_value_size) => {
+                // Small values are stored in the nodes and don't need any space in the file
+                let capacity = if fixed_value_size <= payload::MAX_INLINE_VALUE_SIZE {
+                    0
+                } else {
+                    capacity
+                };
+                ValueFile::Fixed(create_fixed_size_file(
+                    VALUE_FILE_NAME,
+                    capacity,
+                    fixed_value_size,
+                    &config,
+                )?)
+            }
+        };
+
+        // Always add an empty root node
+        let root_id = nodes.allocate_new_node()?;
+
+        let value_blocks = config
+            .deduplicate_values
+            .then(payload::ValueBlocks::default);
+        let bloom_filter = (config.bloom_filter_bits_per_key > 0).then(|| {
+            bloom::BloomFilter::with_bits_per_key(config.bloom_filter_bits_per_key, capacity)
+        });
+        let hash_index = config.hash_index.then(hash_index::HashIndex::default);
+
+        Ok(BtreeIndex {
+            root_id,
+            nodes,
+            values,
+            order,
+            nr_elements: 0,
+            insert_path: Vec::new(),
+            live_range: (Bound::Unbounded, Bound::Unbounded),
+            value_blocks,
+            bloom_filter,
+            hash_index,
+            instance: payload::next_instance(),
+            poisoned: false,
+            #[cfg(feature = "wal")]
+            wal: None,
+            config,
+        })
+    }
+
+    /// Create a new instance for keys whose serialized bytes have the same order as the keys.
+    ///
+    /// When searching inside a node, the serialized keys are compared directly
+    /// instead of deserializing them first.
+    /// This has no effect when [key prefix compression](BtreeConfig::key_prefix_compression) is enabled.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let config = BtreeConfig::default().fixed_key_size(4);
+    ///     let mut b = BtreeIndex::<[u8; 4], u16>::with_ordered_keys(config, 10)?;
+    ///     b.insert(*b"abcd", 1)?;
+    ///     b.insert(*b"aaaa", 2)?;
+    ///
+    ///     assert_eq!(Some(1), b.get(b"abcd")?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn with_ordered_keys(config: BtreeConfig, capacity: usize) -> Result<BtreeIndex<K, V>>
+    where
+        K: OrderedBytes,
+    {
+        let mut result = Self::with_capacity(config, capacity)?;
+        result.nodes.enable_ordered_key_bytes();
+        Ok(result)
+    }
+
+    /// Create a new instance for keys and values that are always serialized with the same size.
+    ///
+    /// The [fixed key size](BtreeConfig::fixed_key_size) and [fixed value size](BtreeConfig::fixed_value_size)
+    /// of the configuration are set from the [`FixedSize`] implementation of the types.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u64, (u32, [u8; 4])>::with_fixed_size(BtreeConfig::default(), 10)?;
+    ///     b.insert(1, (2, *b"abcd"))?;
+    ///
+    ///     assert_eq!(Some((2, *b"abcd")), b.get(&1)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn with_fixed_size(config: BtreeConfig, capacity: usize) -> Result<BtreeIndex<K, V>>
+    where
+        K: FixedSize,
+        V: FixedSize,
+    {
+        let config = config.fixed_key_size(K::SIZE).fixed_value_size(V::SIZE);
+        Self::with_capacity(config, capacity)
+    }
+
+    /// Searches for a key in the index and returns the value if found.
+    ///
+    /// Like for [`BTreeMap::get`](std::collections::BTreeMap::get), the key can be given as a borrowed
+    /// form of the key type, e.g. a `&[u8]` slice for `Vec<u8>` keys, so no owned key needs to be created.
+    /// The [bloom filter](BtreeConfig::bloom_filter_bits_per_key) and the [hash index](BtreeConfig::hash_index) are only
+    /// used if the key has the key type itself, because they use the serialized key.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<Vec<u8>, u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(b"abc".to_vec(), 1)?;
+    ///
+    ///     let probe: &[u8] = b"abc";
+    ///     assert_eq!(Some(1), b.get(probe)?);
+    ///     assert_eq!(Some(1), b.get(&b"abc".to_vec())?);
+    ///     assert_eq!(None, b.get(&b"abd"[..])?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn get<Q>(&self, key: &Q) -> Result<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized + 'static,
+    {
+        self.check_poisoned()?;
+        if let Some((node, i)) = self.search_borrowed_key(key)? {
+            let payload = self.nodes.get_payload(node, i)?;
+            let v = payload::read_value(self.values.as_ref(), payload)?;
+            Ok(Some(v))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Searches for a key in the index and returns the value if found,
+    /// starting with the leaf node of the previous lookup with the same context.
+    ///
+    /// When looking up keys in (nearly) sorted order, most keys are found in the same leaf
+    /// as the previous key, which avoids searching all nodes from the root node.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error, LookupContext};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u32, u32>::with_capacity(BtreeConfig::default(), 100)?;
+    ///     for i in 0..100 {
+    ///         b.insert(i, i * 2)?;
+    ///     }
+    ///     let mut context = LookupContext::new();
+    ///     for i in 10..20 {
+    ///         assert_eq!(Some(i * 2), b.get_with_context(&i, &mut context)?);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn get_with_context(&self, key: &K, context: &mut LookupContext) -> Result<Option<V>> {
+        self.check_poisoned()?;
+        if let Some((node, i)) = self.search_with_context(key, context)? {
+            let payload = self.nodes.get_payload(node, i)?;
+            let v = payload::read_value(self.values.as_ref(), payload)?;
+            Ok(Some(v))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Searches for a key in the index and returns the serialized bytes of the value if found.
+    #[cfg(feature = "rkyv")]
+    pub(crate) fn get_raw_value(&self, key: &K) -> Result<Option<&[u8]>> {
+        if let Some((node, i)) = self.search(self.root_id, key)? {
+            let payload = self.nodes.get_payload(node, i)?;
+            if payload::is_inline(payload) {
+                return Err(Error::DeserializeBlock(
+                    "value is stored inline and has no block".to_string(),
+                ));
+            }
+            #[cfg(feature = "encryption")]
+            if self.values.is_encrypted() {
+                return Err(Error::EncryptionUnsupported("zero-copy access to values"));
+            }
+            let v = self.values.get_raw(payload.try_into()?)?;
+            Ok(Some(v))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Insert a new element into the index and return the ID of its payload.
+    ///
+    /// Existing values will be overwritten, but unlike [`Self::insert`] they are not returned.
+    /// The value can be retrieved with the ID by [`Self::get_by_payload`] without searching the key.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16, String>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     let id = b.insert_returning_id(1, "first value".to_string())?;
+    ///
+    ///     assert_eq!(Some(id), b.payload_id(&1)?);
+    ///     assert_eq!("first value", b.get_by_payload(id)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn insert_returning_id(&mut self, key: K, value: V) -> Result<PayloadId> {
+        self.check_fixed_sizes(&key, &value)?;
+        let mut result = 0;
+        self.poison_on_error(|index| {
+            index.insert_payload(key, |index, existing| {
+                result = if let Some(payload) = existing {
+                    index.write_value(payload, &value)?
+                } else {
+                    index.insert_value(&value)?
+                };
+                Ok(result)
+            })
+        })?;
+        self.create_payload_id(result)
+    }
+
+    /// Searches for a key in the index and returns the ID of its payload if found.
+    pub fn payload_id(&self, key: &K) -> Result<Option<PayloadId>> {
+        self.check_poisoned()?;
+        if let Some((node, i)) = self.search(self.root_id, key)? {
+            let payload = self.nodes.get_payload(node, i)?;
+            Ok(Some(self.create_payload_id(payload)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Creates the ID of a payload of this index, with the current generation of its value block.
+    fn create_payload_id(&self, payload: u64) -> Result<PayloadId> {
+        let generation = if payload::is_inline(payload) {
+            0
+        } else {
+            self.values.block_generation(payload.try_into()?)?
+        };
+        Ok(PayloadId {
+            instance: self.instance,
+            payload,
+            generation,
+        })
+    }
+
+    /// Returns the value referenced by the ID of a payload.
+    ///
+    /// An error is returned if the ID was not returned by this index, if the index
+    /// has been cleared since then, or if the value block has been freed by
+    /// [draining](Self::drain) the entry, even if the block has been reused for another value.
+    /// If the value of the key has been replaced since the ID was returned,
+    /// either the previous or the new value is returned.
+    pub fn get_by_payload(&self, id: PayloadId) -> Result<V> {
+        self.check_poisoned()?;
+        let valid = id.instance == self.instance
+            && (payload::is_inline(id.payload) || {
+                let block_id = id.payload.try_into()?;
+                self.values.contains_block(block_id)
+                    && self.values.block_generation(block_id)? == id.generation
+            });
+        if !valid {
+            return Err(Error::InvalidPayloadId);
+        }
+        payload::read_value(self.values.as_ref(), id.payload)
+    }
+
+    /// Searches for a key in the index and returns the serialized value if found.
+    ///
+    /// Values are only copied if they are stored inline or compressed.
+    pub(crate) fn get_serialized_value<Q>(&self, key: &Q) -> Result<Option<Cow<'_, [u8]>>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized + 'static,
+    {
+        if let Some((node, i)) = self.search(self.root_id, key)? {
+            let payload = self.nodes.get_payload(node, i)?;
+            if payload::is_inline(payload) {
+                let (buffer, size) = payload::inline_buffer(payload);
+                Ok(Some(Cow::Owned(buffer[0..size].to_vec())))
+            } else {
+                Ok(Some(self.values.get_serialized(payload.try_into()?)?))
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Insert a value that is already serialized without deserializing it.
+    ///
+    /// The serialized value consists of the `header` and `len` bytes read from the `reader`,
+    /// which are copied directly into a new block.
+    pub(crate) fn insert_from_reader(
+        &mut self,
+        key: K,
+        header: &[u8],
+        len: usize,
+        reader: &mut dyn std::io::Read,
+    ) -> Result<()> {
+        self.insert_payload(key, |index, _| {
+            let block_id = index.values.insert_from_reader(header, len, reader)?;
+            Ok(block_id.try_into()?)
+        })
+    }
+
+    /// The built-in format in which the values are serialized.
+    ///
+    /// Values with a fixed size are always serialized with fixed integer encoding.
+    pub(crate) fn value_codec(&self) -> ValueCodec {
+        match self.values {
+            ValueFile::Variable(_) => self.config.value_codec,
+            ValueFile::Fixed(_) => ValueCodec::BincodeFixint,
+        }
+    }
+
+    /// Searches for a key in the index and returns the stored key and the value if found.
+    ///
+    /// The stored key can differ from the searched key if their [`Ord`] implementation
+    /// considers different keys as equal (e.g. when ignoring the case of strings).
+    pub fn get_key_value(&self, key: &K) -> Result<Option<(K, V)>> {
+        self.check_poisoned()?;
+        if let Some((node, i)) = self.search(self.root_id, key)? {
+            Ok(Some(self.get_key_value_tuple(node, i)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the number of bytes currently used by the index.
+    ///
+    /// The memory mapped files are reported separately from the main memory
+    /// used by the caches and other bookkeeping.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let keys = self.nodes.key_memory_usage();
+        let values = self.values.memory_usage();
+        MemoryUsage {
+            nodes: self.nodes.mapped_bytes(),
+            keys: keys.mapped,
+            values: values.mapped,
+            block_cache: keys.cache + values.cache,
+            value_hashes: self
+                .value_blocks
+                .as_ref()
+                .map_or(0, payload::ValueBlocks::memory_usage),
+            bloom_filter: self
+                .bloom_filter
+                .as_ref()
+                .map_or(0, bloom::BloomFilter::memory_usage),
+            hash_index: self
+                .hash_index
+                .as_ref()
+                .map_or(0, hash_index::HashIndex::memory_usage),
+        }
+    }
+
+    /// Returns how often the value blocks have been relocated because they needed more space
+    /// and how many bytes the abandoned blocks occupy.
+    ///
+    /// Many relocations indicate that the [estimated value size](BtreeConfig::max_value_size)
+    /// is too small or that the [relocation policy](BtreeConfig::relocation_policy) should reserve
+    /// more space for growing values. The abandoned space is reclaimed by [`Self::compact`].
+    pub fn relocation_stats(&self) -> RelocationStats {
+        self.values.relocation_stats()
+    }
+
+    /// Returns how many bytes of the files holding the nodes, keys and values are used
+    /// and how large the files currently are.
+    ///
+    /// The files grow automatically, but [`Self::try_reserve`] can grow them up front.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<String, String>::with_capacity(BtreeConfig::default(), 100)?;
+    ///     b.insert("key".to_string(), "value".repeat(20))?;
+    ///
+    ///     let capacity = b.capacity();
+    ///     assert!(capacity.values.used > 0);
+    ///     assert!(capacity.values.used <= capacity.values.allocated);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn capacity(&self) -> Capacity {
+        let keys = self.nodes.key_memory_usage();
+        let values = self.values.memory_usage();
+        Capacity {
+            nodes: FileCapacity {
+                used: self.nodes.used_bytes(),
+                allocated: self.nodes.mapped_bytes(),
+            },
+            keys: FileCapacity {
+                used: self.nodes.key_used_bytes(),
+                allocated: keys.mapped,
+            },
+            values: FileCapacity {
+                used: self.values.used_bytes(),
+                allocated: values.mapped,
+            },
+        }
+    }
+
+    /// Returns the order of the tree, which is the configured [order](BtreeConfig::order)
+    /// or the maximum order that fits into a node block.
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Returns the configured size of the keys.
+    pub fn key_size(&self) -> TypeSize {
+        self.config.key_size
+    }
+
+    /// Returns the configured size of the values.
+    pub fn value_size(&self) -> TypeSize {
+        self.config.value_size
+    }
+
+    /// Returns the maximum number of blocks held by each of the caches of the key and value files.
+    ///
+    /// Returns `None` if the caches are limited by the [size of the cached blocks](BtreeConfig::block_cache_bytes)
+    /// instead.
+    pub fn block_cache_size(&self) -> Option<usize> {
+        match self.config.block_cache_bytes {
+            Some(_) => None,
+            None => Some(self.config.block_cache_size),
+        }
+    }
+
+    /// Returns the configuration the index has been created with, e.g. to log it
+    /// or to create another index with the same settings.
+    pub fn config(&self) -> &BtreeConfig {
+        &self.config
+    }
+
+    /// Returns whether the index contains the given key.
+    ///
+    /// The key can be given as a borrowed form of the key type, like for [`Self::get`].
+    pub fn contains_key<Q>(&self, key: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized + 'static,
+    {
+        self.check_poisoned()?;
+        Ok(self.search_borrowed_key(key)?.is_some())
+    }
+
+    /// Returns for each of the given keys whether the index contains it.
+    ///
+    /// The keys are searched in sorted order and each search continues from the lowest node
+    /// of the previous search that can contain the key, instead of descending from the root node.
+    /// This is much faster than calling [`Self::contains_key`] for each key
+    /// when checking many keys, especially if some of them are close to each other.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16, u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(1, 2)?;
+    ///     b.insert(20, 3)?;
+    ///     assert_eq!(vec![true, false, true], b.contains_many(&[20, 5, 1])?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn contains_many(&self, keys: &[K]) -> Result<Vec<bool>> {
+        self.check_poisoned()?;
+        let mut sorted: Vec<usize> = (0..keys.len()).collect();
+        sorted.sort_unstable_by(|a, b| keys[*a].cmp(&keys[*b]));
+
+        let mut result = vec![false; keys.len()];
+        // Nodes of the current search path with the exclusive upper bound of their keys
+        let mut path: Vec<(u64, Option<Arc<K>>)> = vec![(self.root_id, None)];
+        for i in sorted {
+            let key = &keys[i];
+            // Go up until the node can contain the key, which is always true for the root node
+            while path
+                .last()
+                .and_then(|(_, upper)| upper.as_ref())
+                .is_some_and(|upper| key >= upper.as_ref())
+            {
+                path.pop();
+            }
+            while let Some((node_id, upper)) = path.last() {
+                let node_id = *node_id;
+                match self.nodes.binary_search(node_id, key)? {
+                    SearchResult::Found(idx) => {
+                        result[i] = !self.nodes.is_removed(node_id, idx)?;
+                        break;
+                    }
+                    SearchResult::NotFound(idx) => {
+                        if self.nodes.is_leaf(node_id)? {
+                            break;
+                        }
+                        let child_upper = if idx < self.nodes.number_of_keys(node_id)? {
+                            Some(self.nodes.get_key(node_id, idx)?)
+                        } else {
+                            upper.clone()
+                        };
+                        path.push((self.nodes.get_child_node(node_id, idx)?, child_upper));
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Insert a new element into the index.
+    ///
+    /// Existing values will be overwritten and returned.
+    /// If the operation fails, the index is [poisoned](Self::is_poisoned).
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        let mut previous = None;
+        self.merge_insert(key, value, |existing, value| {
+            previous = Some(existing);
+            value
+        })?;
+        Ok(previous)
+    }
+
+    /// Insert a new element into the index or combine it with the existing value.
+    ///
+    /// If the key already exists, the `merge` function is called with the existing and the new value
+    /// and its result is stored as new value.
+    /// This only needs a single search in the tree.
+    /// If the operation fails, the index is [poisoned](Self::is_poisoned).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.merge_insert(1, 2, |existing, value| existing + value)?;
+    ///     b.merge_insert(1, 3, |existing, value| existing + value)?;
+    ///
+    ///     assert_eq!(Some(5), b.get(&1)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn merge_insert<F>(&mut self, key: K, value: V, merge: F) -> Result<()>
+    where
+        F: FnOnce(V, V) -> V,
+    {
+        self.check_fixed_sizes(&key, &value)?;
+        self.poison_on_error(|index| {
+            index.insert_payload(key, |index, existing| {
+                if let Some(payload) = existing {
+                    let previous_value = payload::read_value(index.values.as_ref(), payload)?;
+                    index.write_value(payload, &merge(previous_value, value))
+                } else {
+                    index.insert_value(&value)
+                }
+            })
+        })
+    }
+
+    /// Returns whether a previous operation failed while changing the index.
+    ///
+    /// All operations of a poisoned index, except [`Self::clear`] and [`Self::verify_integrity`],
+    /// return [`Error::Poisoned`], because the failed operation might have left the nodes
+    /// and files in an inconsistent state, e.g. after allocating a value block but before
+    /// the payload of the key was updated.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Allows to use a poisoned index again.
+    ///
+    /// # Safety
+    ///
+    /// This does not repair the index. The caller must make sure that the failed operation
+    /// did not leave it in an inconsistent state, e.g. by checking it with [`Self::verify_integrity`],
+    /// otherwise later operations can return wrong results or corrupt it further.
+    pub unsafe fn clear_poison(&mut self) {
+        self.poisoned = false;
+    }
+
+    fn check_poisoned(&self) -> Result<()> {
+        if self.poisoned {
+            Err(Error::Poisoned)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Runs an operation that changes the index and marks the index as poisoned if it fails.
+    fn poison_on_error<T, F>(&mut self, operation: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        self.check_poisoned()?;
+        let result = operation(self);
+        if result.is_err() {
+            self.poisoned = true;
+        }
+        result
+    }
+
+    /// Checks that the key and the value have the configured fixed sizes.
+    ///
+    /// The files check the size when storing a key or value, too, but only after the nodes
+    /// may have been split, so checking it before the insert keeps the index intact.
+    fn check_fixed_sizes(&self, key: &K, value: &V) -> Result<()> {
+        if let TypeSize::Fixed(key_size) = self.config.key_size {
+            crate::file::check_fixed_size(key, key_size)?;
+        }
+        if let TypeSize::Fixed(value_size) = self.config.value_size {
+            crate::file::check_fixed_size(value, value_size)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the keys are still sorted after the child at `child_idx` of the parent
+    /// node has been split, both inside the parent and the two children and between them.
+    ///
+    /// Panics with a dump of the tree if they are not, so a faulty split is detected
+    /// when it happens and not only when a later search misses an entry.
+    #[cfg(debug_assertions)]
+    fn check_split(&self, parent_id: u64, child_idx: usize) -> Result<()> {
+        let parent_keys = self.nodes.number_of_keys(parent_id)?;
+        let separator = self.nodes.get_key(parent_id, child_idx)?;
+        let lower = match child_idx.checked_sub(1) {
+            Some(i) => Some(self.nodes.get_key(parent_id, i)?),
+            None => None,
+        };
+        let upper = if child_idx + 1 < parent_keys {
+            Some(self.nodes.get_key(parent_id, child_idx + 1)?)
+        } else {
+            None
+        };
+        let left = self.nodes.get_child_node(parent_id, child_idx)?;
+        let right = self.nodes.get_child_node(parent_id, child_idx + 1)?;
+        for (node_id, lower, upper) in [
+            (parent_id, None, None),
+            (left, lower.as_deref(), Some(separator.as_ref())),
+            (right, Some(separator.as_ref()), upper.as_deref()),
+        ] {
+            let mut previous = lower.cloned();
+            for i in 0..self.nodes.number_of_keys(node_id)? {
+                let key = self.nodes.get_key_owned(node_id, i)?;
+                let unsorted = previous.as_ref().is_some_and(|p| p >= &key)
+                    || upper.is_some_and(|u| u <= &key);
+                if unsorted {
+                    panic!(
+                        "key {} of node {} is not sorted after splitting child {} of node {}\n{}",
+                        i,
+                        node_id,
+                        child_idx,
+                        parent_id,
+                        self.dump_tree()?
+                    );
+                }
+                previous = Some(key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Describes each node of the tree on a separate line, indented by its depth.
+    ///
+    /// The keys are not required to implement [`fmt::Debug`], so their serialized bytes are shown.
+    #[cfg(debug_assertions)]
+    fn dump_tree(&self) -> Result<String> {
+        use bincode::Options;
+
+        let mut result = String::new();
+        let mut stack = vec![(self.root_id, 0)];
+        while let Some((node_id, depth)) = stack.pop() {
+            let mut keys = Vec::new();
+            for i in 0..self.nodes.number_of_keys(node_id)? {
+                let key = self.nodes.get_key(node_id, i)?;
+                keys.push(bincode::DefaultOptions::new().serialize(key.as_ref())?);
+            }
+            result.push_str(&format!(
+                "{:indent$}node {}: {:?}\n",
+                "",
+                node_id,
+                keys,
+                indent = depth * 2
+            ));
+            if !self.nodes.is_leaf(node_id)? {
+                // Push the children in reverse order, so the first child is printed first
+                for c in (0..self.nodes.number_of_children(node_id)?).rev() {
+                    stack.push((self.nodes.get_child_node(node_id, c)?, depth + 1));
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Insert a new key or replace the payload of an existing key.
+    ///
+    /// The `store` function is called with the payload of the existing key (if any)
+    /// and must return the payload for the key.
+    pub(crate) fn insert_payload<S>(&mut self, key: K, store: S) -> Result<()>
+    where
+        S: FnOnce(&mut Self, Option<u64>) -> Result<u64>,
+    {
+        #[cfg(feature = "wal")]
+        if self.wal.is_some() {
+            let mut stored = 0;
+            self.insert_payload_unlogged(key.clone(), |index, existing| {
+                stored = store(index, existing)?;
+                Ok(stored)
+            })?;
+            let value = payload::read_value(self.values.as_ref(), stored)?;
+            return self.log_insert(&key, &value);
+        }
+        self.insert_payload_unlogged(key, store)
+    }
+
+    /// Insert a new key or replace the payload of an existing key without writing it to the log.
+    fn insert_payload_unlogged<S>(&mut self, key: K, store: S) -> Result<()>
+    where
+        S: FnOnce(&mut Self, Option<u64>) -> Result<u64>,
+    {
+        if self.bloom_filter.is_none() && self.hash_index.is_none() {
+            return self.insert_payload_into_nodes(key, store);
+        }
+        let hash = bloom::key_hash(&key)?;
+        let previous_len = self.nr_elements;
+        self.insert_payload_into_nodes(key, store)?;
+        if let (Some(hash_index), Some((node_id, _))) =
+            (&mut self.hash_index, self.insert_path.last())
+        {
+            hash_index.insert(hash, *node_id);
+        }
+        if self.nr_elements > previous_len {
+            self.add_to_bloom_filter(hash)?;
+        }
+        Ok(())
+    }
+
+    /// Inserts the key into the nodes of the tree without updating the bloom filter.
+    fn insert_payload_into_nodes<S>(&mut self, key: K, store: S) -> Result<()>
+    where
+        S: FnOnce(&mut Self, Option<u64>) -> Result<u64>,
+    {
+        self.extend_live_range(&key);
+        // On sorted insert, the leaf of the last insert might the one we need to insert the key into
+        if let Some(leaf) = self.cached_insert_leaf(&key)? {
+            // The leaf is added to the path again by the insert
+            self.insert_path.pop();
+            let inserted = self.insert_nonfull(leaf, &key, store)?;
+            if inserted && self.nodes.has_child_counts() {
+                for (node_id, child_idx) in self.insert_path[..self.insert_path.len() - 1].iter() {
+                    let count = self.nodes.get_child_count(*node_id, *child_idx)?;
+                    self.nodes
+                        .set_child_count(*node_id, *child_idx, count + 1)?;
+                }
+            }
+            return Ok(());
+        }
+
+        self.insert_path.clear();
+        let root_number_of_keys = self.nodes.number_of_keys(self.root_id).unwrap_or(0);
+        if root_number_of_keys == (2 * self.order) - 1 {
+            // Create a new root node, because the current will become full
+            let new_root_id = self.nodes.split_root_node(self.root_id, self.order)?;
+            #[cfg(debug_assertions)]
+            self.check_split(new_root_id, 0)?;
+            self.update_hash_index_after_split(new_root_id, 0)?;
+
+            self.insert_nonfull(new_root_id, &key, store)?;
+            self.root_id = new_root_id;
+            Ok(())
+        } else {
+            self.insert_nonfull(self.root_id, &key, store)?;
+            Ok(())
+        }
+    }
+
+    /// Modifies the value for the given key in place by applying the function `f` to it.
+    ///
+    /// Returns `true` if the key existed and the value was updated.
+    /// This avoids a second search in the tree when getting and re-inserting the modified value.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(1, 2)?;
+    ///
+    ///     assert_eq!(true, b.update(&1, |v| *v += 1)?);
+    ///     assert_eq!(false, b.update(&2, |v| *v += 1)?);
+    ///     assert_eq!(Some(3), b.get(&1)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn update<F>(&mut self, key: &K, f: F) -> Result<bool>
+    where
+        F: FnOnce(&mut V),
+    {
+        self.poison_on_error(|index| {
+            if let Some((node, i)) = index.search(index.root_id, key)? {
+                let payload = index.nodes.get_payload(node, i)?;
+                let mut value = payload::read_value(index.values.as_ref(), payload)?;
+                f(&mut value);
+                let new_payload = index.write_value(payload, &value)?;
+                if new_payload != payload {
+                    index.nodes.set_payload(node, i, new_payload)?;
+                }
+                #[cfg(feature = "wal")]
+                index.log_insert(key, &value)?;
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        })
+    }
+
+    /// Removes all entries inside the range and returns the number of removed entries.
+    ///
+    /// The keys are only marked as removed in a single traversal of the nodes inside the range,
+    /// without reading any values.
+    /// The memory of the removed keys and values is not reused, but the keys can be inserted again.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     for i in 0..10 {
+    ///         b.insert(i, i * 2)?;
+    ///     }
+    ///
+    ///     assert_eq!(3, b.remove_range(2..5)?);
+    ///     assert_eq!(7, b.len());
+    ///     assert_eq!(None, b.get(&3)?);
+    ///     assert_eq!(Some(10), b.get(&5)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn remove_range<R>(&mut self, range: R) -> Result<usize>
+    where
+        R: RangeBounds<K>,
+    {
+        self.poison_on_error(|index| {
+            let range = (range.start_bound().cloned(), range.end_bound().cloned());
+            let removed =
+                index.remove_in_node(index.root_id, &range, &mut None::<fn(&K, &V) -> bool>)?;
+            index.nr_elements -= removed;
+            #[cfg(feature = "wal")]
+            index.log_remove(range.0.as_ref(), range.1.as_ref())?;
+            Ok(removed)
+        })
+    }
+
+    /// Keeps only the entries for which the predicate returns `true` and returns the number of removed entries.
+    ///
+    /// All entries are visited once in the order of their keys. The other entries are marked as removed,
+    /// like with [`Self::remove_range`], so no additional memory is needed for filtering the index.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     for i in 0..10 {
+    ///         b.insert(i, i * 2)?;
+    ///     }
+    ///
+    ///     assert_eq!(5, b.retain(|k, _v| k % 2 == 0)?);
+    ///     assert_eq!(5, b.len());
+    ///     assert_eq!(None, b.get(&3)?);
+    ///     assert_eq!(Some(8), b.get(&4)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn retain<F>(&mut self, f: F) -> Result<usize>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        self.poison_on_error(|index| {
+            let removed = index.remove_in_node(
+                index.root_id,
+                &(Bound::Unbounded, Bound::Unbounded),
+                &mut Some(f),
+            )?;
+            index.nr_elements -= removed;
+            Ok(removed)
+        })
+    }
+
+    /// Return an iterator that removes the entries inside the range while returning them in key order.
+    ///
+    /// In contrast to [`Self::remove_range`], the blocks of the removed values are reused for new values,
+    /// so the files don't grow when inserting new entries while consuming old ones.
+    /// The keys are still needed to search the nodes and are only marked as removed.
+    /// Identical values share the same block when [deduplication](BtreeConfig::deduplicate_values)
+    /// is enabled, so their blocks are never reused in this case.
+    /// Entries that have not been returned when the iterator is dropped stay in the index,
+    /// and [payload IDs](PayloadId) of the removed entries become invalid.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,String>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     for i in 0..10 {
+    ///         b.insert(i, format!("value {i}"))?;
+    ///     }
+    ///
+    ///     let drained: Result<Vec<_>, Error> = b.drain(2..5).collect();
+    ///     assert_eq!(3, drained?.len());
+    ///     assert_eq!(7, b.len());
+    ///     assert_eq!(None, b.get(&3)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn drain<R>(&mut self, range: R) -> Drain<'_, K, V>
+    where
+        R: RangeBounds<K>,
+    {
+        let start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+        let mut stack: Vec<TraversalEntry> = self
+            .nodes
+            .find_range(self.root_id, range)
+            .into_iter()
+            .map(TraversalEntry::Entry)
+            .collect();
+        stack.reverse();
+        Drain {
+            index: self,
+            start,
+            end,
+            stack,
+            path: Vec::new(),
+        }
+    }
+
+    /// Marks the entry as removed, frees its value block and returns the entry.
+    ///
+    /// The path contains the parent nodes and positions of the child nodes which contain the entry.
+    fn drain_entry(&mut self, node: u64, idx: usize, path: &[(u64, usize)]) -> Result<(K, V)> {
+        let entry = self.get_key_value_tuple(node, idx)?;
+        let payload = self.nodes.get_payload(node, idx)?;
+        self.nodes.set_payload(node, idx, payload::TOMBSTONE)?;
+        if !payload::is_inline(payload) && self.value_blocks.is_none() {
+            self.values.free_block(payload.try_into()?)?;
+        }
+        if self.nodes.has_child_counts() {
+            for (parent, child_idx) in path {
+                let count = self.nodes.get_child_count(*parent, *child_idx)?;
+                self.nodes.set_child_count(*parent, *child_idx, count - 1)?;
+            }
+        }
+        self.nr_elements -= 1;
+        #[cfg(feature = "wal")]
+        self.log_remove(Bound::Included(&entry.0), Bound::Included(&entry.0))?;
+        Ok(entry)
+    }
+
+    /// Removes the entry with the smallest key and returns it, or `None` if the index is empty.
+    ///
+    /// The entry is removed like with [`Self::drain`], so the block of its value is reused
+    /// for new values, which allows to use the index as a large priority queue.
+    /// The search for the next entry starts behind the key of the last popped entry,
+    /// so repeated pops take logarithmic time. Entries removed by other means still have to
+    /// be skipped, unless [order statistics](BtreeConfig::order_statistics) are enabled and
+    /// a whole child node has no remaining entries.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     for i in [3, 1, 2] {
+    ///         b.insert(i, i * 2)?;
+    ///     }
+    ///
+    ///     assert_eq!(Some((1, 2)), b.pop_first()?);
+    ///     assert_eq!(Some((3, 6)), b.pop_last()?);
+    ///     assert_eq!(Some((2, 4)), b.pop_first()?);
+    ///     assert_eq!(None, b.pop_last()?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn pop_first(&mut self) -> Result<Option<(K, V)>> {
+        self.pop_extreme(false)
+    }
+
+    /// Removes the entry with the largest key and returns it, or `None` if the index is empty.
+    ///
+    /// See [`Self::pop_first`] for details.
+    pub fn pop_last(&mut self) -> Result<Option<(K, V)>> {
+        self.pop_extreme(true)
+    }
+
+    fn pop_extreme(&mut self, last: bool) -> Result<Option<(K, V)>> {
+        self.poison_on_error(|index| {
+            let mut path = Vec::new();
+            let Some((node, idx)) = index.find_extreme_entry(index.root_id, last, &mut path)?
+            else {
+                return Ok(None);
+            };
+            let entry = index.drain_entry(node, idx, &path)?;
+            // All entries up to the popped one have been removed
+            if last {
+                index.live_range.1 = Bound::Excluded(entry.0.clone());
+            } else {
+                index.live_range.0 = Bound::Excluded(entry.0.clone());
+            }
+            Ok(Some(entry))
+        })
+    }
+
+    /// Finds the first (or last) entry of the node and its children that has not been removed.
+    ///
+    /// The parent nodes and positions of the visited child nodes are added to the path.
+    fn find_extreme_entry(
+        &self,
+        node_id: u64,
+        last: bool,
+        path: &mut Vec<(u64, usize)>,
+    ) -> Result<Option<(u64, usize)>> {
+        let mut entries = self.nodes.find_range(node_id, self.live_range.clone());
+        if last {
+            entries.reverse();
+        }
+        for e in entries {
+            match e {
+                StackEntry::Key { node, idx } => {
+                    if !self.nodes.is_removed(node, idx)? {
+                        return Ok(Some((node, idx)));
+                    }
+                }
+                StackEntry::Child { parent, idx } => {
+                    if self.nodes.has_child_counts()
+                        && self.nodes.get_child_count(parent, idx)? == 0
+                    {
+                        continue;
+                    }
+                    path.push((parent, idx));
+                    let child_id = self.nodes.get_child_node(parent, idx)?;
+                    if let Some(found) = self.find_extreme_entry(child_id, last, path)? {
+                        return Ok(Some(found));
+                    }
+                    path.pop();
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Marks the keys inside the range in the node and its children as removed.
+    ///
+    /// If there is a predicate, only the entries for which it returns `false` are removed.
+    /// Returns the number of removed entries.
+    fn remove_in_node<F>(
+        &mut self,
+        node_id: u64,
+        range: &(Bound<K>, Bound<K>),
+        keep: &mut Option<F>,
+    ) -> Result<usize>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let mut removed = 0;
+        for e in self.nodes.find_range(node_id, range.clone()) {
+            match e {
+                StackEntry::Key { node, idx } => {
+                    if self.nodes.is_removed(node, idx)? {
+                        continue;
+                    }
+                    if let Some(keep) = keep {
+                        let (key, value) = self.get_key_value_tuple(node, idx)?;
+                        if keep(&key, &value) {
+                            continue;
+                        }
+                        self.nodes.set_payload(node, idx, payload::TOMBSTONE)?;
+                        #[cfg(feature = "wal")]
+                        self.log_remove(Bound::Included(&key), Bound::Included(&key))?;
+                    } else {
+                        self.nodes.set_payload(node, idx, payload::TOMBSTONE)?;
+                    }
+                    removed += 1;
+                }
+                StackEntry::Child { parent, idx } => {
+                    let child_id = self.nodes.get_child_node(parent, idx)?;
+                    let child_removed = self.remove_in_node(child_id, range, keep)?;
+                    if child_removed > 0 && self.nodes.has_child_counts() {
+                        let count = self.nodes.get_child_count(parent, idx)?;
+                        self.nodes.set_child_count(
+                            parent,
+                            idx,
+                            count - u64::try_from(child_removed)?,
+                        )?;
+                    }
+                    removed += child_removed;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Removes all entries from the index.
+    ///
+    /// The memory mapped files keep their size and are reused for new entries,
+    /// so this is cheaper than creating a new index with the same capacity.
+    /// A [poisoned](Self::is_poisoned) index can be used again after it has been cleared,
+    /// and so can its caches after they returned [`Error::PoisonedCache`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(1,2)?;
+    ///     b.clear()?;
+    ///
+    ///     assert_eq!(true, b.is_empty());
+    ///     assert_eq!(None, b.get(&1)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn clear(&mut self) -> Result<()> {
+        // The index is only usable again after it has been cleared completely
+        self.poisoned = true;
+        self.nodes.clear();
+        self.values.clear();
+        if let Some(value_blocks) = &mut self.value_blocks {
+            value_blocks.clear();
+        }
+        // Invalidate all existing payload IDs
+        self.instance = payload::next_instance();
+        self.root_id = self.nodes.allocate_new_node()?;
+        self.insert_path.clear();
+        self.live_range = (Bound::Unbounded, Bound::Unbounded);
+        if let Some(bloom_filter) = &mut self.bloom_filter {
+            bloom_filter.clear();
+        }
+        if let Some(hash_index) = &mut self.hash_index {
+            hash_index.clear();
+        }
+        self.nr_elements = 0;
+        #[cfg(feature = "wal")]
+        self.clear_log()?;
+        self.poisoned = false;
+        Ok(())
+    }
+
+    /// Moves all entries with a key greater than or equal to the given key into a new index.
+    ///
+    /// The new index has the same configuration as this one, and only the entries
+    /// with a smaller key remain in this index.
+    /// Since removed entries would still use the memory of the tree, both parts are copied in the order
+    /// of their keys into new indexes, unless all or none of the entries are moved.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut a = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     a.insert(1,2)?;
+    ///     a.insert(200, 4)?;
+    ///     a.insert(20, 3)?;
+    ///
+    ///     let b = a.split_off(&20)?;
+    ///     assert_eq!(1, a.len());
+    ///     assert_eq!(Some(2), a.get(&1)?);
+    ///     assert_eq!(2, b.len());
+    ///     assert_eq!(Some(3), b.get(&20)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn split_off(&mut self, key: &K) -> Result<BtreeIndex<K, V>> {
+        if !self.any_in_range(key..)? {
+            return self.with_same_config(0);
+        }
+        if !self.any_in_range(..key)? {
+            let empty = self.with_same_config(self.nr_elements)?;
+            #[cfg(feature = "wal")]
+            let wal = self.wal.take();
+            let upper = std::mem::replace(self, empty);
+            #[cfg(feature = "wal")]
+            {
+                self.wal = wal;
+                self.clear_log()?;
+            }
+            return Ok(upper);
+        }
+
+        let mut lower = self.with_same_config(self.range_len(..key)?)?;
+        for entry in self.range(..key)? {
+            let (k, v) = entry?;
+            lower.insert(k, v)?;
+        }
+        let mut upper = self.with_same_config(self.nr_elements - lower.len())?;
+        for entry in self.range(key..)? {
+            let (k, v) = entry?;
+            upper.insert(k, v)?;
+        }
+        #[cfg(feature = "wal")]
+        {
+            lower.wal = self.wal.take();
+            lower.log_remove(Bound::Included(key), Bound::Unbounded)?;
+        }
+        *self = lower;
+        Ok(upper)
+    }
+
+    /// Tries to grow the files of the nodes, keys and values up front, so that the given number
+    /// of additional entries can be inserted without growing them again.
+    ///
+    /// The needed space is estimated from the configured key and value sizes, like for
+    /// [`Self::with_capacity`]. If a file can't be grown, e.g. because the operating system refuses
+    /// to map more memory or the [maximum file size](BtreeConfig::max_file_size) would be exceeded,
+    /// [`Error::ReserveFailed`] names the file and the requested number of bytes.
+    /// Unlike a failed insert, a failed reservation leaves the index unchanged and usable.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let config = BtreeConfig::default().max_file_size(1 << 20);
+    ///     let mut b = BtreeIndex::<u64, u64>::with_capacity(config, 10)?;
+    ///     b.try_reserve(1000)?;
+    ///
+    ///     let result = b.try_reserve(1 << 20);
+    ///     assert!(matches!(result, Err(Error::ReserveFailed { .. })));
+    ///     b.insert(1, 2)?;
+    ///     assert_eq!(Some(2), b.get(&1)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn try_reserve(&mut self, additional: usize) -> Result<()> {
+        self.check_poisoned()?;
+        self.nodes
+            .try_reserve(additional, self.order, &self.config)?;
+        let requested = match self.config.value_size {
+            TypeSize::Estimated(est_max_value_size) => additional.saturating_mul(
+                (est_max_value_size + BlockHeader::size())
+                    .next_multiple_of(self.config.block_alignment),
+            ),
+            // Small values are stored in the nodes and don't need any space in the file
+            TypeSize::Fixed(fixed_value_size)
+                if fixed_value_size <= payload::MAX_INLINE_VALUE_SIZE =>
+            {
+                0
+            }
+            TypeSize::Fixed(fixed_value_size) => additional.saturating_mul(fixed_value_size),
+        };
+        self.values
+            .try_reserve(requested)
+            .map_err(|e| Error::ReserveFailed {
+                file: "values",
+                requested,
+                source: Box::new(e),
+            })
+    }
+
+    /// Shrinks the memory mapped files of the nodes, keys and values to the space that is actually used.
+    ///
+    /// The files grow by the configured [growth strategy](BtreeConfig::growth_strategy),
+    /// so after inserting many entries a large part of the files might be unused.
+    /// Each file is shrunk to the end of its last block, rounded up to whole memory pages.
+    /// The files grow again when new entries are inserted afterwards.
+    /// Custom [storage backends](StorageBackend::shrink) might keep their size.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16, u16>::with_capacity(BtreeConfig::default(), 10_000)?;
+    ///     b.insert(1, 2)?;
+    ///
+    ///     let mapped = b.memory_usage().nodes;
+    ///     b.shrink_to_fit()?;
+    ///     assert!(b.memory_usage().nodes < mapped);
+    ///     assert_eq!(Some(2), b.get(&1)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn shrink_to_fit(&mut self) -> Result<()> {
+        self.check_poisoned()?;
+        self.nodes.shrink_to_fit()?;
+        self.values.shrink_to_fit()
+    }
+
+    /// Rebuild the index into a new one without removed entries and unused memory.
+    ///
+    /// The entries are copied in the order of their keys into a new index with the same configuration.
+    /// Each value is stored in a block of exactly its size, so after many updates of variable sized values
+    /// the new index needs much less memory and has no relocated blocks.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16, String>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     for i in 0..10 {
+    ///         b.insert(i, "value".to_string())?;
+    ///         b.insert(i, "a much larger value".repeat(10))?;
+    ///     }
+    ///     b.remove_range(5..)?;
+    ///
+    ///     let b = b.compact()?;
+    ///     assert_eq!(5, b.len());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn compact(self) -> Result<BtreeIndex<K, V>> {
+        self.compact_with(None)
+    }
+
+    /// [Compacts](Self::compact) the index, but stops with [`Error::Cancelled`] when the token is
+    /// cancelled, e.g. to abort compacting a large index.
+    ///
+    /// The index and the partially compacted copy are dropped in this case.
+    pub fn compact_cancellable(self, cancellation: &CancellationToken) -> Result<BtreeIndex<K, V>> {
+        self.compact_with(Some(cancellation))
+    }
+
+    fn compact_with(self, cancellation: Option<&CancellationToken>) -> Result<BtreeIndex<K, V>> {
+        let mut result = self.with_same_config(self.nr_elements)?;
+        let mut entries = self.range(..)?;
+        if let Some(cancellation) = cancellation {
+            entries = entries.cancel_on(cancellation.clone());
+        }
+        for entry in entries {
+            let (k, v) = entry?;
+            result.insert(k, v)?;
+        }
+        // The log contains the same entries as the new index
+        #[cfg(feature = "wal")]
+        {
+            let mut source = self;
+            result.wal = source.wal.take();
+        }
+        Ok(result)
+    }
+
+    /// Create a new empty index with the same configuration and codecs as this one.
+    fn with_same_config(&self, capacity: usize) -> Result<BtreeIndex<K, V>> {
+        let mut result = Self::with_capacity(self.config.clone(), capacity)?;
+        if self.nodes.has_ordered_key_bytes() {
+            result.nodes.enable_ordered_key_bytes();
+        }
+        if let Some(codec) = self.nodes.key_codec() {
+            result.nodes.set_key_codec(codec);
+        }
+        if let Some(codec) = self.values.shared_codec() {
+            result.values.set_codec(codec);
+        }
+        Ok(result)
+    }
+
+    /// Returns true if the index does not contain any elements.
+    pub fn is_empty(&self) -> bool {
+        self.nr_elements == 0
+    }
+
+    /// Returns the length of the index.
+    pub fn len(&self) -> usize {
+        self.nr_elements
+    }
+
+    /// Returns the number of levels of the tree.
+    ///
+    /// All leaves have the same depth, so only the leftmost child nodes are followed.
+    /// An index with only a root node has a depth of 1.
+    pub fn depth(&self) -> Result<usize> {
+        self.check_poisoned()?;
+        let mut depth = 1;
+        let mut node_id = self.root_id;
+        while !self.nodes.is_leaf(node_id)? {
+            node_id = self.nodes.get_child_node(node_id, 0)?;
+            depth += 1;
+        }
+        Ok(depth)
+    }
+
+    /// Returns the number of nodes on each level of the tree, starting with the root node.
+    ///
+    /// This visits all nodes, but does not read any keys or values.
+    /// Together with [`Self::depth`], it can be used to monitor the shape of the tree,
+    /// e.g. how full the nodes are for a given insertion pattern.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default().order(2), 10)?;
+    ///     for i in 0..10 {
+    ///         b.insert(i, i)?;
+    ///     }
+    ///
+    ///     let widths = b.level_widths()?;
+    ///     assert_eq!(b.depth()?, widths.len());
+    ///     assert_eq!(1, widths[0]);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn level_widths(&self) -> Result<Vec<usize>> {
+        self.check_poisoned()?;
+        let mut result = Vec::new();
+        let mut level = vec![self.root_id];
+        while !level.is_empty() {
+            result.push(level.len());
+            let mut next_level = Vec::new();
+            for node_id in level {
+                if !self.nodes.is_leaf(node_id)? {
+                    for i in 0..self.nodes.number_of_children(node_id)? {
+                        next_level.push(self.nodes.get_child_node(node_id, i)?);
+                    }
+                }
+            }
+            level = next_level;
+        }
+        Ok(result)
+    }
+
+    /// Returns the number of entries with a key smaller than the given key.
+    ///
+    /// This needs [order statistics](BtreeConfig::order_statistics) to be enabled
+    /// and only visits a single path from the root to a leaf.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let config = BtreeConfig::default().order_statistics(true);
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(config, 10)?;
+    ///     b.insert(10, 1)?;
+    ///     b.insert(20, 2)?;
+    ///     b.insert(30, 3)?;
+    ///
+    ///     assert_eq!(1, b.rank(&20)?);
+    ///     assert_eq!(2, b.rank(&25)?);
+    ///     assert_eq!(Some((30, 3)), b.select(2)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn rank(&self, key: &K) -> Result<usize> {
+        self.check_poisoned()?;
+        if !self.nodes.has_child_counts() {
+            return Err(Error::OrderStatisticsDisabled);
+        }
+        let mut result = 0;
+        let mut node_id = self.root_id;
+        loop {
+            let is_leaf = self.nodes.is_leaf(node_id)?;
+            let (i, found) = match self.nodes.binary_search(node_id, key)? {
+                SearchResult::Found(i) => (i, true),
+                SearchResult::NotFound(i) => (i, false),
+            };
+            // All keys before the position and their child nodes are smaller
+            for c in 0..i {
+                if !self.nodes.is_removed(node_id, c)? {
+                    result += 1;
+                }
+                if !is_leaf {
+                    result += usize::try_from(self.nodes.get_child_count(node_id, c)?)?;
+                }
+            }
+            if is_leaf {
+                return Ok(result);
+            } else if found {
+                // The child node left of the found key is smaller as well
+                result += usize::try_from(self.nodes.get_child_count(node_id, i)?)?;
+                return Ok(result);
+            } else {
+                node_id = self.nodes.get_child_node(node_id, i)?;
+            }
+        }
+    }
+
+    /// Returns the `n`-th smallest entry, starting with zero, or `None` if the index has less entries.
+    ///
+    /// This needs [order statistics](BtreeConfig::order_statistics) to be enabled
+    /// and only visits a single path from the root to a leaf.
+    pub fn select(&self, n: usize) -> Result<Option<(K, V)>> {
+        self.check_poisoned()?;
+        if !self.nodes.has_child_counts() {
+            return Err(Error::OrderStatisticsDisabled);
+        }
+        if n >= self.nr_elements {
+            return Ok(None);
+        }
+        let mut n: u64 = n.try_into()?;
+        let mut node_id = self.root_id;
+        'nodes: loop {
+            let number_of_keys = self.nodes.number_of_keys(node_id)?;
+            if self.nodes.is_leaf(node_id)? {
+                for i in 0..number_of_keys {
+                    if self.nodes.is_removed(node_id, i)? {
+                        continue;
+                    }
+                    if n == 0 {
+                        return Ok(Some(self.get_key_value_tuple(node_id, i)?));
+                    }
+                    n -= 1;
+                }
+                return Err(Error::KeyIndexOutOfBounds {
+                    idx: n.try_into()?,
+                    len: number_of_keys,
+                });
+            }
+            for i in 0..number_of_keys {
+                let child_count = self.nodes.get_child_count(node_id, i)?;
+                if n < child_count {
+                    node_id = self.nodes.get_child_node(node_id, i)?;
+                    continue 'nodes;
+                }
+                n -= child_count;
+                if self.nodes.is_removed(node_id, i)? {
+                    continue;
+                }
+                if n == 0 {
+                    return Ok(Some(self.get_key_value_tuple(node_id, i)?));
+                }
+                n -= 1;
+            }
+            // The entry must be in the last child node
+            node_id = self.nodes.get_child_node(node_id, number_of_keys)?;
+        }
+    }
+
+    fn get_key_value_tuple(&self, node_id: u64, i: usize) -> Result<(K, V)> {
+        let payload = self.nodes.get_payload(node_id, i)?;
+        let value = payload::read_value(self.values.as_ref(), payload)?;
+        let key = self.nodes.get_key_owned(node_id, i)?;
+        Ok((key, value))
+    }
+
+    /// Return an iterator over all entries of the index, sorted by their keys.
+    ///
+    /// This is the same as calling [`Self::range`] with the unbounded `..` range.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(1,2)?;
+    ///     b.insert(200, 4)?;
+    ///     b.insert(20, 3)?;
+    ///
+    ///     for e in b.iter()? {
+    ///         let (k, v) = e?;
+    ///         dbg!(k, v);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn iter(&self) -> Result<Range<'_, K, V>> {
+        self.range::<K, _>(..)
+    }
+
+    /// Return an iterator over a range of keys.
+    ///
+    /// Like for [`BTreeMap::range`](std::collections::BTreeMap::range), the bounds can be given
+    /// as a borrowed form of the key type, e.g. `&str` for `String` keys, so no owned keys need
+    /// to be created for querying the index. If you want to iterate over all entries of the index,
+    /// use [`Self::iter`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::ops::Bound;
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<String,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert("apple".to_string(), 2)?;
+    ///     b.insert("banana".to_string(), 4)?;
+    ///     b.insert("cherry".to_string(), 3)?;
+    ///
+    ///     for e in b.range::<str, _>((Bound::Included("b"), Bound::Excluded("c")))? {
+    ///         let (k, v) = e?;
+    ///         assert_eq!("banana", k);
+    ///         assert_eq!(4, v);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn range<Q, R>(&self, range: R) -> Result<Range<'_, K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized + 'static,
+        R: RangeBounds<Q>,
+    {
+        self.check_poisoned()?;
+        let exact_size = matches!(
+            (range.start_bound(), range.end_bound()),
+            (Bound::Unbounded, Bound::Unbounded)
+        );
+        Ok(self.range_from_entries(
+            self.nodes.find_range_borrowed(self.root_id, &range)?,
+            exact_size,
+        ))
+    }
+
+    /// Creates the iterator for the entries of a range, which must only contain child nodes
+    /// that are completely inside the range.
+    fn range_from_entries(&self, mut stack: Vec<StackEntry>, exact_size: bool) -> Range<'_, K, V> {
+        // The range is sorted by smallest first, but popping values from the end of the
+        // stack is more effective
+        stack.reverse();
+        Range {
+            stack,
+            remaining: self.nr_elements,
+            exact_size,
+            cancellation: None,
+            nodes: &self.nodes,
+            values: &self.values,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Return an iterator over at most `limit` entries of the range, starting after the first `offset` entries.
+    ///
+    /// The skipped entries are not read, see [`Range::skip_entries`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     for i in 0..10 {
+    ///         b.insert(i, i * 2)?;
+    ///     }
+    ///
+    ///     let page: Result<Vec<_>, Error> = b.range_page(2.., 3, 2)?.collect();
+    ///     assert_eq!(vec![(5, 10), (6, 12)], page?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn range_page<R>(
+        &self,
+        range: R,
+        offset: usize,
+        limit: usize,
+    ) -> Result<std::iter::Take<Range<'_, K, V>>>
+    where
+        R: RangeBounds<K>,
+    {
+        let mut result = self.range(range)?;
+        result.skip_entries(offset)?;
+        Ok(result.take(limit))
+    }
+
+    /// Return an iterator over all entries with a key that starts with the given prefix.
+    ///
+    /// This works for all keys implementing [`PrefixKey`], e.g. `Vec<u8>` with a `&[u8]` prefix
+    /// or [`String`] with a `&str` prefix.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<String,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert("ab".to_string(), 1)?;
+    ///     b.insert("abc".to_string(), 2)?;
+    ///     b.insert("b".to_string(), 3)?;
+    ///
+    ///     let result: Result<Vec<_>, Error> = b.prefix_range("ab")?.collect();
+    ///     assert_eq!(vec![("ab".to_string(), 1), ("abc".to_string(), 2)], result?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn prefix_range(&self, prefix: &K::Prefix) -> Result<Range<'_, K, V>>
+    where
+        K: PrefixKey,
+    {
+        self.range(K::prefix_bounds(prefix))
+    }
+
+    /// Return an iterator over the groups of consecutive entries in a range that have the same group key.
+    ///
+    /// The group key of an entry is computed by the `group_key` function.
+    /// Since the entries are sorted, all entries with the same group key are consecutive
+    /// if the group key is a prefix of the key, e.g. the first field of a tuple.
+    /// Only the entries of the current group are held in main memory.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<(u32, u64), u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert((1, 100), 1)?;
+    ///     b.insert((1, 200), 2)?;
+    ///     b.insert((2, 100), 3)?;
+    ///
+    ///     let mut groups = b.range_grouped(.., |(user, _)| *user)?;
+    ///     let (user, entries) = groups.next().unwrap()?;
+    ///     assert_eq!(1, user);
+    ///     assert_eq!(vec![((1, 100), 1), ((1, 200), 2)], entries);
+    ///     assert_eq!(2, groups.next().unwrap()?.0);
+    ///     assert!(groups.next().is_none());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn range_grouped<R, G, F>(
+        &self,
+        range: R,
+        group_key: F,
+    ) -> Result<GroupedRange<'_, K, V, G, F>>
+    where
+        R: RangeBounds<K>,
+        G: PartialEq,
+        F: FnMut(&K) -> G,
+    {
+        Ok(GroupedRange {
+            entries: self.range(range)?.peekable(),
+            group_key,
+        })
+    }
+
+    /// Returns the number of entries inside the range.
+    ///
+    /// In contrast to counting the entries of [`Self::range`], no values are read.
+    /// When [order statistics](BtreeConfig::order_statistics) are enabled,
+    /// the sub-trees completely inside the range are not visited at all.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(1,2)?;
+    ///     b.insert(200, 4)?;
+    ///     b.insert(20, 3)?;
+    ///
+    ///     assert_eq!(2, b.range_len(1..30)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn range_len<R>(&self, range: R) -> Result<usize>
+    where
+        R: RangeBounds<K>,
+    {
+        self.check_poisoned()?;
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        let mut result = 0;
+        let mut stack = vec![self.root_id];
+        while let Some(node_id) = stack.pop() {
+            let entries = self.nodes.find_range(node_id, range.clone());
+            for (i, e) in entries.iter().enumerate() {
+                match e {
+                    StackEntry::Key { node, idx } => {
+                        if !self.nodes.is_removed(*node, *idx)? {
+                            result += 1;
+                        }
+                    }
+                    StackEntry::Child { parent, idx } => {
+                        // If the keys on both sides are inside the range, the whole child is as well
+                        let inside = i > 0
+                            && matches!(entries[i - 1], StackEntry::Key { .. })
+                            && matches!(entries.get(i + 1), Some(StackEntry::Key { .. }));
+                        if inside && self.nodes.has_child_counts() {
+                            result += usize::try_from(self.nodes.get_child_count(*parent, *idx)?)?;
+                        } else {
+                            stack.push(self.nodes.get_child_node(*parent, *idx)?);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Returns whether the index contains any key inside the range.
+    ///
+    /// This stops at the first key that is found and does not read any values.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(1,2)?;
+    ///     b.insert(200, 4)?;
+    ///
+    ///     assert_eq!(true, b.any_in_range(1..30)?);
+    ///     assert_eq!(false, b.any_in_range(2..200)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn any_in_range<R>(&self, range: R) -> Result<bool>
+    where
+        R: RangeBounds<K>,
+    {
+        self.check_poisoned()?;
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        let mut stack = vec![self.root_id];
+        while let Some(node_id) = stack.pop() {
+            for e in self.nodes.find_range(node_id, range.clone()) {
+                match e {
+                    StackEntry::Key { node, idx } => {
+                        if !self.nodes.is_removed(node, idx)? {
+                            return Ok(true);
+                        }
+                    }
+                    StackEntry::Child { parent, idx } => {
+                        // Children might not contain a key at the border of the range or after removals
+                        stack.push(self.nodes.get_child_node(parent, idx)?);
+                    }
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Return an iterator over a range of keys, which combines the entries of the index with the entries of a base iterator.
+    ///
+    /// This allows to use the index as an "overlay" for an immutable sorted map:
+    /// the `base` iterator must return its entries sorted by key and without duplicate keys.
+    /// If a key is contained in both the index and the base, the entry of the index is returned.
+    /// Entries of the base outside the range are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(2, 20)?;
+    ///     b.insert(3, 30)?;
+    ///
+    ///     let base = vec![(1, 1), (2, 2), (4, 4)];
+    ///     let result: Result<Vec<_>, Error> = b.overlay_range(.., base)?.collect();
+    ///     assert_eq!(vec![(1, 1), (2, 20), (3, 30), (4, 4)], result?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn overlay_range<R, I>(
+        &self,
+        range: R,
+        base: I,
+    ) -> Result<OverlayRange<'_, K, V, I::IntoIter>>
+    where
+        R: RangeBounds<K>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+        let index = self.range(range)?;
+        Ok(OverlayRange {
+            index: index.peekable(),
+            base: base.into_iter().peekable(),
+            start,
+            end,
+        })
+    }
+
+    /// Return an iterator over a range of keys of this and another index,
+    /// which returns each key with its value in both indexes.
+    ///
+    /// The entries of both indexes are merged by their key, so the keys are returned in order and
+    /// the value is `None` for the index which does not contain the key.
+    /// This can e.g. be used to find the differences between two versions of the same data.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut a = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     a.insert(1, 10)?;
+    ///     a.insert(2, 20)?;
+    ///     let mut b = BtreeIndex::<u16,String>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(2, "b".to_string())?;
+    ///     b.insert(3, "c".to_string())?;
+    ///
+    ///     let result: Result<Vec<_>, Error> = a.join_range(&b, ..)?.collect();
+    ///     assert_eq!(
+    ///         vec![
+    ///             (1, Some(10), None),
+    ///             (2, Some(20), Some("b".to_string())),
+    ///             (3, None, Some("c".to_string())),
+    ///         ],
+    ///         result?
+    ///     );
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn join_range<'a, V2, R>(
+        &'a self,
+        other: &'a BtreeIndex<K, V2>,
+        range: R,
+    ) -> Result<JoinRange<'a, K, V, V2>>
+    where
+        V2: 'static + Serialize + DeserializeOwned + Clone,
+        R: RangeBounds<K>,
+    {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        Ok(JoinRange {
+            left: self.range(range.clone())?.peekable(),
+            right: other.range(range)?.peekable(),
+        })
+    }
+
+    /// Return an iterator over the keys that are contained in this or the other index, in key order.
+    ///
+    /// Only the keys of both indexes are compared and no values are read.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut a = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     a.insert(1, 10)?;
+    ///     a.insert(2, 20)?;
+    ///     let mut b = BtreeIndex::<u16,bool>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(2, true)?;
+    ///     b.insert(3, false)?;
+    ///
+    ///     let union: Result<Vec<_>, Error> = a.keys_union(&b).collect();
+    ///     assert_eq!(vec![1, 2, 3], union?);
+    ///     let intersection: Result<Vec<_>, Error> = a.keys_intersection(&b).collect();
+    ///     assert_eq!(vec![2], intersection?);
+    ///     let difference: Result<Vec<_>, Error> = a.keys_difference(&b).collect();
+    ///     assert_eq!(vec![1], difference?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn keys_union<'a, V2>(&'a self, other: &'a BtreeIndex<K, V2>) -> KeySetIter<'a, K>
+    where
+        V2: 'static + Serialize + DeserializeOwned + Clone,
+    {
+        KeySetIter::new(
+            self.key_cursor(),
+            other.key_cursor(),
+            KeySetOperation::Union,
+        )
+    }
+
+    /// Return an iterator over the keys that are contained in both this and the other index, in key order.
+    ///
+    /// Only the keys of both indexes are compared and no values are read.
+    pub fn keys_intersection<'a, V2>(&'a self, other: &'a BtreeIndex<K, V2>) -> KeySetIter<'a, K>
+    where
+        V2: 'static + Serialize + DeserializeOwned + Clone,
+    {
+        KeySetIter::new(
+            self.key_cursor(),
+            other.key_cursor(),
+            KeySetOperation::Intersection,
+        )
+    }
+
+    /// Return an iterator over the keys that are contained in this but not in the other index, in key order.
+    ///
+    /// Only the keys of both indexes are compared and no values are read.
+    pub fn keys_difference<'a, V2>(&'a self, other: &'a BtreeIndex<K, V2>) -> KeySetIter<'a, K>
+    where
+        V2: 'static + Serialize + DeserializeOwned + Clone,
+    {
+        KeySetIter::new(
+            self.key_cursor(),
+            other.key_cursor(),
+            KeySetOperation::Difference,
+        )
+    }
+
+    /// Returns a cursor over all keys of the index that have not been removed.
+    fn key_cursor(&self) -> KeyCursor<'_, K> {
+        let mut stack = self.nodes.find_range(self.root_id, ..);
+        stack.reverse();
+        KeyCursor {
+            nodes: &self.nodes,
+            stack,
+        }
+    }
+
+    /// Return an iterator over all entries and consumes the B-tree index.
+    ///
+    /// The memory of the nodes, keys and values that have already been returned is released
+    /// while iterating, so consuming a huge index does not need more memory or disk space
+    /// over time. A memory page is released when all of its content has been consumed, if the
+    /// [storage backend](StorageBackend::discard) supports this.
+    /// Values that are shared by [deduplication](BtreeConfig::deduplicate_values) are kept.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(1,2)?;
+    ///     b.insert(200, 4)?;
+    ///     b.insert(20, 3)?;
+    ///
+    ///     for e in b.into_iter()? {
+    ///         let (k, v) = e?;
+    ///         dbg!(k, v);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    #[allow(clippy::should_implement_trait)]
+    pub fn into_iter(self) -> Result<BtreeIntoIter<K, V>> {
+        self.check_poisoned()?;
+        // The range is sorted by smallest first, but popping values from the end of the
+        // stack is more effective
+        let mut stack = vec![TraversalEntry::LeaveNode(self.root_id)];
+        stack.extend(
+            self.nodes
+                .find_range(self.root_id, ..)
+                .into_iter()
+                .rev()
+                .map(TraversalEntry::Entry),
+        );
+
+        let result = BtreeIntoIter {
+            stack,
+            remaining: self.nr_elements,
+            cancellation: None,
+            nodes: self.nodes,
+            values: self.values,
+            shared_values: self.value_blocks.is_some(),
+            phantom: PhantomData,
+        };
+        Ok(result)
+    }
+
+    /// Returns `n` randomly chosen entries, e.g. to estimate the distribution of the keys.
+    ///
+    /// The entries are drawn with replacement. When [order statistics](BtreeConfig::order_statistics)
+    /// are enabled, each entry is chosen with the same probability. Otherwise, random child nodes
+    /// are followed from the root to a leaf, and paths through partially filled nodes are rejected,
+    /// so that all entries stored in the leaves are equally likely.
+    /// The keys stored in the inner nodes are not returned in this case,
+    /// but these are only a small fraction of all entries.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rand::{rngs::SmallRng, SeedableRng};
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     for i in 0..100 {
+    ///         b.insert(i, i * 2)?;
+    ///     }
+    ///
+    ///     let mut rng = SmallRng::seed_from_u64(42);
+    ///     let samples = b.sample(10, &mut rng)?;
+    ///     assert_eq!(10, samples.len());
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "sample")]
+    pub fn sample<R>(&self, n: usize, rng: &mut R) -> Result<Vec<(K, V)>>
+    where
+        R: rand::Rng + ?Sized,
+    {
+        self.check_poisoned()?;
+        let mut result = Vec::with_capacity(n);
+        if self.nr_elements == 0 {
+            return Ok(result);
+        }
+        if self.nodes.has_child_counts() {
+            for _ in 0..n {
+                if let Some(entry) = self.select(rng.gen_range(0..self.nr_elements))? {
+                    result.push(entry);
+                }
+            }
+            return Ok(result);
+        }
+
+        let max_keys = 2 * self.order - 1;
+        'samples: while result.len() < n {
+            let mut node_id = self.root_id;
+            let mut is_root = true;
+            loop {
+                // All paths go through the root node, so it does not need to be rejected
+                if self.nodes.is_leaf(node_id)? {
+                    let number_of_keys = self.nodes.number_of_keys(node_id)?;
+                    let i = if is_root {
+                        rng.gen_range(0..number_of_keys)
+                    } else {
+                        rng.gen_range(0..max_keys)
+                    };
+                    if i >= number_of_keys || self.nodes.is_removed(node_id, i)? {
+                        continue 'samples;
+                    }
+                    result.push(self.get_key_value_tuple(node_id, i)?);
+                    break;
+                } else {
+                    let number_of_children = self.nodes.number_of_children(node_id)?;
+                    let i = if is_root {
+                        rng.gen_range(0..number_of_children)
+                    } else {
+                        rng.gen_range(0..(max_keys + 1))
+                    };
+                    if i >= number_of_children {
+                        continue 'samples;
+                    }
+                    node_id = self.nodes.get_child_node(node_id, i)?;
+                    is_root = false;
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Writes all entries in the order of their keys to an immutable sorted table.
+    ///
+    /// The entries are streamed to the writer and not collected in memory.
+    /// Wrap the writer in a [`std::io::BufWriter`] when writing to a file.
+    ///
+    /// The format of the table is (all integers are encoded as little-endian `u64`):
+    ///
+    /// 1. the magic bytes `TBTSST01`,
+    /// 2. the number of entries,
+    /// 3. for each entry the length of the serialized key, the serialized key,
+    ///    the length of the serialized value and the serialized value.
+    ///
+    /// Keys and values are serialized with the default options of [bincode](https://crates.io/crates/bincode)
+    /// (`bincode::DefaultOptions`).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(1,2)?;
+    ///     b.insert(200, 4)?;
+    ///
+    ///     let mut table = Vec::new();
+    ///     b.write_sstable(&mut table)?;
+    ///     assert_eq!(b"TBTSST01", &table[0..8]);
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "export")]
+    pub fn write_sstable<W>(&self, mut writer: W) -> Result<()>
+    where
+        W: std::io::Write,
+    {
+        use bincode::Options;
+
+        let serializer = bincode::DefaultOptions::new();
+        writer.write_all(SSTABLE_MAGIC)?;
+        writer.write_all(&u64::try_from(self.nr_elements)?.to_le_bytes())?;
+
+        for entry in self.range(..)? {
+            let (key, value) = entry?;
+            for serialized in [serializer.serialize(&key)?, serializer.serialize(&value)?] {
+                writer.write_all(&u64::try_from(serialized.len())?.to_le_bytes())?;
+                writer.write_all(&serialized)?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the structure of the tree in the Graphviz DOT format.
+    ///
+    /// Each node is shown with its keys and the edges point to the child nodes.
+    /// The values are not included.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(1,2)?;
+    ///
+    ///     let mut dot = Vec::new();
+    ///     b.dump_dot(&mut dot)?;
+    ///     assert!(String::from_utf8_lossy(&dot).starts_with("digraph btree {"));
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "graphviz")]
+    pub fn dump_dot<W>(&self, mut writer: W) -> Result<()>
+    where
+        W: std::io::Write,
+        K: std::fmt::Debug,
+    {
+        writeln!(writer, "digraph btree {{")?;
+        writeln!(writer, "    node [shape=record];")?;
+        let mut stack = vec![self.root_id];
+        while let Some(node_id) = stack.pop() {
+            let is_leaf = self.nodes.is_leaf(node_id)?;
+            let mut fields = Vec::new();
+            for i in 0..self.nodes.number_of_keys(node_id)? {
+                if !is_leaf {
+                    fields.push(format!("<c{}>", i));
+                }
+                let key = format!("{:?}", self.nodes.get_key_owned(node_id, i)?);
+                fields.push(escape_dot_record(&key));
+            }
+            if !is_leaf {
+                fields.push(format!("<c{}>", self.nodes.number_of_keys(node_id)?));
+            }
+            writeln!(writer, "    n{} [label=\"{}\"];", node_id, fields.join("|"))?;
+            if !is_leaf {
+                for c in 0..self.nodes.number_of_children(node_id)? {
+                    let child_id = self.nodes.get_child_node(node_id, c)?;
+                    writeln!(writer, "    n{}:c{} -> n{};", node_id, c, child_id)?;
+                    stack.push(child_id);
+                }
+            }
+        }
+        writeln!(writer, "}}")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Swaps the values for the given keys.
+    pub fn swap(&mut self, a: &K, b: &K) -> Result<()> {
+        self.poison_on_error(|index| {
+            // Get the node ids and position in the node for both keys,
+            // fail when they do not exist
+            let (a_node, a_pos) = index
+                .search(index.root_id, a)?
+                .ok_or(Error::NonExistingKey)?;
+            let (b_node, b_pos) = index
+                .search(index.root_id, b)?
+                .ok_or(Error::NonExistingKey)?;
+
+            // Get the payload IDs for the node positions
+            let a_payload = index.nodes.get_payload(a_node, a_pos)?;
+            let b_payload = index.nodes.get_payload(b_node, b_pos)?;
+
+            // Swap the payload IDs at these positions
+            index.nodes.set_payload(a_node, a_pos, b_payload)?;
+            index.nodes.set_payload(b_node, b_pos, a_payload)?;
+
+            #[cfg(feature = "wal")]
+            if index.wal.is_some() {
+                let a_value = payload::read_value(index.values.as_ref(), b_payload)?;
+                let b_value = payload::read_value(index.values.as_ref(), a_payload)?;
+                index.log_insert(a, &a_value)?;
+                index.log_insert(b, &b_value)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Moves the value of the key `from` to the key `to` without deserializing it.
+    ///
+    /// The key `to` is inserted if it does not exist yet, otherwise its previous value is replaced
+    /// and returned. The key `from` is removed, so the number of entries stays the same
+    /// when moving the value to a new key.
+    /// Fails with [`Error::NonExistingKey`] if `from` does not exist.
+    /// Moving a value to the same key does not change the index.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16, String>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(1, "large value".to_string())?;
+    ///
+    ///     assert_eq!(None, b.move_value(&1, 2)?);
+    ///     assert_eq!(None, b.get(&1)?);
+    ///     assert_eq!(Some("large value".to_string()), b.get(&2)?);
+    ///     assert_eq!(1, b.len());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn move_value(&mut self, from: &K, to: K) -> Result<Option<V>> {
+        self.check_poisoned()?;
+        let Some((node, idx)) = self.search(self.root_id, from)? else {
+            return Err(Error::NonExistingKey);
+        };
+        if from == &to {
+            return Ok(None);
+        }
+        if let TypeSize::Fixed(key_size) = self.config.key_size {
+            crate::file::check_fixed_size(&to, key_size)?;
+        }
+        self.poison_on_error(|index| {
+            let payload = index.nodes.get_payload(node, idx)?;
+            let mut previous = None;
+            index.insert_payload_unlogged(to.clone(), |index, existing| {
+                if let Some(existing) = existing {
+                    previous = Some(payload::read_value(index.values.as_ref(), existing)?);
+                    if !payload::is_inline(existing) && index.value_blocks.is_none() {
+                        index.values.free_block(existing.try_into()?)?;
+                    }
+                }
+                Ok(payload)
+            })?;
+            // The value now belongs to the new key, so only the entry is removed and not its block
+            let from_range = (Bound::Included(from.clone()), Bound::Included(from.clone()));
+            let removed = index.remove_in_node(
+                index.root_id,
+                &from_range,
+                &mut None::<fn(&K, &V) -> bool>,
+            )?;
+            index.nr_elements -= removed;
+
+            #[cfg(feature = "wal")]
+            if index.wal.is_some() {
+                let value = payload::read_value(index.values.as_ref(), payload)?;
+                index.log_insert(&to, &value)?;
+                index.log_remove(Bound::Included(from), Bound::Included(from))?;
+            }
+            Ok(previous)
+        })
+    }
+
+    /// Checks the internal structure of the tree and returns an error if it is corrupted.
+    ///
+    /// The following invariants are checked:
+    ///
+    /// - keys are sorted inside each node and are inside the range given by the parent node,
+    /// - each node except the root has between `order - 1` and `2 * order - 1` keys,
+    /// - all leaf nodes have the same depth,
+    /// - all child node and payload IDs point to existing blocks,
+    /// - the stored sub-tree counts are correct if order statistics are enabled, and
+    /// - the number of entries is the same as returned by [`Self::len`].
+    ///
+    /// This visits every node and key of the tree, so use it for debugging and testing.
+    pub fn verify_integrity(&self) -> Result<()> {
+        let (entries, _) = self.verify_node(self.root_id, true, None, None)?;
+        if entries != self.nr_elements {
+            return Err(Error::IntegrityViolation(format!(
+                "the index contains {} entries, but its length is {}",
+                entries, self.nr_elements
+            )));
+        }
+        Ok(())
+    }
+
+    /// Verifies the node and all its children.
+    ///
+    /// Returns the number of entries in the sub-tree and the depth of its leaves.
+    fn verify_node(
+        &self,
+        node_id: u64,
+        is_root: bool,
+        lower: Option<&K>,
+        upper: Option<&K>,
+    ) -> Result<(usize, usize)> {
+        let violation = |msg: String| Err(Error::IntegrityViolation(msg));
+        if !self.nodes.contains_node(node_id) {
+            return violation(format!("node {} does not exist", node_id));
+        }
+        let number_of_keys = self.nodes.number_of_keys(node_id)?;
+        if number_of_keys > 2 * self.order - 1 || (!is_root && number_of_keys < self.order - 1) {
+            return violation(format!(
+                "node {} has {} keys, but the order is {}",
+                node_id, number_of_keys, self.order
+            ));
+        }
+
+        let mut keys = Vec::with_capacity(number_of_keys);
+        for i in 0..number_of_keys {
+            let key = self.nodes.get_key_owned(node_id, i)?;
+            let smaller_than_previous = keys.last().or(lower).is_some_and(|k| k >= &key);
+            let larger_than_upper = upper.is_some_and(|k| k <= &key);
+            if smaller_than_previous || larger_than_upper {
+                return violation(format!("key {} of node {} is not sorted", i, node_id));
+            }
+            let payload = self.nodes.get_payload(node_id, i)?;
+            if !payload::is_inline(payload) && !self.values.contains_block(payload.try_into()?) {
+                return violation(format!(
+                    "payload {} of key {} in node {} does not exist",
+                    payload, i, node_id
+                ));
+            }
+            keys.push(key);
+        }
+
+        let mut entries = self.nodes.number_of_entries(node_id)?;
+        if self.nodes.is_leaf(node_id)? {
+            return Ok((entries, 0));
+        }
+
+        let mut leaf_depth = None;
+        for c in 0..=number_of_keys {
+            let child_id = self.nodes.get_child_node(node_id, c)?;
+            let child_lower = if c == 0 { lower } else { Some(&keys[c - 1]) };
+            let child_upper = keys.get(c).or(upper);
+            let (child_entries, child_depth) =
+                self.verify_node(child_id, false, child_lower, child_upper)?;
+            if leaf_depth.is_some_and(|d| d != child_depth) {
+                return violation(format!(
+                    "the leaves of node {} have different depths",
+                    node_id
+                ));
+            }
+            leaf_depth = Some(child_depth);
+            if self.nodes.has_child_counts() {
+                let stored_count: usize = self.nodes.get_child_count(node_id, c)?.try_into()?;
+                if stored_count != child_entries {
+                    return violation(format!(
+                        "child {} of node {} has {} entries, but {} are stored",
+                        c, node_id, child_entries, stored_count
+                    ));
+                }
+            }
+            entries += child_entries;
+        }
+        Ok((entries, leaf_depth.unwrap_or_default() + 1))
+    }
+
+    fn search<Q>(&self, node_id: u64, key: &Q) -> Result<Option<(u64, usize)>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized + 'static,
+    {
+        match self.nodes.binary_search_borrowed(node_id, key)? {
+            SearchResult::Found(i) => {
+                if self.nodes.is_removed(node_id, i)? {
+                    Ok(None)
+                } else {
+                    Ok(Some((node_id, i)))
+                }
+            }
+            SearchResult::NotFound(i) => {
+                if self.nodes.is_leaf(node_id)? {
+                    Ok(None)
+                } else {
+                    // search in the matching child node
+                    let child_node_id = self.nodes.get_child_node(node_id, i)?;
+                    self.search(child_node_id, key)
+                }
+            }
+        }
+    }
+
+    /// Searches the remembered leaf of the context if it covers the key, or all nodes from the root node.
+    ///
+    /// The leaf where the search ended is remembered by the context.
+    fn search_with_context(
+        &self,
+        key: &K,
+        context: &mut LookupContext,
+    ) -> Result<Option<(u64, usize)>> {
+        // All keys between the first and the last key of a leaf can only be contained in this leaf,
+        // so it does not matter whether the leaf has been split since the last lookup
+        let remembered = context
+            .leaf
+            .filter(|leaf| leaf.instance == self.instance)
+            .map(|leaf| leaf.node_id);
+        let mut node_id = self.root_id;
+        if let Some(leaf) = remembered {
+            if self.nodes.is_leaf(leaf).unwrap_or(false) {
+                let number_of_keys = self.nodes.number_of_keys(leaf).unwrap_or(0);
+                if number_of_keys > 0
+                    && self.nodes.get_key(leaf, 0)?.as_ref() <= key
+                    && self.nodes.get_key(leaf, number_of_keys - 1)?.as_ref() >= key
+                {
+                    node_id = leaf;
+                }
+            }
+        }
+        loop {
+            let result = self.nodes.binary_search(node_id, key)?;
+            let is_leaf = self.nodes.is_leaf(node_id)?;
+            if is_leaf {
+                context.leaf = Some(lookup::RememberedLeaf {
+                    instance: self.instance,
+                    node_id,
+                });
+            }
+            match result {
+                SearchResult::Found(i) => {
+                    if self.nodes.is_removed(node_id, i)? {
+                        return Ok(None);
+                    } else {
+                        return Ok(Some((node_id, i)));
+                    }
+                }
+                SearchResult::NotFound(i) => {
+                    if is_leaf {
+                        return Ok(None);
+                    }
+                    node_id = self.nodes.get_child_node(node_id, i)?;
+                }
+            }
+        }
+    }
+
+    /// Searches the key from the root node, unless the bloom filter shows that the index
+    /// does not contain the key or the key is found in the node from the hash index.
+    fn search_key(&self, key: &K) -> Result<Option<(u64, usize)>> {
+        if self.bloom_filter.is_some() || self.hash_index.is_some() {
+            let hash = bloom::key_hash(key)?;
+            if let Some(bloom_filter) = &self.bloom_filter {
+                if !bloom_filter.may_contain(hash) {
+                    return Ok(None);
+                }
+            }
+            if let Some(node_id) = self.hash_index.as_ref().and_then(|h| h.get(hash)) {
+                // Each key is contained in only one node, so this must be the entry of the key
+                if let SearchResult::Found(i) = self.nodes.binary_search(node_id, key)? {
+                    if self.nodes.is_removed(node_id, i)? {
+                        return Ok(None);
+                    }
+                    return Ok(Some((node_id, i)));
+                }
+            }
+        }
+        self.search(self.root_id, key)
+    }
+
+    /// Searches a key that is given as a borrowed form of the key type.
+    ///
+    /// The bloom filter and the hash index can only be used for the key type itself,
+    /// because the serialized borrowed form might be different.
+    fn search_borrowed_key<Q>(&self, key: &Q) -> Result<Option<(u64, usize)>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized + 'static,
+    {
+        match node::as_key::<K, Q>(key) {
+            Some(key) => self.search_key(key),
+            None => self.search(self.root_id, key),
+        }
+    }
+
+    /// Points the hash index to the new nodes of the keys that were moved by splitting
+    /// the child node at the given position.
+    ///
+    /// The middle key of the child is moved into the parent node
+    /// and the keys after it into the new right sibling of the child.
+    fn update_hash_index_after_split(&mut self, parent_id: u64, child_idx: usize) -> Result<()> {
+        if self.hash_index.is_none() {
+            return Ok(());
+        }
+        let right = self.nodes.get_child_node(parent_id, child_idx + 1)?;
+        let mut moved = vec![(
+            bloom::key_hash(self.nodes.get_key(parent_id, child_idx)?.as_ref())?,
+            parent_id,
+        )];
+        for i in 0..self.nodes.number_of_keys(right)? {
+            moved.push((
+                bloom::key_hash(self.nodes.get_key(right, i)?.as_ref())?,
+                right,
+            ));
+        }
+        if let Some(hash_index) = &mut self.hash_index {
+            for (hash, node_id) in moved {
+                hash_index.insert(hash, node_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the hash of a new key to the bloom filter.
+    ///
+    /// If the filter contains more keys than it was sized for,
+    /// a filter with twice the size is created from all keys of the index.
+    fn add_to_bloom_filter(&mut self, hash: u64) -> Result<()> {
+        let Some(bloom_filter) = &mut self.bloom_filter else {
+            return Ok(());
+        };
+        bloom_filter.insert(hash);
+        if !bloom_filter.is_overfull() {
+            return Ok(());
+        }
+        let mut resized = bloom_filter.with_double_size();
+        let mut stack = vec![self.root_id];
+        while let Some(node_id) = stack.pop() {
+            let number_of_keys = self.nodes.number_of_keys(node_id)?;
+            for i in 0..number_of_keys {
+                if !self.nodes.is_removed(node_id, i)? {
+                    resized.insert(bloom::key_hash(self.nodes.get_key(node_id, i)?.as_ref())?);
+                }
+            }
+            if !self.nodes.is_leaf(node_id)? {
+                for c in 0..=number_of_keys {
+                    stack.push(self.nodes.get_child_node(node_id, c)?);
+                }
+            }
+        }
+        self.bloom_filter = Some(resized);
+        Ok(())
+    }
+
+    /// Returns the leaf of the last insert if the key belongs into it and it is not full.
+    ///
+    /// The key belongs into the leaf if it lies between the keys of the parent nodes that
+    /// surround the leaf. These are checked from the parent of the leaf upwards,
+    /// so usually only a few nodes of the path need to be checked.
+    /// If the counts of the child nodes are stored, the whole path up to the root node
+    /// must be valid, because the counts of all nodes on the path need to be updated.
+    fn cached_insert_leaf(&self, key: &K) -> Result<Option<u64>> {
+        let Some(&(leaf, _)) = self.insert_path.last() else {
+            return Ok(None);
+        };
+        if !self.nodes.is_leaf(leaf)? || self.nodes.number_of_keys(leaf)? >= (2 * self.order) - 1 {
+            return Ok(None);
+        }
+        let check_whole_path = self.nodes.has_child_counts();
+        let mut has_lower_bound = false;
+        let mut has_upper_bound = false;
+        for level in (0..self.insert_path.len() - 1).rev() {
+            let (node_id, child_idx) = self.insert_path[level];
+            let (child_id, _) = self.insert_path[level + 1];
+            // The node might have been split or changed by an insert that did not use the path
+            if self.nodes.get_child_node(node_id, child_idx)? != child_id {
+                return Ok(None);
+            }
+            if !has_lower_bound && child_idx > 0 {
+                if key <= self.nodes.get_key(node_id, child_idx - 1)?.as_ref() {
+                    return Ok(None);
+                }
+                has_lower_bound = true;
+            }
+            if !has_upper_bound && child_idx < self.nodes.number_of_keys(node_id)? {
+                if key >= self.nodes.get_key(node_id, child_idx)?.as_ref() {
+                    return Ok(None);
+                }
+                has_upper_bound = true;
+            }
+            if has_lower_bound && has_upper_bound && !check_whole_path {
+                return Ok(Some(leaf));
+            }
+        }
+        if self.insert_path[0].0 == self.root_id {
+            Ok(Some(leaf))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Makes sure the key is inside the range of keys that might not have been removed.
+    fn extend_live_range(&mut self, key: &K) {
+        let below = match &self.live_range.0 {
+            Bound::Included(start) => key < start,
+            Bound::Excluded(start) => key <= start,
+            Bound::Unbounded => false,
+        };
+        if below {
+            self.live_range.0 = Bound::Included(key.clone());
+        } else if !self.live_range.contains(key) {
+            self.live_range.1 = Bound::Included(key.clone());
+        }
+    }
+
+    /// Stores a new value and returns its payload.
+    fn insert_value(&mut self, value: &V) -> Result<u64> {
+        if let Some(value_blocks) = &mut self.value_blocks {
+            value_blocks.insert_value(self.values.as_mut(), value)
+        } else {
+            payload::insert_value(self.values.as_mut(), value)
+        }
+    }
+
+    /// Replaces the value of an existing payload and returns the new payload.
+    fn write_value(&mut self, payload: u64, value: &V) -> Result<u64> {
+        if let Some(value_blocks) = &mut self.value_blocks {
+            // The block might be shared with other keys and must not be changed
+            value_blocks.insert_value(self.values.as_mut(), value)
+        } else {
+            payload::write_value(self.values.as_mut(), payload, value)
+        }
+    }
+
+    /// Replaces the payload of the existing key at the given position.
+    ///
+    /// Returns `true` if the key had been removed and is an entry of the index again.
+    fn replace_payload<S>(&mut self, node_id: u64, i: usize, store: S) -> Result<bool>
+    where
+        S: FnOnce(&mut Self, Option<u64>) -> Result<u64>,
+    {
+        let payload = self.nodes.get_payload(node_id, i)?;
+        if payload::is_tombstone(payload) {
+            let new_payload = store(self, None)?;
+            self.nodes.set_payload(node_id, i, new_payload)?;
+            self.nr_elements += 1;
+            return Ok(true);
+        }
+        let new_payload = store(self, Some(payload))?;
+        if new_payload != payload {
+            self.nodes.set_payload(node_id, i, new_payload)?;
+        }
+        Ok(false)
+    }
+
+    /// Inserts the key into the node or one of its children.
+    ///
+    /// Returns `true` if a new entry was added and `false` if the payload of an existing key was replaced.
+    fn insert_nonfull<S>(&mut self, node_id: u64, key: &K, store: S) -> Result<bool>
+    where
+        S: FnOnce(&mut Self, Option<u64>) -> Result<u64>,
+    {
         match self.nodes.binary_search(node_id, key)? {
             SearchResult::Found(i) => {
                 // Key already exists, replace the payload
-                let payload_id = self.nodes.get_payload(node_id, i)?.try_into()?;
-                let previous_payload = self.values.get_owned(payload_id)?;
-                self.values.put(payload_id, &value)?;
-                self.last_inserted_node_id = node_id;
-                Ok(Some(previous_payload))
+                self.insert_path.push((node_id, i));
+                self.replace_payload(node_id, i, store)
             }
             SearchResult::NotFound(i) => {
                 if self.nodes.is_leaf(node_id)? {
-                    let value_size: usize = self.values.serialized_size(&value)?.try_into()?;
-                    let payload_id = self.values.allocate_block(value_size)?;
-                    self.values.put(payload_id, &value)?;
+                    let payload = store(self, None)?;
 
                     // Make space for the new key by moving the other items to the right
                     let number_of_node_keys = self.nodes.number_of_keys(node_id)?;
@@ -382,167 +3707,736 @@ where
                     }
                     // Insert new key with payload at the given position
                     self.nodes.set_key_value(node_id, i, key)?;
-                    self.nodes.set_payload(node_id, i, payload_id.try_into()?)?;
+                    self.nodes.set_payload(node_id, i, payload)?;
                     self.nr_elements += 1;
-                    self.last_inserted_node_id = node_id;
-                    Ok(None)
+                    self.insert_path.push((node_id, i));
+                    Ok(true)
                 } else {
                     // Insert key into correct child
                     // Default to left child
                     let child_id = self.nodes.get_child_node(node_id, i)?;
                     // If the child is full, we need to split it
-                    if self.nodes.number_of_keys(child_id)? == (2 * self.order) - 1 {
-                        let (left, right) = self.nodes.split_child(node_id, i, self.order)?;
-                        let node_key = self.nodes.get_key(node_id, i)?;
-                        if key == node_key.as_ref() {
-                            // Key already exists and was added to the parent node, replace the payload
-                            let payload_id: usize =
-                                self.nodes.get_payload(node_id, i)?.try_into()?;
-                            let previous_payload = self.values.get_owned(payload_id)?;
-                            self.values.put(payload_id, &value)?;
-                            self.last_inserted_node_id = node_id;
-                            Ok(Some(previous_payload))
-                        } else if key > node_key.as_ref() {
-                            // Key is now larger, use the newly created right child
-                            let existing = self.insert_nonfull(right, key, value)?;
-                            Ok(existing)
+                    let (child_idx, inserted) =
+                        if self.nodes.number_of_keys(child_id)? == (2 * self.order) - 1 {
+                            let (left, right) = self.nodes.split_child(node_id, i, self.order)?;
+                            #[cfg(debug_assertions)]
+                            self.check_split(node_id, i)?;
+                            self.update_hash_index_after_split(node_id, i)?;
+                            let node_key = self.nodes.get_key(node_id, i)?;
+                            if key == node_key.as_ref() {
+                                // Key already exists and was added to the parent node, replace the payload
+                                self.insert_path.push((node_id, i));
+                                return self.replace_payload(node_id, i, store);
+                            } else if key > node_key.as_ref() {
+                                // Key is now larger, use the newly created right child
+                                self.insert_path.push((node_id, i + 1));
+                                (i + 1, self.insert_nonfull(right, key, store)?)
+                            } else {
+                                // Use the updated left child (which has a new key vector)
+                                self.insert_path.push((node_id, i));
+                                (i, self.insert_nonfull(left, key, store)?)
+                            }
                         } else {
-                            // Use the updated left child (which has a new key vector)
-                            let existing = self.insert_nonfull(left, key, value)?;
-                            Ok(existing)
-                        }
-                    } else {
-                        let existing = self.insert_nonfull(child_id, key, value)?;
-                        Ok(existing)
+                            self.insert_path.push((node_id, i));
+                            (i, self.insert_nonfull(child_id, key, store)?)
+                        };
+                    if inserted && self.nodes.has_child_counts() {
+                        let count = self.nodes.get_child_count(node_id, child_idx)?;
+                        self.nodes.set_child_count(node_id, child_idx, count + 1)?;
                     }
+                    Ok(inserted)
                 }
             }
         }
     }
 }
 
+impl<K, V> fmt::Debug for BtreeIndex<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Only summarize the index, since printing all entries could be very large
+        // Show the error instead of the depth, e.g. for a poisoned index
+        let depth = self.depth();
+        let depth: &dyn fmt::Debug = match &depth {
+            Ok(depth) => depth,
+            Err(e) => e,
+        };
+        f.debug_struct("BtreeIndex")
+            .field("len", &self.nr_elements)
+            .field("order", &self.order)
+            .field("depth", depth)
+            .field("memory_usage", &self.memory_usage())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Iterator returned by [`BtreeIndex::range`].
+///
+/// The iterator is [`Send`] and [`Sync`] when the keys and values are,
+/// so it can be moved into another thread while the index is borrowed.
 pub struct Range<'a, K, V>
 where
     K: Serialize + DeserializeOwned + Clone,
-    V: Sync,
 {
-    start: Bound<K>,
-    end: Bound<K>,
     nodes: &'a NodeFile<K>,
-    values: &'a dyn TupleFile<V>,
+    values: &'a ValueFile<V>,
+    /// Entries that are not visited yet, all child nodes are completely inside the range.
     stack: Vec<node::StackEntry>,
+    /// Maximum number of entries that are still returned.
+    remaining: usize,
+    /// Whether the range contains all entries, so exactly the remaining entries are returned.
+    exact_size: bool,
+    cancellation: Option<CancellationToken>,
     phantom: PhantomData<V>,
 }
 
 impl<'a, K, V> Range<'a, K, V>
 where
-    K: Clone + Serialize + DeserializeOwned + Ord + Send + Sync,
-    V: Clone + Serialize + DeserializeOwned + Send + Sync,
+    K: Clone + Serialize + DeserializeOwned + Ord,
+    V: Clone + Serialize + DeserializeOwned,
 {
     fn get_key_value_tuple(&self, node: u64, idx: usize) -> Result<(K, V)> {
-        let payload_id = self.nodes.get_payload(node, idx)?;
-        let value = self.values.get_owned(payload_id.try_into()?)?;
+        let payload = self.nodes.get_payload(node, idx)?;
+        let value = payload::read_value(self.values.as_ref(), payload)?;
         let key = self.nodes.get_key_owned(node, idx)?;
         Ok((key, value))
     }
+
+    /// Stop the iteration after the token has been cancelled.
+    ///
+    /// The next call of `next` returns [`Error::Cancelled`] and the iteration ends afterwards.
+    pub fn cancel_on(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Returns the error if the iteration has been cancelled and ends the iteration.
+    fn check_cancelled(&mut self) -> Option<Error> {
+        let error = self.cancellation.as_ref()?.check().err()?;
+        self.stack.clear();
+        self.remaining = 0;
+        Some(error)
+    }
+
+    /// Ends the iteration with an error, which is returned instead of the remaining entries.
+    fn fail(&mut self, error: Error) -> Option<Result<(K, V)>> {
+        self.stack.clear();
+        self.remaining = 0;
+        Some(Err(error))
+    }
+
+    /// Skips the next `n` entries of the range without reading their values.
+    ///
+    /// When [order statistics](BtreeConfig::order_statistics) are enabled,
+    /// child nodes that are completely skipped are not visited at all.
+    /// Returns the number of entries that have been skipped, which is less than `n`
+    /// if the end of the range has been reached.
+    pub fn skip_entries(&mut self, n: usize) -> Result<usize> {
+        let mut skipped = 0;
+        while skipped < n {
+            match self.stack.pop() {
+                Some(StackEntry::Key { node, idx }) => {
+                    if !self.nodes.is_removed(node, idx)? {
+                        skipped += 1;
+                    }
+                }
+                Some(StackEntry::Child { parent, idx }) => {
+                    if let Some(count) = self.child_count(parent, idx)? {
+                        if count <= n - skipped {
+                            skipped += count;
+                            continue;
+                        }
+                    }
+                    let c = self.nodes.get_child_node(parent, idx)?;
+                    let mut new_elements = self.nodes.find_range(c, ..);
+                    new_elements.reverse();
+                    self.stack.extend(new_elements);
+                }
+                None => break,
+            }
+        }
+        self.remaining = self.remaining.saturating_sub(skipped);
+        Ok(skipped)
+    }
+
+    /// Returns the number of entries of the given child node if order statistics are enabled.
+    ///
+    /// The child nodes on the stack are always completely inside the range.
+    fn child_count(&self, parent: u64, idx: usize) -> Result<Option<usize>> {
+        if !self.nodes.has_child_counts() {
+            return Ok(None);
+        }
+        Ok(Some(usize::try_from(
+            self.nodes.get_child_count(parent, idx)?,
+        )?))
+    }
+}
+
+impl<'a, K, V> fmt::Debug for Range<'a, K, V>
+where
+    K: Serialize + DeserializeOwned + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Range")
+            .field("finished", &self.stack.is_empty())
+            .finish_non_exhaustive()
+    }
 }
 
 impl<'a, K, V> Iterator for Range<'a, K, V>
 where
-    K: Clone + Serialize + DeserializeOwned + Ord + Send + Sync,
-    V: Clone + Serialize + DeserializeOwned + Send + Sync,
+    K: Clone + Serialize + DeserializeOwned + Ord,
+    V: Clone + Serialize + DeserializeOwned,
 {
     type Item = Result<(K, V)>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        // The remaining entries are an upper bound, so the stack only contains removed keys
+        if self.remaining == 0 {
+            self.stack.clear();
+            return None;
+        }
+        if !self.stack.is_empty() {
+            if let Some(error) = self.check_cancelled() {
+                return Some(Err(error));
+            }
+        }
         while let Some(e) = self.stack.pop() {
             match e {
                 StackEntry::Child { parent, idx } => {
                     match self.nodes.get_child_node(parent, idx) {
                         Ok(c) => {
                             // Add all entries for this child node on the stack
+                            let mut new_elements = self.nodes.find_range(c, ..);
+                            new_elements.reverse();
+                            self.stack.extend(new_elements);
+                        }
+                        Err(e) => return self.fail(e),
+                    }
+                }
+                StackEntry::Key { node, idx } => match self.nodes.is_removed(node, idx) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        self.remaining -= 1;
+                        return Some(self.get_key_value_tuple(node, idx));
+                    }
+                    Err(e) => return self.fail(e),
+                },
+            }
+        }
+
+        None
+    }
+
+    /// The size is exact for the range of all entries, otherwise only the upper bound is known.
+    /// Errors are counted like entries: an error reading a single entry is returned instead of it,
+    /// and an error reading the nodes is returned instead of all remaining entries.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.exact_size {
+            (self.remaining, Some(self.remaining))
+        } else {
+            (0, Some(self.remaining))
+        }
+    }
+}
+
+/// Iterator returned by [`BtreeIndex::overlay_range`].
+pub struct OverlayRange<'a, K, V, I>
+where
+    K: Clone + Serialize + DeserializeOwned + Ord,
+    V: Clone + Serialize + DeserializeOwned,
+    I: Iterator<Item = (K, V)>,
+{
+    index: Peekable<Range<'a, K, V>>,
+    base: Peekable<I>,
+    start: Bound<K>,
+    end: Bound<K>,
+}
+
+impl<'a, K, V, I> OverlayRange<'a, K, V, I>
+where
+    K: Clone + Serialize + DeserializeOwned + Ord,
+    V: Clone + Serialize + DeserializeOwned,
+    I: Iterator<Item = (K, V)>,
+{
+    /// Returns the key of the next base entry inside the range, skipping all entries before the range.
+    fn peek_base_key(&mut self) -> Option<&K> {
+        while let Some((key, _)) = self.base.peek() {
+            let before_start = match &self.start {
+                Bound::Included(start) => key < start,
+                Bound::Excluded(start) => key <= start,
+                Bound::Unbounded => false,
+            };
+            if before_start {
+                self.base.next();
+            } else {
+                break;
+            }
+        }
+        let (key, _) = self.base.peek()?;
+        let after_end = match &self.end {
+            Bound::Included(end) => key > end,
+            Bound::Excluded(end) => key >= end,
+            Bound::Unbounded => false,
+        };
+        if after_end {
+            None
+        } else {
+            Some(key)
+        }
+    }
+}
+
+impl<'a, K, V, I> Iterator for OverlayRange<'a, K, V, I>
+where
+    K: Clone + Serialize + DeserializeOwned + Ord,
+    V: Clone + Serialize + DeserializeOwned,
+    I: Iterator<Item = (K, V)>,
+{
+    type Item = Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let base_key = self.peek_base_key().cloned();
+        let index_key = match self.index.peek() {
+            Some(Ok((key, _))) => Some(key),
+            Some(Err(_)) => return self.index.next(),
+            None => None,
+        };
+        match (index_key, base_key) {
+            (Some(index_key), Some(base_key)) => match index_key.cmp(&base_key) {
+                Ordering::Less => self.index.next(),
+                Ordering::Equal => {
+                    // The entry of the index shadows the base entry
+                    self.base.next();
+                    self.index.next()
+                }
+                Ordering::Greater => self.base.next().map(Ok),
+            },
+            (Some(_), None) => self.index.next(),
+            (None, Some(_)) => self.base.next().map(Ok),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Entry of the stack of iterators that need to know when a node has been visited completely.
+enum TraversalEntry {
+    Entry(StackEntry),
+    /// All entries of the given node have been returned.
+    LeaveNode(u64),
+}
+
+/// Iterator returned by [`BtreeIndex::drain`].
+pub struct Drain<'a, K, V>
+where
+    K: Serialize + DeserializeOwned + PartialOrd + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    index: &'a mut BtreeIndex<K, V>,
+    start: Bound<K>,
+    end: Bound<K>,
+    stack: Vec<TraversalEntry>,
+    /// Parent nodes and positions of the child nodes that are currently visited.
+    path: Vec<(u64, usize)>,
+}
+
+impl<'a, K, V> Iterator for Drain<'a, K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    type Item = Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index.poisoned {
+            // Return the error only once, so the iterator ends afterwards
+            if self.stack.is_empty() {
+                return None;
+            }
+            self.stack.clear();
+            return Some(Err(Error::Poisoned));
+        }
+        let result = self.next_entry();
+        if let Some(Err(_)) = result {
+            self.index.poisoned = true;
+            self.stack.clear();
+        }
+        result
+    }
+}
+
+impl<K, V> Drain<'_, K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    fn next_entry(&mut self) -> Option<Result<(K, V)>> {
+        while let Some(e) = self.stack.pop() {
+            match e {
+                TraversalEntry::Entry(StackEntry::Child { parent, idx }) => {
+                    match self.index.nodes.get_child_node(parent, idx) {
+                        Ok(c) => {
+                            self.path.push((parent, idx));
+                            self.stack.push(TraversalEntry::LeaveNode(c));
                             let mut new_elements = self
+                                .index
                                 .nodes
                                 .find_range(c, (self.start.clone(), self.end.clone()));
                             new_elements.reverse();
-                            self.stack.extend(new_elements.into_iter());
+                            self.stack
+                                .extend(new_elements.into_iter().map(TraversalEntry::Entry));
                         }
                         Err(e) => return Some(Err(e)),
                     }
                 }
-                StackEntry::Key { node, idx } => match self.get_key_value_tuple(node, idx) {
-                    Ok(result) => {
-                        return Some(Ok(result));
+                TraversalEntry::Entry(StackEntry::Key { node, idx }) => {
+                    match self.index.nodes.is_removed(node, idx) {
+                        Ok(true) => {}
+                        Ok(false) => return Some(self.index.drain_entry(node, idx, &self.path)),
+                        Err(e) => return Some(Err(e)),
                     }
-                    Err(e) => {
-                        return Some(Err(e));
+                }
+                TraversalEntry::LeaveNode(_) => {
+                    self.path.pop();
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Iterator over the keys of an index in key order, which does not read the values.
+struct KeyCursor<'a, K> {
+    nodes: &'a NodeFile<K>,
+    stack: Vec<node::StackEntry>,
+}
+
+impl<'a, K> Iterator for KeyCursor<'a, K>
+where
+    K: Clone + Serialize + DeserializeOwned + Ord,
+{
+    type Item = Result<K>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(e) = self.stack.pop() {
+            match e {
+                StackEntry::Child { parent, idx } => match self.nodes.get_child_node(parent, idx) {
+                    Ok(c) => {
+                        let mut new_elements = self.nodes.find_range(c, ..);
+                        new_elements.reverse();
+                        self.stack.extend(new_elements);
                     }
+                    Err(e) => return Some(Err(e)),
+                },
+                StackEntry::Key { node, idx } => match self.nodes.is_removed(node, idx) {
+                    Ok(true) => {}
+                    Ok(false) => return Some(self.nodes.get_key_owned(node, idx)),
+                    Err(e) => return Some(Err(e)),
                 },
             }
         }
-
         None
     }
 }
 
+/// Set operation that is applied to the keys of two indexes by a [`KeySetIter`].
+#[derive(Clone, Copy)]
+enum KeySetOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+/// Iterator returned by [`BtreeIndex::keys_union`], [`BtreeIndex::keys_intersection`]
+/// and [`BtreeIndex::keys_difference`].
+pub struct KeySetIter<'a, K>
+where
+    K: Clone + Serialize + DeserializeOwned + Ord,
+{
+    left: Peekable<KeyCursor<'a, K>>,
+    right: Peekable<KeyCursor<'a, K>>,
+    operation: KeySetOperation,
+}
+
+impl<'a, K> KeySetIter<'a, K>
+where
+    K: Clone + Serialize + DeserializeOwned + Ord,
+{
+    fn new(
+        left: KeyCursor<'a, K>,
+        right: KeyCursor<'a, K>,
+        operation: KeySetOperation,
+    ) -> KeySetIter<'a, K> {
+        KeySetIter {
+            left: left.peekable(),
+            right: right.peekable(),
+            operation,
+        }
+    }
+}
+
+impl<'a, K> Iterator for KeySetIter<'a, K>
+where
+    K: Clone + Serialize + DeserializeOwned + Ord,
+{
+    type Item = Result<K>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if matches!(self.left.peek(), Some(Err(_))) {
+                return self.left.next();
+            }
+            if matches!(self.right.peek(), Some(Err(_))) {
+                return self.right.next();
+            }
+            let order = match (self.left.peek(), self.right.peek()) {
+                (Some(Ok(left)), Some(Ok(right))) => left.cmp(right),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                _ => return None,
+            };
+            match (order, self.operation) {
+                (Ordering::Less, KeySetOperation::Union | KeySetOperation::Difference) => {
+                    return self.left.next();
+                }
+                (Ordering::Greater, KeySetOperation::Union) => return self.right.next(),
+                (Ordering::Equal, KeySetOperation::Union | KeySetOperation::Intersection) => {
+                    self.right.next();
+                    return self.left.next();
+                }
+                (Ordering::Less, KeySetOperation::Intersection) => {
+                    self.left.next();
+                }
+                (Ordering::Greater, _) => {
+                    self.right.next();
+                }
+                (Ordering::Equal, KeySetOperation::Difference) => {
+                    self.left.next();
+                    self.right.next();
+                }
+            }
+        }
+    }
+}
+
+/// Iterator returned by [`BtreeIndex::join_range`].
+pub struct JoinRange<'a, K, V1, V2>
+where
+    K: Clone + Serialize + DeserializeOwned + Ord,
+    V1: Clone + Serialize + DeserializeOwned,
+    V2: Clone + Serialize + DeserializeOwned,
+{
+    left: Peekable<Range<'a, K, V1>>,
+    right: Peekable<Range<'a, K, V2>>,
+}
+
+impl<'a, K, V1, V2> Iterator for JoinRange<'a, K, V1, V2>
+where
+    K: Clone + Serialize + DeserializeOwned + Ord,
+    V1: Clone + Serialize + DeserializeOwned,
+    V2: Clone + Serialize + DeserializeOwned,
+{
+    type Item = Result<(K, Option<V1>, Option<V2>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if matches!(self.left.peek(), Some(Err(_))) {
+            return self.left.next().and_then(Result::err).map(Err);
+        }
+        if matches!(self.right.peek(), Some(Err(_))) {
+            return self.right.next().and_then(Result::err).map(Err);
+        }
+        let left_key = self.left.peek().and_then(|e| e.as_ref().ok());
+        let right_key = self.right.peek().and_then(|e| e.as_ref().ok());
+        let order = match (left_key, right_key) {
+            (Some((left_key, _)), Some((right_key, _))) => left_key.cmp(right_key),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => return None,
+        };
+        let result = match order {
+            Ordering::Less => self.left.next()?.map(|(k, v)| (k, Some(v), None)),
+            Ordering::Greater => self.right.next()?.map(|(k, v)| (k, None, Some(v))),
+            Ordering::Equal => match (self.left.next()?, self.right.next()?) {
+                (Ok((k, left)), Ok((_, right))) => Ok((k, Some(left), Some(right))),
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            },
+        };
+        Some(result)
+    }
+}
+
+/// Iterator returned by [`BtreeIndex::range_grouped`].
+///
+/// Each item contains the group key and the entries of the group in key order.
+pub struct GroupedRange<'a, K, V, G, F>
+where
+    K: Clone + Serialize + DeserializeOwned + Ord,
+    V: Clone + Serialize + DeserializeOwned,
+    G: PartialEq,
+    F: FnMut(&K) -> G,
+{
+    entries: Peekable<Range<'a, K, V>>,
+    group_key: F,
+}
+
+impl<'a, K, V, G, F> Iterator for GroupedRange<'a, K, V, G, F>
+where
+    K: Clone + Serialize + DeserializeOwned + Ord,
+    V: Clone + Serialize + DeserializeOwned,
+    G: PartialEq,
+    F: FnMut(&K) -> G,
+{
+    type Item = Result<(G, Vec<(K, V)>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = match self.entries.next()? {
+            Ok(first) => first,
+            Err(e) => return Some(Err(e)),
+        };
+        let group = (self.group_key)(&first.0);
+        let mut entries = vec![first];
+        // An error ends the group and is returned on the next call
+        while let Some(Ok((key, _))) = self.entries.peek() {
+            if (self.group_key)(key) != group {
+                break;
+            }
+            if let Some(Ok(entry)) = self.entries.next() {
+                entries.push(entry);
+            }
+        }
+        Some(Ok((group, entries)))
+    }
+}
+
+/// Iterator returned by [`BtreeIndex::into_iter`].
+///
+/// The iterator owns the files of the index and is [`Send`] and [`Sync`]
+/// when the keys and values are.
+/// Nodes are discarded after all of their entries have been returned,
+/// and value blocks directly after their value has been returned.
 pub struct BtreeIntoIter<K, V>
 where
     K: Serialize + DeserializeOwned + Clone,
-    V: Sync,
 {
     nodes: NodeFile<K>,
-    values: Box<dyn TupleFile<V>>,
-    stack: Vec<node::StackEntry>,
+    values: ValueFile<V>,
+    stack: Vec<TraversalEntry>,
+    /// Number of entries that are still returned.
+    remaining: usize,
+    cancellation: Option<CancellationToken>,
+    /// Whether a value block can be referenced by several keys, so it must not be discarded.
+    shared_values: bool,
     phantom: PhantomData<V>,
 }
 
 impl<K, V> BtreeIntoIter<K, V>
 where
-    K: Clone + Serialize + DeserializeOwned + Ord + Send + Sync,
-    V: Clone + Serialize + DeserializeOwned + Send + Sync,
+    K: Clone + Serialize + DeserializeOwned + Ord,
+    V: Clone + Serialize + DeserializeOwned,
 {
-    fn get_key_value_tuple(&self, node: u64, idx: usize) -> Result<(K, V)> {
-        let payload_id = self.nodes.get_payload(node, idx)?;
-        let value = self.values.get_owned(payload_id.try_into()?)?;
+    /// Stop the iteration after the token has been cancelled.
+    ///
+    /// The next call of `next` returns [`Error::Cancelled`] and the iteration ends afterwards.
+    pub fn cancel_on(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Returns the error if the iteration has been cancelled and ends the iteration.
+    fn check_cancelled(&mut self) -> Option<Error> {
+        let error = self.cancellation.as_ref()?.check().err()?;
+        self.stack.clear();
+        self.remaining = 0;
+        Some(error)
+    }
+
+    /// Ends the iteration with an error, which is returned instead of the remaining entries.
+    fn fail(&mut self, error: Error) -> Option<Result<(K, V)>> {
+        self.stack.clear();
+        self.remaining = 0;
+        Some(Err(error))
+    }
+
+    fn take_key_value_tuple(&mut self, node: u64, idx: usize) -> Result<(K, V)> {
+        let payload = self.nodes.get_payload(node, idx)?;
+        let value = payload::read_value(self.values.as_ref(), payload)?;
         let key = self.nodes.get_key_owned(node, idx)?;
+        if !payload::is_inline(payload) && !self.shared_values {
+            self.values.as_mut().discard_block(payload.try_into()?)?;
+        }
         Ok((key, value))
     }
 }
 
 impl<K, V> Iterator for BtreeIntoIter<K, V>
 where
-    K: Clone + Serialize + DeserializeOwned + Ord + Send + Sync,
-    V: Clone + Serialize + DeserializeOwned + Send + Sync,
+    K: Clone + Serialize + DeserializeOwned + Ord,
+    V: Clone + Serialize + DeserializeOwned,
 {
     type Item = Result<(K, V)>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        // The files are dropped with the iterator, so the remaining nodes don't need to be discarded
+        if self.remaining == 0 {
+            self.stack.clear();
+            return None;
+        }
+        if !self.stack.is_empty() {
+            if let Some(error) = self.check_cancelled() {
+                return Some(Err(error));
+            }
+        }
         while let Some(e) = self.stack.pop() {
             match e {
-                StackEntry::Child { parent, idx } => {
+                TraversalEntry::Entry(StackEntry::Child { parent, idx }) => {
                     match self.nodes.get_child_node(parent, idx) {
                         Ok(c) => {
                             // Add all entries for this child node on the stack
-                            let mut new_elements = self.nodes.find_range(c, ..);
-                            new_elements.reverse();
-                            self.stack.extend(new_elements.into_iter());
+                            self.stack.push(TraversalEntry::LeaveNode(c));
+                            let new_elements = self.nodes.find_range(c, ..);
+                            self.stack
+                                .extend(new_elements.into_iter().rev().map(TraversalEntry::Entry));
                         }
-                        Err(e) => return Some(Err(e)),
+                        Err(e) => return self.fail(e),
                     }
                 }
-                StackEntry::Key { node, idx } => match self.get_key_value_tuple(node, idx) {
-                    Ok(result) => {
-                        return Some(Ok(result));
+                TraversalEntry::Entry(StackEntry::Key { node, idx }) => {
+                    match self.nodes.is_removed(node, idx) {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            self.remaining -= 1;
+                            return Some(self.take_key_value_tuple(node, idx));
+                        }
+                        Err(e) => return self.fail(e),
                     }
-                    Err(e) => {
-                        return Some(Err(e));
+                }
+                TraversalEntry::LeaveNode(node) => {
+                    if let Err(e) = self.nodes.discard_node(node) {
+                        return self.fail(e);
                     }
-                },
+                }
             }
         }
 
         None
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+/// The length is the number of entries that have not been returned yet.
+/// Errors are counted like entries: an error reading a single entry is returned instead of it,
+/// and an error reading or discarding the nodes is returned instead of all remaining entries.
+impl<K, V> ExactSizeIterator for BtreeIntoIter<K, V>
+where
+    K: Clone + Serialize + DeserializeOwned + Ord,
+    V: Clone + Serialize + DeserializeOwned,
+{
 }
 
 #[cfg(test)]
@@ -0,0 +1,92 @@
+use std::{collections::hash_map::DefaultHasher, f64::consts::LN_2, hash::Hasher};
+
+use bincode::Options;
+use serde::Serialize;
+
+use crate::error::Result;
+
+/// Hash of the serialized key, which is used by the [`BloomFilter`] and the hash index.
+pub(super) fn key_hash<K>(key: &K) -> Result<u64>
+where
+    K: Serialize,
+{
+    let serialized = bincode::DefaultOptions::new().serialize(key)?;
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&serialized);
+    Ok(hasher.finish())
+}
+
+/// Returns the positions of the bits for the hash of a key,
+/// which are derived from the two halves of the hash (double hashing).
+fn bit_positions(hash: u64, number_of_hashes: u64, words: usize) -> impl Iterator<Item = usize> {
+    let number_of_bits = words as u64 * 64;
+    let h1 = hash & u64::from(u32::MAX);
+    let h2 = (hash >> 32) | 1;
+    (0..number_of_hashes)
+        .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % number_of_bits) as usize)
+}
+
+/// Probabilistic set of the inserted keys, which can tell that a key is definitely not contained.
+///
+/// The number of bits is chosen for a maximum number of keys. When more keys are inserted,
+/// the false positive rate increases and the filter should be rebuilt with a larger size.
+#[derive(Clone, Debug)]
+pub(super) struct BloomFilter {
+    bits: Vec<u64>,
+    number_of_hashes: u64,
+    bits_per_key: usize,
+    max_keys: usize,
+    number_of_keys: usize,
+}
+
+impl BloomFilter {
+    /// Create an empty filter with `bits_per_key` bits for each of `max_keys` keys.
+    pub fn with_bits_per_key(bits_per_key: usize, max_keys: usize) -> BloomFilter {
+        let max_keys = max_keys.max(1);
+        let number_of_bits = bits_per_key.max(1).saturating_mul(max_keys);
+        // The optimal number of hash functions for the number of bits per key
+        let number_of_hashes = (bits_per_key as f64 * LN_2).round().clamp(1.0, 16.0) as u64;
+        BloomFilter {
+            bits: vec![0; number_of_bits.div_ceil(64)],
+            number_of_hashes,
+            bits_per_key,
+            max_keys,
+            number_of_keys: 0,
+        }
+    }
+
+    /// Create an empty filter for twice as many keys as this one.
+    pub fn with_double_size(&self) -> BloomFilter {
+        BloomFilter::with_bits_per_key(self.bits_per_key, self.max_keys.saturating_mul(2))
+    }
+
+    /// Add the hash of a new key.
+    pub fn insert(&mut self, hash: u64) {
+        for pos in bit_positions(hash, self.number_of_hashes, self.bits.len()) {
+            self.bits[pos / 64] |= 1 << (pos % 64);
+        }
+        self.number_of_keys += 1;
+    }
+
+    /// Returns `false` if no key with this hash has been inserted.
+    pub fn may_contain(&self, hash: u64) -> bool {
+        bit_positions(hash, self.number_of_hashes, self.bits.len())
+            .all(|pos| self.bits[pos / 64] & (1 << (pos % 64)) != 0)
+    }
+
+    /// Returns whether more keys than planned have been inserted.
+    pub fn is_overfull(&self) -> bool {
+        self.number_of_keys > self.max_keys
+    }
+
+    /// Remove all keys.
+    pub fn clear(&mut self) {
+        self.bits.fill(0);
+        self.number_of_keys = 0;
+    }
+
+    /// Number of bytes used by the bits of the filter.
+    pub fn memory_usage(&self) -> usize {
+        self.bits.capacity() * std::mem::size_of::<u64>()
+    }
+}
@@ -0,0 +1,221 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{BtreeConfig, BtreeIndex, TypeSize};
+use crate::{codec::Codec, error::Result, ordered::OrderedBytes, Error};
+
+/// Creates a [`BtreeIndex`] and checks that the options fit together, as returned by
+/// [`BtreeIndex::builder`].
+///
+/// The most common options can be set directly, all others with a complete [configuration](Self::config).
+/// Unlike [`BtreeIndex::with_capacity`], [`Self::build`] fails with [`Error::ConflictingOptions`]
+/// instead of ignoring options that have no effect in combination with other options.
+///
+/// # Example
+///
+/// ```rust
+/// use transient_btree_index::{BtreeIndex, Error};
+///
+/// fn main() -> std::result::Result<(), Error> {
+///     let mut b = BtreeIndex::<u64, String>::builder()
+///         .order(42)
+///         .fixed_key_size(8)
+///         .estimated_entries(1000)
+///         .build()?;
+///     b.insert(1, "one".to_string())?;
+///
+///     assert_eq!(42, b.order());
+///     assert_eq!(Some("one".to_string()), b.get(&1)?);
+///     Ok(())
+/// }
+/// ```
+pub struct BtreeBuilder<K, V> {
+    config: BtreeConfig,
+    estimated_entries: usize,
+    ordered_keys: bool,
+    /// Which of the setters for the key and value sizes have been called.
+    key_sizes: SizeSetters,
+    value_sizes: SizeSetters,
+    key_codec: Option<Arc<dyn Codec<K>>>,
+    value_codec: Option<Arc<dyn Codec<V>>>,
+    phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V> BtreeBuilder<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    pub(super) fn new() -> BtreeBuilder<K, V> {
+        BtreeBuilder {
+            config: BtreeConfig::default(),
+            estimated_entries: 0,
+            ordered_keys: false,
+            key_sizes: SizeSetters::default(),
+            value_sizes: SizeSetters::default(),
+            key_codec: None,
+            value_codec: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Replace all options with the given configuration.
+    ///
+    /// Options that are set afterwards change this configuration.
+    pub fn config(mut self, config: BtreeConfig) -> Self {
+        self.config = config;
+        self.key_sizes = SizeSetters::default();
+        self.value_sizes = SizeSetters::default();
+        self
+    }
+
+    /// Set the [order](BtreeConfig::order) of the tree.
+    pub fn order(mut self, order: usize) -> Self {
+        self.config = self.config.order(order);
+        self
+    }
+
+    /// Set the [estimated maximum size](BtreeConfig::max_key_size) in bytes of each key.
+    pub fn max_key_size(mut self, est_max_key_size: usize) -> Self {
+        self.config = self.config.max_key_size(est_max_key_size);
+        self.key_sizes.max = true;
+        self
+    }
+
+    /// Set the [fixed size](BtreeConfig::fixed_key_size) in bytes of each key.
+    pub fn fixed_key_size(mut self, key_size: usize) -> Self {
+        self.config = self.config.fixed_key_size(key_size);
+        self.key_sizes.fixed = true;
+        self
+    }
+
+    /// Set the [estimated maximum size](BtreeConfig::max_value_size) in bytes of each value.
+    pub fn max_value_size(mut self, est_max_value_size: usize) -> Self {
+        self.config = self.config.max_value_size(est_max_value_size);
+        self.value_sizes.max = true;
+        self
+    }
+
+    /// Set the [fixed size](BtreeConfig::fixed_value_size) in bytes of each value.
+    pub fn fixed_value_size(mut self, value_size: usize) -> Self {
+        self.config = self.config.fixed_value_size(value_size);
+        self.value_sizes.fixed = true;
+        self
+    }
+
+    /// Set the number of entries the files are created for, so they don't need to grow
+    /// while inserting them.
+    ///
+    /// By default, the files are created with a minimal size and grow while inserting.
+    pub fn estimated_entries(mut self, entries: usize) -> Self {
+        self.estimated_entries = entries;
+        self
+    }
+
+    /// Compare the serialized bytes of the keys when searching, like [`BtreeIndex::with_ordered_keys`].
+    ///
+    /// Encrypted keys can't be compared by their bytes, so this conflicts with an encryption key.
+    pub fn ordered_keys(mut self) -> Self
+    where
+        K: OrderedBytes,
+    {
+        self.ordered_keys = true;
+        self
+    }
+
+    /// Serialize the keys with the given [`Codec`] instead of bincode.
+    ///
+    /// Searching compares the deserialized keys, so this can't be combined with a fixed key size,
+    /// the key prefix compression or [ordered keys](Self::ordered_keys), which depend on the
+    /// encoding of bincode.
+    pub fn key_codec<C>(mut self, codec: C) -> Self
+    where
+        C: Codec<K> + 'static,
+    {
+        self.key_codec = Some(Arc::new(codec));
+        self
+    }
+
+    /// Serialize the values with the given [`Codec`] instead of the
+    /// [built-in format](BtreeConfig::value_codec) of the configuration.
+    ///
+    /// Values with a fixed size are always serialized with bincode, so this can't be combined
+    /// with a fixed value size.
+    pub fn value_codec<C>(mut self, codec: C) -> Self
+    where
+        C: Codec<V> + 'static,
+    {
+        self.value_codec = Some(Arc::new(codec));
+        self
+    }
+
+    /// Check the options and create the index.
+    ///
+    /// Fails with [`Error::ConflictingOptions`] if an option would have no effect because of
+    /// another one, and with the same errors as [`BtreeIndex::with_capacity`] for invalid options.
+    pub fn build(self) -> Result<BtreeIndex<K, V>> {
+        if self.key_sizes.fixed && self.key_sizes.max {
+            return Err(conflict("fixed_key_size", "max_key_size"));
+        }
+        if self.value_sizes.fixed && self.value_sizes.max {
+            return Err(conflict("fixed_value_size", "max_value_size"));
+        }
+        let config = &self.config;
+        let fixed_key_size = matches!(config.key_size, TypeSize::Fixed(_));
+        if config.key_prefix_compression && fixed_key_size {
+            return Err(conflict("key_prefix_compression", "fixed_key_size"));
+        }
+        if self.ordered_keys && config.key_prefix_compression {
+            return Err(conflict("ordered_keys", "key_prefix_compression"));
+        }
+        if self.ordered_keys && config.is_encrypted() {
+            return Err(conflict("ordered_keys", "encryption_key"));
+        }
+        #[cfg(feature = "compression")]
+        if config.value_compression.is_some() && matches!(config.value_size, TypeSize::Fixed(_)) {
+            return Err(conflict("value_compression", "fixed_value_size"));
+        }
+        if self.key_codec.is_some() {
+            if fixed_key_size {
+                return Err(conflict("key_codec", "fixed_key_size"));
+            }
+            if config.key_prefix_compression {
+                return Err(conflict("key_codec", "key_prefix_compression"));
+            }
+            if self.ordered_keys {
+                return Err(conflict("key_codec", "ordered_keys"));
+            }
+        }
+        if self.value_codec.is_some() && matches!(config.value_size, TypeSize::Fixed(_)) {
+            return Err(conflict("value_codec", "fixed_value_size"));
+        }
+
+        let mut result = BtreeIndex::with_capacity(self.config, self.estimated_entries)?;
+        if self.ordered_keys {
+            result.nodes.enable_ordered_key_bytes();
+        }
+        if let Some(codec) = self.key_codec {
+            result.nodes.set_key_codec(codec);
+        }
+        if let Some(codec) = self.value_codec {
+            result.values.set_codec(codec);
+        }
+        Ok(result)
+    }
+}
+
+/// Remembers whether the fixed and the estimated maximum size have both been set,
+/// since only the last one would be used.
+#[derive(Default)]
+struct SizeSetters {
+    fixed: bool,
+    max: bool,
+}
+
+fn conflict(option: &'static str, conflicting_option: &'static str) -> Error {
+    Error::ConflictingOptions {
+        option,
+        conflicting_option,
+    }
+}
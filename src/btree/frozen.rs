@@ -0,0 +1,108 @@
+use std::ops::RangeBounds;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{BtreeIndex, MemoryUsage, Range};
+use crate::error::Result;
+
+/// Read-only B-tree index, which is created by [`BtreeIndex::freeze`].
+///
+/// All values are accessed directly with the ID of their block, so reading a relocated value
+/// does not need to follow a forwarding pointer. Reads never wait for a lock, and the index
+/// can be shared between threads.
+///
+/// # Example
+///
+/// ```rust
+/// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+///
+/// fn main() -> std::result::Result<(), Error> {
+///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+///     b.insert(1,2)?;
+///     b.insert(200, 4)?;
+///
+///     let frozen = b.freeze()?;
+///     std::thread::scope(|s| {
+///         s.spawn(|| assert_eq!(Some(2), frozen.get(&1).unwrap()));
+///         s.spawn(|| assert_eq!(Some(4), frozen.get(&200).unwrap()));
+///     });
+///     Ok(())
+/// }
+/// ```
+pub struct FrozenBtreeIndex<K, V>
+where
+    K: Serialize + DeserializeOwned + PartialOrd + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    pub(super) index: BtreeIndex<K, V>,
+}
+
+impl<K, V> BtreeIndex<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    /// Finish inserting entries and turn the index into a read-only index.
+    ///
+    /// The references to relocated values are updated in all nodes,
+    /// so reading them does not need to follow the forwarding pointer of the original block.
+    pub fn freeze(mut self) -> Result<FrozenBtreeIndex<K, V>> {
+        self.check_poisoned()?;
+        let values = &self.values;
+        self.nodes
+            .update_payloads(|block_id| values.resolve_block(block_id))?;
+        Ok(FrozenBtreeIndex { index: self })
+    }
+}
+
+impl<K, V> FrozenBtreeIndex<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    /// Searches for a key in the index and returns the value if found.
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        self.index.get(key)
+    }
+
+    /// Searches for a key in the index and returns the stored key together with its value.
+    pub fn get_key_value(&self, key: &K) -> Result<Option<(K, V)>> {
+        self.index.get_key_value(key)
+    }
+
+    /// Returns whether the index contains the given key.
+    pub fn contains_key(&self, key: &K) -> Result<bool> {
+        self.index.contains_key(key)
+    }
+
+    /// Return an iterator over a range of keys.
+    pub fn range<R>(&self, range: R) -> Result<Range<'_, K, V>>
+    where
+        R: RangeBounds<K>,
+    {
+        self.index.range(range)
+    }
+
+    /// Returns the number of entries inside the range without reading their values.
+    pub fn range_len<R>(&self, range: R) -> Result<usize>
+    where
+        R: RangeBounds<K>,
+    {
+        self.index.range_len(range)
+    }
+
+    /// Returns true if the index does not contain any elements.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the length of the index.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns the number of bytes used by the index, see [`BtreeIndex::memory_usage`].
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.index.memory_usage()
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+
+/// Maps the hashes of the keys to the nodes that contain them,
+/// so that exact-match lookups only need to search a single node.
+///
+/// The map is only a hint: an entry can point to the wrong node if two keys have the same hash
+/// or if the key has been moved by a split that was not reported.
+/// If the key is not found in the node, the tree needs to be searched from the root node.
+#[derive(Clone, Debug, Default)]
+pub(super) struct HashIndex {
+    nodes: HashMap<u64, u64>,
+}
+
+impl HashIndex {
+    /// Returns the node that probably contains the key with the given hash.
+    pub fn get(&self, hash: u64) -> Option<u64> {
+        self.nodes.get(&hash).copied()
+    }
+
+    /// Remember that the key with the given hash is contained in the node.
+    pub fn insert(&mut self, hash: u64, node_id: u64) {
+        self.nodes.insert(hash, node_id);
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+    }
+
+    /// Forget the keys of all nodes for which the function returns `false`.
+    pub fn retain_nodes<F>(&mut self, f: F)
+    where
+        F: Fn(u64) -> bool,
+    {
+        self.nodes.retain(|_, node_id| f(*node_id));
+    }
+
+    /// Approximate number of bytes used by the hash map.
+    pub fn memory_usage(&self) -> usize {
+        // Each entry of the hash map has a key, a value and a control byte
+        self.nodes.capacity() * (2 * size_of::<u64>() + 1)
+    }
+}
@@ -0,0 +1,35 @@
+/// Remembers the leaf node of the previous lookup, to speed up lookups of nearby keys.
+///
+/// A context can be passed to [`BtreeIndex::get_with_context`](crate::BtreeIndex::get_with_context).
+/// If the searched key lies between the first and the last key of the remembered leaf,
+/// only this leaf is searched instead of descending from the root node.
+/// Otherwise, the index is searched as usual and the context remembers the new leaf.
+/// This helps when looking up keys in (nearly) sorted order.
+///
+/// The context is independent of the thread that executes the lookup, so it can be kept
+/// e.g. by an asynchronous task that moves between threads.
+/// Using a context with a different index or after the index was cleared is allowed,
+/// but the remembered leaf is ignored in this case.
+#[derive(Clone, Debug, Default)]
+pub struct LookupContext {
+    pub(super) leaf: Option<RememberedLeaf>,
+}
+
+/// Leaf node of a specific index instance.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct RememberedLeaf {
+    pub instance: u64,
+    pub node_id: u64,
+}
+
+impl LookupContext {
+    /// Create a new context that does not remember any leaf yet.
+    pub fn new() -> LookupContext {
+        LookupContext::default()
+    }
+
+    /// Forget the remembered leaf, so that the next lookup starts at the root node.
+    pub fn reset(&mut self) {
+        self.leaf = None;
+    }
+}
@@ -1,104 +1,359 @@
+use std::any::TypeId;
+use std::borrow::Borrow;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::ops::{Bound, RangeBounds};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, TryLockError};
 
+use super::{create_fixed_size_file, create_variable_size_file};
+use crate::codec::{Codec, ValueCodec};
 use crate::error::Result;
-use crate::file::{BlockHeader, FixedSizeTupleFile, TupleFile, VariableSizeTupleFile};
-use crate::{create_mmap, BtreeConfig, Error};
-use binary_layout::prelude::*;
-use memmap2::MmapMut;
+use crate::file::{
+    check_fixed_size, BlockHeader, DiscardedPages, FileCheckpoint, FileMemoryUsage,
+    FixedSizeTupleFile, TupleFile, VariableSizeTupleFile,
+};
+use crate::metrics::Metrics;
+use crate::storage::{storage_error, GrowthPolicy, StorageBackend};
+use crate::{BtreeConfig, Error, OrderedF32, OrderedF64};
+use bincode::Options;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
-const NODE_BLOCK_SIZE: usize = 4081;
-const NODE_BLOCK_ALIGNED_SIZE: usize = 4096;
+use self::key_cache::{KeyCache, NodeKeys};
+pub use self::layout::NodeLayout;
+use self::layout::NodeView;
+use self::prefix::{is_byte_string, CompressedKey, MIN_KEY_PREFIX_LEN};
 
-pub const MAX_NUMBER_KEYS: usize = 169;
-const MAX_NUMBER_CHILD_NODES: usize = MAX_NUMBER_KEYS + 1;
+mod key_cache;
+mod layout;
+mod prefix;
 
-// Defines a single BTree node with references to the actual values in a tuple file
-define_layout!(node, LittleEndian, {
-    id: u64,
-    num_keys: u64,
-    is_leaf: u8,
-    keys: [u8; MAX_NUMBER_KEYS*8],
-    payloads: [u8; MAX_NUMBER_KEYS*8],
-    child_nodes: [u8; MAX_NUMBER_CHILD_NODES*8],
-});
+/// Name of the file with the nodes in storage errors.
+const NODE_FILE_NAME: &str = "nodes";
+/// Name of the file with the keys in storage errors.
+const KEY_FILE_NAME: &str = "keys";
 
+/// Marks a node without a common key prefix.
+const NO_KEY_PREFIX: u64 = u64::MAX;
+
+/// File of fixed size node blocks, which each hold the keys, payloads and child nodes of one node.
+///
+/// The keys are stored in a separate file and the nodes only contain their IDs.
+/// Payloads are opaque `u64` values, which the [`BtreeIndex`](crate::BtreeIndex)
+/// uses to reference the values.
 pub struct NodeFile<K> {
     free_space_offset: usize,
-    mmap: MmapMut,
-    keys: Box<dyn TupleFile<K>>,
+    storage: Box<dyn StorageBackend>,
+    growth: GrowthPolicy,
+    metrics: Metrics,
+    layout: NodeLayout,
+    keys: KeyFile<K>,
+    key_cache: Mutex<KeyCache<K>>,
+    integer_keys: Option<IntegerKeyType>,
+    ordered_key_bytes: bool,
+    /// Set for byte-string keys, whose prefix compressed bytes can be compared without decoding them.
+    byte_string_keys: bool,
+    discarded: DiscardedPages,
+    page_size: usize,
+    /// Set between [`NodeFile::checkpoint`] and the commit or rollback of the changes.
+    journal: Option<NodeJournal>,
+}
+
+/// Original content of the nodes that have been changed since a checkpoint,
+/// so that the changes can be rolled back.
+struct NodeJournal {
+    free_space_offset: usize,
+    /// Not set if the file for the keys had not been allocated yet.
+    keys: Option<FileCheckpoint>,
+    nodes: HashMap<u64, Vec<u8>>,
+}
+
+/// Storage for the actual key values referenced by the nodes.
+enum KeyFile<K> {
+    Variable(VariableSizeTupleFile<K>),
+    Fixed(FixedSizeTupleFile<K>),
+    /// Keys are stored as suffix of a common prefix of the node they were inserted into.
+    PrefixCompressed(VariableSizeTupleFile<CompressedKey>),
+    /// Keys with a fixed size of at most [`MAX_INLINE_KEY_SIZE`] bytes are stored directly
+    /// in the node instead of the ID of a key block.
+    Inline {
+        fixed_key_size: usize,
+    },
+    /// No key has been stored yet, so the file is created with the given capacity
+    /// in number of keys when the first key is stored.
+    /// Keys with a variable size are serialized with the codec, if one is given.
+    Unallocated {
+        capacity: usize,
+        config: Box<BtreeConfig>,
+        codec: Option<Arc<dyn Codec<K>>>,
+    },
+}
+
+/// Maximum size of a key that fits into the field for the key ID of a node.
+///
+/// The field is widened from 8 to 16 bytes for keys that are larger than 8 bytes.
+pub const MAX_INLINE_KEY_SIZE: usize = 16;
+
+/// Describes how a primitive integer key is encoded when using a fixed key size.
+///
+/// Such keys can be compared without deserializing them with bincode.
+/// Floating point keys with a total order are compared by their bits like integers.
+#[derive(Clone, Copy)]
+struct IntegerKeyType {
+    width: usize,
+    signed: bool,
+    float: bool,
 }
 
+impl IntegerKeyType {
+    fn for_type<K: 'static>() -> Option<IntegerKeyType> {
+        let t = TypeId::of::<K>();
+        let (width, signed) = if t == TypeId::of::<u8>() {
+            (1, false)
+        } else if t == TypeId::of::<u16>() {
+            (2, false)
+        } else if t == TypeId::of::<u32>() {
+            (4, false)
+        } else if t == TypeId::of::<u64>() || t == TypeId::of::<usize>() {
+            (8, false)
+        } else if t == TypeId::of::<i8>() {
+            (1, true)
+        } else if t == TypeId::of::<i16>() {
+            (2, true)
+        } else if t == TypeId::of::<i32>() {
+            (4, true)
+        } else if t == TypeId::of::<i64>() || t == TypeId::of::<isize>() {
+            (8, true)
+        } else if t == TypeId::of::<u128>() {
+            (16, false)
+        } else if t == TypeId::of::<i128>() {
+            (16, true)
+        } else if t == TypeId::of::<OrderedF32>() {
+            return Some(IntegerKeyType {
+                width: 4,
+                signed: true,
+                float: true,
+            });
+        } else if t == TypeId::of::<OrderedF64>() {
+            return Some(IntegerKeyType {
+                width: 8,
+                signed: true,
+                float: true,
+            });
+        } else {
+            return None;
+        };
+        Some(IntegerKeyType {
+            width,
+            signed,
+            float: false,
+        })
+    }
+
+    /// Serializes the key and decodes it like the stored keys.
+    fn encode<K: Serialize>(&self, key: &K) -> Result<u128> {
+        let mut buffer = [0; 16];
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .serialize_into(&mut buffer[..], key)?;
+        Ok(self.decode(&buffer))
+    }
+
+    /// Decodes the little-endian bytes into an unsigned integer with the same order as the original key.
+    fn decode(&self, bytes: &[u8]) -> u128 {
+        let mut buffer = [0; 16];
+        buffer[0..self.width].copy_from_slice(&bytes[0..self.width]);
+        let value = u128::from_le_bytes(buffer);
+        let sign_bit = 1 << (self.width * 8 - 1);
+        if self.float {
+            // Negative values are ordered by their inverted bits, and positive values
+            // are moved after them by setting the sign bit
+            if value & sign_bit != 0 {
+                !value & (u128::MAX >> (128 - self.width * 8))
+            } else {
+                value | sign_bit
+            }
+        } else if self.signed {
+            // Flip the sign bit, so negative values are ordered before positive ones
+            value ^ sign_bit
+        } else {
+            value
+        }
+    }
+}
+
+/// Result of a binary search for a key inside a node.
 pub enum SearchResult {
+    /// The key is at the given position.
     Found(usize),
+    /// The key is not in the node and would have to be inserted at the given position.
     NotFound(usize),
 }
 
+/// Entry of the stack that is used to traverse the nodes of a range in key order.
 #[derive(Clone)]
 pub enum StackEntry {
+    /// Child node at the position `idx` of the `parent` node.
     Child { parent: u64, idx: usize },
+    /// Key at the position `idx` of the `node`.
     Key { node: u64, idx: usize },
 }
 
+/// Returns the key as the key type if its borrowed form is the key type itself.
+///
+/// This allows to use the optimizations for the key type when searching for a borrowed key.
+pub fn as_key<K, Q>(key: &Q) -> Option<&K>
+where
+    K: 'static,
+    Q: ?Sized + 'static,
+{
+    if TypeId::of::<Q>() == TypeId::of::<K>() {
+        // SAFETY: both types are the same, so `Q` is sized and the reference points to a `K`
+        Some(unsafe { &*(key as *const Q as *const K) })
+    } else {
+        None
+    }
+}
+
 impl<K> NodeFile<K>
 where
-    K: 'static + Serialize + DeserializeOwned + Clone + Ord + Send + Sync,
+    K: 'static + Serialize + DeserializeOwned + Clone + Ord,
 {
     /// Create a new file with the given capacity in number of keys.
+    ///
+    /// The file for the keys is only created when the first key is stored.
     pub fn with_capacity(capacity: usize, config: &BtreeConfig) -> Result<NodeFile<K>> {
+        let layout = config.node_layout();
+
         // Calculate the number of nodes based on the number of keys each node can hold
-        let capacity_in_nodes = num_integer::div_ceil(capacity, MAX_NUMBER_KEYS);
+        let capacity_in_nodes = num_integer::div_ceil(capacity, layout.max_keys);
         let capacity_in_nodes = capacity_in_nodes.max(1);
 
-        // Create an anonymous memory mapped file that can hold the
-        let mmap = create_mmap(capacity_in_nodes * NODE_BLOCK_ALIGNED_SIZE)?;
+        // Create a storage that can hold the nodes
+        let storage = config
+            .create_node_storage(capacity_in_nodes * layout.block_size)
+            .map_err(|e| storage_error(NODE_FILE_NAME, "Creating", e))?;
 
-        // Create a tuple file that can hold the actual key values
-        let keys: Box<dyn TupleFile<K>> = match config.key_size {
-            super::TypeSize::Estimated(est_max_key_size) => {
-                let f = VariableSizeTupleFile::with_capacity(
-                    capacity * (est_max_key_size + BlockHeader::size()),
-                    config.block_cache_size,
-                )?;
-                Box::new(f)
-            }
-            super::TypeSize::Fixed(fixed_key_size) => {
-                let f =
-                    FixedSizeTupleFile::with_capacity(capacity * fixed_key_size, fixed_key_size)?;
-                Box::new(f)
+        // Keys that fit into the node don't need a tuple file
+        let keys = match config.key_size {
+            super::TypeSize::Fixed(fixed_key_size) if fixed_key_size <= MAX_INLINE_KEY_SIZE => {
+                KeyFile::Inline { fixed_key_size }
             }
+            _ => KeyFile::Unallocated {
+                capacity,
+                config: Box::new(config.clone()),
+                codec: None,
+            },
+        };
+
+        // Check if we can compare the raw bytes of primitive integer keys
+        let integer_keys = match config.key_size {
+            super::TypeSize::Fixed(fixed_key_size) => IntegerKeyType::for_type::<K>()
+                .filter(|integer_keys| integer_keys.width == fixed_key_size),
+            super::TypeSize::Estimated(_) => None,
         };
 
         Ok(NodeFile {
-            mmap,
+            storage,
+            growth: config.growth,
+            metrics: config.metrics.clone(),
+            layout,
             keys,
+            key_cache: Mutex::new(KeyCache::with_max_nodes(
+                config.node_key_cache_size,
+                config.leaf_key_cache_size,
+            )),
+            integer_keys,
+            ordered_key_bytes: false,
+            byte_string_keys: is_byte_string::<K>(),
             free_space_offset: 0,
+            discarded: DiscardedPages::default(),
+            page_size: config.page_size,
+            journal: None,
         })
     }
+
+    /// Search for a key that is given as a borrowed form of the key type, e.g. a `str` for `String` keys.
+    ///
+    /// If the borrowed form is the key type itself, the same optimized searches as in
+    /// [`Self::binary_search`] are used, otherwise the decoded keys of the node are compared.
+    pub fn binary_search_borrowed<Q>(&self, node_id: u64, key: &Q) -> Result<SearchResult>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized + 'static,
+    {
+        if let Some(key) = as_key::<K, Q>(key) {
+            self.binary_search(node_id, key)
+        } else {
+            self.search_decoded_keys(node_id, key)
+        }
+    }
+
+    /// Finds all children and keys that are inside a range with bounds of a borrowed form of the key type
+    /// in the sub-tree of the given node.
+    ///
+    /// Unlike [`Self::find_range`], the child nodes that contain the start or end bound,
+    /// and therefore might contain keys outside the range, are searched immediately for the bound.
+    /// All child nodes in the result only contain keys inside the range, so they can be searched
+    /// without the range later.
+    pub fn find_range_borrowed<Q, R>(&self, node_id: u64, range: &R) -> Result<Vec<StackEntry>>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized + 'static,
+        R: RangeBounds<Q>,
+    {
+        let entries = self.find_range_by(node_id, range, |node_id, key| {
+            self.binary_search_borrowed(node_id, key)
+        });
+        let last = entries.len().saturating_sub(1);
+        let mut result = Vec::with_capacity(entries.len());
+        for (i, e) in entries.into_iter().enumerate() {
+            // Only the first child can contain the start bound and only the last child the end bound
+            let start = if i == 0 {
+                range.start_bound()
+            } else {
+                Bound::Unbounded
+            };
+            let end = if i == last {
+                range.end_bound()
+            } else {
+                Bound::Unbounded
+            };
+            match e {
+                StackEntry::Child { parent, idx }
+                    if !matches!((start, end), (Bound::Unbounded, Bound::Unbounded)) =>
+                {
+                    let child_id = self.get_child_node(parent, idx)?;
+                    result.extend(self.find_range_borrowed(child_id, &(start, end))?);
+                }
+                e => result.push(e),
+            }
+        }
+        Ok(result)
+    }
 }
 
-impl<'a, K> NodeFile<K>
+impl<K> NodeFile<K>
 where
-    K: Serialize + DeserializeOwned + Clone + Ord + Send + Sync,
+    K: Serialize + DeserializeOwned + Clone + Ord,
 {
     /// Allocate a new node.
     ///
     /// Returns the ID of the new node.
     pub fn allocate_new_node(&mut self) -> Result<u64> {
         // Make sure we still have enough space left
-        let new_offset = self.free_space_offset + NODE_BLOCK_ALIGNED_SIZE;
+        let new_offset = self.free_space_offset + self.layout.block_size;
         self.grow(new_offset)?;
 
         // Return the old start of free space as block index
-        let result: u64 = (self.free_space_offset / NODE_BLOCK_ALIGNED_SIZE).try_into()?;
+        let result: u64 = (self.free_space_offset / self.layout.block_size).try_into()?;
+        self.invalidate_cached_keys(result)?;
 
         // Initialize some of the values
-        self.get_mut(result)?.id_mut().write(result);
-        self.get_mut(result)?.num_keys_mut().write(0);
-        self.get_mut(result)?.is_leaf_mut().write(1);
+        self.get_mut(result)?.set_id(result);
+        self.get_mut(result)?.set_num_keys(0);
+        self.get_mut(result)?.set_is_leaf(true);
+        self.get_mut(result)?.set_key_prefix(NO_KEY_PREFIX);
 
         // The next free block can be added after this block
         self.free_space_offset = new_offset;
@@ -107,7 +362,14 @@ where
 
     pub fn number_of_keys(&self, node_id: u64) -> Result<usize> {
         let view = self.get(node_id)?;
-        Ok(view.num_keys().read() as usize)
+        let n = view.num_keys() as usize;
+        if n > self.layout.max_keys {
+            return Err(Error::IntegrityViolation(format!(
+                "node {node_id} has {n} keys, but can only hold {}",
+                self.layout.max_keys
+            )));
+        }
+        Ok(n)
     }
 
     pub fn number_of_children(&self, node_id: u64) -> Result<usize> {
@@ -120,19 +382,35 @@ where
 
     pub fn is_leaf(&self, node_id: u64) -> Result<bool> {
         let view = self.get(node_id)?;
-        Ok(view.is_leaf().read() != 0)
+        Ok(view.is_leaf())
     }
 
     /// Finds all children and keys that are inside the range
     pub fn find_range<R>(&self, node_id: u64, range: R) -> Vec<StackEntry>
     where
         R: RangeBounds<K>,
+    {
+        self.find_range_by(node_id, &range, |node_id, key| {
+            self.binary_search(node_id, key)
+        })
+    }
+
+    /// Finds all children and keys that are inside the range and uses the given function
+    /// to search for the start of the range in a node.
+    fn find_range_by<Q, R, S>(&self, node_id: u64, range: &R, search: S) -> Vec<StackEntry>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+        S: Fn(u64, &Q) -> Result<SearchResult>,
     {
         let mut result: Vec<StackEntry> =
             Vec::with_capacity(2 * (self.number_of_keys(node_id).unwrap_or(1024) + 1));
 
         // Get first matching item for both the key and children list
-        let mut candidate = self.find_first_candidate(node_id, range.start_bound()).ok();
+        let mut candidate = self
+            .find_first_candidate(node_id, range.start_bound(), search)
+            .ok();
 
         // Iterate over all remaining children and keys but stop when end range is reached
         while let Some(item) = candidate {
@@ -145,14 +423,14 @@ where
                 StackEntry::Key { node, idx } => match range.end_bound() {
                     Bound::Included(end) => {
                         if let Ok(key) = self.get_key_owned(*node, *idx) {
-                            &key <= end
+                            key.borrow() <= end
                         } else {
                             false
                         }
                     }
                     Bound::Excluded(end) => {
                         if let Ok(key) = self.get_key_owned(*node, *idx) {
-                            &key < end
+                            key.borrow() < end
                         } else {
                             false
                         }
@@ -186,10 +464,19 @@ where
         result
     }
 
-    fn find_first_candidate(&self, node_id: u64, start_bound: Bound<&K>) -> Result<StackEntry> {
+    fn find_first_candidate<Q, S>(
+        &self,
+        node_id: u64,
+        start_bound: Bound<&Q>,
+        search: S,
+    ) -> Result<StackEntry>
+    where
+        Q: ?Sized,
+        S: Fn(u64, &Q) -> Result<SearchResult>,
+    {
         let result = match start_bound {
             Bound::Included(key) => {
-                let key_pos = self.binary_search(node_id, key)?;
+                let key_pos = search(node_id, key)?;
                 match &key_pos {
                     // Key was found, start at this position
                     SearchResult::Found(i) => StackEntry::Key {
@@ -218,7 +505,7 @@ where
                 }
             }
             Bound::Excluded(key) => {
-                let key_pos = self.binary_search(node_id, key)?;
+                let key_pos = search(node_id, key)?;
                 match &key_pos {
                     // Key was found, start at child or key after the key
                     SearchResult::Found(i) => {
@@ -273,54 +560,38 @@ where
 
     /// Get a block with the given id give ownership of the result to the caller.
     pub fn get_key_owned(&self, node_id: u64, i: usize) -> Result<K> {
-        let view = self.get(node_id)?;
-        let n: usize = view.num_keys().read() as usize;
-        if i < n && i < MAX_NUMBER_KEYS {
-            let offset = i * 8;
-            let key_id: u64 =
-                u64::from_le_bytes(view.keys().data()[offset..(offset + 8)].try_into()?);
-            let result = self.keys.get_owned(key_id.try_into()?)?;
-            Ok(result)
-        } else {
-            Err(Error::KeyIndexOutOfBounds { idx: i, len: n })
-        }
+        let key_id = self.get_key_id(node_id, i)?;
+        self.keys.get_owned(key_id)
     }
 
     pub fn get_key(&self, node_id: u64, i: usize) -> Result<Arc<K>> {
         let key_id = self.get_key_id(node_id, i)?;
-        let result = self.keys.get(key_id.try_into()?)?;
-        Ok(result)
+        self.keys.get(key_id)
     }
 
-    pub fn get_key_id(&self, node_id: u64, i: usize) -> Result<u64> {
+    pub fn get_key_id(&self, node_id: u64, i: usize) -> Result<u128> {
         let view = self.get(node_id)?;
-        let n: usize = view.num_keys().read() as usize;
-        if i < n && i < MAX_NUMBER_KEYS {
-            let offset = i * 8;
-            let key_id: u64 =
-                u64::from_le_bytes(view.keys().data()[offset..(offset + 8)].try_into()?);
-            Ok(key_id)
+        let n: usize = view.num_keys() as usize;
+        if i < n && i < self.layout.max_keys {
+            Ok(view.key_id(i))
         } else {
             Err(Error::KeyIndexOutOfBounds { idx: i, len: n })
         }
     }
 
     /// Sets the key for the given index `i` in the node `node_id`.
-    pub fn set_key_id(&mut self, node_id: u64, i: usize, key_id: u64) -> Result<()> {
-        let n: usize = self.get(node_id)?.num_keys().read() as usize;
-        if i <= n && i < MAX_NUMBER_KEYS {
-            let offset = i * 8;
-
-            let key_id = key_id.to_le_bytes();
+    pub fn set_key_id(&mut self, node_id: u64, i: usize, key_id: u128) -> Result<()> {
+        let n: usize = self.get(node_id)?.num_keys() as usize;
+        if i <= n && i < self.layout.max_keys {
+            self.invalidate_cached_keys(node_id)?;
             let mut view = self.get_mut(node_id)?;
-
-            view.keys_mut().data_mut()[offset..(offset + 8)].copy_from_slice(&key_id);
+            view.set_key_id(i, key_id);
 
             if i == n {
                 // The key was inserted at the end of the list
                 let mut view = self.get_mut(node_id)?;
                 let n: u64 = (n + 1).try_into()?;
-                view.num_keys_mut().write(n);
+                view.set_num_keys(n);
             }
             Ok(())
         } else {
@@ -331,24 +602,30 @@ where
     /// Sets the key value for the given index `i` in the node `node_id`.
     /// This will allocate a new block for the key.
     pub fn set_key_value(&mut self, node_id: u64, i: usize, key: &K) -> Result<()> {
-        let n: usize = self.get(node_id)?.num_keys().read() as usize;
-        if i <= n && i < MAX_NUMBER_KEYS {
-            let offset = i * 8;
-            let key_size: usize = self.keys.serialized_size(key)?.try_into()?;
-            let key_id = self.keys.allocate_block(key_size)?;
-            self.keys.put(key_id, key)?;
-
-            let key_id: u64 = key_id.try_into()?;
-            let key_id = key_id.to_le_bytes();
+        let n: usize = self.get(node_id)?.num_keys() as usize;
+        if i <= n && i < self.layout.max_keys {
+            self.invalidate_cached_keys(node_id)?;
+            // A neighboring key is needed to find a common prefix for nodes without one
+            let node_prefix = self.get(node_id)?.key_prefix();
+            let neighbor = if i > 0 {
+                Some(self.get_key_id(node_id, i - 1)?)
+            } else if i < n {
+                Some(self.get_key_id(node_id, i)?)
+            } else {
+                None
+            };
+            let (key_id, key_prefix) = self.keys.store(key, node_prefix, neighbor)?;
+            if key_prefix != node_prefix {
+                self.get_mut(node_id)?.set_key_prefix(key_prefix);
+            }
             let mut view = self.get_mut(node_id)?;
-
-            view.keys_mut().data_mut()[offset..(offset + 8)].copy_from_slice(&key_id);
+            view.set_key_id(i, key_id);
 
             if i == n {
                 // The key was inserted at the end of the list
                 let mut view = self.get_mut(node_id)?;
                 let n: u64 = (n + 1).try_into()?;
-                view.num_keys_mut().write(n);
+                view.set_num_keys(n);
             }
             Ok(())
         } else {
@@ -358,24 +635,40 @@ where
 
     pub fn get_payload(&self, node_id: u64, i: usize) -> Result<u64> {
         let view = self.get(node_id)?;
-        let n: usize = view.num_keys().read() as usize;
-        if i < n && i < MAX_NUMBER_KEYS {
+        let n: usize = view.num_keys() as usize;
+        if i < n && i < self.layout.max_keys {
             let offset = i * 8;
-            let result: u64 =
-                u64::from_le_bytes(view.payloads().data()[offset..(offset + 8)].try_into()?);
+            let result: u64 = u64::from_le_bytes(view.payloads()[offset..(offset + 8)].try_into()?);
             Ok(result)
         } else {
             Err(Error::KeyIndexOutOfBounds { idx: i, len: n })
         }
     }
 
+    /// Returns whether the key at the given position has been removed.
+    pub fn is_removed(&self, node_id: u64, i: usize) -> Result<bool> {
+        Ok(super::payload::is_tombstone(self.get_payload(node_id, i)?))
+    }
+
+    /// Get the number of keys of the node that have not been removed.
+    pub fn number_of_entries(&self, node_id: u64) -> Result<usize> {
+        let mut result = 0;
+        for i in 0..self.number_of_keys(node_id)? {
+            if !self.is_removed(node_id, i)? {
+                result += 1;
+            }
+        }
+        Ok(result)
+    }
+
     pub fn set_payload(&mut self, node_id: u64, i: usize, value: u64) -> Result<()> {
+        let max_keys = self.layout.max_keys;
         let mut view = self.get_mut(node_id)?;
-        let n: usize = view.num_keys().read() as usize;
-        if i < n && i < MAX_NUMBER_KEYS {
+        let n: usize = view.num_keys() as usize;
+        if i < n && i < max_keys {
             let offset = i * 8;
             let value = value.to_le_bytes();
-            view.payloads_mut().data_mut()[offset..(offset + 8)].copy_from_slice(&value);
+            view.payloads_mut()[offset..(offset + 8)].copy_from_slice(&value);
             Ok(())
         } else {
             Err(Error::KeyIndexOutOfBounds { idx: i, len: n })
@@ -384,12 +677,12 @@ where
 
     pub fn get_child_node(&self, node_id: u64, i: usize) -> Result<u64> {
         let view = self.get(node_id)?;
-        let n: usize = view.num_keys().read() as usize;
-        let has_children: bool = view.is_leaf().read() == 0;
-        if has_children && i < (n + 1) && i < MAX_NUMBER_CHILD_NODES {
+        let n: usize = view.num_keys() as usize;
+        let has_children: bool = !view.is_leaf();
+        if has_children && i < (n + 1) && i < self.layout.max_child_nodes() {
             let offset = i * 8;
             let result: u64 =
-                u64::from_le_bytes(view.child_nodes().data()[offset..(offset + 8)].try_into()?);
+                u64::from_le_bytes(view.child_nodes()[offset..(offset + 8)].try_into()?);
             Ok(result)
         } else {
             Err(Error::KeyIndexOutOfBounds { idx: i, len: n })
@@ -397,34 +690,195 @@ where
     }
 
     pub fn set_child_node(&mut self, node_id: u64, i: usize, value: u64) -> Result<()> {
+        let max_child_nodes = self.layout.max_child_nodes();
         let mut view = self.get_mut(node_id)?;
-        let has_children: bool = view.is_leaf().read() == 0;
+        let has_children: bool = !view.is_leaf();
         let n: usize = if has_children {
-            (view.num_keys().read() as usize) + 1
+            (view.num_keys() as usize) + 1
         } else {
             0
         };
 
-        if i <= n && i < MAX_NUMBER_CHILD_NODES {
+        if i <= n && i < max_child_nodes {
             let offset = i * 8;
             let value = value.to_le_bytes();
-            view.child_nodes_mut().data_mut()[offset..(offset + 8)].copy_from_slice(&value);
-            view.is_leaf_mut().write(0);
+            view.child_nodes_mut()[offset..(offset + 8)].copy_from_slice(&value);
+            view.set_is_leaf(false);
             Ok(())
         } else {
             Err(Error::KeyIndexOutOfBounds { idx: i, len: n })
         }
     }
 
+    /// Returns whether a node with the given ID was allocated.
+    pub fn contains_node(&self, node_id: u64) -> bool {
+        usize::try_from(node_id)
+            .map(|node_id| node_id < self.free_space_offset / self.layout.block_size)
+            .unwrap_or(false)
+    }
+
+    /// Returns whether the number of entries in the sub-tree of each child node is stored.
+    pub fn has_child_counts(&self) -> bool {
+        self.layout.child_counts
+    }
+
+    /// Get the number of entries in the sub-tree of the child node at index `i`.
+    pub fn get_child_count(&self, node_id: u64, i: usize) -> Result<u64> {
+        let view = self.get(node_id)?;
+        let n: usize = view.num_keys() as usize;
+        if self.layout.child_counts && !view.is_leaf() && i < (n + 1) {
+            let offset = i * 8;
+            let result: u64 =
+                u64::from_le_bytes(view.child_counts()[offset..(offset + 8)].try_into()?);
+            Ok(result)
+        } else {
+            Err(Error::KeyIndexOutOfBounds { idx: i, len: n })
+        }
+    }
+
+    /// Set the number of entries in the sub-tree of the child node at index `i`.
+    ///
+    /// Does nothing if the child counts are not stored.
+    pub fn set_child_count(&mut self, node_id: u64, i: usize, count: u64) -> Result<()> {
+        if !self.layout.child_counts {
+            return Ok(());
+        }
+        let max_child_nodes = self.layout.max_child_nodes();
+        let mut view = self.get_mut(node_id)?;
+        if i < max_child_nodes {
+            let offset = i * 8;
+            view.child_counts_mut()[offset..(offset + 8)].copy_from_slice(&count.to_le_bytes());
+            Ok(())
+        } else {
+            Err(Error::KeyIndexOutOfBounds {
+                idx: i,
+                len: max_child_nodes,
+            })
+        }
+    }
+
+    /// Get the number of entries in the sub-tree starting with the given node.
+    ///
+    /// Removed keys are not counted.
+    pub fn subtree_count(&self, node_id: u64) -> Result<u64> {
+        let mut result: u64 = self.number_of_entries(node_id)?.try_into()?;
+        for i in 0..self.number_of_children(node_id)? {
+            result += self.get_child_count(node_id, i)?;
+        }
+        Ok(result)
+    }
+
+    /// Compare the serialized bytes of the keys instead of deserializing them when searching.
+    ///
+    /// This is only correct if the keys implement [`OrderedBytes`](crate::OrderedBytes).
+    /// Encrypted keys can't be compared without decrypting them, so this has no effect for them.
+    pub fn enable_ordered_key_bytes(&mut self) {
+        self.ordered_key_bytes = match &self.keys {
+            KeyFile::Variable(keys) => !keys.is_encrypted(),
+            KeyFile::Fixed(keys) => !keys.is_encrypted(),
+            KeyFile::PrefixCompressed(_) | KeyFile::Inline { .. } => true,
+            // Prefix compressed keys are compared by deserializing them anyway
+            KeyFile::Unallocated { config, .. } => {
+                config.key_prefix_compression || !config.is_encrypted()
+            }
+        };
+    }
+
+    /// Serialize keys with a variable size with the given codec instead of bincode.
+    ///
+    /// This has no effect once the first key has been stored, and for keys with a fixed size
+    /// or with a compressed prefix.
+    pub fn set_key_codec(&mut self, key_codec: Arc<dyn Codec<K>>) {
+        if let KeyFile::Unallocated { codec, .. } = &mut self.keys {
+            *codec = Some(key_codec);
+        }
+    }
+
+    /// Get the codec for keys with a variable size, if it can be configured for the keys.
+    pub fn key_codec(&self) -> Option<Arc<dyn Codec<K>>> {
+        match &self.keys {
+            KeyFile::Variable(keys) => Some(keys.shared_codec()),
+            KeyFile::Unallocated { codec, .. } => codec.clone(),
+            KeyFile::Fixed(_) | KeyFile::PrefixCompressed(_) | KeyFile::Inline { .. } => None,
+        }
+    }
+
+    /// Get the number of bytes needed to serialize the key with the configured key codec.
+    pub fn key_serialized_size(&self, key: &K) -> Result<u64> {
+        match &self.keys {
+            KeyFile::Variable(keys) => keys.serialized_size(key),
+            KeyFile::Fixed(keys) => keys.serialized_size(key),
+            KeyFile::Unallocated {
+                codec: Some(codec), ..
+            } => codec.serialized_size(key),
+            KeyFile::PrefixCompressed(_) | KeyFile::Inline { .. } | KeyFile::Unallocated { .. } => {
+                ValueCodec::default().serialized_size(key)
+            }
+        }
+    }
+
+    pub fn has_ordered_key_bytes(&self) -> bool {
+        self.ordered_key_bytes
+    }
+
     pub fn binary_search(&self, node_id: u64, key: &K) -> Result<SearchResult> {
-        let mut size = self.number_of_keys(node_id).unwrap_or(0);
+        match (&self.keys, self.integer_keys) {
+            (KeyFile::Inline { .. }, Some(integer_keys)) => {
+                return self.integer_search(node_id, key, integer_keys);
+            }
+            (KeyFile::PrefixCompressed(keys), _) if self.byte_string_keys => {
+                let (_, needle) =
+                    prefix::strip_length_header(bincode::DefaultOptions::new().serialize(key)?);
+                return self.compressed_search(node_id, keys, &needle);
+            }
+            _ => {}
+        }
+        if self.ordered_key_bytes {
+            match &self.keys {
+                KeyFile::Variable(keys) => {
+                    let needle = bincode::DefaultOptions::new().serialize(key)?;
+                    return self.bytes_search(node_id, &needle, |key_id| keys.get_raw(key_id));
+                }
+                KeyFile::Fixed(keys) => {
+                    let needle = bincode::DefaultOptions::new()
+                        .with_fixint_encoding()
+                        .serialize(key)?;
+                    // The stored keys always have the fixed size, so a needle with a
+                    // different size would be compared with the wrong bytes
+                    if needle.len() != keys.fixed_tuple_size() {
+                        return Err(Error::FixedSizeMismatch {
+                            expected: keys.fixed_tuple_size(),
+                            actual: needle.len(),
+                        });
+                    }
+                    return self.bytes_search(node_id, &needle, |key_id| keys.get_raw(key_id));
+                }
+                // Other prefix compressed keys are compared by deserializing them,
+                // which is cheap for inlined keys
+                KeyFile::PrefixCompressed(_)
+                | KeyFile::Inline { .. }
+                | KeyFile::Unallocated { .. } => {}
+            }
+        }
+        self.search_decoded_keys(node_id, key)
+    }
+
+    /// Binary search that compares the decoded keys of the node, which are cached if possible.
+    fn search_decoded_keys<Q>(&self, node_id: u64, key: &Q) -> Result<SearchResult>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut size = self.number_of_keys(node_id)?;
+        let cached_keys = self.cached_keys(node_id, size)?;
         let mut left = 0;
         let mut right = size;
         while left < right {
             let mid = left + size / 2;
 
-            let mid_key = self.get_key_owned(node_id, mid)?;
-            let cmp = mid_key.cmp(key);
+            let mid_key =
+                self.get_cached_key(node_id, mid, cached_keys.as_ref().map(|k| k.as_slice()))?;
+            let cmp = mid_key.as_ref().borrow().cmp(key);
 
             if cmp == Ordering::Less {
                 left = mid + 1;
@@ -439,6 +893,163 @@ where
         Ok(SearchResult::NotFound(left))
     }
 
+    /// Get the entry of the node in the cache of decoded keys, unless the cache is disabled or in use.
+    fn cached_keys(&self, node_id: u64, number_of_keys: usize) -> Result<Option<NodeKeys<K>>> {
+        let is_leaf = self.is_leaf(node_id)?;
+        let mut key_cache = match self.key_cache.try_lock() {
+            Ok(key_cache) => key_cache,
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Poisoned(_)) => return Err(Error::PoisonedCache),
+        };
+        Ok(key_cache.get_or_insert(node_id, is_leaf, number_of_keys))
+    }
+
+    /// Get the key from the cached keys of the node, and add it to them if it was not decoded before.
+    fn get_cached_key(
+        &self,
+        node_id: u64,
+        i: usize,
+        cached_keys: Option<&[OnceLock<Arc<K>>]>,
+    ) -> Result<Arc<K>> {
+        if let Some(cached) = cached_keys.and_then(|keys| keys.get(i)) {
+            if let Some(key) = cached.get() {
+                return Ok(key.clone());
+            }
+            let key = self.get_key(node_id, i)?;
+            // Another thread might have decoded the same key in the meantime, which is fine
+            let _ = cached.set(key.clone());
+            Ok(key)
+        } else {
+            self.get_key(node_id, i)
+        }
+    }
+
+    /// Remove the decoded keys of the node from the cache before its keys are changed.
+    fn invalidate_cached_keys(&mut self, node_id: u64) -> Result<()> {
+        self.key_cache
+            .get_mut()
+            .map_err(|_| Error::PoisonedCache)?
+            .invalidate(node_id);
+        Ok(())
+    }
+
+    /// Binary search that compares the serialized bytes of the keys.
+    fn bytes_search<'a, F>(
+        &'a self,
+        node_id: u64,
+        needle: &[u8],
+        get_raw: F,
+    ) -> Result<SearchResult>
+    where
+        F: Fn(usize) -> Result<&'a [u8]>,
+    {
+        self.binary_search_by(node_id, |key_id| {
+            Ok(get_raw(key_id.try_into()?)?.cmp(needle))
+        })
+    }
+
+    /// Binary search with a function that compares the key with the given ID to the searched key.
+    fn binary_search_by<F>(&self, node_id: u64, compare: F) -> Result<SearchResult>
+    where
+        F: Fn(u128) -> Result<Ordering>,
+    {
+        let mut size = self.number_of_keys(node_id)?;
+        let mut left = 0;
+        let mut right = size;
+        while left < right {
+            let mid = left + size / 2;
+
+            match compare(self.get_key_id(node_id, mid)?)? {
+                Ordering::Less => left = mid + 1,
+                Ordering::Greater => right = mid,
+                Ordering::Equal => return Ok(SearchResult::Found(mid)),
+            }
+
+            size = right - left;
+        }
+        Ok(SearchResult::NotFound(left))
+    }
+
+    /// Search for prefix compressed byte-string keys that compares the prefix of the node
+    /// with the searched bytes only once and then only the suffixes of the keys with this prefix.
+    fn compressed_search(
+        &self,
+        node_id: u64,
+        keys: &VariableSizeTupleFile<CompressedKey>,
+        needle: &[u8],
+    ) -> Result<SearchResult> {
+        let node_prefix = self.get(node_id)?.key_prefix();
+        let prefix = if node_prefix == NO_KEY_PREFIX {
+            None
+        } else {
+            Some(keys.get(node_prefix.try_into()?)?)
+        };
+        // If the searched bytes do not start with the prefix, they have the same order
+        // to all keys with the prefix as to the prefix itself
+        let (prefix_order, needle_suffix) = match &prefix {
+            Some(prefix) if needle.starts_with(&prefix.suffix) => {
+                (None, &needle[prefix.suffix.len()..])
+            }
+            Some(prefix) => (Some(prefix.suffix.as_slice().cmp(needle)), needle),
+            None => (None, needle),
+        };
+        self.binary_search_by(node_id, |key_id| {
+            let serialized = keys.get_serialized(key_id.try_into()?)?;
+            let (key_prefix, key_suffix) =
+                prefix::split_serialized(&serialized).ok_or_else(|| {
+                    Error::IntegrityViolation(format!("invalid prefix compressed key {key_id}"))
+                })?;
+            if prefix.is_some() && key_prefix == Some(node_prefix) {
+                Ok(prefix_order.unwrap_or_else(|| key_suffix.cmp(needle_suffix)))
+            } else {
+                // Keys inserted before the prefix of the node changed might have another prefix
+                let (_, bytes) = compressed_key_bytes(keys, key_id)?;
+                Ok(bytes.as_slice().cmp(needle))
+            }
+        })
+    }
+
+    /// Search for primitive integer keys that are stored inside the node by scanning the raw bytes
+    /// of the key ID fields instead of deserializing them.
+    fn integer_search(
+        &self,
+        node_id: u64,
+        key: &K,
+        integer_keys: IntegerKeyType,
+    ) -> Result<SearchResult> {
+        let needle = integer_keys.encode(key)?;
+
+        let n = self.number_of_keys(node_id)?;
+        let view = self.get(node_id)?;
+        let key_size = self.layout.key_size;
+
+        // Decode the keys in chunks and count all smaller keys without branching,
+        // which can be vectorized by the compiler
+        let mut pos = 0;
+        let mut node_keys = [0; 64];
+        for chunk in view.keys()[0..(n * key_size)].chunks(node_keys.len() * key_size) {
+            let chunk_len = chunk.len() / key_size;
+            for (i, key_id) in chunk.chunks_exact(key_size).enumerate() {
+                node_keys[i] = integer_keys.decode(key_id);
+            }
+            let smaller: usize = node_keys[0..chunk_len]
+                .iter()
+                .map(|k| (*k < needle) as usize)
+                .sum();
+            pos += smaller;
+            if smaller < chunk_len {
+                // All remaining keys are larger
+                break;
+            }
+        }
+
+        if pos < n && integer_keys.decode(&view.keys()[(pos * key_size)..]) == needle {
+            Ok(SearchResult::Found(pos))
+        } else {
+            Ok(SearchResult::NotFound(pos))
+        }
+    }
+
     pub fn split_child(
         &mut self,
         parent_node_id: u64,
@@ -453,10 +1064,9 @@ where
         // use it as the key for the parent node
         let split_key = self.get_key_id(existing_node, split_at - 1)?;
         let split_payload = self.get_payload(existing_node, split_at - 1)?;
+        self.invalidate_cached_keys(existing_node)?;
         let mut existing_node_view = self.get_mut(existing_node)?;
-        existing_node_view
-            .num_keys_mut()
-            .write((split_at - 1).try_into()?);
+        existing_node_view.set_num_keys((split_at - 1).try_into()?);
 
         // Make space for the new entry in the parent node
         for i in ((child_idx + 1)..=self.number_of_keys(parent_node_id)?).rev() {
@@ -469,12 +1079,31 @@ where
                 i,
                 self.get_child_node(parent_node_id, i - 1)?,
             )?;
+            if self.layout.child_counts {
+                self.set_child_count(
+                    parent_node_id,
+                    i,
+                    self.get_child_count(parent_node_id, i - 1)?,
+                )?;
+            }
         }
 
         // Insert the new child entry, the key and the payload into the parent node
         self.set_key_id(parent_node_id, child_idx, split_key)?;
         self.set_payload(parent_node_id, child_idx, split_payload)?;
         self.set_child_node(parent_node_id, child_idx + 1, new_node_id)?;
+        if self.layout.child_counts {
+            self.set_child_count(
+                parent_node_id,
+                child_idx,
+                self.subtree_count(existing_node)?,
+            )?;
+            self.set_child_count(
+                parent_node_id,
+                child_idx + 1,
+                self.subtree_count(new_node_id)?,
+            )?;
+        }
 
         Ok((existing_node, new_node_id))
     }
@@ -488,16 +1117,19 @@ where
         // use it as the key for the parent node
         let split_key = self.get_key_id(old_root_id, split_at - 1)?;
         let split_payload = self.get_payload(old_root_id, split_at - 1)?;
+        self.invalidate_cached_keys(old_root_id)?;
         let mut existing_node_view = self.get_mut(old_root_id)?;
-        existing_node_view
-            .num_keys_mut()
-            .write((split_at - 1).try_into()?);
+        existing_node_view.set_num_keys((split_at - 1).try_into()?);
 
         // Insert the new child entry, the key and the payload into the parent node
         self.set_key_id(new_root_id, 0, split_key)?;
         self.set_payload(new_root_id, 0, split_payload)?;
         self.set_child_node(new_root_id, 0, old_root_id)?;
         self.set_child_node(new_root_id, 1, new_node_id)?;
+        if self.layout.child_counts {
+            self.set_child_count(new_root_id, 0, self.subtree_count(old_root_id)?)?;
+            self.set_child_count(new_root_id, 1, self.subtree_count(new_node_id)?)?;
+        }
 
         Ok(new_root_id)
     }
@@ -528,12 +1160,25 @@ where
                         i - split_at,
                         self.get_child_node(source_node_id, i)?,
                     )?;
+                    if self.layout.child_counts {
+                        self.set_child_count(
+                            target_node_id,
+                            i - split_at,
+                            self.get_child_count(source_node_id, i)?,
+                        )?;
+                    }
                 }
             }
 
+            // The keys of the new node share the same prefix
+            let key_prefix = self.get(source_node_id)?.key_prefix();
+            self.get_mut(target_node_id)?.set_key_prefix(key_prefix);
+
             // Clip the size of keys in the source node
+            self.invalidate_cached_keys(source_node_id)?;
             let mut source_node_view = self.get_mut(source_node_id)?;
-            source_node_view.num_keys_mut().write(split_at.try_into()?);
+            source_node_view.set_num_keys(split_at.try_into()?);
+            self.metrics.split(source_node_id, target_node_id);
             Ok(target_node_id)
         } else {
             Err(Error::KeyIndexOutOfBounds {
@@ -543,40 +1188,459 @@ where
         }
     }
 
-    fn get(&self, node_id: u64) -> Result<node::View<&[u8]>> {
-        let node_id: usize = node_id.try_into()?;
-        let offset: usize = NODE_BLOCK_ALIGNED_SIZE * node_id;
-        let view = node::View::new(&self.mmap[offset..(offset + NODE_BLOCK_SIZE)]);
+    /// Get the number of bytes used by the storage for the nodes.
+    pub fn mapped_bytes(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Get the number of bytes occupied by the allocated nodes.
+    pub fn used_bytes(&self) -> usize {
+        self.free_space_offset
+    }
+
+    /// Replace the payloads of all nodes that reference a relocated block with the ID of the new block,
+    /// which is returned by the given function.
+    pub fn update_payloads<F>(&mut self, resolve_block: F) -> Result<()>
+    where
+        F: Fn(usize) -> Result<usize>,
+    {
+        let number_of_nodes: u64 = (self.free_space_offset / self.layout.block_size).try_into()?;
+        for node_id in 0..number_of_nodes {
+            for i in 0..self.number_of_keys(node_id)? {
+                let payload = self.get_payload(node_id, i)?;
+                if super::payload::is_inline(payload) {
+                    continue;
+                }
+                let block_id: usize = payload.try_into()?;
+                let new_block_id = resolve_block(block_id)?;
+                if new_block_id != block_id {
+                    self.set_payload(node_id, i, new_block_id.try_into()?)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Remember the current nodes and keys, so that all following changes can be
+    /// [rolled back](Self::rollback) until they are [committed](Self::commit).
+    ///
+    /// Existing key blocks are never changed, so only the allocation state of the key file
+    /// is needed, but the original content of each existing node is copied before it is changed.
+    pub fn checkpoint(&mut self) {
+        let keys = match &self.keys {
+            KeyFile::Variable(keys) => Some(keys.checkpoint()),
+            KeyFile::Fixed(keys) => Some(keys.checkpoint()),
+            KeyFile::PrefixCompressed(keys) => Some(keys.checkpoint()),
+            KeyFile::Inline { .. } | KeyFile::Unallocated { .. } => None,
+        };
+        self.journal = Some(NodeJournal {
+            free_space_offset: self.free_space_offset,
+            keys,
+            nodes: HashMap::new(),
+        });
+    }
+
+    /// Keep the changes since the last [checkpoint](Self::checkpoint).
+    pub fn commit(&mut self) {
+        self.journal = None;
+    }
+
+    /// Restore the nodes that have been changed since the last [checkpoint](Self::checkpoint)
+    /// and remove the nodes and keys that have been allocated since then.
+    pub fn rollback(&mut self) -> Result<()> {
+        let Some(journal) = self.journal.take() else {
+            return Ok(());
+        };
+        for (node_id, content) in journal.nodes {
+            let block = self.node_block(node_id)?;
+            self.storage.as_mut_slice()[block].copy_from_slice(&content);
+        }
+        self.free_space_offset = journal.free_space_offset;
+        match (&mut self.keys, journal.keys) {
+            (KeyFile::Variable(keys), Some(checkpoint)) => keys.rollback(checkpoint)?,
+            (KeyFile::Fixed(keys), Some(checkpoint)) => keys.rollback(checkpoint),
+            (KeyFile::PrefixCompressed(keys), Some(checkpoint)) => keys.rollback(checkpoint)?,
+            // The file has been allocated since the checkpoint
+            (KeyFile::Variable(keys), None) => keys.clear(),
+            (KeyFile::Fixed(keys), None) => keys.clear(),
+            (KeyFile::PrefixCompressed(keys), None) => keys.clear(),
+            (KeyFile::Inline { .. } | KeyFile::Unallocated { .. }, _) => {}
+        }
+        self.key_cache
+            .get_mut()
+            .map_err(|_| Error::PoisonedCache)?
+            .clear();
+        Ok(())
+    }
+
+    /// Remove all nodes and keys, but keep the allocated storage.
+    ///
+    /// A new root node has to be allocated afterwards.
+    /// A key cache that is unusable because a thread panicked while using it is cleared, too,
+    /// and can be used again.
+    pub fn clear(&mut self) {
+        self.free_space_offset = 0;
+        self.discarded.clear();
+        self.key_cache
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        self.key_cache.clear_poison();
+        match &mut self.keys {
+            KeyFile::Variable(keys) => keys.clear(),
+            KeyFile::Fixed(keys) => keys.clear(),
+            KeyFile::PrefixCompressed(keys) => keys.clear(),
+            KeyFile::Inline { .. } | KeyFile::Unallocated { .. } => {}
+        }
+    }
+
+    /// Release the memory of the node and its keys, which must not be accessed afterwards.
+    ///
+    /// The memory pages are released as soon as all nodes or keys in them have been discarded.
+    /// Common key prefixes can be shared by several nodes and are kept.
+    pub fn discard_node(&mut self, node_id: u64) -> Result<()> {
+        if !matches!(
+            self.keys,
+            KeyFile::Inline { .. } | KeyFile::Unallocated { .. }
+        ) {
+            for i in 0..self.number_of_keys(node_id)? {
+                let key_id: usize = self.get_key_id(node_id, i)?.try_into()?;
+                match &mut self.keys {
+                    KeyFile::Variable(keys) => keys.discard_block(key_id)?,
+                    KeyFile::Fixed(keys) => keys.discard_block(key_id)?,
+                    KeyFile::PrefixCompressed(keys) => keys.discard_block(key_id)?,
+                    KeyFile::Inline { .. } | KeyFile::Unallocated { .. } => {}
+                }
+            }
+        }
+        self.invalidate_cached_keys(node_id)?;
+        let offset = usize::try_from(node_id)? * self.layout.block_size;
+        self.discarded.discard(
+            NODE_FILE_NAME,
+            self.storage.as_mut(),
+            offset,
+            offset + self.layout.block_size,
+            self.page_size,
+        )
+    }
+
+    /// Shrink the storages of the nodes and keys to the used space.
+    pub fn shrink_to_fit(&mut self) -> Result<()> {
+        crate::storage::shrink_to_fit(
+            NODE_FILE_NAME,
+            self.storage.as_mut(),
+            self.free_space_offset,
+            self.page_size,
+        )?;
+        match &mut self.keys {
+            KeyFile::Variable(keys) => keys.shrink_to_fit(),
+            KeyFile::Fixed(keys) => keys.shrink_to_fit(),
+            KeyFile::PrefixCompressed(keys) => keys.shrink_to_fit(),
+            KeyFile::Inline { .. } | KeyFile::Unallocated { .. } => Ok(()),
+        }
+    }
+
+    /// Grow the files of the nodes and keys up front, so that the given number of additional entries
+    /// can be inserted without growing them again.
+    ///
+    /// The needed space is estimated like when creating the file, but includes the padding
+    /// for the block alignment.
+    pub fn try_reserve(
+        &mut self,
+        additional: usize,
+        order: usize,
+        config: &BtreeConfig,
+    ) -> Result<()> {
+        let additional_nodes =
+            num_integer::div_ceil(additional, order.clamp(1, self.layout.max_keys));
+        let requested = additional_nodes.saturating_mul(self.layout.block_size);
+        self.grow(self.free_space_offset.saturating_add(requested))
+            .map_err(|e| Error::ReserveFailed {
+                file: "nodes",
+                requested,
+                source: Box::new(e),
+            })?;
+
+        let requested = match config.key_size {
+            super::TypeSize::Estimated(est_max_key_size) => additional.saturating_mul(
+                (est_max_key_size + BlockHeader::size()).next_multiple_of(config.block_alignment),
+            ),
+            super::TypeSize::Fixed(fixed_key_size) => additional.saturating_mul(fixed_key_size),
+        };
+        // Reserving the space needs the file for the keys
+        let result = self.keys.allocate().and_then(|_| match &mut self.keys {
+            KeyFile::Variable(keys) => keys.try_reserve(requested),
+            KeyFile::PrefixCompressed(keys) => keys.try_reserve(requested),
+            KeyFile::Fixed(keys) => keys.try_reserve(requested),
+            KeyFile::Inline { .. } | KeyFile::Unallocated { .. } => Ok(()),
+        });
+        result.map_err(|e| Error::ReserveFailed {
+            file: "keys",
+            requested,
+            source: Box::new(e),
+        })
+    }
+
+    /// Get the number of bytes used by the file for the keys.
+    pub fn key_memory_usage(&self) -> FileMemoryUsage {
+        match &self.keys {
+            KeyFile::Variable(keys) => keys.memory_usage(),
+            KeyFile::Fixed(keys) => keys.memory_usage(),
+            KeyFile::PrefixCompressed(keys) => keys.memory_usage(),
+            KeyFile::Inline { .. } | KeyFile::Unallocated { .. } => FileMemoryUsage::default(),
+        }
+    }
+
+    /// Get the number of bytes occupied by the allocated key blocks.
+    pub fn key_used_bytes(&self) -> usize {
+        match &self.keys {
+            KeyFile::Variable(keys) => keys.used_bytes(),
+            KeyFile::Fixed(keys) => keys.used_bytes(),
+            KeyFile::PrefixCompressed(keys) => keys.used_bytes(),
+            KeyFile::Inline { .. } | KeyFile::Unallocated { .. } => 0,
+        }
+    }
+
+    fn get(&self, node_id: u64) -> Result<NodeView<&[u8]>> {
+        let block = self.node_block(node_id)?;
+        let view = NodeView::new(&self.storage.as_slice()[block], self.layout);
         Ok(view)
     }
 
-    fn get_mut(&mut self, node_id: u64) -> Result<node::View<&mut [u8]>> {
-        let node_id: usize = node_id.try_into()?;
-        let offset: usize = NODE_BLOCK_ALIGNED_SIZE * node_id;
-        let view = node::View::new(&mut self.mmap[offset..(offset + NODE_BLOCK_SIZE)]);
+    fn get_mut(&mut self, node_id: u64) -> Result<NodeView<&mut [u8]>> {
+        let block = self.node_block(node_id)?;
+        if let Some(journal) = &mut self.journal {
+            if block.start < journal.free_space_offset {
+                journal
+                    .nodes
+                    .entry(node_id)
+                    .or_insert_with(|| self.storage.as_slice()[block.clone()].to_vec());
+            }
+        }
+        let view = NodeView::new(&mut self.storage.as_mut_slice()[block], self.layout);
         Ok(view)
     }
 
+    /// Returns the range of the node block in the storage, or an error if the node ID
+    /// is outside of the storage, e.g. because it is corrupted.
+    fn node_block(&self, node_id: u64) -> Result<std::ops::Range<usize>> {
+        let block_id: usize = node_id.try_into()?;
+        block_id
+            .checked_mul(self.layout.block_size)
+            .and_then(|offset| Some(offset..offset.checked_add(self.layout.block_size)?))
+            .filter(|block| block.end <= self.storage.len())
+            .ok_or(Error::BlockOutOfBounds { block_id })
+    }
+
     /// Grows the file to contain at least the requested number of bytes.
-    /// This needs to copy all content into a new temporary file.
+    /// This might need to copy all content into a new temporary file.
     /// To avoid this costly operation, the file size is at least doubled.
     fn grow(&mut self, requested_size: usize) -> Result<()> {
-        if requested_size <= self.mmap.len() {
-            // Still enough space, no action required
-            return Ok(());
-        }
+        self.growth.grow(
+            NODE_FILE_NAME,
+            self.storage.as_mut(),
+            requested_size,
+            &self.metrics,
+        )
+    }
+}
 
-        // Create a new anonymous memory mapped the content is copied to.
-        // Allocate at least twice the old file size so we don't need to grow too often
-        let new_size = requested_size.max(self.mmap.len() * 2);
-        let mut new_mmap = create_mmap(new_size)?;
+impl<K> KeyFile<K>
+where
+    K: Serialize + DeserializeOwned + Clone,
+{
+    fn get(&self, key_id: u128) -> Result<Arc<K>> {
+        match self {
+            KeyFile::Variable(keys) => keys.get(key_id.try_into()?),
+            KeyFile::Fixed(keys) => keys.get(key_id.try_into()?),
+            KeyFile::PrefixCompressed(keys) => Ok(Arc::new(decode_compressed_key(keys, key_id)?)),
+            KeyFile::Inline { fixed_key_size } => {
+                Ok(Arc::new(decode_inline_key(key_id, *fixed_key_size)?))
+            }
+            KeyFile::Unallocated { .. } => Err(Error::BlockOutOfBounds {
+                block_id: key_id.try_into()?,
+            }),
+        }
+    }
 
-        // Copy all content from the old file into the new file
-        new_mmap[0..self.mmap.len()].copy_from_slice(&self.mmap);
+    fn get_owned(&self, key_id: u128) -> Result<K> {
+        match self {
+            KeyFile::Variable(keys) => keys.get_owned(key_id.try_into()?),
+            KeyFile::Fixed(keys) => keys.get_owned(key_id.try_into()?),
+            KeyFile::PrefixCompressed(keys) => decode_compressed_key(keys, key_id),
+            KeyFile::Inline { fixed_key_size } => decode_inline_key(key_id, *fixed_key_size),
+            KeyFile::Unallocated { .. } => Err(Error::BlockOutOfBounds {
+                block_id: key_id.try_into()?,
+            }),
+        }
+    }
 
-        self.mmap = new_mmap;
+    /// Create the file for the keys if it has not been created yet.
+    fn allocate(&mut self) -> Result<()> {
+        if let KeyFile::Unallocated {
+            capacity,
+            config,
+            codec,
+        } = self
+        {
+            let capacity = *capacity;
+            *self =
+                match config.key_size {
+                    super::TypeSize::Estimated(est_max_key_size) => {
+                        let capacity = capacity * (est_max_key_size + BlockHeader::size());
+                        if config.key_prefix_compression {
+                            KeyFile::PrefixCompressed(create_variable_size_file(
+                                KEY_FILE_NAME,
+                                capacity,
+                                config,
+                            )?)
+                        } else {
+                            let mut keys =
+                                create_variable_size_file(KEY_FILE_NAME, capacity, config)?;
+                            if let Some(codec) = codec.take() {
+                                keys.set_codec(codec);
+                            }
+                            KeyFile::Variable(keys)
+                        }
+                    }
+                    super::TypeSize::Fixed(fixed_key_size) => KeyFile::Fixed(
+                        create_fixed_size_file(KEY_FILE_NAME, capacity, fixed_key_size, config)?,
+                    ),
+                };
+        }
         Ok(())
     }
+
+    /// Allocates a new block for the key and stores it.
+    ///
+    /// Returns the ID of the key block and the (possibly updated) key prefix of the node.
+    fn store(&mut self, key: &K, node_prefix: u64, neighbor: Option<u128>) -> Result<(u128, u64)> {
+        self.allocate()?;
+        match self {
+            KeyFile::Variable(keys) => Ok((store_key(keys, key)?, node_prefix)),
+            KeyFile::Fixed(keys) => Ok((store_key(keys, key)?, node_prefix)),
+            KeyFile::PrefixCompressed(keys) => {
+                store_compressed_key(keys, key, node_prefix, neighbor)
+            }
+            KeyFile::Inline { fixed_key_size } => {
+                Ok((encode_inline_key(key, *fixed_key_size)?, node_prefix))
+            }
+            KeyFile::Unallocated { .. } => unreachable!("the file for the keys was allocated"),
+        }
+    }
+}
+
+/// Serializes the key into the value of a key ID field.
+fn encode_inline_key<K>(key: &K, fixed_key_size: usize) -> Result<u128>
+where
+    K: Serialize,
+{
+    check_fixed_size(key, fixed_key_size)?;
+    let mut buffer = [0; MAX_INLINE_KEY_SIZE];
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .serialize_into(&mut buffer[0..fixed_key_size], key)?;
+    Ok(u128::from_le_bytes(buffer))
+}
+
+fn decode_inline_key<K>(key_id: u128, fixed_key_size: usize) -> Result<K>
+where
+    K: DeserializeOwned,
+{
+    let buffer = key_id.to_le_bytes();
+    let result = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .deserialize(&buffer[0..fixed_key_size])?;
+    Ok(result)
+}
+
+fn store_key<K>(keys: &mut dyn TupleFile<K>, key: &K) -> Result<u128> {
+    let key_id = keys.insert(key)?;
+    Ok(key_id.try_into()?)
+}
+
+/// Stores the key as suffix of a prefix it shares with the other keys of the node.
+///
+/// If the node does not have a prefix yet, the common prefix with the neighboring key is used.
+/// Returns the ID of the allocated key block and the (possibly updated) prefix of the node.
+fn store_compressed_key<K>(
+    keys: &mut VariableSizeTupleFile<CompressedKey>,
+    key: &K,
+    node_prefix: u64,
+    neighbor: Option<u128>,
+) -> Result<(u128, u64)>
+where
+    K: Serialize,
+{
+    let (length_prefixed, bytes) =
+        prefix::strip_length_header(bincode::DefaultOptions::new().serialize(key)?);
+
+    let candidate = if node_prefix != NO_KEY_PREFIX {
+        Some(keys.get(node_prefix.try_into()?)?.suffix.clone())
+    } else if let Some(neighbor) = neighbor {
+        Some(compressed_key_bytes(keys, neighbor)?.1)
+    } else {
+        None
+    };
+    let candidate_len = candidate.as_ref().map(|c| c.len()).unwrap_or(0);
+    let common_len = candidate
+        .map(|c| prefix::common_prefix_len(&c, &bytes))
+        .unwrap_or(0);
+
+    let (key_prefix, node_prefix) = if node_prefix != NO_KEY_PREFIX && common_len == candidate_len {
+        // The existing prefix of the node can be re-used
+        (Some(node_prefix), node_prefix)
+    } else if common_len >= MIN_KEY_PREFIX_LEN {
+        // Store the new (shorter) prefix for this and all future keys of this node
+        let prefix_block = CompressedKey {
+            prefix: None,
+            length_prefixed: false,
+            suffix: bytes[0..common_len].to_vec(),
+        };
+        let prefix_size: usize = keys.serialized_size(&prefix_block)?.try_into()?;
+        let prefix_id = keys.allocate_block(prefix_size)?;
+        keys.put(prefix_id, &prefix_block)?;
+        let prefix_id: u64 = prefix_id.try_into()?;
+        (Some(prefix_id), prefix_id)
+    } else {
+        (None, node_prefix)
+    };
+
+    let suffix_start = if key_prefix.is_some() { common_len } else { 0 };
+    let compressed = CompressedKey {
+        prefix: key_prefix,
+        length_prefixed,
+        suffix: bytes[suffix_start..].to_vec(),
+    };
+    let key_size: usize = keys.serialized_size(&compressed)?.try_into()?;
+    let key_id = keys.allocate_block(key_size)?;
+    keys.put(key_id, &compressed)?;
+    Ok((key_id.try_into()?, node_prefix))
+}
+
+/// Returns the stripped serialized bytes of a prefix compressed key and whether it had a length header.
+fn compressed_key_bytes(
+    keys: &VariableSizeTupleFile<CompressedKey>,
+    key_id: u128,
+) -> Result<(bool, Vec<u8>)> {
+    let key = keys.get(key_id.try_into()?)?;
+    let mut bytes = if let Some(prefix_id) = key.prefix {
+        keys.get(prefix_id.try_into()?)?.suffix.clone()
+    } else {
+        Vec::with_capacity(key.suffix.len())
+    };
+    bytes.extend_from_slice(&key.suffix);
+    Ok((key.length_prefixed, bytes))
+}
+
+fn decode_compressed_key<K>(keys: &VariableSizeTupleFile<CompressedKey>, key_id: u128) -> Result<K>
+where
+    K: DeserializeOwned,
+{
+    let (length_prefixed, bytes) = compressed_key_bytes(keys, key_id)?;
+    let serialized = prefix::restore_length_header(length_prefixed, bytes);
+    let result = bincode::DefaultOptions::new().deserialize(&serialized)?;
+    Ok(result)
 }
 
 #[cfg(test)]
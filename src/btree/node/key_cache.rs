@@ -0,0 +1,92 @@
+use std::sync::{Arc, OnceLock};
+
+use linked_hash_map::LinkedHashMap;
+
+/// Decoded keys of a node, which are filled when they are accessed for the first time.
+pub type NodeKeys<K> = Arc<Vec<OnceLock<Arc<K>>>>;
+
+/// Cache for the decoded keys of nodes.
+///
+/// Searching a node compares the searched key with some of the keys of the node,
+/// so the upper nodes of the tree would deserialize the same keys on each search.
+/// Internal nodes are passed by every search, while most leaves are only searched once,
+/// so both kinds of nodes have their own least recently used cache.
+/// Searching many different leaves therefore never evicts the keys of internal nodes.
+/// An entry must be invalidated whenever the keys of its node are changed.
+pub struct KeyCache<K> {
+    internal: Tier<K>,
+    leaves: Tier<K>,
+}
+
+/// Least recently used cache for one kind of nodes.
+struct Tier<K> {
+    nodes: LinkedHashMap<u64, NodeKeys<K>>,
+    max_nodes: usize,
+}
+
+impl<K> Tier<K> {
+    fn with_max_nodes(max_nodes: usize) -> Tier<K> {
+        Tier {
+            nodes: LinkedHashMap::with_capacity(max_nodes),
+            max_nodes,
+        }
+    }
+
+    fn get_or_insert(&mut self, node_id: u64, number_of_keys: usize) -> Option<NodeKeys<K>> {
+        if self.max_nodes == 0 {
+            return None;
+        }
+        if let Some(keys) = self.nodes.get_refresh(&node_id) {
+            if keys.len() == number_of_keys {
+                return Some(keys.clone());
+            }
+        }
+        let keys: NodeKeys<K> = Arc::new((0..number_of_keys).map(|_| OnceLock::new()).collect());
+        self.nodes.insert(node_id, keys.clone());
+        if self.nodes.len() > self.max_nodes {
+            self.nodes.pop_front();
+        }
+        Some(keys)
+    }
+}
+
+impl<K> KeyCache<K> {
+    /// Create a cache that holds the keys of at most `max_internal_nodes` internal nodes
+    /// and `max_leaf_nodes` leaf nodes.
+    pub fn with_max_nodes(max_internal_nodes: usize, max_leaf_nodes: usize) -> KeyCache<K> {
+        KeyCache {
+            internal: Tier::with_max_nodes(max_internal_nodes),
+            leaves: Tier::with_max_nodes(max_leaf_nodes),
+        }
+    }
+
+    /// Get the keys of the node and mark them as recently used.
+    ///
+    /// If the node is not cached yet, an entry without any decoded keys is added.
+    /// Returns `None` if the cache for this kind of node is disabled.
+    pub fn get_or_insert(
+        &mut self,
+        node_id: u64,
+        is_leaf: bool,
+        number_of_keys: usize,
+    ) -> Option<NodeKeys<K>> {
+        if is_leaf {
+            self.leaves.get_or_insert(node_id, number_of_keys)
+        } else {
+            self.internal.get_or_insert(node_id, number_of_keys)
+        }
+    }
+
+    /// Remove the keys of a node that has been changed.
+    pub fn invalidate(&mut self, node_id: u64) {
+        // A split can turn a leaf into an internal node, so remove it from both caches
+        self.internal.nodes.remove(&node_id);
+        self.leaves.nodes.remove(&node_id);
+    }
+
+    /// Remove the keys of all nodes.
+    pub fn clear(&mut self) {
+        self.internal.nodes.clear();
+        self.leaves.nodes.clear();
+    }
+}
@@ -0,0 +1,197 @@
+/// Size of the fields at the start of each node block (ID, number of keys, leaf marker and key prefix).
+const HEADER_SIZE: usize = 8 + 8 + 1 + 8;
+
+/// Describes where the fields of a node are located inside a node block.
+///
+/// A node block has the following fields:
+/// `id: u64`, `num_keys: u64`, `is_leaf: u8`, `key_prefix: u64`,
+/// followed by an array with a field of `key_size` bytes for each key
+/// and arrays of `u64` for the payloads and the child nodes.
+/// If order statistics are enabled, there is an additional array with the number of entries
+/// in the sub-tree of each child node.
+/// The length of the arrays depends on the size of the node block.
+#[derive(Clone, Copy)]
+pub struct NodeLayout {
+    /// Size of a node block in bytes.
+    pub block_size: usize,
+    /// Maximum number of keys in a node.
+    pub max_keys: usize,
+    /// Whether the number of entries in the sub-tree of each child node is stored.
+    pub child_counts: bool,
+    /// Size of the field for each key in bytes, either 8 or 16.
+    pub key_size: usize,
+}
+
+impl NodeLayout {
+    /// Layout for node blocks of the given size in bytes.
+    pub const fn new(block_size: usize) -> NodeLayout {
+        NodeLayout::with_fields(block_size, false, 8)
+    }
+
+    /// Layout that also stores the number of entries in the sub-tree of each child node.
+    pub const fn with_child_counts(block_size: usize) -> NodeLayout {
+        NodeLayout::with_fields(block_size, true, 8)
+    }
+
+    /// The same layout with fields of the given size for the keys.
+    pub const fn with_key_size(self, key_size: usize) -> NodeLayout {
+        NodeLayout::with_fields(self.block_size, self.child_counts, key_size)
+    }
+
+    const fn with_fields(block_size: usize, child_counts: bool, key_size: usize) -> NodeLayout {
+        // Each key needs an entry for the key, the payload and the child node,
+        // and there is one more child node than keys.
+        // Each child node needs an additional entry for its count.
+        let max_keys = if child_counts {
+            (block_size - HEADER_SIZE - 16) / (key_size + 24)
+        } else {
+            (block_size - HEADER_SIZE - 8) / (key_size + 16)
+        };
+        NodeLayout {
+            block_size,
+            max_keys,
+            child_counts,
+            key_size,
+        }
+    }
+
+    /// Maximum number of child nodes of a node.
+    pub const fn max_child_nodes(&self) -> usize {
+        self.max_keys + 1
+    }
+
+    const fn keys_offset(&self) -> usize {
+        HEADER_SIZE
+    }
+
+    const fn payloads_offset(&self) -> usize {
+        self.keys_offset() + self.max_keys * self.key_size
+    }
+
+    const fn child_nodes_offset(&self) -> usize {
+        self.payloads_offset() + self.max_keys * 8
+    }
+
+    const fn child_counts_offset(&self) -> usize {
+        self.child_nodes_offset() + self.max_child_nodes() * 8
+    }
+
+    const fn end_offset(&self) -> usize {
+        if self.child_counts {
+            self.child_counts_offset() + self.max_child_nodes() * 8
+        } else {
+            self.child_counts_offset()
+        }
+    }
+}
+
+/// View on the memory of a single node block.
+pub struct NodeView<S> {
+    data: S,
+    layout: NodeLayout,
+}
+
+impl<S> NodeView<S>
+where
+    S: AsRef<[u8]>,
+{
+    pub fn new(data: S, layout: NodeLayout) -> NodeView<S> {
+        NodeView { data, layout }
+    }
+
+    fn read_u64(&self, offset: usize) -> u64 {
+        let mut buffer = [0; 8];
+        buffer.copy_from_slice(&self.data.as_ref()[offset..(offset + 8)]);
+        u64::from_le_bytes(buffer)
+    }
+
+    pub fn num_keys(&self) -> u64 {
+        self.read_u64(8)
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.data.as_ref()[16] != 0
+    }
+
+    pub fn key_prefix(&self) -> u64 {
+        self.read_u64(17)
+    }
+
+    pub fn keys(&self) -> &[u8] {
+        &self.data.as_ref()[self.layout.keys_offset()..self.layout.payloads_offset()]
+    }
+
+    /// Reads the field of the key at the given position.
+    pub fn key_id(&self, i: usize) -> u128 {
+        let key_size = self.layout.key_size;
+        let mut buffer = [0; 16];
+        buffer[0..key_size].copy_from_slice(&self.keys()[(i * key_size)..((i + 1) * key_size)]);
+        u128::from_le_bytes(buffer)
+    }
+
+    pub fn payloads(&self) -> &[u8] {
+        &self.data.as_ref()[self.layout.payloads_offset()..self.layout.child_nodes_offset()]
+    }
+
+    pub fn child_nodes(&self) -> &[u8] {
+        &self.data.as_ref()[self.layout.child_nodes_offset()..self.layout.child_counts_offset()]
+    }
+
+    pub fn child_counts(&self) -> &[u8] {
+        &self.data.as_ref()[self.layout.child_counts_offset()..self.layout.end_offset()]
+    }
+}
+
+impl<S> NodeView<S>
+where
+    S: AsRef<[u8]> + AsMut<[u8]>,
+{
+    fn write_u64(&mut self, offset: usize, value: u64) {
+        self.data.as_mut()[offset..(offset + 8)].copy_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn set_id(&mut self, id: u64) {
+        self.write_u64(0, id);
+    }
+
+    pub fn set_num_keys(&mut self, num_keys: u64) {
+        self.write_u64(8, num_keys);
+    }
+
+    pub fn set_is_leaf(&mut self, is_leaf: bool) {
+        self.data.as_mut()[16] = u8::from(is_leaf);
+    }
+
+    pub fn set_key_prefix(&mut self, key_prefix: u64) {
+        self.write_u64(17, key_prefix);
+    }
+
+    pub fn keys_mut(&mut self) -> &mut [u8] {
+        let range = self.layout.keys_offset()..self.layout.payloads_offset();
+        &mut self.data.as_mut()[range]
+    }
+
+    /// Writes the field of the key at the given position.
+    ///
+    /// Only the lower bytes that fit into the field are written.
+    pub fn set_key_id(&mut self, i: usize, key_id: u128) {
+        let key_size = self.layout.key_size;
+        self.keys_mut()[(i * key_size)..((i + 1) * key_size)]
+            .copy_from_slice(&key_id.to_le_bytes()[0..key_size]);
+    }
+
+    pub fn payloads_mut(&mut self) -> &mut [u8] {
+        let range = self.layout.payloads_offset()..self.layout.child_nodes_offset();
+        &mut self.data.as_mut()[range]
+    }
+
+    pub fn child_nodes_mut(&mut self) -> &mut [u8] {
+        let range = self.layout.child_nodes_offset()..self.layout.child_counts_offset();
+        &mut self.data.as_mut()[range]
+    }
+
+    pub fn child_counts_mut(&mut self) -> &mut [u8] {
+        let range = self.layout.child_counts_offset()..self.layout.end_offset();
+        &mut self.data.as_mut()[range]
+    }
+}
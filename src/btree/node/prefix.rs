@@ -0,0 +1,112 @@
+use std::any::TypeId;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// Minimal number of bytes a prefix must have before it is worth to store it in its own block.
+pub const MIN_KEY_PREFIX_LEN: usize = 8;
+
+/// Representation of a key when prefix compression is enabled.
+///
+/// The blocks for the prefixes themselves use the same representation, but never reference another prefix.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CompressedKey {
+    pub prefix: Option<u64>,
+    pub length_prefixed: bool,
+    pub suffix: Vec<u8>,
+}
+
+/// Remove the length header from the serialized key if the key is a sequence of bytes.
+///
+/// Byte-strings like [`String`] or `Vec<u8>` are serialized with their length first,
+/// so two keys with a common prefix but a different length would not share any bytes.
+/// Returns whether the header was removed and the remaining bytes.
+pub fn strip_length_header(mut serialized: Vec<u8>) -> (bool, Vec<u8>) {
+    if let Some((header_len, len)) = read_varint(&serialized) {
+        if Some(serialized.len()) == len.checked_add(header_len as u64).map(|l| l as usize) {
+            serialized.drain(0..header_len);
+            return (true, serialized);
+        }
+    }
+    (false, serialized)
+}
+
+/// Whether the key type is a byte-string, whose bytes without the length header
+/// have the same order as the keys themselves.
+pub fn is_byte_string<K: 'static>() -> bool {
+    let t = TypeId::of::<K>();
+    t == TypeId::of::<String>() || t == TypeId::of::<Vec<u8>>()
+}
+
+/// Restore the serialized representation of a key that was returned by [`strip_length_header`].
+pub fn restore_length_header(length_prefixed: bool, bytes: Vec<u8>) -> Vec<u8> {
+    if length_prefixed {
+        let mut result = write_varint(bytes.len() as u64);
+        result.extend(bytes);
+        result
+    } else {
+        bytes
+    }
+}
+
+/// Reads the prefix ID and the suffix of a [`CompressedKey`] from its serialized bytes,
+/// without deserializing and copying the suffix.
+///
+/// Returns `None` if the bytes are not a valid serialized key.
+pub fn split_serialized(bytes: &[u8]) -> Option<(Option<u64>, &[u8])> {
+    let (prefix, rest) = match bytes.first()? {
+        0 => (None, &bytes[1..]),
+        1 => {
+            let (header_len, prefix_id) = read_varint(&bytes[1..])?;
+            (Some(prefix_id), &bytes[(1 + header_len)..])
+        }
+        _ => return None,
+    };
+    // Skip whether the key had a length header
+    let rest = rest.get(1..)?;
+    let (header_len, suffix_len) = read_varint(rest)?;
+    let suffix_end = header_len.checked_add(usize::try_from(suffix_len).ok()?)?;
+    Some((prefix, rest.get(header_len..suffix_end)?))
+}
+
+/// Returns the number of bytes both slices have in common at their start.
+pub fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b.iter()).take_while(|(a, b)| a == b).count()
+}
+
+/// Parses the variable integer encoding used by bincode.
+///
+/// Returns the number of bytes used by the encoding and the value.
+fn read_varint(bytes: &[u8]) -> Option<(usize, u64)> {
+    let first = *bytes.first()?;
+    match first {
+        0..=250 => Some((1, first as u64)),
+        251 => Some((
+            3,
+            u16::from_le_bytes(bytes.get(1..3)?.try_into().ok()?) as u64,
+        )),
+        252 => Some((
+            5,
+            u32::from_le_bytes(bytes.get(1..5)?.try_into().ok()?) as u64,
+        )),
+        253 => Some((9, u64::from_le_bytes(bytes.get(1..9)?.try_into().ok()?))),
+        _ => None,
+    }
+}
+
+fn write_varint(value: u64) -> Vec<u8> {
+    if value <= 250 {
+        vec![value as u8]
+    } else if value <= u16::MAX as u64 {
+        let mut result = vec![251];
+        result.extend((value as u16).to_le_bytes());
+        result
+    } else if value <= u32::MAX as u64 {
+        let mut result = vec![252];
+        result.extend((value as u32).to_le_bytes());
+        result
+    } else {
+        let mut result = vec![253];
+        result.extend(value.to_le_bytes());
+        result
+    }
+}
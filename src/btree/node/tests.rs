@@ -12,5 +12,175 @@ fn allocate_nodes() {
     assert_eq!(2, n3);
 
     assert_eq!(0, f.number_of_keys(n1).unwrap());
-    assert_eq!(true, f.is_leaf(n1).unwrap());
+    assert!(f.is_leaf(n1).unwrap());
+}
+
+#[test]
+fn strip_and_restore_length_header() {
+    let serializer = bincode::DefaultOptions::new();
+    for s in [
+        String::new(),
+        "abc".to_string(),
+        "x".repeat(250),
+        "x".repeat(251),
+        "x".repeat(70_000),
+    ] {
+        let serialized = serializer.serialize(&s).unwrap();
+        let (length_prefixed, bytes) = prefix::strip_length_header(serialized.clone());
+        assert!(length_prefixed);
+        assert_eq!(s.as_bytes(), &bytes[..]);
+        assert_eq!(
+            serialized,
+            prefix::restore_length_header(length_prefixed, bytes)
+        );
+    }
+
+    // Types that are not serialized as byte-string are kept as they are
+    let serialized = serializer.serialize(&(1000u64, 42u8)).unwrap();
+    let (length_prefixed, bytes) = prefix::strip_length_header(serialized.clone());
+    assert!(!length_prefixed);
+    assert_eq!(serialized, bytes);
+}
+
+#[test]
+fn split_serialized_compressed_key() {
+    let serializer = bincode::DefaultOptions::new();
+    for (prefix, suffix) in [
+        (None, vec![]),
+        (Some(0), b"abc".to_vec()),
+        (Some(1 << 40), vec![42; 300]),
+        (None, vec![1; 70_000]),
+    ] {
+        let key = prefix::CompressedKey {
+            prefix,
+            length_prefixed: true,
+            suffix: suffix.clone(),
+        };
+        let serialized = serializer.serialize(&key).unwrap();
+        assert_eq!(
+            Some((prefix, &suffix[..])),
+            prefix::split_serialized(&serialized)
+        );
+        // Truncated keys are rejected instead of panicking
+        assert_eq!(None, prefix::split_serialized(&serialized[0..2]));
+    }
+    assert_eq!(None, prefix::split_serialized(&[]));
+    assert_eq!(None, prefix::split_serialized(&[2, 0, 0]));
+}
+
+#[test]
+fn node_layout_for_block_size() {
+    // A single page can hold up to 169 keys
+    assert_eq!(169, NodeLayout::new(4096).max_keys);
+    assert_eq!(339, NodeLayout::new(8192).max_keys);
+    assert_eq!(2729, NodeLayout::new(65536).max_keys);
+    // Storing the child counts needs additional space
+    assert_eq!(126, NodeLayout::with_child_counts(4096).max_keys);
+    // So does storing keys with up to 16 bytes inside the node
+    assert_eq!(126, NodeLayout::new(4096).with_key_size(16).max_keys);
+    assert_eq!(
+        101,
+        NodeLayout::with_child_counts(4096)
+            .with_key_size(16)
+            .max_keys
+    );
+
+    let config = BtreeConfig::default().node_block_size(16384);
+    let mut f: NodeFile<u64> = NodeFile::with_capacity(0, &config).unwrap();
+    let n1 = f.allocate_new_node().unwrap();
+    let n2 = f.allocate_new_node().unwrap();
+    // Fill the second node with the maximum number of keys
+    for i in 0..681 {
+        f.set_key_value(n2, i, &(i as u64)).unwrap();
+    }
+    assert!(f.set_key_value(n2, 681, &681).is_err());
+    assert_eq!(681, f.number_of_keys(n2).unwrap());
+    assert_eq!(0, f.number_of_keys(n1).unwrap());
+    assert_eq!(680, f.get_key_owned(n2, 680).unwrap());
+}
+
+/// Fills a node with the keys and checks that searching it returns an error instead of panicking
+/// after its key IDs or its number of keys have been corrupted.
+fn search_corrupted_node<K>(config: BtreeConfig, keys: &[K], ordered_key_bytes: bool)
+where
+    K: 'static + Serialize + DeserializeOwned + Clone + Ord + Send + Sync,
+{
+    let mut f: NodeFile<K> = NodeFile::with_capacity(0, &config).unwrap();
+    if ordered_key_bytes {
+        f.enable_ordered_key_bytes();
+    }
+    let node = f.allocate_new_node().unwrap();
+    for (i, key) in keys.iter().enumerate() {
+        f.set_key_value(node, i, key).unwrap();
+    }
+    assert!(matches!(
+        f.binary_search(node, &keys[1]),
+        Ok(SearchResult::Found(1))
+    ));
+
+    // Node IDs outside of the file
+    assert!(matches!(
+        f.binary_search(node + 1000, &keys[1]),
+        Err(Error::BlockOutOfBounds { .. })
+    ));
+    assert!(f.binary_search(u64::MAX, &keys[1]).is_err());
+
+    // Key IDs outside of the key file
+    for key_id in [1 << 40, u64::MAX.into()] {
+        for i in 0..keys.len() {
+            f.set_key_id(node, i, key_id).unwrap();
+        }
+        assert!(matches!(
+            f.binary_search(node, &keys[1]),
+            Err(Error::BlockOutOfBounds { .. })
+        ));
+    }
+
+    // More keys than the node can hold
+    f.get_mut(node).unwrap().set_num_keys(u64::MAX);
+    assert!(matches!(
+        f.binary_search(node, &keys[1]),
+        Err(Error::IntegrityViolation(_))
+    ));
+}
+
+#[test]
+fn binary_search_corrupted_node() {
+    let strings: Vec<String> = (0..10).map(|i| format!("key-{i:03}")).collect();
+    for ordered_key_bytes in [false, true] {
+        search_corrupted_node(BtreeConfig::default(), &strings, ordered_key_bytes);
+        search_corrupted_node(
+            BtreeConfig::default().key_prefix_compression(true),
+            &strings,
+            ordered_key_bytes,
+        );
+    }
+
+    let triples: Vec<(u64, u64, u64)> = (0..10).map(|i| (i, i * 2, i * 3)).collect();
+    let config = BtreeConfig::default().fixed_key_size(24);
+    search_corrupted_node(config.clone(), &triples, false);
+    search_corrupted_node(config, &triples, true);
+}
+
+#[test]
+fn ordered_bytes_search_rejects_other_fixed_sizes() {
+    let mut f: NodeFile<Vec<u8>> =
+        NodeFile::with_capacity(0, &BtreeConfig::default().fixed_key_size(24)).unwrap();
+    f.enable_ordered_key_bytes();
+    let node = f.allocate_new_node().unwrap();
+    for i in 0..4 {
+        f.set_key_value(node, i, &vec![i as u8; 16]).unwrap();
+    }
+    assert!(matches!(
+        f.binary_search(node, &vec![2; 16]),
+        Ok(SearchResult::Found(2))
+    ));
+    // A shorter key would be compared with the first bytes of the stored keys
+    assert!(matches!(
+        f.binary_search(node, &vec![2; 4]),
+        Err(Error::FixedSizeMismatch {
+            expected: 24,
+            actual: 12
+        })
+    ));
 }
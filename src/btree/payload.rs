@@ -0,0 +1,296 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::Hasher,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    codec::Codec,
+    error::Result,
+    file::{FileCheckpoint, FixedSizeTupleFile, TupleFile, VariableSizeTupleFile},
+};
+
+/// Counter for the instances of an index, which are referenced by each [`PayloadId`].
+static NEXT_INSTANCE: AtomicU64 = AtomicU64::new(0);
+
+/// Opaque reference to a value stored in a [`BtreeIndex`](crate::BtreeIndex).
+///
+/// The ID is returned by [`BtreeIndex::insert_returning_id`](crate::BtreeIndex::insert_returning_id)
+/// or [`BtreeIndex::payload_id`](crate::BtreeIndex::payload_id) and can be used to get the value
+/// with [`BtreeIndex::get_by_payload`](crate::BtreeIndex::get_by_payload) without searching the key,
+/// e.g. to build secondary indexes.
+/// IDs can't be created otherwise, and they are only valid for the index instance that returned them.
+/// They become invalid when the entry is removed and its value block is freed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PayloadId {
+    pub(super) instance: u64,
+    pub(super) payload: u64,
+    /// Generation of the value block, which changes when the block is freed.
+    pub(super) generation: u64,
+}
+
+/// File with the value blocks of an index.
+///
+/// Unlike a boxed [`TupleFile`], the file is only [`Send`] and [`Sync`] if the values are,
+/// so the values of an index that is only used by a single thread don't need to be.
+pub(super) enum ValueFile<V> {
+    Variable(Box<VariableSizeTupleFile<V>>),
+    Fixed(FixedSizeTupleFile<V>),
+}
+
+impl<V> ValueFile<V>
+where
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// Returns the allocation state of the file, so that the value blocks allocated afterwards
+    /// can be removed again with [`Self::rollback`].
+    pub(super) fn checkpoint(&self) -> FileCheckpoint {
+        match self {
+            ValueFile::Variable(values) => values.checkpoint(),
+            ValueFile::Fixed(values) => values.checkpoint(),
+        }
+    }
+
+    /// Removes all value blocks that were allocated after the checkpoint was taken.
+    pub(super) fn rollback(&mut self, checkpoint: FileCheckpoint) -> Result<()> {
+        match self {
+            ValueFile::Variable(values) => values.rollback(checkpoint),
+            ValueFile::Fixed(values) => {
+                values.rollback(checkpoint);
+                Ok(())
+            }
+        }
+    }
+
+    /// Get the codec of values with a variable size, to use it for another file.
+    pub(super) fn shared_codec(&self) -> Option<Arc<dyn Codec<V>>> {
+        match self {
+            ValueFile::Variable(values) => Some(values.shared_codec()),
+            ValueFile::Fixed(_) => None,
+        }
+    }
+
+    /// Serialize the values with the given codec. Values with a fixed size always use
+    /// bincode with fixed integer encoding, so this has no effect for them.
+    pub(super) fn set_codec(&mut self, codec: Arc<dyn Codec<V>>) {
+        if let ValueFile::Variable(values) = self {
+            values.set_codec(codec);
+        }
+    }
+}
+
+impl<'a, V> AsRef<dyn TupleFile<V> + 'a> for ValueFile<V>
+where
+    V: 'a + Serialize + DeserializeOwned + Clone,
+{
+    fn as_ref(&self) -> &(dyn TupleFile<V> + 'a) {
+        match self {
+            ValueFile::Variable(values) => values.as_ref(),
+            ValueFile::Fixed(values) => values,
+        }
+    }
+}
+
+impl<'a, V> AsMut<dyn TupleFile<V> + 'a> for ValueFile<V>
+where
+    V: 'a + Serialize + DeserializeOwned + Clone,
+{
+    fn as_mut(&mut self) -> &mut (dyn TupleFile<V> + 'a) {
+        match self {
+            ValueFile::Variable(values) => values.as_mut(),
+            ValueFile::Fixed(values) => values,
+        }
+    }
+}
+
+impl<V> Deref for ValueFile<V>
+where
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    type Target = dyn TupleFile<V>;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_ref()
+    }
+}
+
+impl<V> DerefMut for ValueFile<V>
+where
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.as_mut()
+    }
+}
+
+/// Returns a new identifier for an index instance.
+pub(super) fn next_instance() -> u64 {
+    NEXT_INSTANCE.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Payloads with this bit set contain the serialized value instead of the ID of a value block.
+const INLINE_TAG: u64 = 1 << 63;
+
+/// Maximum serialized size of a value that is stored directly in the payload of a node.
+///
+/// The last byte of the payload is needed for the tag and the size of the value.
+pub(super) const MAX_INLINE_VALUE_SIZE: usize = 7;
+
+/// Payload of a removed key.
+///
+/// It is tagged as inline value so that it is never treated as ID of a value block,
+/// but its size is larger than any inline value.
+pub(super) const TOMBSTONE: u64 = INLINE_TAG | (0x7f << 56);
+
+/// Returns whether the payload contains the value itself instead of the ID of a value block.
+pub(crate) fn is_inline(payload: u64) -> bool {
+    payload & INLINE_TAG != 0
+}
+
+/// Returns whether the payload marks a removed key.
+pub(super) fn is_tombstone(payload: u64) -> bool {
+    payload == TOMBSTONE
+}
+
+/// Stores the value in the payload if it is small enough or in a new block of the file otherwise.
+///
+/// Returns the new payload.
+pub(super) fn insert_value<V>(values: &mut dyn TupleFile<V>, value: &V) -> Result<u64>
+where
+    V: Serialize,
+{
+    if let Some(payload) = encode_inline_value(values, value)? {
+        Ok(payload)
+    } else {
+        let block_id = values.insert(value)?;
+        Ok(block_id.try_into()?)
+    }
+}
+
+/// Reads the value from the payload or from the block it references.
+pub(super) fn read_value<V>(values: &dyn TupleFile<V>, payload: u64) -> Result<V>
+where
+    V: DeserializeOwned,
+{
+    if is_inline(payload) {
+        let (buffer, size) = inline_buffer(payload);
+        values.codec().deserialize(&buffer[0..size])
+    } else {
+        values.get_owned(payload.try_into()?)
+    }
+}
+
+/// Returns the bytes of a payload with an inline value and the size of the serialized value in it.
+pub(super) fn inline_buffer(payload: u64) -> ([u8; MAX_INLINE_VALUE_SIZE + 1], usize) {
+    let buffer = payload.to_le_bytes();
+    let size = usize::from(buffer[MAX_INLINE_VALUE_SIZE] & 0x7f);
+    (buffer, size)
+}
+
+/// Replaces the value of an existing payload.
+///
+/// Values inside a value block stay in that block, so the payload only changes
+/// if the previous value was stored inline.
+/// Returns the new payload.
+pub(super) fn write_value<V>(values: &mut dyn TupleFile<V>, payload: u64, value: &V) -> Result<u64>
+where
+    V: Serialize,
+{
+    if is_inline(payload) {
+        insert_value(values, value)
+    } else {
+        values.put(payload.try_into()?, value)?;
+        Ok(payload)
+    }
+}
+
+/// Remembers the blocks of the stored values by the hash of their serialized bytes,
+/// so that identical values can share the same block.
+#[derive(Clone, Debug, Default)]
+pub(super) struct ValueBlocks {
+    blocks: HashMap<u64, Vec<u64>>,
+}
+
+impl ValueBlocks {
+    /// Stores the value in the payload if it is small enough, or reuses the block of an identical
+    /// value. Only if there is no such block, the value is stored in a new block of the file.
+    ///
+    /// Returns the new payload.
+    pub fn insert_value<V>(&mut self, values: &mut dyn TupleFile<V>, value: &V) -> Result<u64>
+    where
+        V: Serialize + DeserializeOwned,
+    {
+        if let Some(payload) = encode_inline_value(values, value)? {
+            return Ok(payload);
+        }
+        let codec = values.codec();
+        let serialized = codec.serialize(value)?;
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&serialized);
+
+        let candidates = self.blocks.entry(hasher.finish()).or_default();
+        for payload in candidates.iter() {
+            // Different values can have the same hash, so compare the serialized values
+            let existing: V = values.get_owned((*payload).try_into()?)?;
+            if codec.serialize(&existing)? == serialized {
+                return Ok(*payload);
+            }
+        }
+        let payload = values.insert(value)?.try_into()?;
+        candidates.push(payload);
+        Ok(payload)
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+    }
+
+    /// Forget the blocks that were allocated after the checkpoint of the file.
+    ///
+    /// Shared blocks are never freed, so all of them have an ID after the checkpoint offset.
+    pub fn rollback(&mut self, checkpoint: &FileCheckpoint) {
+        let offset = u64::try_from(checkpoint.free_space_offset()).unwrap_or(u64::MAX);
+        self.blocks.retain(|_, blocks| {
+            blocks.retain(|payload| *payload < offset);
+            !blocks.is_empty()
+        });
+    }
+
+    /// Approximate number of bytes used by the hash map and the lists of blocks.
+    pub fn memory_usage(&self) -> usize {
+        // Each entry of the hash map has a key, a vector and a control byte
+        let map = self.blocks.capacity() * (size_of::<u64>() + size_of::<Vec<u64>>() + 1);
+        let lists: usize = self
+            .blocks
+            .values()
+            .map(|blocks| blocks.capacity() * size_of::<u64>())
+            .sum();
+        map + lists
+    }
+}
+
+fn encode_inline_value<V>(values: &dyn TupleFile<V>, value: &V) -> Result<Option<u64>>
+where
+    V: Serialize,
+{
+    // Values in the nodes would not be encrypted
+    if values.is_encrypted() {
+        return Ok(None);
+    }
+    let codec = values.codec();
+    let size: usize = codec.serialized_size(value)?.try_into()?;
+    if size > MAX_INLINE_VALUE_SIZE {
+        return Ok(None);
+    }
+    let mut buffer = [0; MAX_INLINE_VALUE_SIZE + 1];
+    codec.serialize_into(&mut buffer[0..size], value)?;
+    // The size is at most 7 and does not overlap with the tag
+    buffer[MAX_INLINE_VALUE_SIZE] = size as u8;
+    Ok(Some(u64::from_le_bytes(buffer) | INLINE_TAG))
+}
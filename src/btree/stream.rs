@@ -0,0 +1,103 @@
+use std::{
+    ops::RangeBounds,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{BtreeIndex, Range};
+use crate::error::Result;
+
+/// Default number of entries a [`RangeStream`] yields before giving control back to the executor.
+const DEFAULT_CHUNK_SIZE: usize = 1024;
+
+/// Asynchronous stream over a range of keys, which is returned by [`BtreeIndex::range_stream`].
+///
+/// Reading the entries does not block on any I/O besides accessing the memory mapped files,
+/// but iterating over a large range can take a long time.
+/// Therefore, the stream gives control back to the executor after each chunk of entries.
+pub struct RangeStream<'a, K, V>
+where
+    K: Serialize + DeserializeOwned + Clone,
+{
+    range: Range<'a, K, V>,
+    chunk_size: usize,
+    remaining_in_chunk: usize,
+}
+
+impl<'a, K, V> RangeStream<'a, K, V>
+where
+    K: Serialize + DeserializeOwned + Clone,
+{
+    /// Set the number of entries that are yielded before giving control back to the executor.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self.remaining_in_chunk = self.chunk_size;
+        self
+    }
+}
+
+// The fields are never pinned, so the stream can be moved even if the keys or values can't
+impl<'a, K, V> Unpin for RangeStream<'a, K, V> where K: Serialize + DeserializeOwned + Clone {}
+
+impl<'a, K, V> Stream for RangeStream<'a, K, V>
+where
+    K: Clone + Serialize + DeserializeOwned + Ord,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    type Item = Result<(K, V)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.remaining_in_chunk == 0 {
+            // Let other tasks run before continuing with the next chunk
+            this.remaining_in_chunk = this.chunk_size;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        this.remaining_in_chunk -= 1;
+        Poll::Ready(this.range.next())
+    }
+}
+
+impl<K, V> BtreeIndex<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    /// Return an asynchronous stream over a range of keys.
+    ///
+    /// The stream yields the same entries as [`Self::range`], but gives control back
+    /// to the executor after every 1024 entries, which can be changed with
+    /// [`RangeStream::chunk_size`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use futures_util::StreamExt;
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16,u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.insert(1,2)?;
+    ///     b.insert(200, 4)?;
+    ///     b.insert(20, 3)?;
+    ///
+    ///     let entries: Vec<_> = futures_executor::block_on(b.range_stream(1..30)?.collect());
+    ///     assert_eq!(2, entries.len());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn range_stream<R>(&self, range: R) -> Result<RangeStream<'_, K, V>>
+    where
+        R: RangeBounds<K>,
+    {
+        Ok(RangeStream {
+            range: self.range(range)?,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            remaining_in_chunk: DEFAULT_CHUNK_SIZE,
+        })
+    }
+}
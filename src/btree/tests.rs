@@ -1,9 +1,16 @@
-use crate::BtreeIndex;
+use crate::{
+    test_util::{insert_random, seeded_rng},
+    BtreeIndex, MemoryStorage,
+};
 use debug_tree::TreeBuilder;
 use fake::{Fake, StringFaker};
-use rand::SeedableRng;
+use rand::Rng;
 use rayon::prelude::*;
-use std::{cmp::Ordering, collections::BTreeMap, fmt::Debug};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+};
 
 use super::*;
 
@@ -79,7 +86,7 @@ where
         let (k, _v) = e.unwrap();
 
         if let Some(previous) = previous {
-            if &previous >= &k {
+            if previous >= k {
                 dbg!(&previous, &k);
             }
             assert_eq!(Ordering::Less, previous.cmp(&k));
@@ -97,16 +104,32 @@ where
     let mut previous: Option<K> = None;
     for (k, _v) in c {
         if let Some(previous) = previous {
-            if &previous >= &k {
+            if &previous >= k {
                 dbg!(&previous, &k);
             }
-            assert_eq!(Ordering::Less, previous.cmp(&k));
+            assert_eq!(Ordering::Less, previous.cmp(k));
         }
 
         previous = Some(k.clone());
     }
 }
 
+/// Count the keys whose value block has been relocated and is only reachable
+/// through the forwarding pointer of the original block.
+fn forwarded_payloads<K, V>(t: &BtreeIndex<K, V>, keys: impl IntoIterator<Item = K>) -> usize
+where
+    K: Serialize + DeserializeOwned + PartialOrd + Clone + Ord + Send + Sync + 'static,
+    V: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    keys.into_iter()
+        .filter(|k| {
+            let payload = t.payload_id(k).unwrap().unwrap().payload;
+            let block_id = payload as usize;
+            !payload::is_inline(payload) && t.values.resolve_block(block_id).unwrap() != block_id
+        })
+        .count()
+}
+
 #[test]
 fn insert_get_static_size() {
     let nr_entries = 2000;
@@ -115,21 +138,21 @@ fn insert_get_static_size() {
 
     let mut t: BtreeIndex<u64, u64> = BtreeIndex::with_capacity(config, 2000).unwrap();
 
-    assert_eq!(true, t.is_empty());
+    assert!(t.is_empty());
 
     assert_eq!(None, t.insert(0, 42).unwrap());
 
-    assert_eq!(false, t.is_empty());
+    assert!(!t.is_empty());
     assert_eq!(1, t.len());
 
     for i in 1..nr_entries {
         assert_eq!(None, t.insert(i, i).unwrap());
     }
 
-    assert_eq!(false, t.is_empty());
+    assert!(!t.is_empty());
     assert_eq!(nr_entries as usize, t.len());
 
-    assert_eq!(true, t.contains_key(&0).unwrap());
+    assert!(t.contains_key(&0).unwrap());
     assert_eq!(Some(42), t.get(&0).unwrap());
     assert_eq!(Some(42), t.insert(0, 100).unwrap());
     assert_eq!(Some(100), t.insert(0, 42).unwrap());
@@ -137,14 +160,14 @@ fn insert_get_static_size() {
     print_tree(&t).unwrap();
 
     for i in 1..nr_entries {
-        assert_eq!(true, t.contains_key(&i).unwrap());
+        assert!(t.contains_key(&i).unwrap());
 
         let v = t.get(&i).unwrap();
         assert_eq!(Some(i), v);
     }
-    assert_eq!(false, t.contains_key(&nr_entries).unwrap());
+    assert!(!t.contains_key(&nr_entries).unwrap());
     assert_eq!(None, t.get(&nr_entries).unwrap());
-    assert_eq!(false, t.contains_key(&5000).unwrap());
+    assert!(!t.contains_key(&5000).unwrap());
     assert_eq!(None, t.get(&5000).unwrap());
 
     // also test a simple swap
@@ -171,8 +194,8 @@ fn parallel_get() {
     let entries: Result<Vec<Option<usize>>> =
         (0..nr_entries).into_par_iter().map(|i| t.get(&i)).collect();
     let entries: Vec<Option<usize>> = entries.unwrap();
-    for i in 0..nr_entries {
-        assert_eq!(Some(i), entries[i]);
+    for (i, e) in entries.into_iter().enumerate() {
+        assert_eq!(Some(i), e);
     }
 }
 
@@ -251,6 +274,56 @@ fn range_query_sparse() {
     check_order(&t, ..=1024);
 }
 
+#[test]
+fn range_with_borrowed_bounds() {
+    let mut m = BTreeMap::new();
+    let mut t: BtreeIndex<String, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(2), 200).unwrap();
+    for i in (0..2000).step_by(10) {
+        m.insert(format!("{:05}", i), i);
+        t.insert(format!("{:05}", i), i).unwrap();
+    }
+    assert!(t.depth().unwrap() > 2);
+
+    let bounds = [
+        Bound::Unbounded,
+        Bound::Included("00040"),
+        Bound::Excluded("00040"),
+        Bound::Included("00045"),
+        Bound::Excluded("01200"),
+        Bound::Included("01200"),
+        Bound::Included("99999"),
+    ];
+    for start in bounds {
+        for end in bounds {
+            let range = (start, end);
+            let expected: Vec<_> = m
+                .iter()
+                .filter(|(k, _)| RangeBounds::<str>::contains(&range, k.as_str()))
+                .map(|(k, v)| (k.clone(), *v))
+                .collect();
+            let result: Result<Vec<_>> = t.range::<str, _>(range).unwrap().collect();
+            assert_eq!(expected, result.unwrap(), "{:?}", range);
+        }
+    }
+
+    // Skipping uses the sizes of the child nodes that are completely inside the range
+    let mut t: BtreeIndex<Vec<u8>, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(2).order_statistics(true), 200)
+            .unwrap();
+    for i in 0..200_u32 {
+        t.insert(i.to_be_bytes().to_vec(), i).unwrap();
+    }
+    let start = 20_u32.to_be_bytes();
+    let end = 150_u32.to_be_bytes();
+    let mut range = t
+        .range::<[u8], _>((Bound::Included(&start[..]), Bound::Excluded(&end[..])))
+        .unwrap();
+    assert_eq!(100, range.skip_entries(100).unwrap());
+    let rest: Vec<_> = range.map(|e| e.unwrap().1).collect();
+    assert_eq!((120..150).collect::<Vec<_>>(), rest);
+}
+
 #[test]
 fn into_iterator_dense() {
     let nr_entries = 2000;
@@ -295,21 +368,234 @@ fn into_iterator_sparse() {
     check_slice_order(&result);
 }
 
+/// Storage that overwrites discarded pages, so that reading them afterwards returns garbage.
+struct OverwritingStorage {
+    data: MemoryStorage,
+    discarded: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl StorageBackend for OverwritingStorage {
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn grow(&mut self, new_size: usize) -> Result<()> {
+        self.data.grow(new_size)
+    }
+
+    fn discard(&mut self, offset: usize, len: usize) -> Result<()> {
+        let page_size = system_page_size();
+        assert!(offset.is_multiple_of(page_size) && len.is_multiple_of(page_size));
+        self.data.as_mut_slice()[offset..offset + len].fill(0xff);
+        self.discarded
+            .fetch_add(len, std::sync::atomic::Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.data.as_mut_slice()
+    }
+}
+
+/// Storage that cannot grow beyond its initial capacity.
+struct FixedCapacityStorage(MemoryStorage);
+
+impl StorageBackend for FixedCapacityStorage {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn grow(&mut self, _new_size: usize) -> Result<()> {
+        Err(Error::IO(std::io::Error::new(
+            std::io::ErrorKind::StorageFull,
+            "no space left",
+        )))
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.0.as_mut_slice()
+    }
+}
+
+#[test]
+fn storage_errors_name_the_file() {
+    let config = BtreeConfig::default().storage_backend(|capacity| {
+        let storage: Box<dyn StorageBackend> =
+            Box::new(FixedCapacityStorage(MemoryStorage::with_capacity(capacity)));
+        Ok(storage)
+    });
+    let mut t: BtreeIndex<u64, String> = BtreeIndex::with_capacity(config, 10).unwrap();
+    let value = "x".repeat(10_000);
+    let err = (0..100)
+        .map(|i| t.insert(i, value.clone()))
+        .find_map(|result| result.err())
+        .unwrap();
+
+    assert!(matches!(
+        &err,
+        Error::Storage {
+            file: "values",
+            operation: "Growing",
+            source,
+        } if matches!(source.as_ref(), Error::IO(e) if e.kind() == std::io::ErrorKind::StorageFull)
+    ));
+    assert_eq!(
+        "Growing the storage of the values failed: no space left",
+        err.to_string()
+    );
+}
+
+#[test]
+fn iterator_size_hint() {
+    let mut t: BtreeIndex<u64, u64> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(4), 1000).unwrap();
+    for i in 0..1000 {
+        t.insert(i, i).unwrap();
+    }
+    t.remove_range(100..200).unwrap();
+
+    let mut range = t.range(..).unwrap();
+    assert_eq!((900, Some(900)), range.size_hint());
+    range.next().unwrap().unwrap();
+    assert_eq!(10, range.skip_entries(10).unwrap());
+    assert_eq!((889, Some(889)), range.size_hint());
+    assert_eq!(889, range.count());
+
+    // Only the upper bound is known for other ranges
+    let range = t.range(10..20).unwrap();
+    assert_eq!((0, Some(900)), range.size_hint());
+
+    let mut iter = t.into_iter().unwrap();
+    assert_eq!(900, iter.len());
+    iter.next().unwrap().unwrap();
+    assert_eq!(899, iter.len());
+    let rest: Vec<_> = iter.map(|e| e.unwrap()).collect();
+    assert_eq!(899, rest.len());
+}
+
+/// Storage that fails to release discarded pages.
+struct FailingDiscardStorage(MemoryStorage);
+
+impl StorageBackend for FailingDiscardStorage {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn grow(&mut self, new_size: usize) -> Result<()> {
+        self.0.grow(new_size)
+    }
+
+    fn discard(&mut self, _offset: usize, _len: usize) -> Result<()> {
+        Err(Error::IO(std::io::Error::other("discard failed")))
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.0.as_mut_slice()
+    }
+}
+
+#[test]
+fn into_iterator_counts_errors_as_entries() {
+    let config = BtreeConfig::default().order(4).storage_backend(|capacity| {
+        let storage: Box<dyn StorageBackend> = Box::new(FailingDiscardStorage(
+            MemoryStorage::with_capacity(capacity),
+        ));
+        Ok(storage)
+    });
+    let mut t: BtreeIndex<u64, u64> = BtreeIndex::with_capacity(config, 1000).unwrap();
+    for i in 0..1000 {
+        t.insert(i, i).unwrap();
+    }
+
+    let mut iter = t.into_iter().unwrap();
+    let mut returned = 0;
+    while let Some(entry) = iter.next() {
+        returned += 1;
+        if entry.is_err() {
+            // The error ends the iteration
+            assert_eq!(0, iter.len());
+            assert!(iter.next().is_none());
+            break;
+        }
+        assert_eq!(1000 - returned, iter.len());
+    }
+    assert!(returned < 1000);
+}
+
+#[test]
+fn into_iterator_discards_consumed_pages() {
+    for (key_prefix_compression, deduplicate_values) in
+        [(false, false), (true, false), (false, true)]
+    {
+        let discarded = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let storage_discarded = discarded.clone();
+        let config = BtreeConfig::default()
+            .key_prefix_compression(key_prefix_compression)
+            .deduplicate_values(deduplicate_values)
+            .storage_backend(move |capacity| {
+                let storage: Box<dyn StorageBackend> = Box::new(OverwritingStorage {
+                    data: MemoryStorage::with_capacity(capacity),
+                    discarded: storage_discarded.clone(),
+                });
+                Ok(storage)
+            });
+        let mut t: BtreeIndex<String, String> = BtreeIndex::with_capacity(config, 100).unwrap();
+        let mut expected = BTreeMap::new();
+        for i in 0..5000 {
+            let key = format!("key-{i:05}");
+            let value = format!("value-{}", "x".repeat(i % 50));
+            t.insert(key.clone(), value.clone()).unwrap();
+            expected.insert(key, value);
+        }
+        for i in (0..5000).step_by(7) {
+            let key = format!("key-{i:05}");
+            t.remove_range(key.clone()..=key.clone()).unwrap();
+            expected.remove(&key);
+        }
+
+        let mut iter = t.into_iter().unwrap();
+        let first_half: Vec<_> = iter
+            .by_ref()
+            .take(expected.len() / 2)
+            .map(|e| e.unwrap())
+            .collect();
+        // Pages are already released while iterating
+        assert!(discarded.load(std::sync::atomic::Ordering::Relaxed) > 0);
+
+        let mut result = first_half;
+        result.extend(iter.map(|e| e.unwrap()));
+        let expected: Vec<_> = expected.into_iter().collect();
+        assert_eq!(expected, result);
+    }
+}
+
 #[test]
 fn minimal_order() {
     let nr_entries = 2000u64;
 
     // Too small orders should create an error
-    assert_eq!(
-        true,
-        BtreeIndex::<u64, u64>::with_capacity(BtreeConfig::default().order(0), nr_entries as usize)
-            .is_err()
-    );
-    assert_eq!(
-        true,
-        BtreeIndex::<u64, u64>::with_capacity(BtreeConfig::default().order(1), nr_entries as usize)
-            .is_err()
-    );
+    assert!(BtreeIndex::<u64, u64>::with_capacity(
+        BtreeConfig::default().order(0),
+        nr_entries as usize
+    )
+    .is_err());
+    assert!(BtreeIndex::<u64, u64>::with_capacity(
+        BtreeConfig::default().order(1),
+        nr_entries as usize
+    )
+    .is_err());
 
     // Test with the minimal order 2
     let config = BtreeConfig::default()
@@ -376,20 +662,37 @@ fn insert_twice_at_split_point() {
     }
 
     let m: Vec<_> = m.into_iter().collect();
-    let t: Result<Vec<_>> = t.range(..).unwrap().collect();
+    let t: Result<Vec<_>> = t.iter().unwrap().collect();
     let t = t.unwrap();
 
     assert_eq!(m, t);
 }
 
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "is not sorted after splitting child 0 of node")]
+fn check_split_detects_unsorted_keys() {
+    let mut t = BtreeIndex::with_capacity(BtreeConfig::default().order(2), 1024).unwrap();
+    for i in 0..4 {
+        t.insert(i, i).unwrap();
+    }
+    t.check_split(t.root_id, 0).unwrap();
+
+    // Simulate a faulty split by moving a key of the left child to the right child
+    let left = t.nodes.get_child_node(t.root_id, 0).unwrap();
+    let right = t.nodes.get_child_node(t.root_id, 1).unwrap();
+    let key_id = t.nodes.get_key_id(left, 0).unwrap();
+    t.nodes.set_key_id(right, 0, key_id).unwrap();
+    t.check_split(t.root_id, 0).unwrap();
+}
+
 #[test]
 fn get_after_relocation() {
     // Create a series of strings in a larger map that forces reloaction
-    let seed = 1971428643569665;
 
     // Create an index with random entries
     let n_entries = 2_000;
-    let mut rng = rand::rngs::SmallRng::seed_from_u64(seed);
+    let mut rng = seeded_rng();
     const ASCII: &str = "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
     let id_faker = StringFaker::with(Vec::from(ASCII), 8..16);
     let name_faker = fake::faker::name::en::Name();
@@ -419,3 +722,2980 @@ fn get_after_relocation() {
     let found = btree.get(&search_key).unwrap().unwrap();
     assert_eq!(&search_value, &found);
 }
+
+#[test]
+fn key_prefix_compression() {
+    let config = BtreeConfig::default()
+        .max_key_size(64)
+        .order(4)
+        .key_prefix_compression(true);
+
+    let mut m = BTreeMap::default();
+    let mut t: BtreeIndex<String, u64> = BtreeIndex::with_capacity(config, 1024).unwrap();
+
+    // Insert URLs with a long common prefix in unsorted order
+    for i in (0..500).rev().chain(500..1000) {
+        let key = format!("https://example.com/some/long/path/{}", i * 7919 % 1000);
+        m.insert(key.clone(), i);
+        t.insert(key, i).unwrap();
+    }
+    // Also add keys that don't share the common prefix
+    for i in 0..100 {
+        let key = format!("{}", i);
+        m.insert(key.clone(), i);
+        t.insert(key, i).unwrap();
+    }
+    assert_eq!(m.len(), t.len());
+
+    for (k, v) in m.iter() {
+        assert_eq!(Some(*v), t.get(k).unwrap());
+    }
+    // Keys that are shorter than, longer than or different from the prefix of a node
+    for missing in [
+        "",
+        "https://example.com/some",
+        "https://example.com/some/long/path/",
+        "https://example.com/some/long/path/5000",
+        "https://example.com/some/long/path/-1",
+        "https://example.com/some/other/path/1",
+        "zzz",
+    ] {
+        assert_eq!(None, t.get(&missing.to_string()).unwrap());
+    }
+    let start = "https://example.com/some/long/path/2".to_string();
+    let end = "https://example.com/some/long/path/3".to_string();
+    let expected: Vec<_> = m
+        .range(start.clone()..end.clone())
+        .map(|(k, v)| (k.clone(), *v))
+        .collect();
+    let found: Result<Vec<_>> = t.range(start..end).unwrap().collect();
+    assert_eq!(expected, found.unwrap());
+
+    let m: Vec<_> = m.into_iter().collect();
+    let t: Result<Vec<_>> = t.iter().unwrap().collect();
+    assert_eq!(m, t.unwrap());
+
+    // Byte vectors are compared by their bytes like strings
+    let config = BtreeConfig::default()
+        .max_key_size(64)
+        .order(4)
+        .key_prefix_compression(true);
+    let mut t: BtreeIndex<Vec<u8>, u64> = BtreeIndex::with_capacity(config, 1024).unwrap();
+    let mut m = BTreeMap::default();
+    for i in 0..1000u64 {
+        let mut key = vec![0xff; 20];
+        key.extend((i * 7919 % 1000).to_be_bytes());
+        key.truncate(20 + (i % 9) as usize);
+        m.insert(key.clone(), i);
+        t.insert(key, i).unwrap();
+    }
+    for (k, v) in m.iter() {
+        assert_eq!(Some(*v), t.get(k).unwrap());
+    }
+    assert_eq!(None, t.get(&vec![0xff; 19]).unwrap());
+    assert_eq!(None, t.get(&vec![0xff; 30]).unwrap());
+    let m: Vec<_> = m.into_iter().collect();
+    let t: Result<Vec<_>> = t.iter().unwrap().collect();
+    assert_eq!(m, t.unwrap());
+}
+
+#[test]
+fn fixed_size_integer_keys() {
+    let mut rng = seeded_rng();
+
+    let mut m = BTreeMap::default();
+    let config = BtreeConfig::default().fixed_key_size(8).order(8);
+    let mut t: BtreeIndex<i64, i64> = BtreeIndex::with_capacity(config, 1024).unwrap();
+    for _ in 0..2000 {
+        let key = rng.gen_range(-5000..5000);
+        m.insert(key, key * 2);
+        t.insert(key, key * 2).unwrap();
+    }
+    assert_eq!(m.len(), t.len());
+    for key in -6000..6000 {
+        assert_eq!(m.get(&key).copied(), t.get(&key).unwrap());
+    }
+    let m: Vec<_> = m.into_iter().collect();
+    let result: Result<Vec<_>> = t.range(..).unwrap().collect();
+    assert_eq!(m, result.unwrap());
+
+    let config = BtreeConfig::default().fixed_key_size(2);
+    let mut t: BtreeIndex<u16, u16> = BtreeIndex::with_capacity(config, 1024).unwrap();
+    for key in (0..=u16::MAX).rev().step_by(3) {
+        t.insert(key, key).unwrap();
+    }
+    for key in 0..=u16::MAX {
+        let expected = if (u16::MAX - key) % 3 == 0 {
+            Some(key)
+        } else {
+            None
+        };
+        assert_eq!(expected, t.get(&key).unwrap());
+    }
+    check_order(&t, ..);
+}
+
+#[test]
+fn large_node_block_size() {
+    // The node block size must be a multiple of the page size
+    assert!(BtreeIndex::<u64, u64>::with_capacity(
+        BtreeConfig::default().node_block_size(5000),
+        10
+    )
+    .is_err());
+    // The order is limited by the node block size
+    assert!(BtreeIndex::<u64, u64>::with_capacity(BtreeConfig::default().order(85), 10).is_err());
+    assert!(BtreeIndex::<u64, u64>::with_capacity(
+        BtreeConfig::default().node_block_size(65536).order(1365),
+        10
+    )
+    .is_err());
+
+    let config = BtreeConfig::default()
+        .node_block_size(16384)
+        .order(340)
+        .max_key_size(8)
+        .max_value_size(8);
+    let mut t: BtreeIndex<u64, u64> = BtreeIndex::with_capacity(config, 10).unwrap();
+    for i in 0..10_000 {
+        t.insert(i, i).unwrap();
+    }
+    assert_eq!(10_000, t.len());
+    for i in 0..10_000 {
+        assert_eq!(Some(i), t.get(&i).unwrap());
+    }
+    let result: Result<Vec<_>> = t.range(100..9000).unwrap().collect();
+    let result = result.unwrap();
+    assert_eq!(8900, result.len());
+    assert_eq!((100, 100), result[0]);
+    check_order(&t, ..);
+}
+
+#[test]
+fn update_in_place() {
+    let config = BtreeConfig::default().order(2);
+    let mut t: BtreeIndex<u64, Vec<u64>> = BtreeIndex::with_capacity(config, 128).unwrap();
+    for i in 0..100 {
+        t.insert(i, vec![]).unwrap();
+    }
+    // Growing values must be relocated
+    for round in 0..50 {
+        for i in (0..100).step_by(3) {
+            assert!(t.update(&i, |v| v.push(round)).unwrap());
+        }
+    }
+    assert!(!t.update(&100, |v| v.push(1)).unwrap());
+    assert_eq!(100, t.len());
+    assert_eq!(None, t.get(&100).unwrap());
+
+    let expected: Vec<u64> = (0..50).collect();
+    for i in 0..100 {
+        if i % 3 == 0 {
+            assert_eq!(Some(&expected), t.get(&i).unwrap().as_ref());
+        } else {
+            assert_eq!(Some(vec![]), t.get(&i).unwrap());
+        }
+    }
+}
+
+#[test]
+fn merge_insert_values() {
+    let mut m: BTreeMap<u64, Vec<u64>> = BTreeMap::default();
+    let mut t: BtreeIndex<u64, Vec<u64>> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 128).unwrap();
+
+    let mut rng = seeded_rng();
+    for i in 0..2000 {
+        let key = rng.gen_range(0..200);
+        m.entry(key).or_default().push(i);
+        t.merge_insert(key, vec![i], |mut existing, value| {
+            existing.extend(value);
+            existing
+        })
+        .unwrap();
+    }
+    assert_eq!(m.len(), t.len());
+
+    let m: Vec<_> = m.into_iter().collect();
+    let result: Result<Vec<_>> = t.range(..).unwrap().collect();
+    assert_eq!(m, result.unwrap());
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn value_compression() {
+    let config = BtreeConfig::default().value_compression(32).order(4);
+    let mut t: BtreeIndex<u64, String> = BtreeIndex::with_capacity(config, 128).unwrap();
+    for i in 0..1000 {
+        t.insert(i, format!("{}", i).repeat((i % 20) as usize))
+            .unwrap();
+    }
+    for i in 0..1000 {
+        assert_eq!(
+            Some(format!("{}", i).repeat((i % 20) as usize)),
+            t.get(&i).unwrap()
+        );
+    }
+    t.insert(0, "updated".repeat(50)).unwrap();
+    assert_eq!(Some("updated".repeat(50)), t.get(&0).unwrap());
+}
+
+#[test]
+fn memory_usage() {
+    let config = BtreeConfig::default().max_key_size(8).max_value_size(4);
+    let mut t: BtreeIndex<String, String> = BtreeIndex::with_capacity(config, 100).unwrap();
+
+    let initial = t.memory_usage();
+    assert_eq!(DEFAULT_PAGE_SIZE, initial.nodes);
+    // The file for the keys is created with the capacity when the first key is inserted
+    assert_eq!(0, initial.keys);
+    assert_eq!(100 * (4 + BlockHeader::size()), initial.values);
+    assert_eq!(0, initial.block_cache);
+    t.insert("first".to_string(), "a".to_string()).unwrap();
+    assert_eq!(100 * (8 + BlockHeader::size()), t.memory_usage().keys);
+
+    for i in 0..1000 {
+        t.insert(format!("{:08}", i), "a".repeat(i % 8)).unwrap();
+    }
+    // Force relocations by growing some values
+    for i in 0..10 {
+        t.insert(format!("{:08}", i), "b".repeat(100)).unwrap();
+    }
+
+    let usage = t.memory_usage();
+    assert!(usage.nodes > initial.nodes);
+    assert!(usage.keys > initial.keys);
+    assert!(usage.values > initial.values);
+    assert!(usage.block_cache > 0);
+    assert_eq!(
+        usage.nodes + usage.keys + usage.values + usage.block_cache + usage.value_hashes,
+        usage.total()
+    );
+}
+
+#[test]
+fn configuration_and_capacity() {
+    let config = BtreeConfig::default()
+        .order(10)
+        .fixed_key_size(8)
+        .max_value_size(4)
+        .block_cache_size(5);
+    let mut t: BtreeIndex<u64, String> = BtreeIndex::with_capacity(config, 100).unwrap();
+    assert_eq!(10, t.order());
+    assert_eq!(TypeSize::Fixed(8), t.key_size());
+    assert_eq!(TypeSize::Estimated(4), t.value_size());
+    assert_eq!(Some(5), t.block_cache_size());
+    assert!(format!("{:?}", t.config()).contains("order: Some(10)"));
+
+    // The maximum order is used by default
+    let default: BtreeIndex<u64, String> =
+        BtreeIndex::with_capacity(BtreeConfig::default().block_cache_bytes(1000), 10).unwrap();
+    assert_eq!(84, default.order());
+    assert_eq!(None, default.block_cache_size());
+
+    let initial = t.capacity();
+    // The keys are stored inside the nodes
+    assert_eq!(FileCapacity::default(), initial.keys);
+    assert_eq!(DEFAULT_PAGE_SIZE, initial.nodes.used);
+    assert_eq!(0, initial.values.used);
+    assert_eq!(t.memory_usage().values, initial.values.allocated);
+
+    for i in 0..1000 {
+        t.insert(i, "x".repeat(100)).unwrap();
+    }
+    let capacity = t.capacity();
+    assert!(capacity.nodes.used > initial.nodes.used);
+    assert!(capacity.nodes.used <= capacity.nodes.allocated);
+    assert!(capacity.values.used >= 1000 * (101 + BlockHeader::size()));
+    assert!(capacity.values.used <= capacity.values.allocated);
+}
+
+#[test]
+fn builder_checks_options() {
+    let mut t = BtreeIndex::<u64, String>::builder()
+        .order(10)
+        .fixed_key_size(8)
+        .max_value_size(16)
+        .estimated_entries(1000)
+        .build()
+        .unwrap();
+    assert_eq!(10, t.order());
+    assert_eq!(TypeSize::Fixed(8), t.key_size());
+    assert_eq!(TypeSize::Estimated(16), t.value_size());
+    // The files are created for the estimated number of entries
+    assert!(t.capacity().values.allocated >= 1000 * (16 + BlockHeader::size()));
+    t.insert(1, "one".to_string()).unwrap();
+    assert_eq!(Some("one".to_string()), t.get(&1).unwrap());
+
+    let ordered = BtreeIndex::<[u8; 4], u64>::builder()
+        .ordered_keys()
+        .build()
+        .unwrap();
+    assert!(ordered.nodes.has_ordered_key_bytes());
+
+    assert!(matches!(
+        BtreeIndex::<u64, u64>::builder().order(1000).build(),
+        Err(Error::OrderTooLarge(1000))
+    ));
+    assert!(matches!(
+        BtreeIndex::<u64, u64>::builder()
+            .config(BtreeConfig::default().key_prefix_compression(true))
+            .fixed_key_size(8)
+            .build(),
+        Err(Error::ConflictingOptions {
+            option: "key_prefix_compression",
+            conflicting_option: "fixed_key_size"
+        })
+    ));
+    assert!(matches!(
+        BtreeIndex::<[u8; 4], u64>::builder()
+            .config(BtreeConfig::default().key_prefix_compression(true))
+            .ordered_keys()
+            .build(),
+        Err(Error::ConflictingOptions {
+            option: "ordered_keys",
+            conflicting_option: "key_prefix_compression"
+        })
+    ));
+    assert!(matches!(
+        BtreeIndex::<u64, u64>::builder()
+            .fixed_key_size(8)
+            .max_key_size(8)
+            .build(),
+        Err(Error::ConflictingOptions {
+            option: "fixed_key_size",
+            conflicting_option: "max_key_size"
+        })
+    ));
+    assert!(matches!(
+        BtreeIndex::<u64, u64>::builder()
+            .max_value_size(8)
+            .fixed_value_size(8)
+            .build(),
+        Err(Error::ConflictingOptions {
+            option: "fixed_value_size",
+            conflicting_option: "max_value_size"
+        })
+    ));
+    // A new configuration replaces the sizes that have been set before
+    BtreeIndex::<u64, u64>::builder()
+        .fixed_key_size(8)
+        .config(BtreeConfig::default())
+        .max_key_size(8)
+        .build()
+        .unwrap();
+    #[cfg(feature = "encryption")]
+    assert!(matches!(
+        BtreeIndex::<[u8; 4], u64>::builder()
+            .config(BtreeConfig::default().encryption_key([42; 32]))
+            .ordered_keys()
+            .build(),
+        Err(Error::ConflictingOptions {
+            option: "ordered_keys",
+            conflicting_option: "encryption_key"
+        })
+    ));
+    #[cfg(feature = "compression")]
+    assert!(matches!(
+        BtreeIndex::<u64, u64>::builder()
+            .config(BtreeConfig::default().value_compression(100))
+            .fixed_value_size(8)
+            .build(),
+        Err(Error::ConflictingOptions {
+            option: "value_compression",
+            conflicting_option: "fixed_value_size"
+        })
+    ));
+}
+
+#[cfg(feature = "postcard")]
+#[test]
+fn new_indexes_keep_the_codecs() {
+    let mut t = BtreeIndex::<u64, Vec<u64>>::builder()
+        .order(3)
+        .key_codec(ValueCodec::Postcard)
+        .value_codec(ValueCodec::Postcard)
+        .build()
+        .unwrap();
+    // Large integers are encoded differently by postcard and bincode
+    let value = |key: u64| vec![key, 70_000];
+    for i in 0..200 {
+        t.insert(i * 300_000, value(i * 300_000)).unwrap();
+    }
+    let check = |t: &BtreeIndex<u64, Vec<u64>>| {
+        for (key, _) in t.iter().unwrap().map(|e| e.unwrap()) {
+            let serialized = t.get_serialized_value(&key).unwrap().unwrap();
+            assert_eq!(
+                postcard::to_allocvec(&value(key)).unwrap(),
+                serialized.as_ref()
+            );
+            let key_size = postcard::to_allocvec(&key).unwrap().len() as u64;
+            assert_eq!(key_size, t.nodes.key_serialized_size(&key).unwrap());
+        }
+    };
+
+    let mut upper = t.split_off(&(100 * 300_000)).unwrap();
+    upper.insert(1, value(1)).unwrap();
+    check(&upper);
+    let t = t.compact().unwrap();
+    check(&t);
+    assert_eq!(100, t.len());
+    assert_eq!(101, upper.len());
+}
+
+/// Stores strings as plain UTF-8 bytes and counts how often it is used.
+#[derive(Default)]
+struct Utf8Codec {
+    serialized: std::sync::atomic::AtomicUsize,
+}
+
+impl crate::Codec<String> for Arc<Utf8Codec> {
+    fn serialize(&self, value: &String) -> Result<Vec<u8>> {
+        self.serialized
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Ok(value.as_bytes().to_vec())
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Result<String> {
+        String::from_utf8(bytes.to_vec()).map_err(|e| Error::Codec(Box::new(e)))
+    }
+}
+
+#[test]
+fn custom_codecs() {
+    let keys = Arc::new(Utf8Codec::default());
+    let values = Arc::new(Utf8Codec::default());
+    let mut t = BtreeIndex::<String, String>::builder()
+        .order(4)
+        .key_codec(keys.clone())
+        .value_codec(values.clone())
+        .build()
+        .unwrap();
+    for i in (0..500).rev() {
+        t.insert(format!("key {i:03}"), format!("value {i}"))
+            .unwrap();
+    }
+    assert!(keys.serialized.load(std::sync::atomic::Ordering::Relaxed) >= 500);
+    assert!(values.serialized.load(std::sync::atomic::Ordering::Relaxed) >= 500);
+
+    assert_eq!(
+        Some("value 42".to_string()),
+        t.get(&"key 042".to_string()).unwrap()
+    );
+    let entries: Vec<_> = t
+        .range::<String, _>(..)
+        .unwrap()
+        .map(|e| e.unwrap())
+        .collect();
+    assert_eq!(500, entries.len());
+    for (i, (key, value)) in entries.into_iter().enumerate() {
+        assert_eq!(format!("key {i:03}"), key);
+        assert_eq!(format!("value {i}"), value);
+    }
+
+    assert!(matches!(
+        BtreeIndex::<String, String>::builder()
+            .fixed_key_size(8)
+            .key_codec(Arc::new(Utf8Codec::default()))
+            .build(),
+        Err(Error::ConflictingOptions {
+            option: "key_codec",
+            conflicting_option: "fixed_key_size"
+        })
+    ));
+    assert!(matches!(
+        BtreeIndex::<String, String>::builder()
+            .config(BtreeConfig::default().key_prefix_compression(true))
+            .key_codec(Arc::new(Utf8Codec::default()))
+            .build(),
+        Err(Error::ConflictingOptions {
+            option: "key_codec",
+            conflicting_option: "key_prefix_compression"
+        })
+    ));
+    assert!(matches!(
+        BtreeIndex::<u64, String>::builder()
+            .fixed_value_size(8)
+            .value_codec(Arc::new(Utf8Codec::default()))
+            .build(),
+        Err(Error::ConflictingOptions {
+            option: "value_codec",
+            conflicting_option: "fixed_value_size"
+        })
+    ));
+}
+
+#[test]
+fn in_memory_storage() {
+    let config = BtreeConfig::default()
+        .order(4)
+        .storage_backend(|capacity| Ok(Box::new(MemoryStorage::with_capacity(capacity))));
+    let mut t: BtreeIndex<String, u64> = BtreeIndex::with_capacity(config, 10).unwrap();
+
+    // Insert more entries than the initial capacity, so all storages need to grow
+    for i in 0..1000 {
+        t.insert(format!("key{}", i), i).unwrap();
+    }
+    for i in 0..1000 {
+        assert_eq!(Some(i), t.get(&format!("key{}", i)).unwrap());
+    }
+    let result: Result<Vec<_>> = t.iter().unwrap().collect();
+    let result = result.unwrap();
+    assert_eq!(1000, result.len());
+    check_slice_order(&result);
+}
+
+#[cfg(feature = "export")]
+#[test]
+fn write_sstable() {
+    use bincode::Options;
+
+    let mut t: BtreeIndex<String, u64> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(4), 100).unwrap();
+    for i in (0..500).rev() {
+        t.insert(format!("{:04}", i), i).unwrap();
+    }
+    let mut table = Vec::new();
+    t.write_sstable(&mut table).unwrap();
+
+    assert_eq!(b"TBTSST01", &table[0..8]);
+    assert_eq!(500, u64::from_le_bytes(table[8..16].try_into().unwrap()));
+
+    // Read all entries back and check they are sorted
+    let serializer = bincode::DefaultOptions::new();
+    let mut offset = 16;
+    let read_field = |offset: &mut usize| {
+        let len = u64::from_le_bytes(table[*offset..(*offset + 8)].try_into().unwrap()) as usize;
+        let start = *offset + 8;
+        *offset = start + len;
+        &table[start..(start + len)]
+    };
+    for i in 0..500 {
+        let key: String = serializer.deserialize(read_field(&mut offset)).unwrap();
+        let value: u64 = serializer.deserialize(read_field(&mut offset)).unwrap();
+        assert_eq!(format!("{:04}", i), key);
+        assert_eq!(i, value);
+    }
+    assert_eq!(table.len(), offset);
+}
+
+#[test]
+fn overlay_range() {
+    let mut t: BtreeIndex<u64, String> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(4), 100).unwrap();
+    // Index contains the even numbers and shadows every 10th base entry
+    for i in (0..200).step_by(2) {
+        t.insert(i, format!("index{}", i)).unwrap();
+    }
+    let base: Vec<(u64, String)> = (0..200)
+        .filter(|i| i % 2 == 1 || i % 10 == 0)
+        .map(|i| (i, format!("base{}", i)))
+        .collect();
+
+    let expected: Vec<(u64, String)> = (0..200)
+        .map(|i| {
+            if i % 2 == 0 {
+                (i, format!("index{}", i))
+            } else {
+                (i, format!("base{}", i))
+            }
+        })
+        .collect();
+    let result: Result<Vec<_>> = t.overlay_range(.., base.clone()).unwrap().collect();
+    assert_eq!(expected, result.unwrap());
+
+    // Base entries outside the range are skipped
+    let result: Result<Vec<_>> = t.overlay_range(15..=40, base.clone()).unwrap().collect();
+    assert_eq!(&expected[15..=40], &result.unwrap()[..]);
+    let result: Result<Vec<_>> = t
+        .overlay_range((Bound::Excluded(15), Bound::Excluded(40)), base)
+        .unwrap()
+        .collect();
+    assert_eq!(&expected[16..40], &result.unwrap()[..]);
+
+    // Only base entries
+    let empty: BtreeIndex<u64, String> =
+        BtreeIndex::with_capacity(BtreeConfig::default(), 10).unwrap();
+    let result: Result<Vec<_>> = empty
+        .overlay_range(.., vec![(1, "a".to_string())])
+        .unwrap()
+        .collect();
+    assert_eq!(vec![(1, "a".to_string())], result.unwrap());
+}
+
+#[test]
+fn rank_and_select() {
+    let mut rng = seeded_rng();
+    let config = BtreeConfig::default().order(2).order_statistics(true);
+    let mut t: BtreeIndex<u32, u32> = BtreeIndex::with_capacity(config, 100).unwrap();
+    let expected = insert_random(&mut t, &mut rng, 2000, |rng| rng.gen_range(0..1500));
+    assert_eq!(expected.len(), t.len());
+
+    for (n, (key, value)) in expected.iter().enumerate() {
+        assert_eq!(n, t.rank(key).unwrap());
+        assert_eq!(Some((*key, *value)), t.select(n).unwrap());
+    }
+    for key in 0..1600 {
+        assert_eq!(expected.range(..key).count(), t.rank(&key).unwrap());
+    }
+    assert_eq!(None, t.select(expected.len()).unwrap());
+
+    // Queries fail without order statistics
+    let t: BtreeIndex<u32, u32> = BtreeIndex::with_capacity(BtreeConfig::default(), 10).unwrap();
+    assert!(matches!(t.rank(&0), Err(Error::OrderStatisticsDisabled)));
+    assert!(matches!(t.select(0), Err(Error::OrderStatisticsDisabled)));
+}
+
+#[test]
+fn range_len() {
+    for order_statistics in [false, true] {
+        let config = BtreeConfig::default()
+            .order(2)
+            .order_statistics(order_statistics);
+        let mut t: BtreeIndex<u32, u32> = BtreeIndex::with_capacity(config, 100).unwrap();
+        let mut expected = BTreeMap::new();
+        for i in 0..500 {
+            let key = (i * 7919) % 1000;
+            t.insert(key, i).unwrap();
+            expected.insert(key, i);
+        }
+
+        assert_eq!(expected.len(), t.range_len(..).unwrap());
+        for (start, end) in [(0, 0), (0, 1), (10, 20), (13, 997), (500, 2000)] {
+            assert_eq!(
+                expected.range(start..end).count(),
+                t.range_len(start..end).unwrap()
+            );
+            assert_eq!(
+                expected.range(start..=end).count(),
+                t.range_len(start..=end).unwrap()
+            );
+            assert_eq!(
+                expected
+                    .range((Bound::Excluded(start), Bound::Unbounded))
+                    .count(),
+                t.range_len((Bound::Excluded(start), Bound::Unbounded))
+                    .unwrap()
+            );
+            assert_eq!(expected.range(..end).count(), t.range_len(..end).unwrap());
+        }
+    }
+}
+
+#[test]
+fn prefix_range() {
+    let mut t: BtreeIndex<Vec<u8>, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(2), 100).unwrap();
+    let keys: Vec<Vec<u8>> = vec![
+        vec![],
+        vec![0x00],
+        vec![0x01],
+        vec![0x01, 0xFF],
+        vec![0x01, 0xFF, 0x00],
+        vec![0x01, 0xFF, 0xFF],
+        vec![0x02],
+        vec![0xFF],
+        vec![0xFF, 0x00],
+        vec![0xFF, 0xFF, 0xFF],
+    ];
+    for (i, k) in keys.iter().enumerate() {
+        t.insert(k.clone(), i as u32).unwrap();
+    }
+    let prefix_keys = |prefix: &[u8]| -> Vec<Vec<u8>> {
+        t.prefix_range(prefix)
+            .unwrap()
+            .map(|e| e.unwrap().0)
+            .collect()
+    };
+    for prefix in [
+        &[][..],
+        &[0x01],
+        &[0x01, 0xFF],
+        &[0x01, 0xFF, 0xFF],
+        &[0xFF],
+        &[0xFF, 0xFF],
+        &[0x03],
+    ] {
+        let expected: Vec<Vec<u8>> = keys
+            .iter()
+            .filter(|k| k.starts_with(prefix))
+            .cloned()
+            .collect();
+        assert_eq!(expected, prefix_keys(prefix));
+    }
+
+    let mut t: BtreeIndex<String, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(2), 100).unwrap();
+    let keys = [
+        "a",
+        "a\u{7F}",
+        "a\u{7F}b",
+        "a\u{80}",
+        "a\u{D7FF}",
+        "a\u{D7FF}x",
+        "a\u{E000}",
+        "a\u{10FFFF}",
+        "a\u{10FFFF}\u{10FFFF}",
+        "b",
+    ];
+    for (i, k) in keys.iter().enumerate() {
+        t.insert(k.to_string(), i as u32).unwrap();
+    }
+    for prefix in ["", "a", "a\u{7F}", "a\u{D7FF}", "a\u{10FFFF}", "c"] {
+        let expected: Vec<String> = keys
+            .iter()
+            .filter(|k| k.starts_with(prefix))
+            .map(|k| k.to_string())
+            .collect();
+        let result: Vec<String> = t
+            .prefix_range(prefix)
+            .unwrap()
+            .map(|e| e.unwrap().0)
+            .collect();
+        assert_eq!(expected, result);
+    }
+}
+
+#[test]
+fn ordered_key_bytes() {
+    for config in [
+        BtreeConfig::default().order(3),
+        BtreeConfig::default().order(3).fixed_key_size(8),
+    ] {
+        let mut rng = seeded_rng();
+        let mut t: BtreeIndex<[u8; 8], u32> = BtreeIndex::with_ordered_keys(config, 10).unwrap();
+        let mut expected = BTreeMap::new();
+        for i in 0..1000 {
+            let key: [u8; 8] = rng.gen();
+            t.insert(key, i).unwrap();
+            expected.insert(key, i);
+        }
+        for (key, value) in expected.iter() {
+            assert_eq!(Some(*value), t.get(key).unwrap());
+        }
+        assert_eq!(None, t.get(&[0; 8]).unwrap());
+
+        let start = [64; 8];
+        let end = [128; 8];
+        let result: Result<Vec<_>> = t.range(start..end).unwrap().collect();
+        let expected: Vec<_> = expected.range(start..end).map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(expected, result.unwrap());
+    }
+}
+
+#[test]
+fn verify_integrity() {
+    for order_statistics in [false, true] {
+        let config = BtreeConfig::default()
+            .order(3)
+            .order_statistics(order_statistics);
+        let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 100).unwrap();
+        t.verify_integrity().unwrap();
+        for i in 0..500 {
+            let key = (i * 7919) % 1000;
+            t.insert(key, key.to_string()).unwrap();
+        }
+        t.verify_integrity().unwrap();
+
+        t.nr_elements += 1;
+        assert!(matches!(
+            t.verify_integrity(),
+            Err(Error::IntegrityViolation(_))
+        ));
+        t.nr_elements -= 1;
+
+        // Swap two keys of a node to break the ordering
+        let node_id = t.nodes.get_child_node(t.root_id, 0).unwrap();
+        let first = t.nodes.get_key_owned(node_id, 0).unwrap();
+        let second = t.nodes.get_key_owned(node_id, 1).unwrap();
+        t.nodes.set_key_value(node_id, 0, &second).unwrap();
+        t.nodes.set_key_value(node_id, 1, &first).unwrap();
+        assert!(matches!(
+            t.verify_integrity(),
+            Err(Error::IntegrityViolation(_))
+        ));
+    }
+}
+
+#[cfg(feature = "graphviz")]
+#[test]
+fn dump_dot() {
+    let mut t: BtreeIndex<String, u64> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(2), 100).unwrap();
+    for i in 0..10 {
+        t.insert(format!("<{}>", i), i).unwrap();
+    }
+    let mut dot = Vec::new();
+    t.dump_dot(&mut dot).unwrap();
+    let dot = String::from_utf8(dot).unwrap();
+
+    assert!(dot.starts_with("digraph btree {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("\\\"\\<0\\>\\\""));
+    // Each node except the root has exactly one incoming edge
+    let nodes = dot.matches("[label=").count();
+    let edges = dot.matches(" -> ").count();
+    assert!(nodes > 1);
+    assert_eq!(nodes - 1, edges);
+}
+
+#[test]
+fn debug_summary() {
+    let config = BtreeConfig::default().order(2);
+    assert!(format!("{:?}", config).starts_with("BtreeConfig { order: Some(2),"));
+
+    let mut t: BtreeIndex<u32, u32> = BtreeIndex::with_capacity(config, 100).unwrap();
+    for i in 0..20 {
+        t.insert(i, i).unwrap();
+    }
+    let index_debug = format!("{:?}", t);
+    assert!(t.depth().unwrap() > 1);
+    assert!(index_debug.starts_with(&format!(
+        "BtreeIndex {{ len: 20, order: 2, depth: {},",
+        t.depth().unwrap()
+    )));
+    assert!(index_debug.contains("memory_usage: MemoryUsage {"));
+
+    let range = t.range(5..).unwrap();
+    assert_eq!("Range { finished: false, .. }", format!("{:?}", range));
+}
+
+#[test]
+fn auto_size_from_samples() {
+    let samples: Vec<(String, Vec<u8>)> = (0..50)
+        .map(|i| (format!("key{}", i), vec![0; i * 10]))
+        .collect();
+    let config = BtreeConfig::default()
+        .auto_size_from_samples(&samples)
+        .unwrap();
+    assert!(matches!(config.key_size, TypeSize::Estimated(6)));
+    assert!(matches!(config.value_size, TypeSize::Estimated(493)));
+
+    let mut t: BtreeIndex<String, Vec<u8>> =
+        BtreeIndex::with_capacity(config, samples.len()).unwrap();
+    for (key, value) in samples.iter() {
+        t.insert(key.clone(), value.clone()).unwrap();
+    }
+    assert_eq!(
+        0,
+        forwarded_payloads(&t, samples.into_iter().map(|(k, _)| k))
+    );
+
+    // Without samples, the default estimations are kept
+    let config = BtreeConfig::default()
+        .auto_size_from_samples::<u64, u64, _>(&[])
+        .unwrap();
+    assert!(matches!(config.key_size, TypeSize::Estimated(32)));
+}
+
+#[test]
+fn growth_strategy() {
+    for strategy in [
+        GrowthStrategy::Factor(1.25),
+        GrowthStrategy::Increment(4096),
+    ] {
+        let config = BtreeConfig::default().growth_strategy(strategy);
+        let mut t: BtreeIndex<u64, String> = BtreeIndex::with_capacity(config, 1).unwrap();
+        for i in 0..2000 {
+            t.insert(i, i.to_string()).unwrap();
+        }
+        for i in 0..2000 {
+            assert_eq!(Some(i.to_string()), t.get(&i).unwrap());
+        }
+    }
+}
+
+#[test]
+fn max_file_size() {
+    let config = BtreeConfig::default().max_file_size(16 * 4096);
+    let mut t: BtreeIndex<u64, Vec<u8>> = BtreeIndex::with_capacity(config, 1_000_000).unwrap();
+    assert!(t.memory_usage().total() <= 3 * 16 * 4096);
+
+    let mut result = Ok(None);
+    for i in 0..10_000 {
+        result = t.insert(i, vec![1; 64]);
+        if result.is_err() {
+            break;
+        }
+    }
+    assert!(matches!(
+        result,
+        Err(Error::MaximumFileSizeExceeded { maximum: 65536, .. })
+    ));
+}
+
+#[test]
+fn try_reserve() {
+    // Keys and values are serialized with their length, so they need 9 and 17 bytes
+    let config = BtreeConfig::default().max_key_size(9).max_value_size(17);
+    let mut t: BtreeIndex<String, String> = BtreeIndex::with_capacity(config, 10).unwrap();
+    let initial = t.memory_usage();
+    t.try_reserve(10_000).unwrap();
+    let reserved = t.memory_usage();
+    assert!(reserved.nodes > initial.nodes);
+    assert!(reserved.keys >= 10_000 * (9 + BlockHeader::size()));
+    assert!(reserved.values >= 10_000 * (17 + BlockHeader::size()));
+
+    // The files don't need to grow while inserting the reserved entries
+    let mut rng = seeded_rng();
+    for _ in 0..10_000 {
+        let key = format!("{:08}", rng.gen_range(0..100_000_000));
+        t.insert(key, "a".repeat(16)).unwrap();
+    }
+    let usage = t.memory_usage();
+    assert_eq!(reserved.nodes, usage.nodes);
+    assert_eq!(reserved.keys, usage.keys);
+    assert_eq!(reserved.values, usage.values);
+
+    // A failed reservation names the file and does not change the index
+    let config = BtreeConfig::default().max_file_size(1 << 20);
+    let mut t: BtreeIndex<u64, String> = BtreeIndex::with_capacity(config, 10).unwrap();
+    t.insert(1, "a".to_string()).unwrap();
+    let result = t.try_reserve(1 << 20);
+    match result {
+        Err(Error::ReserveFailed {
+            file,
+            requested,
+            source,
+        }) => {
+            assert_eq!("nodes", file);
+            assert!(requested > 1 << 20);
+            assert!(matches!(*source, Error::MaximumFileSizeExceeded { .. }));
+        }
+        _ => panic!("reserving should fail, but returned {:?}", result),
+    }
+    t.verify_integrity().unwrap();
+    assert_eq!(Some("a".to_string()), t.get(&1).unwrap());
+}
+
+#[test]
+fn poisoned_after_failed_insert() {
+    let config = BtreeConfig::default().max_file_size(16 * 4096);
+    let mut t: BtreeIndex<u64, Vec<u8>> = BtreeIndex::with_capacity(config, 100).unwrap();
+    // Errors that are detected before the index is changed don't poison it
+    assert!(t.try_reserve(1_000_000).is_err());
+    assert!(!t.is_poisoned());
+
+    let mut inserted = 0;
+    while t.insert(inserted, vec![1; 64]).is_ok() {
+        inserted += 1;
+    }
+    assert!(t.is_poisoned());
+    assert!(matches!(t.get(&0), Err(Error::Poisoned)));
+    assert!(matches!(t.insert(0, vec![2]), Err(Error::Poisoned)));
+    assert!(matches!(t.range(..), Err(Error::Poisoned)));
+    assert!(matches!(t.pop_first(), Err(Error::Poisoned)));
+    let mut drain = t.drain(..);
+    assert!(matches!(drain.next(), Some(Err(Error::Poisoned))));
+    assert!(drain.next().is_none());
+    assert!(format!("{:?}", t).contains("depth: Poisoned,"));
+
+    // The failed insert did not change the existing entries
+    t.verify_integrity().unwrap();
+    unsafe {
+        t.clear_poison();
+    }
+    assert_eq!(Some(vec![1; 64]), t.get(&0).unwrap());
+    assert_eq!(inserted as usize, t.len());
+
+    // Clearing the index makes a poisoned index usable again
+    assert!(t.insert(inserted, vec![1; 64]).is_err());
+    assert!(t.is_poisoned());
+    t.clear().unwrap();
+    assert!(!t.is_poisoned());
+    t.insert(1, vec![3]).unwrap();
+    assert_eq!(Some(vec![3]), t.get(&1).unwrap());
+}
+
+#[test]
+fn transaction() {
+    let mut t: BtreeIndex<u32, String> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 10).unwrap();
+    t.insert(0, "existing".to_string()).unwrap();
+
+    let staged = t
+        .transaction(|txn| {
+            for i in (1..100).rev() {
+                txn.insert(i, i.to_string());
+            }
+            assert_eq!(Some("existing".to_string()), txn.get(&0)?);
+            assert_eq!(Some("5".to_string()), txn.get(&5)?);
+            assert!(txn.contains_key(&99)?);
+            Ok(txn.staged_len())
+        })
+        .unwrap();
+    assert_eq!(99, staged);
+    assert_eq!(100, t.len());
+    assert_eq!(Some("42".to_string()), t.get(&42).unwrap());
+    t.verify_integrity().unwrap();
+
+    // A failing transaction does not change the index
+    let result = t.transaction(|txn| {
+        txn.insert(0, "replaced".to_string());
+        txn.insert(100, "new".to_string());
+        Err::<(), _>(Error::NonExistingKey)
+    });
+    assert!(matches!(result, Err(Error::NonExistingKey)));
+    assert_eq!(100, t.len());
+    assert_eq!(Some("existing".to_string()), t.get(&0).unwrap());
+    assert_eq!(None, t.get(&100).unwrap());
+
+    // Entries that can't be stored are detected before any entry is inserted
+    let config = BtreeConfig::default().fixed_value_size(4);
+    let mut t: BtreeIndex<u32, Vec<u8>> = BtreeIndex::with_capacity(config, 10).unwrap();
+    let result = t.transaction(|txn| {
+        txn.insert(1, vec![]);
+        txn.insert(2, vec![1, 2, 3, 4]);
+        Ok(())
+    });
+    assert!(matches!(
+        result,
+        Err(Error::FixedSizeMismatch {
+            expected: 4,
+            actual: 8
+        })
+    ));
+    assert!(t.is_empty());
+    assert!(!t.is_poisoned());
+}
+
+#[test]
+fn failed_transaction_commit_is_rolled_back() {
+    let config = BtreeConfig::default()
+        .order(3)
+        .max_value_size(8)
+        .max_file_size(1 << 20);
+    let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 10).unwrap();
+    let mut expected = BTreeMap::new();
+    for i in (0..100).step_by(2) {
+        t.insert(i, i.to_string()).unwrap();
+        expected.insert(i, i.to_string());
+    }
+    let used = |t: &BtreeIndex<u32, String>| {
+        let capacity = t.capacity();
+        (
+            capacity.nodes.used,
+            capacity.keys.used,
+            capacity.values.used,
+        )
+    };
+    let used_before = used(&t);
+
+    // The last staged entry is too large for the file, after all other entries have been inserted
+    let result = t.transaction(|txn| {
+        for i in 0..200 {
+            txn.insert(i, format!("staged {}", i));
+        }
+        txn.insert(u32::MAX, "x".repeat(1 << 20));
+        Ok(())
+    });
+    assert!(result.is_err());
+    assert!(!t.is_poisoned());
+    assert_eq!(expected.len(), t.len());
+    assert_eq!(used_before, used(&t));
+    t.verify_integrity().unwrap();
+    let entries: BTreeMap<u32, String> = t.range(..).unwrap().map(|e| e.unwrap()).collect();
+    assert_eq!(expected, entries);
+
+    // The index can still be changed after the rollback
+    t.transaction(|txn| {
+        txn.insert(1, "one".to_string());
+        txn.insert(2, "two".to_string());
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(Some("one".to_string()), t.get(&1).unwrap());
+    assert_eq!(Some("two".to_string()), t.get(&2).unwrap());
+    assert_eq!(expected.len() + 1, t.len());
+    t.verify_integrity().unwrap();
+}
+
+#[test]
+fn inline_fixed_size_keys() {
+    let config = BtreeConfig::default().fixed_key_size(4).order(3);
+    let mut t: BtreeIndex<i32, String> = BtreeIndex::with_capacity(config, 100).unwrap();
+    let config = BtreeConfig::default().fixed_key_size(8).order(3);
+    let mut pairs: BtreeIndex<(u32, u32), u32> = BtreeIndex::with_capacity(config, 100).unwrap();
+    let mut expected = BTreeMap::new();
+    for i in 0..500 {
+        let key = (i * 7919) % 1000 - 500;
+        t.insert(key, key.to_string()).unwrap();
+        pairs.insert((i as u32 % 10, i as u32), i as u32).unwrap();
+        expected.insert(key, key.to_string());
+    }
+    assert_eq!(0, t.memory_usage().keys);
+    assert_eq!(0, pairs.memory_usage().keys);
+    t.verify_integrity().unwrap();
+    pairs.verify_integrity().unwrap();
+
+    for (key, value) in expected.iter() {
+        assert_eq!(Some(value), t.get(key).unwrap().as_ref());
+    }
+    assert_eq!(None, t.get(&-501).unwrap());
+    let result: Result<Vec<_>> = t.range(-10..10).unwrap().collect();
+    let expected: Vec<_> = expected
+        .range(-10..10)
+        .map(|(k, v)| (*k, v.clone()))
+        .collect();
+    assert_eq!(expected, result.unwrap());
+    assert_eq!(Some(42), pairs.get(&(2, 42)).unwrap());
+    assert_eq!(None, pairs.get(&(4, 42)).unwrap());
+}
+
+#[test]
+fn inline_wide_fixed_size_keys() {
+    fn check<K>(key_size: usize, create_key: impl Fn(u64) -> K)
+    where
+        K: 'static + Serialize + DeserializeOwned + Clone + Ord + Send + Sync + Debug,
+    {
+        for config in [
+            BtreeConfig::default().order(3),
+            BtreeConfig::default(),
+            BtreeConfig::default().order_statistics(true),
+        ] {
+            let config = config.fixed_key_size(key_size);
+            let mut t: BtreeIndex<K, u64> = BtreeIndex::with_capacity(config, 100).unwrap();
+            let mut expected = BTreeMap::new();
+            for i in 0..2000 {
+                let key = create_key((i * 7919) % 5000);
+                t.insert(key.clone(), i).unwrap();
+                expected.insert(key, i);
+            }
+            assert_eq!(0, t.memory_usage().keys);
+            t.verify_integrity().unwrap();
+            for (key, value) in expected.iter() {
+                assert_eq!(Some(*value), t.get(key).unwrap());
+            }
+            assert_eq!(None, t.get(&create_key(5001)).unwrap());
+            let result: Result<Vec<_>> = t.range(..).unwrap().collect();
+            let expected: Vec<_> = expected.into_iter().collect();
+            assert_eq!(expected, result.unwrap());
+        }
+    }
+
+    check(9, |i| (i << 20, i as u8));
+    check(12, |i| (i as u32, i << 20));
+    check(15, |i| (i as u8, [i as u16; 3], i << 20));
+    check(16, |i| (i as u128) << 64 | 1);
+    check(16, |i| (i as i128 - 2500) << 64);
+    check(16, |i| ((i as u128) << 64).to_be_bytes());
+}
+
+#[test]
+fn with_fixed_size() {
+    use bincode::Options;
+
+    fn serialized_size<T: Serialize>(value: T) -> usize {
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .serialized_size(&value)
+            .unwrap() as usize
+    }
+    assert_eq!(serialized_size(1_usize), usize::SIZE);
+    assert_eq!(serialized_size(-1_i128), i128::SIZE);
+    assert_eq!(serialized_size([1_u16; 3]), <[u16; 3]>::SIZE);
+    assert_eq!(
+        serialized_size((1_u8, 2.0_f64, true)),
+        <(u8, f64, bool)>::SIZE
+    );
+
+    let mut t: BtreeIndex<(u32, u32), [u8; 16]> =
+        BtreeIndex::with_fixed_size(BtreeConfig::default().order(3), 10).unwrap();
+    for i in 0..100 {
+        t.insert((i % 7, i), [i as u8; 16]).unwrap();
+    }
+    for i in 0..100 {
+        assert_eq!(Some([i as u8; 16]), t.get(&(i % 7, i)).unwrap());
+    }
+    assert_eq!(0, t.memory_usage().keys);
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn derive_fixed_size() {
+    use crate::FixedSize;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(FixedSize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct CompositeId {
+        corpus: u32,
+        node: u32,
+    }
+
+    #[derive(FixedSize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct Measurement<T>(T, [u8; 3], bool);
+
+    assert_eq!(8, CompositeId::SIZE);
+    assert_eq!(12, Measurement::<f64>::SIZE);
+
+    let mut t: BtreeIndex<CompositeId, Measurement<f64>> =
+        BtreeIndex::with_fixed_size(BtreeConfig::default().order(3), 10).unwrap();
+    for i in 0..100 {
+        let key = CompositeId {
+            corpus: i % 3,
+            node: i,
+        };
+        t.insert(key, Measurement(i as f64, [1, 2, 3], i % 2 == 0))
+            .unwrap();
+    }
+    let key = CompositeId {
+        corpus: 1,
+        node: 10,
+    };
+    assert_eq!(
+        Some(Measurement(10.0, [1, 2, 3], true)),
+        t.get(&key).unwrap()
+    );
+    assert_eq!(0, t.memory_usage().keys);
+}
+
+#[test]
+fn wide_integer_keys() {
+    let mut unsigned: BtreeIndex<u128, u32> =
+        BtreeIndex::with_fixed_size(BtreeConfig::default().order(3), 10).unwrap();
+    let mut signed: BtreeIndex<i128, u32> =
+        BtreeIndex::with_fixed_size(BtreeConfig::default().order(3), 10).unwrap();
+    // UUIDs are stored as bytes, which have the same order as the UUIDs
+    let mut uuids: BtreeIndex<[u8; 16], u32> =
+        BtreeIndex::with_ordered_keys(BtreeConfig::default().fixed_key_size(16), 10).unwrap();
+    for i in 0..500_u32 {
+        let key = (i as u128 * 7919 % 1000) << 70 | i as u128;
+        unsigned.insert(key, i).unwrap();
+        signed.insert(key as i128 - (1 << 78), i).unwrap();
+        uuids.insert(key.to_be_bytes(), i).unwrap();
+    }
+    for i in 0..500_u32 {
+        let key = (i as u128 * 7919 % 1000) << 70 | i as u128;
+        assert_eq!(Some(i), unsigned.get(&key).unwrap());
+        assert_eq!(Some(i), signed.get(&(key as i128 - (1 << 78))).unwrap());
+        assert_eq!(Some(i), uuids.get(&key.to_be_bytes()).unwrap());
+    }
+    assert_eq!(None, unsigned.get(&1).unwrap());
+    assert_eq!(None, signed.get(&-1).unwrap());
+
+    let keys: Vec<_> = signed.range(..).unwrap().map(|e| e.unwrap().0).collect();
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    assert!(keys[0] < 0);
+}
+
+#[test]
+fn move_value() {
+    for order_statistics in [false, true] {
+        let config = BtreeConfig::default()
+            .order(2)
+            .order_statistics(order_statistics);
+        let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 10).unwrap();
+        for i in (0..100).step_by(2) {
+            t.insert(i, i.to_string().repeat(10)).unwrap();
+        }
+        let mapped = t.memory_usage().values;
+
+        // Move to new keys, which causes node splits
+        for i in (0..100).step_by(2) {
+            assert_eq!(None, t.move_value(&i, i + 1).unwrap());
+        }
+        assert_eq!(50, t.len());
+        for i in (0..100).step_by(2) {
+            assert_eq!(None, t.get(&i).unwrap());
+            assert_eq!(Some(i.to_string().repeat(10)), t.get(&(i + 1)).unwrap());
+        }
+        // The values have not been copied
+        assert_eq!(mapped, t.memory_usage().values);
+
+        // Move to an existing key
+        assert_eq!(Some("2".repeat(10)), t.move_value(&1, 3).unwrap());
+        assert_eq!(None, t.get(&1).unwrap());
+        assert_eq!(Some("0".repeat(10)), t.get(&3).unwrap());
+        assert_eq!(49, t.len());
+
+        // Move to the same key
+        assert_eq!(None, t.move_value(&3, 3).unwrap());
+        assert_eq!(Some("0".repeat(10)), t.get(&3).unwrap());
+
+        // Moved keys can be inserted again
+        t.insert(0, "new".to_string()).unwrap();
+        assert_eq!(Some("new".to_string()), t.get(&0).unwrap());
+        assert_eq!(Some("0".repeat(10)), t.get(&3).unwrap());
+
+        assert!(matches!(
+            t.move_value(&1000, 1001),
+            Err(Error::NonExistingKey)
+        ));
+        assert!(matches!(t.move_value(&1, 1001), Err(Error::NonExistingKey)));
+        assert_eq!(None, t.get(&1001).unwrap());
+        assert!(!t.is_poisoned());
+        t.verify_integrity().unwrap();
+    }
+}
+
+#[test]
+fn get_with_borrowed_key() {
+    for config in [
+        BtreeConfig::default().order(2),
+        BtreeConfig::default().order(2).fixed_key_size(12),
+        BtreeConfig::default().order(2).key_prefix_compression(true),
+    ] {
+        let mut t: BtreeIndex<Vec<u8>, u32> = BtreeIndex::with_capacity(config, 100).unwrap();
+        for i in (0..200_u32).step_by(2) {
+            t.insert(i.to_be_bytes().to_vec(), i).unwrap();
+        }
+        t.remove_range(10_u32.to_be_bytes().to_vec()..20_u32.to_be_bytes().to_vec())
+            .unwrap();
+        for i in 0..200_u32 {
+            let key = i.to_be_bytes();
+            let expected = (i % 2 == 0 && !(10..20).contains(&i)).then_some(i);
+            assert_eq!(expected, t.get(&key[..]).unwrap());
+            assert_eq!(expected.is_some(), t.contains_key(&key[..]).unwrap());
+        }
+    }
+}
+
+#[test]
+fn get_key_value() {
+    /// String that ignores the case when comparing
+    #[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+    struct CaseInsensitive(String);
+
+    impl PartialEq for CaseInsensitive {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+    impl Eq for CaseInsensitive {}
+    impl PartialOrd for CaseInsensitive {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl Ord for CaseInsensitive {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.0.to_lowercase().cmp(&other.0.to_lowercase())
+        }
+    }
+
+    let mut t: BtreeIndex<CaseInsensitive, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(2), 10).unwrap();
+    for (i, name) in ["Alice", "bob", "CAROL", "Dave", "eve"].iter().enumerate() {
+        t.insert(CaseInsensitive(name.to_string()), i as u32)
+            .unwrap();
+    }
+    let (key, value) = t
+        .get_key_value(&CaseInsensitive("carol".to_string()))
+        .unwrap()
+        .unwrap();
+    assert_eq!("CAROL", key.0);
+    assert_eq!(2, value);
+    assert!(t
+        .get_key_value(&CaseInsensitive("mallory".to_string()))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn any_in_range() {
+    let mut t: BtreeIndex<u32, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(2), 100).unwrap();
+    assert!(!t.any_in_range(..).unwrap());
+
+    let mut expected = BTreeMap::new();
+    for i in 0..200 {
+        let key = (i * 7919) % 1000;
+        t.insert(key, i).unwrap();
+        expected.insert(key, i);
+    }
+    for start in 0..1010 {
+        for len in [0, 1, 2, 5, 10] {
+            assert_eq!(
+                expected.range(start..(start + len)).next().is_some(),
+                t.any_in_range(start..(start + len)).unwrap()
+            );
+        }
+        assert_eq!(
+            expected.range(start..=start).next().is_some(),
+            t.any_in_range(start..=start).unwrap()
+        );
+        assert_eq!(
+            expected.range(start..).next().is_some(),
+            t.any_in_range(start..).unwrap()
+        );
+    }
+}
+
+#[test]
+fn range_page() {
+    for order_statistics in [false, true] {
+        let config = BtreeConfig::default()
+            .order(2)
+            .order_statistics(order_statistics);
+        let mut t: BtreeIndex<u32, u32> = BtreeIndex::with_capacity(config, 100).unwrap();
+        let mut expected = BTreeMap::new();
+        for i in 0..300 {
+            let key = (i * 7919) % 1000;
+            t.insert(key, i).unwrap();
+            expected.insert(key, i);
+        }
+        for (start, end) in [(0, 1000), (13, 500), (250, 251), (999, 1000)] {
+            for offset in [0, 1, 2, 7, 50, 120, 400] {
+                for limit in [0, 1, 10, 100] {
+                    let page: Vec<_> = t
+                        .range_page(start..end, offset, limit)
+                        .unwrap()
+                        .map(|e| e.unwrap())
+                        .collect();
+                    let expected_page: Vec<_> = expected
+                        .range(start..end)
+                        .skip(offset)
+                        .take(limit)
+                        .map(|(k, v)| (*k, *v))
+                        .collect();
+                    assert_eq!(expected_page, page);
+                }
+            }
+        }
+
+        let mut range = t.range(10..=20).unwrap();
+        let n = expected.range(10..=20).count();
+        assert_eq!(n, range.skip_entries(n + 5).unwrap());
+        assert!(range.next().is_none());
+    }
+}
+
+#[cfg(feature = "sample")]
+#[test]
+fn sample() {
+    for order_statistics in [false, true] {
+        let config = BtreeConfig::default()
+            .order(3)
+            .order_statistics(order_statistics);
+        let mut t: BtreeIndex<u32, u32> = BtreeIndex::with_capacity(config, 1000).unwrap();
+        let mut rng = seeded_rng();
+        assert!(t.sample(10, &mut rng).unwrap().is_empty());
+
+        for i in 0..1000 {
+            t.insert(i, i * 2).unwrap();
+        }
+        let samples = t.sample(20_000, &mut rng).unwrap();
+        assert_eq!(20_000, samples.len());
+
+        let mut buckets = [0; 10];
+        for (k, v) in samples {
+            assert_eq!(k * 2, v);
+            buckets[(k / 100) as usize] += 1;
+        }
+        for b in buckets {
+            assert!((1600..2400).contains(&b), "{:?}", buckets);
+        }
+    }
+}
+
+#[test]
+fn depth_and_level_widths() {
+    let order = 3;
+    let mut t: BtreeIndex<u32, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(order), 1000).unwrap();
+    assert_eq!(1, t.depth().unwrap());
+    assert_eq!(vec![1], t.level_widths().unwrap());
+
+    for i in 0..1000 {
+        t.insert(i, i).unwrap();
+    }
+    let widths = t.level_widths().unwrap();
+    assert_eq!(t.depth().unwrap(), widths.len());
+    assert!(widths.len() > 2);
+    assert_eq!(1, widths[0]);
+    for i in 1..widths.len() {
+        // Each inner node except the root has between order and 2 * order child nodes
+        let min_children = if i == 1 { 2 } else { order };
+        assert!(widths[i] >= widths[i - 1] * min_children);
+        assert!(widths[i] <= widths[i - 1] * 2 * order);
+    }
+}
+
+#[test]
+fn clear() {
+    let configs = [
+        BtreeConfig::default().order(3),
+        BtreeConfig::default().order(3).fixed_key_size(4),
+        BtreeConfig::default()
+            .order(3)
+            .max_key_size(16)
+            .key_prefix_compression(true),
+    ];
+    for config in configs {
+        let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 500).unwrap();
+        for i in 0..500 {
+            t.insert(i, format!("a{}", i + 500)).unwrap();
+        }
+        let usage = t.memory_usage();
+
+        t.clear().unwrap();
+        assert!(t.is_empty());
+        assert_eq!(None, t.get(&10).unwrap());
+        assert_eq!(0, t.range(..).unwrap().count());
+        t.verify_integrity().unwrap();
+
+        // Insert different entries, which reuse the already allocated files
+        for i in 500..1000 {
+            t.insert(i, format!("b{}", i)).unwrap();
+        }
+        assert_eq!(500, t.len());
+        assert_eq!(None, t.get(&10).unwrap());
+        assert_eq!(Some("b600".to_string()), t.get(&600).unwrap());
+        t.verify_integrity().unwrap();
+
+        let new_usage = t.memory_usage();
+        assert_eq!(usage.nodes, new_usage.nodes);
+        assert_eq!(usage.keys, new_usage.keys);
+        assert_eq!(usage.values, new_usage.values);
+    }
+}
+
+#[test]
+fn split_off() {
+    for order_statistics in [false, true] {
+        let config = BtreeConfig::default()
+            .order(3)
+            .order_statistics(order_statistics);
+        let mut expected = BTreeMap::new();
+        let mut t: BtreeIndex<u32, u32> = BtreeIndex::with_capacity(config, 200).unwrap();
+        for i in 0..200 {
+            let key = (i * 7919) % 1000;
+            t.insert(key, i).unwrap();
+            expected.insert(key, i);
+        }
+
+        for split in [500, 0, 999, 250] {
+            let upper = t.split_off(&split).unwrap();
+            let expected_upper = expected.split_off(&split);
+
+            for (index, expected) in [(&t, &expected), (&upper, &expected_upper)] {
+                index.verify_integrity().unwrap();
+                assert_eq!(expected.len(), index.len());
+                let entries: Vec<_> = index.range(..).unwrap().map(|e| e.unwrap()).collect();
+                let expected_entries: Vec<_> = expected.iter().map(|(k, v)| (*k, *v)).collect();
+                assert_eq!(expected_entries, entries);
+            }
+            assert_eq!(order_statistics, upper.nodes.has_child_counts());
+        }
+    }
+}
+
+#[test]
+fn freeze() {
+    let mut t: BtreeIndex<u32, String> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 100).unwrap();
+    let mut expected = BTreeMap::new();
+    for i in 0..500 {
+        t.insert(i, format!("value {}", i)).unwrap();
+        expected.insert(i, format!("value {}", i));
+    }
+    // Relocate some of the values
+    for i in (0..500).step_by(3) {
+        let value = format!("{} with a much longer value", i);
+        t.insert(i, value.clone()).unwrap();
+        expected.insert(i, value);
+    }
+    assert!(forwarded_payloads(&t, 0..500) > 0);
+
+    let frozen = t.freeze().unwrap();
+    assert_eq!(0, forwarded_payloads(&frozen.index, 0..500));
+    assert_eq!(expected.len(), frozen.len());
+
+    expected.par_iter().for_each(|(k, v)| {
+        assert_eq!(Some(v), frozen.get(k).unwrap().as_ref());
+    });
+    let entries: Vec<_> = frozen.range(..).unwrap().map(|e| e.unwrap()).collect();
+    let expected_entries: Vec<_> = expected.into_iter().collect();
+    assert_eq!(expected_entries, entries);
+}
+
+#[test]
+fn iterators_are_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    let mut t: BtreeIndex<u32, String> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 100).unwrap();
+    for i in 0..100 {
+        t.insert(i, i.to_string()).unwrap();
+    }
+
+    let lower = t.range(..50).unwrap();
+    let upper = t.range(50..).unwrap();
+    assert_send_sync(&lower);
+    let (lower, upper) = std::thread::scope(|s| {
+        let lower = s.spawn(move || lower.map(|e| e.unwrap().0).collect::<Vec<_>>());
+        let upper = s.spawn(move || upper.map(|e| e.unwrap().0).collect::<Vec<_>>());
+        (lower.join().unwrap(), upper.join().unwrap())
+    });
+    assert_eq!((0..50).collect::<Vec<_>>(), lower);
+    assert_eq!((50..100).collect::<Vec<_>>(), upper);
+
+    let it = t.into_iter().unwrap();
+    assert_send_sync(&it);
+    let values = std::thread::spawn(move || it.map(|e| e.unwrap().1).collect::<Vec<_>>())
+        .join()
+        .unwrap();
+    assert_eq!(100, values.len());
+}
+
+#[test]
+fn keys_and_values_without_send_and_sync() {
+    /// Key that can't be shared between threads, like types containing an `Rc`
+    #[derive(
+        serde_derive::Serialize,
+        serde_derive::Deserialize,
+        Clone,
+        Debug,
+        PartialEq,
+        Eq,
+        PartialOrd,
+        Ord,
+    )]
+    struct LocalKey(u32, #[serde(skip)] PhantomData<std::rc::Rc<()>>);
+
+    let key = |i| LocalKey(i, PhantomData);
+    let mut t: BtreeIndex<LocalKey, std::cell::Cell<u32>> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 100).unwrap();
+    for i in 0..100 {
+        t.insert(key(i), std::cell::Cell::new(i * 2)).unwrap();
+    }
+    assert_eq!(Some(20), t.get(&key(10)).unwrap().map(|v| v.get()));
+    assert_eq!(10, t.remove_range(key(0)..key(10)).unwrap());
+    let entries: Vec<_> = t
+        .range(key(10)..key(15))
+        .unwrap()
+        .map(|e| e.unwrap().1.get())
+        .collect();
+    assert_eq!(vec![20, 22, 24, 26, 28], entries);
+
+    let t = t.compact().unwrap();
+    t.verify_integrity().unwrap();
+    assert_eq!(90, t.into_iter().unwrap().count());
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn range_stream() {
+    use futures_core::Stream;
+    use futures_util::task::noop_waker;
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    let mut t: BtreeIndex<u32, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 100).unwrap();
+    for i in 0..100 {
+        t.insert(i, i * 2).unwrap();
+    }
+
+    let mut stream = t.range_stream(5..).unwrap().chunk_size(10);
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut entries = Vec::new();
+    let mut yields = 0;
+    loop {
+        match Pin::new(&mut stream).poll_next(&mut cx) {
+            Poll::Ready(Some(e)) => entries.push(e.unwrap()),
+            Poll::Ready(None) => break,
+            Poll::Pending => yields += 1,
+        }
+    }
+    assert_eq!((5..100).map(|i| (i, i * 2)).collect::<Vec<_>>(), entries);
+    assert_eq!(9, yields);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_events() {
+    use std::sync::{Arc, Mutex};
+    use tracing::{
+        field::{Field, Visit},
+        span, Event, Metadata, Subscriber,
+    };
+
+    /// Collects the messages of all events.
+    struct MessageCollector(Arc<Mutex<Vec<String>>>);
+
+    impl Visit for MessageCollector {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            if field.name() == "message" {
+                self.0.lock().unwrap().push(format!("{:?}", value));
+            }
+        }
+    }
+
+    struct TestSubscriber(Arc<Mutex<Vec<String>>>);
+
+    impl Subscriber for TestSubscriber {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+            span::Id::from_u64(1)
+        }
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+        fn event(&self, event: &Event<'_>) {
+            event.record(&mut MessageCollector(self.0.clone()));
+        }
+        fn enter(&self, _: &span::Id) {}
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    tracing::subscriber::with_default(TestSubscriber(messages.clone()), || {
+        let config = BtreeConfig::default().order(3).block_cache_size(2);
+        let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 10).unwrap();
+        for i in 0..1000 {
+            t.insert(i, format!("value {}", i)).unwrap();
+        }
+        t.insert(1, "a much longer value".repeat(10)).unwrap();
+    });
+
+    let messages = messages.lock().unwrap();
+    for expected in [
+        "split node",
+        "grow storage",
+        "relocate block",
+        "evict cached block",
+    ] {
+        assert!(
+            messages.iter().any(|m| m == expected),
+            "missing event {}",
+            expected
+        );
+    }
+}
+
+#[test]
+fn metrics_observer() {
+    use crate::BtreeMetricsObserver;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct Counter {
+        splits: AtomicUsize,
+        grown: AtomicUsize,
+        relocations: AtomicUsize,
+        evictions: AtomicUsize,
+    }
+
+    impl BtreeMetricsObserver for Counter {
+        fn on_split(&self, node_id: u64, new_node_id: u64) {
+            assert_ne!(node_id, new_node_id);
+            self.splits.fetch_add(1, Ordering::Relaxed);
+        }
+        fn on_grow(&self, old_size: usize, new_size: usize) {
+            assert!(old_size < new_size);
+            self.grown.fetch_add(1, Ordering::Relaxed);
+        }
+        fn on_relocate(&self, _block_id: usize, old_capacity: usize, new_capacity: usize) {
+            assert!(old_capacity < new_capacity);
+            self.relocations.fetch_add(1, Ordering::Relaxed);
+        }
+        fn on_cache_eviction(&self, _block_id: usize, _size: usize) {
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let counter = Arc::new(Counter::default());
+    let config = BtreeConfig::default()
+        .order(3)
+        .block_cache_size(2)
+        .metrics_observer(counter.clone());
+    let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 10).unwrap();
+    for i in 0..1000 {
+        t.insert(i, format!("value {}", i)).unwrap();
+    }
+    assert_eq!(0, counter.relocations.load(Ordering::Relaxed));
+    t.insert(1, "a much longer value".repeat(10)).unwrap();
+
+    // Each split creates one new node, splitting the root node also creates a new root
+    let number_of_nodes: usize = t.level_widths().unwrap().iter().sum();
+    assert!(counter.splits.load(Ordering::Relaxed) > 0);
+    assert!(counter.splits.load(Ordering::Relaxed) < number_of_nodes);
+    assert!(counter.grown.load(Ordering::Relaxed) > 0);
+    assert_eq!(1, counter.relocations.load(Ordering::Relaxed));
+    assert!(counter.evictions.load(Ordering::Relaxed) > 0);
+}
+
+#[test]
+fn fixint_values_avoid_relocation() {
+    let small = || "small".to_string();
+    let samples: Vec<(u32, (u64, u64, String))> = vec![(0, (1, 2, small()))];
+    for (codec, relocated) in [
+        (ValueCodec::Bincode, true),
+        (ValueCodec::BincodeFixint, false),
+    ] {
+        let config = BtreeConfig::default()
+            .value_codec(codec)
+            .auto_size_from_samples(&samples)
+            .unwrap();
+        let mut t: BtreeIndex<u32, (u64, u64, String)> =
+            BtreeIndex::with_capacity(config, 100).unwrap();
+        for i in 0..100 {
+            t.insert(i, (1, 2, small())).unwrap();
+        }
+        for i in 0..100 {
+            t.insert(i, (u64::MAX, u64::from(i), small())).unwrap();
+        }
+        assert_eq!(relocated, forwarded_payloads(&t, 0..100) > 0);
+        for i in 0..100 {
+            assert_eq!(Some((u64::MAX, u64::from(i), small())), t.get(&i).unwrap());
+        }
+    }
+}
+
+#[test]
+fn fixed_size_mismatch() {
+    let config = BtreeConfig::default().fixed_value_size(16);
+    let mut t: BtreeIndex<u32, u64> = BtreeIndex::with_capacity(config, 10).unwrap();
+    assert!(matches!(
+        t.insert(1, 2),
+        Err(Error::FixedSizeMismatch {
+            expected: 16,
+            actual: 8
+        })
+    ));
+    assert!(t.is_empty());
+    assert_eq!(None, t.get(&1).unwrap());
+
+    // Keys which are stored inside the nodes are checked, too
+    let config = BtreeConfig::default().fixed_key_size(4);
+    let mut t: BtreeIndex<u64, u64> = BtreeIndex::with_capacity(config, 10).unwrap();
+    assert!(matches!(
+        t.insert(1, 2),
+        Err(Error::FixedSizeMismatch {
+            expected: 4,
+            actual: 8
+        })
+    ));
+    assert!(t.is_empty());
+}
+
+#[test]
+fn inline_values() {
+    // Values with a fixed size of up to 7 bytes never need a block in the value file
+    let config = BtreeConfig::default().order(3).fixed_value_size(4);
+    let mut t: BtreeIndex<u64, u32> = BtreeIndex::with_capacity(config, 1000).unwrap();
+    let initial = t.memory_usage();
+    assert!(initial.values < 1000 * 4);
+    for i in 0..1000 {
+        t.insert(i, (i as u32) * 7).unwrap();
+    }
+    assert!(t.update(&10, |v| *v = u32::MAX).unwrap());
+    assert_eq!(initial.values, t.memory_usage().values);
+    assert_eq!(Some(u32::MAX), t.get(&10).unwrap());
+    assert_eq!(Some(21), t.get(&3).unwrap());
+    t.verify_integrity().unwrap();
+
+    // Small values of a variable size are moved into a block when they grow
+    let config = BtreeConfig::default().order(3);
+    let mut t: BtreeIndex<u32, Option<String>> = BtreeIndex::with_capacity(config, 100).unwrap();
+    let mut expected = BTreeMap::new();
+    for i in 0..500 {
+        t.insert(i, None).unwrap();
+        expected.insert(i, None);
+    }
+    for i in (0..500).step_by(3) {
+        let value = Some(format!("a longer value for {}", i));
+        t.insert(i, value.clone()).unwrap();
+        expected.insert(i, value);
+    }
+    for i in (0..500).step_by(6) {
+        t.update(&i, |v| *v = Some("a".to_string())).unwrap();
+        expected.insert(i, Some("a".to_string()));
+    }
+    t.verify_integrity().unwrap();
+    let entries: Vec<_> = t.range(..).unwrap().map(|e| e.unwrap()).collect();
+    assert_eq!(expected.clone().into_iter().collect::<Vec<_>>(), entries);
+
+    let frozen = t.freeze().unwrap();
+    for (k, v) in expected {
+        assert_eq!(Some(v), frozen.get(&k).unwrap());
+    }
+}
+
+#[test]
+fn deduplicate_values() {
+    let categories: Vec<String> = (0..20).map(|c| format!("category number {}", c)).collect();
+    let mut indexes = Vec::new();
+    for deduplicate in [false, true] {
+        let config = BtreeConfig::default()
+            .order(3)
+            .max_value_size(8)
+            .deduplicate_values(deduplicate);
+        let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 100).unwrap();
+        let mut expected = BTreeMap::new();
+        for i in 0..5000 {
+            let value = categories[i as usize % categories.len()].clone();
+            t.insert(i, value.clone()).unwrap();
+            expected.insert(i, value);
+        }
+        // Changing a shared value must not change the value of the other keys
+        t.update(&0, |v| v.push_str(" (changed)")).unwrap();
+        expected.insert(0, format!("{} (changed)", categories[0]));
+        t.insert(1, categories[2].clone()).unwrap();
+        expected.insert(1, categories[2].clone());
+
+        t.verify_integrity().unwrap();
+        let entries: Vec<_> = t.range(..).unwrap().map(|e| e.unwrap()).collect();
+        assert_eq!(expected.into_iter().collect::<Vec<_>>(), entries);
+        indexes.push(t);
+    }
+    let usage = indexes[0].memory_usage();
+    let deduplicated_usage = indexes[1].memory_usage();
+    assert_eq!(0, usage.value_hashes);
+    assert!(deduplicated_usage.value_hashes > 0);
+    assert!(deduplicated_usage.values < usage.values);
+}
+
+#[test]
+fn payload_ids() {
+    let config = BtreeConfig::default().order(3).max_value_size(8);
+    let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config.clone(), 100).unwrap();
+    // Secondary index from the length of the value to the payloads
+    let mut by_length: BTreeMap<usize, Vec<PayloadId>> = BTreeMap::new();
+    for i in 0..500 {
+        let value = "x".repeat(i as usize % 50);
+        let id = t.insert_returning_id(i, value.clone()).unwrap();
+        assert_eq!(Some(id), t.payload_id(&i).unwrap());
+        by_length.entry(value.len()).or_default().push(id);
+    }
+    assert_eq!(None, t.payload_id(&1000).unwrap());
+    for (length, ids) in by_length.iter() {
+        assert_eq!(10, ids.len());
+        for id in ids {
+            assert_eq!(*length, t.get_by_payload(*id).unwrap().len());
+        }
+    }
+
+    // Replacing a value with a larger one keeps the ID valid
+    let id = t
+        .insert_returning_id(0, "a much larger value".to_string())
+        .unwrap();
+    assert_eq!("a much larger value", t.get_by_payload(id).unwrap());
+
+    // IDs of another index are rejected
+    let mut other: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 100).unwrap();
+    other.insert(0, "other value".to_string()).unwrap();
+    let other_id = other.payload_id(&0).unwrap().unwrap();
+    assert!(matches!(
+        t.get_by_payload(other_id),
+        Err(Error::InvalidPayloadId)
+    ));
+
+    // Draining an entry frees its block, which invalidates the ID even after the block is reused
+    let id = t.payload_id(&20).unwrap().unwrap();
+    assert_eq!(1, t.drain(20..=20).count());
+    assert!(matches!(t.get_by_payload(id), Err(Error::InvalidPayloadId)));
+    t.insert(1000, "x".repeat(20)).unwrap();
+    assert_eq!(
+        Some(id.payload),
+        t.payload_id(&1000).unwrap().map(|id| id.payload)
+    );
+    assert!(matches!(t.get_by_payload(id), Err(Error::InvalidPayloadId)));
+
+    // Clearing the index invalidates all IDs
+    t.clear().unwrap();
+    assert!(matches!(t.get_by_payload(id), Err(Error::InvalidPayloadId)));
+}
+
+#[test]
+fn remove_range() {
+    for order_statistics in [false, true] {
+        let config = BtreeConfig::default()
+            .order(3)
+            .order_statistics(order_statistics);
+        let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 1000).unwrap();
+        let mut expected = BTreeMap::new();
+        for i in 0..1000 {
+            t.insert(i, format!("value {}", i)).unwrap();
+            expected.insert(i, format!("value {}", i));
+        }
+
+        assert_eq!(100, t.remove_range(100..200).unwrap());
+        assert_eq!(1, t.remove_range(500..=500).unwrap());
+        // Removing the same range again does not change anything
+        assert_eq!(0, t.remove_range(150..200).unwrap());
+        expected.retain(|k, _| !(100..200).contains(k) && *k != 500);
+        t.verify_integrity().unwrap();
+
+        assert_eq!(expected.len(), t.len());
+        assert_eq!(None, t.get(&150).unwrap());
+        assert!(!t.contains_key(&500).unwrap());
+        assert_eq!(Some("value 200".to_string()), t.get(&200).unwrap());
+        assert_eq!(100, t.range_len(50..250).unwrap());
+        assert!(!t.any_in_range(100..200).unwrap());
+        let entries: Vec<_> = t.range(..).unwrap().map(|e| e.unwrap()).collect();
+        assert_eq!(expected.clone().into_iter().collect::<Vec<_>>(), entries);
+        let mut page = t.range(90..).unwrap();
+        assert_eq!(20, page.skip_entries(20).unwrap());
+        assert_eq!(
+            Some((210, "value 210".to_string())),
+            page.next().transpose().unwrap()
+        );
+        if order_statistics {
+            assert_eq!(100, t.rank(&150).unwrap());
+            assert_eq!(Some((200, "value 200".to_string())), t.select(100).unwrap());
+        }
+
+        // Removed keys can be inserted again
+        assert_eq!(None, t.insert(150, "new value".to_string()).unwrap());
+        expected.insert(150, "new value".to_string());
+        assert_eq!(expected.len(), t.len());
+        assert_eq!(Some("new value".to_string()), t.get(&150).unwrap());
+        t.verify_integrity().unwrap();
+
+        let entries: Vec<_> = t.into_iter().unwrap().map(|e| e.unwrap()).collect();
+        assert_eq!(expected.into_iter().collect::<Vec<_>>(), entries);
+    }
+}
+
+#[test]
+fn retain() {
+    for order_statistics in [false, true] {
+        let config = BtreeConfig::default()
+            .order(3)
+            .order_statistics(order_statistics);
+        let mut t: BtreeIndex<u32, u32> = BtreeIndex::with_capacity(config, 1000).unwrap();
+        for i in 0..1000 {
+            t.insert(i, i % 7).unwrap();
+        }
+        t.remove_range(0..10).unwrap();
+
+        let mut visited = Vec::new();
+        let removed = t
+            .retain(|k, v| {
+                visited.push(*k);
+                *v != 0
+            })
+            .unwrap();
+        // Removed entries are not visited again
+        assert_eq!((10..1000).collect::<Vec<_>>(), visited);
+        let expected: Vec<_> = (10..1000)
+            .filter(|i| i % 7 != 0)
+            .map(|i| (i, i % 7))
+            .collect();
+        assert_eq!(990 - expected.len(), removed);
+        assert_eq!(expected.len(), t.len());
+        t.verify_integrity().unwrap();
+
+        let entries: Vec<_> = t.range(..).unwrap().map(|e| e.unwrap()).collect();
+        assert_eq!(expected, entries);
+    }
+}
+
+#[test]
+fn relocation_policy() {
+    let relocations = |policy: RelocationPolicy| {
+        let config = BtreeConfig::default()
+            .max_value_size(8)
+            .page_size(64)
+            .relocation_policy(policy);
+        let mut t: BtreeIndex<u32, Vec<u32>> = BtreeIndex::with_capacity(config, 100).unwrap();
+        for i in 0..100 {
+            t.insert(i, vec![i; 2]).unwrap();
+        }
+        // Grow each value many times
+        for length in (4..1000).step_by(8) {
+            for i in 0..100 {
+                t.insert(i, vec![i; length]).unwrap();
+            }
+        }
+        for i in 0..100 {
+            assert_eq!(Some(vec![i; 996]), t.get(&i).unwrap());
+        }
+        t.relocation_stats()
+    };
+
+    let default = relocations(RelocationPolicy::default());
+    assert!(default.relocations > 100);
+    assert!(default.repeated_relocations > 0);
+    assert!(default.abandoned_bytes > 0);
+
+    let aggressive = relocations(RelocationPolicy {
+        factor: 2,
+        repeated_after: 2,
+        repeated_factor: 4,
+    });
+    assert!(aggressive.relocations < default.relocations);
+    assert!(aggressive.abandoned_bytes < default.abandoned_bytes);
+}
+
+#[test]
+fn compact() {
+    let config = BtreeConfig::default().order(3).max_value_size(8);
+    let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 1000).unwrap();
+    for i in 0..1000 {
+        t.insert(i, format!("value {}", i)).unwrap();
+    }
+    // Growing values are relocated into larger blocks
+    for round in 1..4 {
+        for i in 0..1000 {
+            t.insert(i, format!("value {} ", i).repeat(round * 10))
+                .unwrap();
+        }
+    }
+    t.remove_range(500..).unwrap();
+    let usage = t.memory_usage();
+    assert!(forwarded_payloads(&t, 0..500) > 0);
+    assert!(t.relocation_stats().abandoned_bytes > 0);
+
+    let t = t.compact().unwrap();
+    assert_eq!(RelocationStats::default(), t.relocation_stats());
+    t.verify_integrity().unwrap();
+    let compacted_usage = t.memory_usage();
+    assert_eq!(0, forwarded_payloads(&t, 0..500));
+    assert!(compacted_usage.values < usage.values);
+    assert!(compacted_usage.nodes < usage.nodes);
+
+    assert_eq!(500, t.len());
+    let entries: Vec<_> = t.range(..).unwrap().map(|e| e.unwrap()).collect();
+    let expected: Vec<_> = (0..500)
+        .map(|i| (i, format!("value {} ", i).repeat(30)))
+        .collect();
+    assert_eq!(expected, entries);
+}
+
+#[test]
+fn cancel_long_running_operations() {
+    let mut t: BtreeIndex<u32, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 1000).unwrap();
+    for i in 0..1000 {
+        t.insert(i, i).unwrap();
+    }
+    let token = CancellationToken::new();
+
+    let mut range = t.range(10..).unwrap().cancel_on(token.clone());
+    let first: Vec<_> = range.by_ref().take(5).map(|e| e.unwrap()).collect();
+    assert_eq!(
+        vec![(10, 10), (11, 11), (12, 12), (13, 13), (14, 14)],
+        first
+    );
+    token.cancel();
+    assert!(matches!(range.next(), Some(Err(Error::Cancelled))));
+    assert!(range.next().is_none());
+
+    let t = t.compact_cancellable(&CancellationToken::new()).unwrap();
+    assert_eq!(1000, t.len());
+    let mut iter = t.into_iter().unwrap().cancel_on(token.clone());
+    assert!(matches!(iter.next(), Some(Err(Error::Cancelled))));
+    assert!(iter.next().is_none());
+    assert_eq!(0, iter.len());
+
+    let mut t: BtreeIndex<u32, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 10).unwrap();
+    t.insert(1, 1).unwrap();
+    assert!(matches!(
+        t.compact_cancellable(&token),
+        Err(Error::Cancelled)
+    ));
+}
+
+#[test]
+fn shrink_to_fit() {
+    let config = BtreeConfig::default().order(3).max_value_size(8);
+    let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 100_000).unwrap();
+    for i in 0..1000 {
+        t.insert(i, format!("value {}", i)).unwrap();
+    }
+    let usage = t.memory_usage();
+    t.shrink_to_fit().unwrap();
+    let shrunk_usage = t.memory_usage();
+    assert!(shrunk_usage.nodes < usage.nodes);
+    assert!(shrunk_usage.keys < usage.keys);
+    assert!(shrunk_usage.values < usage.values);
+
+    // The index grows again for new entries
+    for i in 1000..2000 {
+        t.insert(i, format!("value {}", i)).unwrap();
+    }
+    t.verify_integrity().unwrap();
+    let entries: Vec<_> = t.range(..).unwrap().map(|e| e.unwrap()).collect();
+    let expected: Vec<_> = (0..2000).map(|i| (i, format!("value {}", i))).collect();
+    assert_eq!(expected, entries);
+}
+
+#[test]
+fn page_size() {
+    // The page size must be a power of two and fit to the node block size
+    assert!(
+        BtreeIndex::<u32, String>::with_capacity(BtreeConfig::default().page_size(5000), 10)
+            .is_err()
+    );
+    assert!(BtreeIndex::<u32, String>::with_capacity(
+        BtreeConfig::default()
+            .page_size(16384)
+            .node_block_size(12288),
+        10
+    )
+    .is_err());
+
+    // Node blocks can be smaller than a page, as long as they don't overlap two pages
+    let config = BtreeConfig::default()
+        .page_size(16384)
+        .order(3)
+        .max_value_size(8);
+    let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 100_000).unwrap();
+    for i in 0..1000 {
+        t.insert(i, format!("value {}", i)).unwrap();
+    }
+    // Values that grow are relocated to blocks ending at a page
+    t.insert(0, "updated".repeat(100)).unwrap();
+    t.shrink_to_fit().unwrap();
+    let usage = t.memory_usage();
+    assert_eq!(0, usage.nodes % 16384);
+    assert_eq!(0, usage.keys % 16384);
+    assert_eq!(0, usage.values % 16384);
+
+    t.verify_integrity().unwrap();
+    assert_eq!(Some("updated".repeat(100)), t.get(&0).unwrap());
+    assert_eq!(Some("value 999".to_string()), t.get(&999).unwrap());
+}
+
+#[test]
+fn block_alignment() {
+    assert!(BtreeIndex::<u32, String>::with_capacity(
+        BtreeConfig::default().block_alignment(12),
+        10
+    )
+    .is_err());
+
+    let config = BtreeConfig::default().block_alignment(64).max_value_size(8);
+    let mut t: BtreeIndex<String, String> = BtreeIndex::with_capacity(config, 100).unwrap();
+    for i in 0..1000 {
+        t.insert(format!("key {}", i), "a".repeat(i % 100)).unwrap();
+    }
+    t.verify_integrity().unwrap();
+    for i in 0..1000 {
+        let key = format!("key {}", i);
+        assert_eq!(Some("a".repeat(i % 100)), t.get(&key).unwrap());
+        let id = t.payload_id(&key).unwrap().unwrap();
+        if !payload::is_inline(id.payload) {
+            assert_eq!(0, (id.payload as usize + BlockHeader::size()) % 64);
+        }
+    }
+}
+
+#[test]
+fn prefault() {
+    let config = BtreeConfig::default().order(3).prefault(true);
+    let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 1000).unwrap();
+    assert!(t.memory_usage().nodes > 0);
+    for i in 0..1000 {
+        t.insert(i, format!("value {}", i)).unwrap();
+    }
+    t.verify_integrity().unwrap();
+    assert_eq!(Some("value 500".to_string()), t.get(&500).unwrap());
+}
+
+#[test]
+fn huge_pages() {
+    let config = BtreeConfig::default().order(3).huge_pages(true);
+    let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 1000).unwrap();
+    assert_eq!(0, t.memory_usage().nodes % crate::HUGE_PAGE_SIZE);
+    for i in 0..1000 {
+        t.insert(i, format!("value {}", i)).unwrap();
+    }
+    t.verify_integrity().unwrap();
+    assert_eq!(Some("value 500".to_string()), t.get(&500).unwrap());
+}
+
+#[test]
+fn node_key_cache() {
+    for (cache_size, leaf_cache_size, prefix_compression) in [
+        (0, 0, false),
+        (1, 0, false),
+        (0, 1, false),
+        (64, 16, false),
+        (64, 16, true),
+    ] {
+        let mut rng = seeded_rng();
+        let config = BtreeConfig::default()
+            .order(3)
+            .node_key_cache_size(cache_size)
+            .leaf_key_cache_size(leaf_cache_size)
+            .key_prefix_compression(prefix_compression);
+        let mut t: BtreeIndex<String, u32> = BtreeIndex::with_capacity(config, 1024).unwrap();
+        let mut m = BTreeMap::new();
+        for i in 0..2000 {
+            let key = format!("key {}", rng.gen_range(0..1500));
+            // Searches between the inserts fill the cache with keys of nodes that are split later
+            assert_eq!(m.get(&key).copied(), t.get(&key).unwrap());
+            m.insert(key.clone(), i);
+            t.insert(key, i).unwrap();
+        }
+        t.verify_integrity().unwrap();
+        for i in 0..1600 {
+            let key = format!("key {}", i);
+            assert_eq!(m.get(&key).copied(), t.get(&key).unwrap());
+        }
+        t.clear().unwrap();
+        assert_eq!(None, t.get(&"key 1".to_string()).unwrap());
+    }
+}
+
+#[test]
+fn cache_eviction_policy() {
+    let config = BtreeConfig::default()
+        .block_cache_size(8)
+        .cache_eviction_policy(|| Box::new(crate::SegmentedLruPolicy::default()));
+    let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 1000).unwrap();
+    for i in 0..1000 {
+        t.insert(i, format!("value {}", i)).unwrap();
+    }
+    // Probe some keys repeatedly between scans over all entries
+    for _ in 0..3 {
+        for i in [1, 500, 999] {
+            assert_eq!(Some(format!("value {}", i)), t.get(&i).unwrap());
+        }
+        assert_eq!(1000, t.range(..).unwrap().count());
+    }
+    t.verify_integrity().unwrap();
+}
+
+#[test]
+fn get_with_context() {
+    let mut rng = seeded_rng();
+    let config = BtreeConfig::default().order(3);
+    let mut t: BtreeIndex<u32, u32> = BtreeIndex::with_capacity(config, 1000).unwrap();
+    let mut context = LookupContext::new();
+    for i in 0..1000 {
+        let key = rng.gen_range(0..2000);
+        t.insert(key, i).unwrap();
+        // Lookups between the inserts remember leaves that are split later
+        let probe = rng.gen_range(0..2000);
+        assert_eq!(
+            t.get(&probe).unwrap(),
+            t.get_with_context(&probe, &mut context).unwrap()
+        );
+    }
+    for key in 0..2000 {
+        assert_eq!(
+            t.get(&key).unwrap(),
+            t.get_with_context(&key, &mut context).unwrap()
+        );
+    }
+    for key in (0..2000).rev() {
+        assert_eq!(
+            t.get(&key).unwrap(),
+            t.get_with_context(&key, &mut context).unwrap()
+        );
+    }
+
+    // Removed keys are not found in the remembered leaf
+    t.remove_range(500..600).unwrap();
+    for key in 450..650 {
+        assert_eq!(
+            t.get(&key).unwrap(),
+            t.get_with_context(&key, &mut context).unwrap()
+        );
+    }
+
+    // The remembered leaf of another index or before clearing the index is ignored
+    let mut other: BtreeIndex<u32, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 10).unwrap();
+    other.insert(5, 50).unwrap();
+    assert_eq!(Some(50), other.get_with_context(&5, &mut context).unwrap());
+    t.clear().unwrap();
+    t.insert(1000, 1).unwrap();
+    assert_eq!(None, t.get_with_context(&5, &mut context).unwrap());
+    assert_eq!(Some(1), t.get_with_context(&1000, &mut context).unwrap());
+    context.reset();
+    assert_eq!(Some(1), t.get_with_context(&1000, &mut context).unwrap());
+}
+
+#[test]
+fn nearly_sorted_insert() {
+    for order_statistics in [false, true] {
+        let mut rng = seeded_rng();
+        let config = BtreeConfig::default()
+            .order(3)
+            .order_statistics(order_statistics);
+        let mut t: BtreeIndex<u32, u32> = BtreeIndex::with_capacity(config, 3000).unwrap();
+        let mut m = BTreeMap::new();
+        for i in 0..3000 {
+            // Mostly ascending keys with some jumps back and some existing keys
+            let key = match rng.gen_range(0..10) {
+                0 => rng.gen_range(0..10000),
+                1 => i * 3,
+                _ => i * 3 + 1,
+            };
+            assert_eq!(m.insert(key, i), t.insert(key, i).unwrap());
+        }
+        t.verify_integrity().unwrap();
+        assert_eq!(m.len(), t.len());
+        for (n, (key, value)) in m.iter().enumerate() {
+            assert_eq!(Some(*value), t.get(key).unwrap());
+            if order_statistics {
+                assert_eq!(n, t.rank(key).unwrap());
+            }
+        }
+    }
+}
+
+#[test]
+fn contains_many() {
+    let mut rng = seeded_rng();
+    let config = BtreeConfig::default().order(3);
+    let mut t: BtreeIndex<u32, u32> = BtreeIndex::with_capacity(config, 1000).unwrap();
+    assert!(t.contains_many(&[]).unwrap().is_empty());
+    assert_eq!(vec![false, false], t.contains_many(&[1, 2]).unwrap());
+
+    insert_random(&mut t, &mut rng, 1000, |rng| rng.gen_range(0..3000));
+    t.remove_range(1000..1100).unwrap();
+
+    let probes: Vec<u32> = (0..5000).map(|_| rng.gen_range(0..3500)).collect();
+    let expected: Vec<bool> = probes.iter().map(|k| t.contains_key(k).unwrap()).collect();
+    assert_eq!(expected, t.contains_many(&probes).unwrap());
+
+    let all: Vec<u32> = (0..3000).collect();
+    let expected: Vec<bool> = all.iter().map(|k| t.contains_key(k).unwrap()).collect();
+    assert_eq!(expected, t.contains_many(&all).unwrap());
+}
+
+#[test]
+fn bloom_filter() {
+    let mut rng = seeded_rng();
+    let config = BtreeConfig::default()
+        .order(3)
+        .bloom_filter_bits_per_key(10);
+    // The filter is rebuilt several times, because the index grows beyond its capacity
+    let mut t: BtreeIndex<u32, u32> = BtreeIndex::with_capacity(config, 100).unwrap();
+    assert!(t.memory_usage().bloom_filter > 0);
+    assert_eq!(None, t.get(&1).unwrap());
+
+    let mut m = insert_random(&mut t, &mut rng, 2000, |rng| rng.gen_range(0..10000) * 2);
+    t.remove_range(1000..2000).unwrap();
+    m.retain(|k, _| !(1000..2000).contains(k));
+    for key in 0..20000 {
+        assert_eq!(m.get(&key).copied(), t.get(&key).unwrap());
+        assert_eq!(m.contains_key(&key), t.contains_key(&key).unwrap());
+    }
+    // Only odd keys are missing, so few of them should pass the filter
+    let false_positives = (0..10000u32)
+        .filter(|k| {
+            let hash = bloom::key_hash(&(k * 2 + 1)).unwrap();
+            t.bloom_filter.as_ref().unwrap().may_contain(hash)
+        })
+        .count();
+    assert!(false_positives < 500, "{} false positives", false_positives);
+
+    t.clear().unwrap();
+    for key in m.keys() {
+        assert_eq!(None, t.get(key).unwrap());
+    }
+}
+
+#[test]
+fn hash_index() {
+    for (bloom_filter_bits, order_statistics) in [(0, false), (10, true)] {
+        let mut rng = seeded_rng();
+        let config = BtreeConfig::default()
+            .order(3)
+            .hash_index(true)
+            .bloom_filter_bits_per_key(bloom_filter_bits)
+            .order_statistics(order_statistics);
+        let mut t: BtreeIndex<String, u32> = BtreeIndex::with_capacity(config, 100).unwrap();
+        let mut m = BTreeMap::new();
+        for i in 0..2000 {
+            let key = format!("key {}", rng.gen_range(0..3000));
+            m.insert(key.clone(), i);
+            t.insert(key, i).unwrap();
+        }
+        assert!(t.memory_usage().hash_index > 0);
+        t.verify_integrity().unwrap();
+        t.remove_range("key 1".to_string().."key 2".to_string())
+            .unwrap();
+        m.retain(|k, _| !(k.as_str() >= "key 1" && k.as_str() < "key 2"));
+        for i in 0..3000 {
+            let key = format!("key {}", i);
+            assert_eq!(m.get(&key).copied(), t.get(&key).unwrap());
+            assert_eq!(m.contains_key(&key), t.contains_key(&key).unwrap());
+            // Borrowed keys can't use the hash index, but must find the same entries
+            assert_eq!(m.get(&key).copied(), t.get(key.as_str()).unwrap());
+            assert_eq!(m.contains_key(&key), t.contains_key(key.as_str()).unwrap());
+        }
+
+        t.clear().unwrap();
+        t.insert("key 5".to_string(), 5).unwrap();
+        assert_eq!(Some(5), t.get(&"key 5".to_string()).unwrap());
+        for key in m.keys().filter(|key| *key != "key 5") {
+            assert_eq!(None, t.get(key).unwrap());
+        }
+    }
+}
+
+#[test]
+fn range_grouped() {
+    let mut t: BtreeIndex<(u32, u32), u64> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 1000).unwrap();
+    // User i has i entries
+    for user in 0..40u32 {
+        for timestamp in 0..user {
+            t.insert((user, timestamp), u64::from(user * timestamp))
+                .unwrap();
+        }
+    }
+
+    let groups: Vec<_> = t
+        .range_grouped(.., |(user, _)| *user)
+        .unwrap()
+        .map(|g| g.unwrap())
+        .collect();
+    assert_eq!(39, groups.len());
+    for (user, entries) in groups {
+        let expected: Vec<_> = (0..user)
+            .map(|timestamp| ((user, timestamp), u64::from(user * timestamp)))
+            .collect();
+        assert_eq!(expected, entries);
+    }
+
+    // Groups are limited by the range
+    let groups: Vec<(u32, usize)> = t
+        .range_grouped((10, 5)..(12, 3), |(user, _)| *user)
+        .unwrap()
+        .map(|g| g.map(|(user, entries)| (user, entries.len())).unwrap())
+        .collect();
+    assert_eq!(vec![(10, 5), (11, 11), (12, 3)], groups);
+
+    // The group key can be computed from the key
+    let groups: Vec<(bool, usize)> = t
+        .range_grouped((5, 0)..(7, 0), |(_, timestamp)| *timestamp < 2)
+        .unwrap()
+        .map(|g| g.map(|(group, entries)| (group, entries.len())).unwrap())
+        .collect();
+    assert_eq!(vec![(true, 2), (false, 3), (true, 2), (false, 4)], groups);
+}
+
+#[test]
+fn join_range() {
+    let mut a: BtreeIndex<u32, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 1000).unwrap();
+    let mut b: BtreeIndex<u32, String> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(5), 1000).unwrap();
+    for i in (0..600u32).step_by(2) {
+        a.insert(i, i).unwrap();
+    }
+    for i in (0..600u32).step_by(3) {
+        b.insert(i, i.to_string()).unwrap();
+    }
+    // Removed entries are not part of the join
+    a.remove_range(12..=12).unwrap();
+
+    let expected: Vec<_> = (0..600u32)
+        .filter_map(|i| {
+            let left = (i % 2 == 0 && i != 12).then_some(i);
+            let right = (i % 3 == 0).then(|| i.to_string());
+            (left.is_some() || right.is_some()).then_some((i, left, right))
+        })
+        .collect();
+    let result: Result<Vec<_>> = a.join_range(&b, ..).unwrap().collect();
+    assert_eq!(expected, result.unwrap());
+
+    let result: Result<Vec<_>> = a.join_range(&b, 10..=20).unwrap().collect();
+    assert_eq!(
+        vec![
+            (10, Some(10), None),
+            (12, None, Some("12".to_string())),
+            (14, Some(14), None),
+            (15, None, Some("15".to_string())),
+            (16, Some(16), None),
+            (18, Some(18), Some("18".to_string())),
+            (20, Some(20), None),
+        ],
+        result.unwrap()
+    );
+
+    let empty: BtreeIndex<u32, String> =
+        BtreeIndex::with_capacity(BtreeConfig::default(), 10).unwrap();
+    assert_eq!(299, a.join_range(&empty, ..).unwrap().count());
+}
+
+#[test]
+fn key_set_operations() {
+    let mut a: BtreeIndex<u32, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 1000).unwrap();
+    let mut b: BtreeIndex<u32, String> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(4), 1000).unwrap();
+    let mut rng = seeded_rng();
+    let mut expected_a = BTreeSet::new();
+    let mut expected_b = BTreeSet::new();
+    for _ in 0..500 {
+        let key = rng.gen_range(0..1000u32);
+        a.insert(key, key).unwrap();
+        expected_a.insert(key);
+        let key = rng.gen_range(0..1000u32);
+        b.insert(key, key.to_string()).unwrap();
+        expected_b.insert(key);
+    }
+    // Removed keys are not part of any set
+    a.remove_range(100..200).unwrap();
+    expected_a.retain(|k| !(100..200).contains(k));
+
+    let union: Result<Vec<_>> = a.keys_union(&b).collect();
+    assert_eq!(
+        expected_a.union(&expected_b).copied().collect::<Vec<_>>(),
+        union.unwrap()
+    );
+    let intersection: Result<Vec<_>> = a.keys_intersection(&b).collect();
+    assert_eq!(
+        expected_a
+            .intersection(&expected_b)
+            .copied()
+            .collect::<Vec<_>>(),
+        intersection.unwrap()
+    );
+    let difference: Result<Vec<_>> = a.keys_difference(&b).collect();
+    assert_eq!(
+        expected_a
+            .difference(&expected_b)
+            .copied()
+            .collect::<Vec<_>>(),
+        difference.unwrap()
+    );
+    let difference: Result<Vec<_>> = b.keys_difference(&a).collect();
+    assert_eq!(
+        expected_b
+            .difference(&expected_a)
+            .copied()
+            .collect::<Vec<_>>(),
+        difference.unwrap()
+    );
+}
+
+#[test]
+fn pop_first_and_last() {
+    for order_statistics in [false, true] {
+        let config = BtreeConfig::default()
+            .order(3)
+            .order_statistics(order_statistics);
+        let mut t: BtreeIndex<u32, u64> = BtreeIndex::with_capacity(config, 1000).unwrap();
+        let mut rng = seeded_rng();
+        let mut expected = insert_random(&mut t, &mut rng, 1000, |rng| rng.gen_range(0..5000));
+
+        for i in 0..400 {
+            if i % 3 == 0 {
+                assert_eq!(expected.pop_last(), t.pop_last().unwrap());
+            } else {
+                assert_eq!(expected.pop_first(), t.pop_first().unwrap());
+            }
+            // Removed keys can be inserted again
+            if i % 10 == 0 {
+                let key = rng.gen_range(0..5000u32);
+                t.insert(key, i).unwrap();
+                expected.insert(key, i);
+            }
+        }
+        assert_eq!(expected.len(), t.len());
+        if order_statistics {
+            assert_eq!(
+                expected.first_key_value().map(|(k, v)| (*k, *v)),
+                t.select(0).unwrap()
+            );
+            let last = expected.last_key_value().unwrap();
+            assert_eq!(expected.len() - 1, t.rank(last.0).unwrap());
+        }
+
+        while let Some(entry) = expected.pop_first() {
+            assert_eq!(Some(entry), t.pop_first().unwrap());
+        }
+        assert_eq!(None, t.pop_first().unwrap());
+        assert_eq!(None, t.pop_last().unwrap());
+        assert!(t.is_empty());
+    }
+}
+
+#[test]
+fn pop_does_not_search_removed_entries() {
+    let mut t: BtreeIndex<u32, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 10_000).unwrap();
+    for i in 0..10_000 {
+        t.insert(i, i).unwrap();
+    }
+    for i in 0..5_000 {
+        assert_eq!(Some((i, i)), t.pop_first().unwrap());
+        assert_eq!(Some((9_999 - i, 9_999 - i)), t.pop_last().unwrap());
+    }
+    // The search starts behind the popped keys
+    assert_eq!(
+        (Bound::Excluded(4_999), Bound::Excluded(5_000)),
+        t.live_range
+    );
+    assert_eq!(None, t.pop_first().unwrap());
+
+    // Inserting a key outside the range makes it visible again
+    t.insert(1, 1).unwrap();
+    t.insert(20_000, 2).unwrap();
+    assert_eq!(Some((20_000, 2)), t.pop_last().unwrap());
+    assert_eq!(Some((1, 1)), t.pop_last().unwrap());
+    assert!(t.is_empty());
+}
+
+#[test]
+fn pop_first_reuses_value_blocks() {
+    let mut t: BtreeIndex<u32, String> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(3), 100).unwrap();
+    for i in 0..100u32 {
+        t.insert(i, format!("value {i:04}").repeat(10)).unwrap();
+    }
+    let mapped = t.memory_usage().values;
+    for i in 100..1000u32 {
+        assert_eq!(i - 100, t.pop_first().unwrap().unwrap().0);
+        t.insert(i, format!("value {i:04}").repeat(10)).unwrap();
+    }
+    assert_eq!(100, t.len());
+    assert_eq!(mapped, t.memory_usage().values);
+}
+
+#[test]
+fn drain() {
+    for order_statistics in [false, true] {
+        let config = BtreeConfig::default()
+            .order(3)
+            .order_statistics(order_statistics);
+        let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 1000).unwrap();
+        let mut expected = BTreeMap::new();
+        for i in 0..1000u32 {
+            let value = format!("value {i}").repeat(10);
+            t.insert(i, value.clone()).unwrap();
+            expected.insert(i, value);
+        }
+
+        let drained: Result<Vec<_>> = t.drain(100..600).collect();
+        let expected_drained: Vec<_> = (100..600u32)
+            .map(|i| expected.remove(&i).map(|v| (i, v)).unwrap())
+            .collect();
+        assert_eq!(expected_drained, drained.unwrap());
+        assert_eq!(500, t.len());
+        assert!(!t.contains_key(&100).unwrap());
+
+        // Dropping the iterator keeps the remaining entries
+        let drained: Vec<_> = t.drain(..).take(10).map(|e| e.unwrap()).collect();
+        assert_eq!(10, drained.len());
+        for (k, _) in drained {
+            expected.remove(&k);
+        }
+        assert_eq!(expected.len(), t.len());
+
+        // New values reuse the freed blocks
+        let mapped = t.memory_usage().values;
+        for i in 200..500u32 {
+            let value = format!("new {i}").repeat(10);
+            t.insert(i, value.clone()).unwrap();
+            expected.insert(i, value);
+        }
+        assert_eq!(mapped, t.memory_usage().values);
+
+        let result: Result<Vec<_>> = t.range(..).unwrap().collect();
+        assert_eq!(expected.into_iter().collect::<Vec<_>>(), result.unwrap());
+        if order_statistics {
+            assert_eq!(Some(10), t.select(0).unwrap().map(|(k, _)| k));
+        }
+    }
+}
+
+#[test]
+fn drain_invalidates_payload_ids() {
+    let config = BtreeConfig::default().order(3).max_value_size(8);
+    let mut t: BtreeIndex<u32, String> = BtreeIndex::with_capacity(config, 100).unwrap();
+    t.insert(1, "short value".to_string()).unwrap();
+    // The relocated value is referenced by the original block with the forwarding pointer
+    t.update(&1, |v| v.push_str(" that needs a larger block"))
+        .unwrap();
+    assert_eq!(1, t.relocation_stats().relocations);
+    let id = t.payload_id(&1).unwrap().unwrap();
+
+    assert_eq!(1, t.drain(..).count());
+    assert!(matches!(t.get_by_payload(id), Err(Error::InvalidPayloadId)));
+    // Both the original and the relocated block have been freed
+    assert_eq!(0, t.relocation_stats().abandoned_bytes);
+
+    // The original block is reused for a new value, but the old ID stays invalid
+    t.insert(2, "short value".to_string()).unwrap();
+    let new_id = t.payload_id(&2).unwrap().unwrap();
+    assert_eq!(id.payload, new_id.payload);
+    assert!(matches!(t.get_by_payload(id), Err(Error::InvalidPayloadId)));
+    assert_eq!("short value", t.get_by_payload(new_id).unwrap());
+    t.insert(3, "x".repeat(30)).unwrap();
+    assert_eq!(Some("x".repeat(30)), t.get(&3).unwrap());
+    assert_eq!(Some("short value".to_string()), t.get(&2).unwrap());
+    t.verify_integrity().unwrap();
+}
+
+#[cfg(feature = "wal")]
+fn temporary_log_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("transient-btree-{}-{name}.wal", std::process::id()))
+}
+
+#[cfg(feature = "wal")]
+#[test]
+fn recover_from_write_ahead_log() {
+    let log = temporary_log_path("recover");
+    let mut t: BtreeIndex<u32, String> =
+        BtreeIndex::with_capacity(BtreeConfig::default(), 16).unwrap();
+    // Existing entries are written to the log when it is enabled
+    t.insert(10_000, "existing".to_string()).unwrap();
+    t.write_ahead_log(&log).unwrap();
+
+    for i in 0..2000u32 {
+        t.insert(i, format!("value {i}")).unwrap();
+    }
+    t.merge_insert(1, "merged".to_string(), |a, b| a + &b)
+        .unwrap();
+    t.update(&2, |v| v.push_str(" updated")).unwrap();
+    t.swap(&3, &4).unwrap();
+    t.move_value(&5, 5000).unwrap();
+    t.remove_range(100..200).unwrap();
+    t.retain(|k, _| k % 7 != 0).unwrap();
+    t.pop_first().unwrap();
+    t.pop_last().unwrap();
+    let drained: Result<Vec<_>> = t.drain(300..310).collect();
+    assert_eq!(8, drained.unwrap().len());
+    let t = t.compact().unwrap();
+    let expected: Vec<_> = t.range(..).unwrap().map(|e| e.unwrap()).collect();
+    drop(t);
+
+    let mut recovered: BtreeIndex<u32, String> =
+        BtreeIndex::recover(BtreeConfig::default(), &log).unwrap();
+    let result: Vec<_> = recovered.range(..).unwrap().map(|e| e.unwrap()).collect();
+    assert_eq!(expected, result);
+    recovered.verify_integrity().unwrap();
+
+    // The recovered index continues the log
+    let mut upper = recovered.split_off(&1000).unwrap();
+    upper.insert(1, "not logged".to_string()).unwrap();
+    recovered.insert(1, "logged".to_string()).unwrap();
+    recovered.sync_write_ahead_log().unwrap();
+    let expected: Vec<_> = recovered.range(..).unwrap().map(|e| e.unwrap()).collect();
+    drop(recovered);
+    let recovered: BtreeIndex<u32, String> =
+        BtreeIndex::recover(BtreeConfig::default(), &log).unwrap();
+    let result: Vec<_> = recovered.range(..).unwrap().map(|e| e.unwrap()).collect();
+    assert_eq!(expected, result);
+    drop(recovered);
+
+    std::fs::remove_file(log).unwrap();
+}
+
+#[cfg(feature = "wal")]
+#[test]
+fn recover_ignores_incomplete_record() {
+    let log = temporary_log_path("incomplete");
+    let mut t: BtreeIndex<u32, u64> =
+        BtreeIndex::with_capacity(BtreeConfig::default(), 16).unwrap();
+    t.write_ahead_log(&log).unwrap();
+    for i in 0..100 {
+        t.insert(i, u64::from(i) * 2).unwrap();
+    }
+    drop(t);
+
+    // Cut the last record, like a process that was killed while writing it
+    let len = std::fs::metadata(&log).unwrap().len();
+    let file = std::fs::OpenOptions::new().write(true).open(&log).unwrap();
+    file.set_len(len - 2).unwrap();
+    drop(file);
+
+    let mut recovered: BtreeIndex<u32, u64> =
+        BtreeIndex::recover(BtreeConfig::default(), &log).unwrap();
+    assert_eq!(99, recovered.len());
+    assert_eq!(None, recovered.get(&99).unwrap());
+    assert_eq!(Some(196), recovered.get(&98).unwrap());
+
+    // The incomplete record is replaced by new ones
+    recovered.insert(99, 1).unwrap();
+    recovered.clear().unwrap();
+    recovered.insert(1, 1).unwrap();
+    drop(recovered);
+    let recovered: BtreeIndex<u32, u64> =
+        BtreeIndex::recover(BtreeConfig::default(), &log).unwrap();
+    assert_eq!(1, recovered.len());
+    assert_eq!(Some(1), recovered.get(&1).unwrap());
+    drop(recovered);
+
+    std::fs::write(&log, b"not a log").unwrap();
+    let result = BtreeIndex::<u32, u64>::recover(BtreeConfig::default(), &log);
+    assert!(matches!(result, Err(Error::InvalidWriteAheadLog(_))));
+
+    std::fs::remove_file(log).unwrap();
+}
+
+#[cfg(feature = "wal")]
+#[test]
+fn recover_ignores_invalid_record_lengths() {
+    use std::io::Write;
+
+    let log = temporary_log_path("invalid-lengths");
+    let write_log = || {
+        let mut t: BtreeIndex<u32, u64> =
+            BtreeIndex::with_capacity(BtreeConfig::default(), 16).unwrap();
+        t.write_ahead_log(&log).unwrap();
+        for i in 0..10 {
+            t.insert(i, u64::from(i)).unwrap();
+        }
+    };
+    let append = |bytes: &[u8]| {
+        let mut file = std::fs::OpenOptions::new().append(true).open(&log).unwrap();
+        file.write_all(bytes).unwrap();
+    };
+
+    // A tail of zeros, e.g. after the file system extended the file before the record was written
+    write_log();
+    let len = std::fs::metadata(&log).unwrap().len();
+    append(&[0; 64]);
+    let mut recovered: BtreeIndex<u32, u64> =
+        BtreeIndex::recover(BtreeConfig::default(), &log).unwrap();
+    assert_eq!(10, recovered.len());
+    assert_eq!(len, std::fs::metadata(&log).unwrap().len());
+    recovered.insert(10, 10).unwrap();
+    drop(recovered);
+    let recovered: BtreeIndex<u32, u64> =
+        BtreeIndex::recover(BtreeConfig::default(), &log).unwrap();
+    assert_eq!(11, recovered.len());
+    drop(recovered);
+
+    // A garbage length must not be used to allocate the buffer for the record
+    write_log();
+    append(&u64::MAX.to_le_bytes());
+    append(&[1, 2, 3]);
+    let recovered: BtreeIndex<u32, u64> =
+        BtreeIndex::recover(BtreeConfig::default(), &log).unwrap();
+    assert_eq!(10, recovered.len());
+    assert_eq!(len, std::fs::metadata(&log).unwrap().len());
+    drop(recovered);
+
+    std::fs::remove_file(log).unwrap();
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn encrypted_keys_and_values() {
+    let config = BtreeConfig::default()
+        .encryption_key([42; 32])
+        .key_prefix_compression(true);
+    let mut t: BtreeIndex<String, u32> = BtreeIndex::with_capacity(config.clone(), 16).unwrap();
+    for i in 0..1000u32 {
+        t.insert(format!("customer-{i:05}"), i).unwrap();
+    }
+    assert_eq!(Some(42), t.get(&"customer-00042".to_string()).unwrap());
+    t.verify_integrity().unwrap();
+
+    // Small values are not stored in the nodes
+    let (node, i) = t
+        .search_key(&"customer-00001".to_string())
+        .unwrap()
+        .unwrap();
+    assert!(!payload::is_inline(t.nodes.get_payload(node, i).unwrap()));
+
+    let result: Result<Vec<_>> = t.iter().unwrap().collect();
+    let expected: Vec<_> = (0..1000u32)
+        .map(|i| (format!("customer-{i:05}"), i))
+        .collect();
+    assert_eq!(expected, result.unwrap());
+
+    let result = BtreeIndex::<u64, u32>::with_capacity(config.fixed_key_size(8), 16);
+    assert!(matches!(result, Err(Error::EncryptionUnsupported(_))));
+}
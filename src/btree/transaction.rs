@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{BtreeIndex, TypeSize};
+use crate::{
+    error::Result,
+    file::{check_fixed_size, FileCheckpoint},
+};
+
+/// Entries that are inserted as a single batch, which is created by [`BtreeIndex::transaction`].
+///
+/// The inserted entries are staged in main memory and the index is not changed until the
+/// transaction is committed, so they are discarded completely if the function that
+/// fills the transaction fails.
+/// Lookups see the staged entries before the entries of the index.
+pub struct Transaction<'a, K, V>
+where
+    K: Serialize + DeserializeOwned + PartialOrd + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    index: &'a BtreeIndex<K, V>,
+    staged: BTreeMap<K, V>,
+}
+
+/// State of an index before the staged entries are inserted, to which a failed commit
+/// is rolled back.
+///
+/// The nodes and keys are restored by the journal of the [node file](super::node::NodeFile).
+struct Checkpoint {
+    root_id: u64,
+    nr_elements: usize,
+    insert_path: Vec<(u64, usize)>,
+    values: FileCheckpoint,
+}
+
+impl<K, V> Transaction<'_, K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    /// Stage a new element, which is inserted into the index when the transaction is committed.
+    ///
+    /// Existing values will be overwritten.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.staged.insert(key, value);
+    }
+
+    /// Searches for a key in the staged entries and the index and returns the value if found.
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        if let Some(value) = self.staged.get(key) {
+            Ok(Some(value.clone()))
+        } else {
+            self.index.get(key)
+        }
+    }
+
+    /// Returns whether the staged entries or the index contain the given key.
+    pub fn contains_key(&self, key: &K) -> Result<bool> {
+        Ok(self.staged.contains_key(key) || self.index.contains_key(key)?)
+    }
+
+    /// Returns the number of staged entries.
+    pub fn staged_len(&self) -> usize {
+        self.staged.len()
+    }
+}
+
+impl<K, V> BtreeIndex<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    /// Insert a batch of entries that are staged in main memory first.
+    ///
+    /// The entries inserted into the [`Transaction`] by the given function are only inserted
+    /// into the index if the function returns `Ok`. Otherwise, the index is not changed
+    /// and the error is returned.
+    ///
+    /// Committing the staged entries is atomic: if inserting any of them fails, e.g. because
+    /// a file can't be grown anymore, the nodes changed by the commit are restored and the
+    /// blocks allocated by it are removed, so the index contains none of the staged entries
+    /// and is not [poisoned](Self::is_poisoned).
+    /// To allow this, the values of existing keys are stored in new blocks instead of
+    /// replacing the previous values, whose space is not reused.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = BtreeIndex::<u16, u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.transaction(|txn| {
+    ///         txn.insert(1, 2);
+    ///         txn.insert(2, 4);
+    ///         Ok(())
+    ///     })?;
+    ///     assert_eq!(2, b.len());
+    ///
+    ///     let result: Result<(), Error> = b.transaction(|txn| {
+    ///         txn.insert(3, 6);
+    ///         Err(Error::NonExistingKey)
+    ///     });
+    ///     assert!(result.is_err());
+    ///     assert_eq!(None, b.get(&3)?);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn transaction<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Transaction<'_, K, V>) -> Result<T>,
+    {
+        self.check_poisoned()?;
+        let mut txn = Transaction {
+            index: self,
+            staged: BTreeMap::new(),
+        };
+        let result = f(&mut txn)?;
+        let staged = txn.staged;
+
+        for (key, value) in staged.iter() {
+            self.check_serializable(key, value)?;
+        }
+        self.try_reserve(staged.len())?;
+
+        let checkpoint = Checkpoint {
+            root_id: self.root_id,
+            nr_elements: self.nr_elements,
+            insert_path: self.insert_path.clone(),
+            values: self.values.checkpoint(),
+        };
+        self.nodes.checkpoint();
+        // The staged entries are sorted, so consecutive keys are inserted into the same leaf
+        let inserted = staged
+            .iter()
+            .try_for_each(|(key, value)| self.insert_staged(key, value));
+        if let Err(e) = inserted {
+            // The index can only be used again if all changes have been reverted
+            if self.rollback(checkpoint).is_err() {
+                self.poisoned = true;
+            }
+            return Err(e);
+        }
+        self.nodes.commit();
+
+        #[cfg(feature = "wal")]
+        if self.wal.is_some() {
+            self.poison_on_error(|index| {
+                staged
+                    .iter()
+                    .try_for_each(|(key, value)| index.log_insert(key, value))
+            })?;
+        }
+        Ok(result)
+    }
+
+    /// Inserts a staged entry without changing any existing key or value block,
+    /// so that the insert can be rolled back.
+    fn insert_staged(&mut self, key: &K, value: &V) -> Result<()> {
+        // Like for shared value blocks, the value of an existing key is stored in a new block
+        self.insert_payload_unlogged(key.clone(), |index, _existing| index.insert_value(value))
+    }
+
+    /// Restores the nodes and files to the checkpoint taken before the staged entries
+    /// were inserted.
+    fn rollback(&mut self, checkpoint: Checkpoint) -> Result<()> {
+        self.nodes.rollback()?;
+        if let Some(value_blocks) = &mut self.value_blocks {
+            value_blocks.rollback(&checkpoint.values);
+        }
+        self.values.rollback(checkpoint.values)?;
+        if let Some(hash_index) = &mut self.hash_index {
+            let nodes = &self.nodes;
+            hash_index.retain_nodes(|node_id| nodes.contains_node(node_id));
+        }
+        // Additional keys in the bloom filter only cause false positives, which are checked anyway
+        self.root_id = checkpoint.root_id;
+        self.nr_elements = checkpoint.nr_elements;
+        self.insert_path = checkpoint.insert_path;
+        Ok(())
+    }
+
+    /// Checks that the key and value can be serialized with the configured codecs
+    /// and have the configured fixed sizes.
+    fn check_serializable(&self, key: &K, value: &V) -> Result<()> {
+        match self.config.key_size {
+            TypeSize::Fixed(key_size) => check_fixed_size(key, key_size)?,
+            TypeSize::Estimated(_) => {
+                self.nodes.key_serialized_size(key)?;
+            }
+        }
+        match self.config.value_size {
+            TypeSize::Fixed(value_size) => check_fixed_size(value, value_size)?,
+            TypeSize::Estimated(_) => {
+                self.values.serialized_size(value)?;
+            }
+        }
+        Ok(())
+    }
+}
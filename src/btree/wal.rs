@@ -0,0 +1,274 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::Bound,
+    path::Path,
+};
+
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{BtreeConfig, BtreeIndex};
+use crate::{error::Result, Error};
+
+/// Magic bytes at the start of a log written by [`BtreeIndex::write_ahead_log`].
+const WAL_MAGIC: &[u8; 8] = b"TBTWAL01";
+
+/// Record of an inserted or updated entry with the serialized key and value.
+const INSERT_RECORD: u8 = 0;
+/// Record of removed entries with the serialized start and end bound of the range.
+const REMOVE_RECORD: u8 = 1;
+
+/// Append-only file to which all changes of an index are written after they have been applied.
+pub(super) struct WriteAheadLog {
+    file: File,
+    /// Reused for serializing the records, so appending does not allocate.
+    buffer: Vec<u8>,
+}
+
+impl WriteAheadLog {
+    /// Create a new empty log, replacing an existing file.
+    fn create(path: &Path) -> Result<WriteAheadLog> {
+        let mut file = File::create(path)?;
+        file.write_all(WAL_MAGIC)?;
+        Ok(WriteAheadLog {
+            file,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Open an existing log and continue it after the given number of valid bytes.
+    fn open(path: &Path, valid_len: u64) -> Result<WriteAheadLog> {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        // Remove an incomplete last record
+        file.set_len(valid_len)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(WriteAheadLog {
+            file,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Append a record with a single write, so that a killed process leaves at most the last
+    /// record incomplete.
+    fn append<T: Serialize>(&mut self, tag: u8, record: &T) -> Result<()> {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(&[0; 8]);
+        self.buffer.push(tag);
+        bincode::DefaultOptions::new().serialize_into(&mut self.buffer, record)?;
+        let len = u64::try_from(self.buffer.len() - 8)?;
+        self.buffer[0..8].copy_from_slice(&len.to_le_bytes());
+        self.file.write_all(&self.buffer)?;
+        Ok(())
+    }
+
+    /// Remove all records.
+    fn clear(&mut self) -> Result<()> {
+        self.file.set_len(u64::try_from(WAL_MAGIC.len())?)?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
+
+/// Reads the records of a log in the order they were written.
+struct LogReader {
+    reader: BufReader<File>,
+    /// Number of bytes of the magic bytes and all complete records read so far.
+    valid_len: u64,
+    /// Size of the whole file, which limits the length of the records.
+    file_len: u64,
+}
+
+impl LogReader {
+    fn open(path: &Path) -> Result<LogReader> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != WAL_MAGIC {
+            return Err(Error::InvalidWriteAheadLog(
+                "the file does not start with the magic bytes".to_string(),
+            ));
+        }
+        Ok(LogReader {
+            reader,
+            valid_len: u64::try_from(WAL_MAGIC.len())?,
+            file_len,
+        })
+    }
+
+    /// Reads the next record into the buffer, or only skips it if no buffer is given.
+    ///
+    /// Returns `false` at the end of the log or if the last record is incomplete,
+    /// e.g. because the process was killed while writing it.
+    /// A record with a length of zero or a length beyond the end of the file is treated
+    /// as incomplete, too, since the file system might have extended the file with zeros
+    /// or garbage before the record was written.
+    fn next_record(&mut self, buffer: Option<&mut Vec<u8>>) -> Result<bool> {
+        let mut len = [0; 8];
+        if !read_complete(&mut self.reader, &mut len)? {
+            return Ok(false);
+        }
+        let len = u64::from_le_bytes(len);
+        let remaining = self.file_len.saturating_sub(self.valid_len + 8);
+        if len == 0 || len > remaining {
+            return Ok(false);
+        }
+        let complete = if let Some(buffer) = buffer {
+            buffer.resize(usize::try_from(len)?, 0);
+            read_complete(&mut self.reader, buffer)?
+        } else {
+            let skipped = std::io::copy(&mut (&mut self.reader).take(len), &mut std::io::sink())?;
+            skipped == len
+        };
+        if complete {
+            self.valid_len += 8 + len;
+        }
+        Ok(complete)
+    }
+}
+
+/// Fills the buffer and returns `false` if the end of the file was reached before.
+fn read_complete<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<bool> {
+    match reader.read_exact(buffer) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl<K, V> BtreeIndex<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    /// Writes all changes of the index to an append-only log file, from which the index can be
+    /// [recovered](Self::recover) if the process is killed.
+    ///
+    /// The file is replaced by a log that contains the current entries of the index.
+    /// Afterwards, each inserted or updated entry is appended with its key and value,
+    /// and each removal with its key or range, after the change has been applied to the index.
+    /// The records are written without buffering and the operating system writes them to disk
+    /// even if the process is killed. Use [`Self::sync_write_ahead_log`] to make sure they survive
+    /// a crash of the whole system, too.
+    /// Clearing the index removes all records.
+    /// If writing to the log fails, the index is [poisoned](Self::is_poisoned).
+    ///
+    /// The format of the log is (all integers are encoded as little-endian `u64`):
+    ///
+    /// 1. the magic bytes `TBTWAL01`,
+    /// 2. for each record its length, a tag byte (`0` for inserts and `1` for removals)
+    ///    and the serialized key and value or the serialized start and end bound.
+    ///
+    /// The records are serialized with the default options of [bincode](https://crates.io/crates/bincode)
+    /// (`bincode::DefaultOptions`).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, BtreeIndex, Error};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let log = std::env::temp_dir().join("write_ahead_log_example.wal");
+    ///     let mut b = BtreeIndex::<u16, u16>::with_capacity(BtreeConfig::default(), 10)?;
+    ///     b.write_ahead_log(&log)?;
+    ///     b.insert(1, 2)?;
+    ///     b.insert(3, 4)?;
+    ///     b.remove_range(3..)?;
+    ///     drop(b);
+    ///
+    ///     let recovered = BtreeIndex::<u16, u16>::recover(BtreeConfig::default(), &log)?;
+    ///     assert_eq!(1, recovered.len());
+    ///     assert_eq!(Some(2), recovered.get(&1)?);
+    ///     std::fs::remove_file(log)?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn write_ahead_log<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.check_poisoned()?;
+        let mut wal = WriteAheadLog::create(path.as_ref())?;
+        for entry in self.range(..)? {
+            let (key, value) = entry?;
+            wal.append(INSERT_RECORD, &(key, value))?;
+        }
+        self.wal = Some(wal);
+        Ok(())
+    }
+
+    /// Create a new index with the given configuration from the entries in a log written by
+    /// [`Self::write_ahead_log`] and continue writing the log.
+    ///
+    /// The records are applied in the order they were written. An incomplete last record,
+    /// e.g. because the process was killed while writing it, is ignored and removed from the log,
+    /// like a record with an invalid length and everything after it.
+    /// The capacity of the new index is the number of records in the log.
+    pub fn recover<P: AsRef<Path>>(config: BtreeConfig, path: P) -> Result<BtreeIndex<K, V>> {
+        let path = path.as_ref();
+        let mut records = LogReader::open(path)?;
+        let mut capacity = 0;
+        while records.next_record(None)? {
+            capacity += 1;
+        }
+
+        let mut result = Self::with_capacity(config, capacity)?;
+        let mut records = LogReader::open(path)?;
+        let mut buffer = Vec::new();
+        let deserializer = bincode::DefaultOptions::new();
+        while records.next_record(Some(&mut buffer))? {
+            match buffer.split_first() {
+                Some((&INSERT_RECORD, record)) => {
+                    let (key, value): (K, V) = deserializer.deserialize(record)?;
+                    result.insert(key, value)?;
+                }
+                Some((&REMOVE_RECORD, record)) => {
+                    let range: (Bound<K>, Bound<K>) = deserializer.deserialize(record)?;
+                    result.remove_range(range)?;
+                }
+                _ => {
+                    return Err(Error::InvalidWriteAheadLog(format!(
+                        "unknown record at byte {}",
+                        records.valid_len
+                    )))
+                }
+            }
+        }
+        result.wal = Some(WriteAheadLog::open(path, records.valid_len)?);
+        Ok(result)
+    }
+
+    /// Flushes the [write-ahead log](Self::write_ahead_log) to disk, if the index has one.
+    ///
+    /// All records written before are guaranteed to be recoverable after a crash of the system.
+    /// This is slow, so call it periodically and not after each change.
+    pub fn sync_write_ahead_log(&self) -> Result<()> {
+        if let Some(wal) = &self.wal {
+            wal.file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Appends a record for an inserted or updated entry to the log.
+    pub(super) fn log_insert(&mut self, key: &K, value: &V) -> Result<()> {
+        if let Some(wal) = &mut self.wal {
+            wal.append(INSERT_RECORD, &(key, value))?;
+        }
+        Ok(())
+    }
+
+    /// Appends a record for the removed entries inside the range to the log.
+    pub(super) fn log_remove(&mut self, start: Bound<&K>, end: Bound<&K>) -> Result<()> {
+        if let Some(wal) = &mut self.wal {
+            wal.append(REMOVE_RECORD, &(start, end))?;
+        }
+        Ok(())
+    }
+
+    /// Removes all records from the log.
+    pub(super) fn clear_log(&mut self) -> Result<()> {
+        if let Some(wal) = &mut self.wal {
+            wal.clear()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use std::{collections::BTreeMap, ops::RangeBounds};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{error::Result, BtreeConfig, BtreeIndex, Range};
+
+/// B-tree index that collects inserted entries in main memory and inserts them in sorted batches.
+///
+/// Inserting keys in random order touches a different leaf of the tree for each key, which
+/// is slow when the nodes are not in main memory. The buffered entries are inserted into the
+/// index in sorted order once the buffer is full, so consecutive keys are inserted into the
+/// same leaf without searching the tree again.
+/// Lookups check the buffer first, while [range queries](Self::range) insert the buffered
+/// entries before iterating over the index.
+///
+/// Buffered entries are lost when the wrapper is dropped, so use [`Self::into_inner`] to get
+/// the index with all entries.
+///
+/// # Example
+///
+/// ```rust
+/// use transient_btree_index::{BtreeConfig, BufferedBtreeIndex, Error};
+///
+/// fn main() -> std::result::Result<(), Error> {
+///     let mut b = BufferedBtreeIndex::<u32, u32>::with_capacity(BtreeConfig::default(), 100, 10)?;
+///     for i in (0..100).rev() {
+///         b.insert(i, i * 2)?;
+///     }
+///     assert_eq!(Some(10), b.get(&5)?);
+///
+///     let index = b.into_inner()?;
+///     assert_eq!(100, index.len());
+///     Ok(())
+/// }
+/// ```
+pub struct BufferedBtreeIndex<K, V>
+where
+    K: Serialize + DeserializeOwned + PartialOrd + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    index: BtreeIndex<K, V>,
+    buffer: BTreeMap<K, V>,
+    max_buffered: usize,
+}
+
+impl<K, V> BufferedBtreeIndex<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    /// Create a new index with the given configuration and capacity in number of elements,
+    /// which buffers up to `max_buffered` entries before inserting them.
+    pub fn with_capacity(
+        config: BtreeConfig,
+        capacity: usize,
+        max_buffered: usize,
+    ) -> Result<BufferedBtreeIndex<K, V>> {
+        Ok(Self::new(
+            BtreeIndex::with_capacity(config, capacity)?,
+            max_buffered,
+        ))
+    }
+
+    /// Wrap an existing index and buffer up to `max_buffered` entries before inserting them.
+    pub fn new(index: BtreeIndex<K, V>, max_buffered: usize) -> BufferedBtreeIndex<K, V> {
+        BufferedBtreeIndex {
+            index,
+            buffer: BTreeMap::new(),
+            max_buffered,
+        }
+    }
+
+    /// Insert a new element into the buffer, and insert all buffered elements
+    /// into the index if the buffer is full.
+    ///
+    /// Existing values will be overwritten.
+    pub fn insert(&mut self, key: K, value: V) -> Result<()> {
+        self.buffer.insert(key, value);
+        if self.buffer.len() >= self.max_buffered {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Insert all buffered elements into the index in sorted order.
+    ///
+    /// If the operation fails, you should assume that the whole index is corrupted.
+    pub fn flush(&mut self) -> Result<()> {
+        for (key, value) in std::mem::take(&mut self.buffer) {
+            self.index.insert(key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Searches for a key in the buffer and the index and returns the value if found.
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        if let Some(value) = self.buffer.get(key) {
+            Ok(Some(value.clone()))
+        } else {
+            self.index.get(key)
+        }
+    }
+
+    /// Returns whether the buffer or the index contains the given key.
+    pub fn contains_key(&self, key: &K) -> Result<bool> {
+        Ok(self.buffer.contains_key(key) || self.index.contains_key(key)?)
+    }
+
+    /// Returns the number of elements that have not been inserted into the index yet.
+    pub fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Insert all buffered elements and return an iterator over a range of keys of the index.
+    pub fn range<R>(&mut self, range: R) -> Result<Range<'_, K, V>>
+    where
+        R: RangeBounds<K>,
+    {
+        self.flush()?;
+        self.index.range(range)
+    }
+
+    /// Insert all buffered elements and return the index with all elements.
+    pub fn index(&mut self) -> Result<&BtreeIndex<K, V>> {
+        self.flush()?;
+        Ok(&self.index)
+    }
+
+    /// Insert all buffered elements and return the wrapped index.
+    pub fn into_inner(mut self) -> Result<BtreeIndex<K, V>> {
+        self.flush()?;
+        Ok(self.index)
+    }
+}
+
+#[cfg(test)]
+mod tests;
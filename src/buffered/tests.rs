@@ -0,0 +1,39 @@
+use std::collections::BTreeMap;
+
+use rand::Rng;
+
+use super::*;
+use crate::test_util::seeded_rng;
+
+#[test]
+fn buffered_insert() {
+    let mut rng = seeded_rng();
+    let mut b: BufferedBtreeIndex<u32, u32> =
+        BufferedBtreeIndex::with_capacity(BtreeConfig::default().order(3), 1000, 64).unwrap();
+    let mut m = BTreeMap::new();
+    for i in 0..2000 {
+        let key = rng.gen_range(0..3000);
+        m.insert(key, i);
+        b.insert(key, i).unwrap();
+        assert!(b.buffered_len() < 64);
+        // Lookups find both buffered and inserted entries
+        let probe = rng.gen_range(0..3000);
+        assert_eq!(m.get(&probe).copied(), b.get(&probe).unwrap());
+        assert_eq!(m.contains_key(&probe), b.contains_key(&probe).unwrap());
+    }
+
+    let range: Vec<_> = b.range(100..200).unwrap().map(|e| e.unwrap()).collect();
+    let expected: Vec<_> = m.range(100..200).map(|(k, v)| (*k, *v)).collect();
+    assert_eq!(expected, range);
+    assert_eq!(0, b.buffered_len());
+
+    b.insert(5000, 1).unwrap();
+    assert_eq!(1, b.buffered_len());
+    assert_eq!(m.len(), b.index().unwrap().len() - 1);
+
+    b.insert(5001, 2).unwrap();
+    let index = b.into_inner().unwrap();
+    index.verify_integrity().unwrap();
+    assert_eq!(m.len() + 2, index.len());
+    assert_eq!(Some(2), index.get(&5001).unwrap());
+}
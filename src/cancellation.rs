@@ -0,0 +1,62 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::{error::Result, Error};
+
+/// Stops long running operations from another thread, e.g. when the user presses Ctrl-C
+/// or a query times out.
+///
+/// Clones of the token share the same state. Operations that accept a token check it regularly
+/// and return [`Error::Cancelled`] after it has been [cancelled](Self::cancel).
+///
+/// # Example
+///
+/// ```rust
+/// use transient_btree_index::{BtreeConfig, BtreeIndex, CancellationToken, Error};
+///
+/// fn main() -> std::result::Result<(), Error> {
+///     let mut b = BtreeIndex::<u16, u16>::with_capacity(BtreeConfig::default(), 10)?;
+///     for i in 0..10 {
+///         b.insert(i, i)?;
+///     }
+///
+///     let token = CancellationToken::new();
+///     let mut range = b.range(..)?.cancel_on(token.clone());
+///     assert_eq!((0, 0), range.next().unwrap()?);
+///
+///     token.cancel();
+///     assert!(matches!(range.next(), Some(Err(Error::Cancelled))));
+///     assert!(range.next().is_none());
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token that has not been cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancel all operations that use this token or one of its clones.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails with [`Error::Cancelled`] if the token has been cancelled.
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
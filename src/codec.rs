@@ -0,0 +1,211 @@
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::Result;
+
+/// Converts keys or values of type `T` to bytes and back.
+///
+/// All built-in formats of [`ValueCodec`] implement this trait. Other formats can be used by
+/// implementing it and passing the codec to [`BtreeBuilder::key_codec`](crate::BtreeBuilder::key_codec)
+/// or [`BtreeBuilder::value_codec`](crate::BtreeBuilder::value_codec).
+/// Errors of the format can be returned as [`Error::Codec`](crate::Error::Codec).
+///
+/// # Example
+///
+/// ```rust
+/// use transient_btree_index::{BtreeIndex, Codec, Error};
+///
+/// /// Stores strings as plain UTF-8 without a length header.
+/// struct Utf8;
+///
+/// impl Codec<String> for Utf8 {
+///     fn serialize(&self, value: &String) -> Result<Vec<u8>, Error> {
+///         Ok(value.as_bytes().to_vec())
+///     }
+///
+///     fn deserialize(&self, bytes: &[u8]) -> Result<String, Error> {
+///         String::from_utf8(bytes.to_vec()).map_err(|e| Error::Codec(Box::new(e)))
+///     }
+/// }
+///
+/// fn main() -> std::result::Result<(), Error> {
+///     let mut b = BtreeIndex::<u64, String>::builder()
+///         .value_codec(Utf8)
+///         .build()?;
+///     b.insert(1, "one".to_string())?;
+///     assert_eq!(Some("one".to_string()), b.get(&1)?);
+///     Ok(())
+/// }
+/// ```
+pub trait Codec<T>: Send + Sync {
+    /// Serialize the value into a new buffer.
+    fn serialize(&self, value: &T) -> Result<Vec<u8>>;
+
+    /// Deserialize a value from bytes created by [`Self::serialize`].
+    fn deserialize(&self, bytes: &[u8]) -> Result<T>;
+
+    /// Get the number of bytes needed to serialize the value.
+    ///
+    /// The default implementation serializes the value, so implement it if the size can be
+    /// calculated cheaper.
+    fn serialized_size(&self, value: &T) -> Result<u64> {
+        Ok(self.serialize(value)?.len().try_into()?)
+    }
+
+    /// Serialize the value into the buffer, which has exactly the [serialized size](Self::serialized_size).
+    fn serialize_into(&self, buffer: &mut [u8], value: &T) -> Result<()> {
+        buffer.copy_from_slice(&self.serialize(value)?);
+        Ok(())
+    }
+}
+
+/// Format in which the values are serialized.
+///
+/// The format can be configured with [`BtreeConfig::value_codec`](crate::BtreeConfig::value_codec),
+/// e.g. to use the same format for the values as other parts of a pipeline.
+/// Keys are serialized with bincode unless a different [`Codec`] is given to the
+/// [builder](crate::BtreeBuilder::key_codec), because searching and the
+/// [key prefix compression](crate::BtreeConfig::key_prefix_compression) depend on its encoding.
+/// Values with a [fixed size](crate::BtreeConfig::fixed_value_size) are always serialized with
+/// bincode with fixed integer encoding, since the other formats don't guarantee a fixed size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValueCodec {
+    /// The default [bincode](https://docs.rs/bincode) format with variable integer encoding.
+    #[default]
+    Bincode,
+    /// The bincode format with fixed integer encoding.
+    ///
+    /// Integers always need the same number of bytes, e.g. 8 bytes for a `u64`, so values
+    /// of the same type with different integer contents have the same serialized size.
+    /// This avoids relocating values which are updated with larger integers than estimated.
+    BincodeFixint,
+    /// The [postcard](https://docs.rs/postcard) format, which needs the `postcard` feature.
+    #[cfg(feature = "postcard")]
+    Postcard,
+    /// The [MessagePack](https://msgpack.org/) format with named struct fields,
+    /// which needs the `messagepack` feature.
+    #[cfg(feature = "messagepack")]
+    MessagePack,
+}
+
+impl ValueCodec {
+    /// Get the number of bytes needed to serialize the value.
+    pub(crate) fn serialized_size<T>(&self, value: &T) -> Result<u64>
+    where
+        T: Serialize,
+    {
+        let size = match self {
+            ValueCodec::Bincode => bincode::DefaultOptions::new().serialized_size(value)?,
+            ValueCodec::BincodeFixint => bincode::DefaultOptions::new()
+                .with_fixint_encoding()
+                .serialized_size(value)?,
+            #[cfg(feature = "postcard")]
+            ValueCodec::Postcard => {
+                postcard::serialize_with_flavor(value, postcard::ser_flavors::Size::default())?
+                    .try_into()?
+            }
+            #[cfg(feature = "messagepack")]
+            ValueCodec::MessagePack => {
+                let mut counter = ByteCounter(0);
+                rmp_serde::encode::write_named(&mut counter, value)?;
+                counter.0
+            }
+        };
+        Ok(size)
+    }
+
+    pub(crate) fn serialize<T>(&self, value: &T) -> Result<Vec<u8>>
+    where
+        T: Serialize,
+    {
+        let result = match self {
+            ValueCodec::Bincode => bincode::DefaultOptions::new().serialize(value)?,
+            ValueCodec::BincodeFixint => bincode::DefaultOptions::new()
+                .with_fixint_encoding()
+                .serialize(value)?,
+            #[cfg(feature = "postcard")]
+            ValueCodec::Postcard => postcard::to_allocvec(value)?,
+            #[cfg(feature = "messagepack")]
+            ValueCodec::MessagePack => rmp_serde::to_vec_named(value)?,
+        };
+        Ok(result)
+    }
+
+    /// Serialize the value into the buffer, which must have exactly the serialized size.
+    pub(crate) fn serialize_into<T>(&self, buffer: &mut [u8], value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        match self {
+            ValueCodec::Bincode => bincode::DefaultOptions::new().serialize_into(buffer, value)?,
+            ValueCodec::BincodeFixint => bincode::DefaultOptions::new()
+                .with_fixint_encoding()
+                .serialize_into(buffer, value)?,
+            #[cfg(feature = "postcard")]
+            ValueCodec::Postcard => {
+                postcard::to_slice(value, buffer)?;
+            }
+            #[cfg(feature = "messagepack")]
+            ValueCodec::MessagePack => rmp_serde::encode::write_named(&mut &mut buffer[..], value)?,
+        }
+        Ok(())
+    }
+
+    pub(crate) fn deserialize<T>(&self, bytes: &[u8]) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let result = match self {
+            ValueCodec::Bincode => bincode::DefaultOptions::new().deserialize(bytes)?,
+            ValueCodec::BincodeFixint => bincode::DefaultOptions::new()
+                .with_fixint_encoding()
+                .deserialize(bytes)?,
+            #[cfg(feature = "postcard")]
+            ValueCodec::Postcard => postcard::from_bytes(bytes)?,
+            #[cfg(feature = "messagepack")]
+            ValueCodec::MessagePack => rmp_serde::from_slice(bytes)?,
+        };
+        Ok(result)
+    }
+}
+
+impl<T> Codec<T> for ValueCodec
+where
+    T: Serialize + DeserializeOwned,
+{
+    fn serialize(&self, value: &T) -> Result<Vec<u8>> {
+        ValueCodec::serialize(self, value)
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Result<T> {
+        ValueCodec::deserialize(self, bytes)
+    }
+
+    fn serialized_size(&self, value: &T) -> Result<u64> {
+        ValueCodec::serialized_size(self, value)
+    }
+
+    fn serialize_into(&self, buffer: &mut [u8], value: &T) -> Result<()> {
+        ValueCodec::serialize_into(self, buffer, value)
+    }
+}
+
+/// Writer that only counts the number of written bytes.
+#[cfg(feature = "messagepack")]
+struct ByteCounter(u64);
+
+#[cfg(feature = "messagepack")]
+impl std::io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
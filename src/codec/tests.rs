@@ -0,0 +1,96 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{BtreeConfig, BtreeIndex};
+
+use super::*;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Record {
+    id: u64,
+    name: String,
+    scores: Vec<i32>,
+}
+
+fn codecs() -> Vec<ValueCodec> {
+    vec![
+        ValueCodec::Bincode,
+        ValueCodec::BincodeFixint,
+        #[cfg(feature = "postcard")]
+        ValueCodec::Postcard,
+        #[cfg(feature = "messagepack")]
+        ValueCodec::MessagePack,
+    ]
+}
+
+fn record(i: u64) -> Record {
+    Record {
+        id: i * 1000,
+        name: "x".repeat(i as usize % 50),
+        scores: (0..(i as i32 % 7)).map(|s| s * -300).collect(),
+    }
+}
+
+#[test]
+fn serialized_size_matches() {
+    for codec in codecs() {
+        for i in 0..100 {
+            let value = record(i);
+            let size: usize = codec.serialized_size(&value).unwrap().try_into().unwrap();
+            let mut buffer = vec![0; size];
+            codec.serialize_into(&mut buffer, &value).unwrap();
+            assert_eq!(value, codec.deserialize::<Record>(&buffer).unwrap());
+        }
+    }
+}
+
+#[cfg(feature = "messagepack")]
+#[test]
+fn messagepack_uses_field_names() {
+    let value = record(3);
+    let size = ValueCodec::MessagePack.serialized_size(&value).unwrap();
+    let mut buffer = vec![0; size as usize];
+    ValueCodec::MessagePack
+        .serialize_into(&mut buffer, &value)
+        .unwrap();
+    assert_eq!(rmp_serde::to_vec_named(&value).unwrap(), buffer);
+}
+
+#[test]
+fn fixint_size_does_not_depend_on_value() {
+    let small = ValueCodec::BincodeFixint
+        .serialized_size(&(1_u64, 2_i32))
+        .unwrap();
+    let large = ValueCodec::BincodeFixint
+        .serialized_size(&(u64::MAX, i32::MIN))
+        .unwrap();
+    assert_eq!(12, small);
+    assert_eq!(small, large);
+    assert!(
+        ValueCodec::Bincode
+            .serialized_size(&(1_u64, 2_i32))
+            .unwrap()
+            < small
+    );
+}
+
+#[test]
+fn index_with_codec() {
+    for codec in codecs() {
+        let config = BtreeConfig::default().order(3).value_codec(codec);
+        let mut t: BtreeIndex<u64, Record> = BtreeIndex::with_capacity(config, 100).unwrap();
+        for i in 0..200 {
+            t.insert(i, record(i)).unwrap();
+        }
+        // Grow some of the values, so they need to be relocated
+        for i in (0..200).step_by(5) {
+            t.update(&i, |v| v.name.push_str(&"y".repeat(100))).unwrap();
+        }
+        for i in 0..200 {
+            let mut expected = record(i);
+            if i % 5 == 0 {
+                expected.name.push_str(&"y".repeat(100));
+            }
+            assert_eq!(Some(expected), t.get(&i).unwrap());
+        }
+    }
+}
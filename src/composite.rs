@@ -0,0 +1,190 @@
+use std::ops::Bound;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{prefix::PrefixKey, raw::RawBytes};
+
+/// Marks an escaped zero byte inside a string or byte field.
+const ESCAPED_ZERO: u8 = 0xFF;
+/// Marks the end of a string or byte field after a zero byte.
+const END_OF_FIELD: u8 = 0x01;
+
+/// Key consisting of several fields, which is encoded as a byte string with the same order as the fields.
+///
+/// Integers are encoded as big-endian (with the sign bit flipped for signed integers)
+/// and strings and byte slices are terminated, so the lexicographic order of the encoded bytes
+/// is the same as the order of the tuple of fields.
+/// This allows to use the [bytes](Self::as_bytes) for the [`RawBtreeIndex`](crate::RawBtreeIndex),
+/// or to search all keys that start with the same fields with
+/// [`BtreeIndex::prefix_range`](crate::BtreeIndex::prefix_range).
+///
+/// # Example
+///
+/// ```rust
+/// use transient_btree_index::CompositeKey;
+///
+/// let a = CompositeKey::new().push_u32(1).push_str("b").push_i64(-3);
+/// let b = CompositeKey::new().push_u32(1).push_str("b").push_i64(2);
+/// assert!(a < b);
+///
+/// let mut fields = a.reader();
+/// assert_eq!(Some(1), fields.read_u32());
+/// assert_eq!(Some("b".to_string()), fields.read_str());
+/// assert_eq!(Some(-3), fields.read_i64());
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompositeKey(RawBytes);
+
+macro_rules! composite_key_integers {
+    ($($t:ty, $push:ident, $read:ident, $sign_bit:expr);* $(;)?) => {
+        impl CompositeKey {
+            $(
+                #[doc = concat!("Appends a `", stringify!($t), "` field.")]
+                pub fn $push(mut self, value: $t) -> Self {
+                    let value = value ^ $sign_bit;
+                    self.0 .0.extend_from_slice(&value.to_be_bytes());
+                    self
+                }
+            )*
+        }
+
+        impl<'a> CompositeKeyReader<'a> {
+            $(
+                #[doc = concat!("Reads the next field as `", stringify!($t), "`.")]
+                ///
+                /// Returns `None` if there are not enough bytes left.
+                pub fn $read(&mut self) -> Option<$t> {
+                    const SIZE: usize = std::mem::size_of::<$t>();
+                    let field = self.remaining.get(0..SIZE)?;
+                    self.remaining = &self.remaining[SIZE..];
+                    Some(<$t>::from_be_bytes(field.try_into().ok()?) ^ $sign_bit)
+                }
+            )*
+        }
+    };
+}
+
+composite_key_integers!(
+    u8, push_u8, read_u8, 0;
+    u16, push_u16, read_u16, 0;
+    u32, push_u32, read_u32, 0;
+    u64, push_u64, read_u64, 0;
+    u128, push_u128, read_u128, 0;
+    i8, push_i8, read_i8, i8::MIN;
+    i16, push_i16, read_i16, i16::MIN;
+    i32, push_i32, read_i32, i32::MIN;
+    i64, push_i64, read_i64, i64::MIN;
+    i128, push_i128, read_i128, i128::MIN;
+);
+
+impl CompositeKey {
+    /// Create a new key without any fields.
+    pub fn new() -> CompositeKey {
+        CompositeKey::default()
+    }
+
+    /// Create a key from its encoded bytes, as returned by [`Self::as_bytes`].
+    pub fn from_bytes(bytes: Vec<u8>) -> CompositeKey {
+        CompositeKey(RawBytes(bytes))
+    }
+
+    /// Appends a string field.
+    pub fn push_str(self, value: &str) -> Self {
+        self.push_bytes(value.as_bytes())
+    }
+
+    /// Appends a field with a slice of bytes.
+    ///
+    /// Zero bytes are escaped and the field is terminated, so shorter fields are
+    /// ordered before longer fields with the same start.
+    pub fn push_bytes(mut self, value: &[u8]) -> Self {
+        let encoded = &mut self.0 .0;
+        for b in value {
+            encoded.push(*b);
+            if *b == 0 {
+                encoded.push(ESCAPED_ZERO);
+            }
+        }
+        encoded.extend_from_slice(&[0, END_OF_FIELD]);
+        self
+    }
+
+    /// The encoded bytes of all fields.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0 .0
+    }
+
+    /// Returns the encoded bytes of all fields.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0 .0
+    }
+
+    /// Returns a reader to decode the fields in the order they were added.
+    pub fn reader(&self) -> CompositeKeyReader<'_> {
+        CompositeKeyReader {
+            remaining: self.as_bytes(),
+        }
+    }
+}
+
+impl PrefixKey for CompositeKey {
+    type Prefix = CompositeKey;
+
+    fn prefix_bounds(prefix: &CompositeKey) -> (Bound<Self>, Bound<Self>) {
+        // The keys are ordered by their bytes, so the prefix of the bytes can be used
+        let (start, end) = Vec::<u8>::prefix_bounds(prefix.as_bytes());
+        (
+            start.map(CompositeKey::from_bytes),
+            end.map(CompositeKey::from_bytes),
+        )
+    }
+}
+
+/// Decodes the fields of a [`CompositeKey`].
+///
+/// The fields must be read with the same types they were added with.
+pub struct CompositeKeyReader<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> CompositeKeyReader<'a> {
+    /// Reads the next field as byte slice.
+    ///
+    /// Returns `None` if the field is not terminated.
+    pub fn read_bytes(&mut self) -> Option<Vec<u8>> {
+        let mut result = Vec::new();
+        let mut i = 0;
+        loop {
+            let b = *self.remaining.get(i)?;
+            if b == 0 {
+                match *self.remaining.get(i + 1)? {
+                    ESCAPED_ZERO => result.push(0),
+                    END_OF_FIELD => {
+                        self.remaining = &self.remaining[(i + 2)..];
+                        return Some(result);
+                    }
+                    _ => return None,
+                }
+                i += 2;
+            } else {
+                result.push(b);
+                i += 1;
+            }
+        }
+    }
+
+    /// Reads the next field as string.
+    ///
+    /// Returns `None` if the field is not terminated or not valid UTF-8.
+    pub fn read_str(&mut self) -> Option<String> {
+        String::from_utf8(self.read_bytes()?).ok()
+    }
+
+    /// Returns `true` if all fields have been read.
+    pub fn is_empty(&self) -> bool {
+        self.remaining.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests;
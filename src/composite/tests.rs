@@ -0,0 +1,105 @@
+use crate::{BtreeConfig, BtreeIndex, RawBtreeIndex};
+
+use super::*;
+
+fn encode(fields: &(u32, &str, i64)) -> CompositeKey {
+    CompositeKey::new()
+        .push_u32(fields.0)
+        .push_str(fields.1)
+        .push_i64(fields.2)
+}
+
+#[test]
+fn byte_order_is_tuple_order() {
+    let mut tuples = Vec::new();
+    for a in [0, 1, 255, 256, u32::MAX] {
+        for b in ["", "\0", "\0\0", "a", "a\0", "a\0b", "ab", "b", "\u{FF}"] {
+            for c in [i64::MIN, -256, -1, 0, 1, 255, i64::MAX] {
+                tuples.push((a, b, c));
+            }
+        }
+    }
+    for x in tuples.iter() {
+        for y in tuples.iter() {
+            assert_eq!(
+                x.cmp(y),
+                encode(x).as_bytes().cmp(encode(y).as_bytes()),
+                "{:?} {:?}",
+                x,
+                y
+            );
+        }
+    }
+}
+
+#[test]
+fn read_fields() {
+    let key = CompositeKey::new()
+        .push_u8(200)
+        .push_i8(-100)
+        .push_u16(1000)
+        .push_i16(-1000)
+        .push_bytes(&[0, 1, 0, 255])
+        .push_i32(i32::MIN)
+        .push_u64(u64::MAX)
+        .push_str("\0x\0")
+        .push_u128(42)
+        .push_i128(-42);
+    let mut reader = key.reader();
+    assert_eq!(Some(200), reader.read_u8());
+    assert_eq!(Some(-100), reader.read_i8());
+    assert_eq!(Some(1000), reader.read_u16());
+    assert_eq!(Some(-1000), reader.read_i16());
+    assert_eq!(Some(vec![0, 1, 0, 255]), reader.read_bytes());
+    assert_eq!(Some(i32::MIN), reader.read_i32());
+    assert_eq!(Some(u64::MAX), reader.read_u64());
+    assert_eq!(Some("\0x\0".to_string()), reader.read_str());
+    assert_eq!(Some(42), reader.read_u128());
+    assert_eq!(Some(-42), reader.read_i128());
+    assert!(reader.is_empty());
+    assert_eq!(None, reader.read_u8());
+
+    // Invalid or incomplete fields
+    assert_eq!(
+        None,
+        CompositeKey::from_bytes(vec![1, 2]).reader().read_u32()
+    );
+    assert_eq!(
+        None,
+        CompositeKey::from_bytes(vec![1, 2]).reader().read_bytes()
+    );
+    assert_eq!(
+        None,
+        CompositeKey::from_bytes(vec![0, 2]).reader().read_bytes()
+    );
+}
+
+#[test]
+fn composite_keys_in_index() {
+    let mut t: BtreeIndex<CompositeKey, u32> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(2), 10).unwrap();
+    let mut raw = RawBtreeIndex::with_capacity(BtreeConfig::default().order(2), 10).unwrap();
+    for i in 0..100 {
+        let key = encode(&(i % 5, &format!("{}", i % 7), i as i64 - 50));
+        t.insert(key.clone(), i).unwrap();
+        raw.insert_raw(key.as_bytes(), &i.to_le_bytes()).unwrap();
+    }
+
+    let prefix = CompositeKey::new().push_u32(3).push_str("4");
+    let found: Vec<u32> = t
+        .prefix_range(&prefix)
+        .unwrap()
+        .map(|e| e.unwrap().1)
+        .collect();
+    let expected: Vec<u32> = (0..100).filter(|i| i % 5 == 3 && i % 7 == 4).collect();
+    assert_eq!(expected, found);
+
+    let raw_found: Vec<u32> = raw
+        .range_raw(prefix.as_bytes()..)
+        .unwrap()
+        .map(|e| e.unwrap())
+        .take_while(|(k, _)| k.starts_with(prefix.as_bytes()))
+        .map(|(_, v)| u32::from_le_bytes(v.try_into().unwrap()))
+        .collect();
+    assert_eq!(expected, raw_found);
+}
@@ -13,6 +13,12 @@ pub enum Error {
     OrderTooSmall(usize),
     #[error("The order of the tree must is too large ({0} was requested).")]
     OrderTooLarge(usize),
+    #[error("The node block size must be at least 4 KiB and a multiple or divisor of the page size, but {0} was requested.")]
+    InvalidNodeBlockSize(usize),
+    #[error("The page size must be a power of two, but {0} was requested.")]
+    InvalidPageSize(usize),
+    #[error("The block alignment must be a power of two, but {0} was requested.")]
+    InvalidBlockAlignment(usize),
     #[error("Requested index {idx} is larger than the number of keys in the node ({len})")]
     KeyIndexOutOfBounds { idx: usize, len: usize },
     #[error("When trying to insert a non-existing key, the found node block was internal and not a leaf node")]
@@ -21,6 +27,8 @@ pub enum Error {
     EmptyChildNodeInSplit,
     #[error("The given capacity of {capacity} was invalid.")]
     InvalidCapacity { capacity: usize },
+    #[error("The fixed size is {expected} bytes, but the serialized block needs {actual} bytes.")]
+    FixedSizeMismatch { expected: usize, actual: usize },
     #[error("Deserialization of block failed: {0}")]
     DeserializeBlock(String),
     #[error(transparent)]
@@ -31,6 +39,72 @@ pub enum Error {
     SliceConversion(#[from] TryFromSliceError),
     #[error(transparent)]
     Bincode(#[from] bincode::Error),
+    #[cfg(feature = "compression")]
+    #[error(transparent)]
+    Decompression(#[from] lz4_flex::block::DecompressError),
+    #[cfg(feature = "postcard")]
+    #[error(transparent)]
+    Postcard(#[from] postcard::Error),
+    #[cfg(feature = "messagepack")]
+    #[error(transparent)]
+    MessagePackEncode(#[from] rmp_serde::encode::Error),
+    #[cfg(feature = "messagepack")]
+    #[error(transparent)]
+    MessagePackDecode(#[from] rmp_serde::decode::Error),
+    #[cfg(feature = "rkyv")]
+    #[error(transparent)]
+    Rkyv(#[from] rkyv::rancor::Error),
+    #[error("Block with ID {block_id} is outside of the file")]
+    BlockOutOfBounds { block_id: usize },
     #[error("Non-existing key")]
     NonExistingKey,
+    #[error("A previous operation failed while changing the index, so it might be corrupted")]
+    Poisoned,
+    #[error("The payload ID does not belong to this index")]
+    InvalidPayloadId,
+    #[error("Order statistics must be enabled in the configuration for rank and select queries")]
+    OrderStatisticsDisabled,
+    #[error("The index is corrupted: {0}")]
+    IntegrityViolation(String),
+    #[error("A file would need {requested} bytes, but the maximum file size is {maximum} bytes")]
+    MaximumFileSizeExceeded { requested: usize, maximum: usize },
+    #[error("Reserving {requested} additional bytes in the file for the {file} failed: {source}")]
+    ReserveFailed {
+        file: &'static str,
+        requested: usize,
+        source: Box<Error>,
+    },
+    #[cfg(feature = "encryption")]
+    #[error("Encrypting or decrypting a block failed, e.g. because it has been changed")]
+    Encryption,
+    #[cfg(feature = "encryption")]
+    #[error("Encryption can't be used with {0}")]
+    EncryptionUnsupported(&'static str),
+    #[cfg(feature = "wal")]
+    #[error("The write-ahead log is invalid: {0}")]
+    InvalidWriteAheadLog(String),
+    #[error("{operation} the storage of the {file} failed: {source}")]
+    Storage {
+        file: &'static str,
+        operation: &'static str,
+        source: Box<Error>,
+    },
+    #[error("{operation} failed: {source}")]
+    StorageIo {
+        operation: &'static str,
+        source: std::io::Error,
+    },
+    #[error("The option {option} can't be used together with {conflicting_option}")]
+    ConflictingOptions {
+        option: &'static str,
+        conflicting_option: &'static str,
+    },
+    #[error("The operation has been cancelled")]
+    Cancelled,
+    #[error("A cache is unusable, because a thread panicked while using it")]
+    PoisonedCache,
+    #[error("A shard of the parallel builder is unusable, because a thread panicked while inserting into it")]
+    PoisonedShard,
+    #[error("A custom codec failed: {0}")]
+    Codec(#[source] Box<dyn std::error::Error + Send + Sync>),
 }
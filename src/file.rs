@@ -1,31 +1,87 @@
+//! Files of serialized blocks, which are the building blocks of the B-tree index.
+//!
+//! The files can also be used on their own for other transient data structures on disk,
+//! e.g. adjacency lists of a graph.
+//! A [`TupleFile`] allocates blocks for serialized values of the same type and returns
+//! the ID of each block, which is used to read or replace its value later.
+//! [`VariableSizeTupleFile`] can hold values of any size and relocates blocks that outgrow
+//! their capacity, while [`FixedSizeTupleFile`] stores values that always have the same
+//! serialized size without any overhead.
+//! Both files grow automatically and use a [`StorageBackend`],
+//! which is a memory mapped temporary file per default.
+//!
+//! # Example
+//!
+//! ```rust
+//! use transient_btree_index::{
+//!     file::{TupleFile, VariableSizeTupleFile},
+//!     Error,
+//! };
+//!
+//! fn main() -> std::result::Result<(), Error> {
+//!     // Neighbors of each node of a graph
+//!     let mut adjacency: VariableSizeTupleFile<Vec<u64>> =
+//!         VariableSizeTupleFile::with_capacity(4096, 16)?;
+//!     let first = adjacency.insert(&vec![1, 2])?;
+//!     let second = adjacency.insert(&vec![0])?;
+//!
+//!     // Blocks which need more space than allocated are relocated, but keep their ID
+//!     adjacency.put(first, &vec![1, 2, 3, 4, 5, 6, 7, 8])?;
+//!     assert_eq!(vec![1, 2, 3, 4, 5, 6, 7, 8], adjacency.get_owned(first)?);
+//!     assert_eq!(vec![0], *adjacency.get(second)?);
+//!     Ok(())
+//! }
+//! ```
 use std::{
-    collections::HashMap,
-    io::Write,
+    borrow::Cow,
+    collections::BTreeMap,
+    io::{Read, Write},
     marker::PhantomData,
     mem::size_of,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError},
 };
 
-use crate::{create_mmap, error::Result, Error, PAGE_SIZE};
+use crate::{
+    codec::{Codec, ValueCodec},
+    error::Result,
+    metrics::Metrics,
+    storage::{discard_pages, shrink_to_fit, system_page_size, GrowthPolicy, StorageBackend},
+    Error,
+};
 use bincode::Options;
-use linked_hash_map::LinkedHashMap;
-use memmap2::MmapMut;
 use serde::{de::DeserializeOwned, Serialize};
 
+use self::cache::BlockCache;
+pub(crate) use self::cache::{default_eviction_policy, EvictionPolicyFactory};
+pub use self::cache::{EvictionPolicy, LruPolicy, SegmentedLruPolicy};
+
+mod cache;
+#[cfg(feature = "encryption")]
+mod encryption;
+
+/// Default alignment in bytes of the data of each block in a [`VariableSizeTupleFile`].
+pub(crate) const DEFAULT_BLOCK_ALIGNMENT: usize = 8;
+
+/// Name of a file in storage errors, if it has not been given a more specific one.
+const DEFAULT_FILE_NAME: &str = "blocks";
+
 /// Return a value that is at least the given capacity, but ensures the block ends at a memory page
-pub fn page_aligned_capacity(capacity: usize) -> usize {
-    let mut num_full_pages = capacity / PAGE_SIZE;
-    if capacity % PAGE_SIZE != 0 {
-        num_full_pages += 1;
-    }
-    // Make sure there is enough space for the block header
-    (num_full_pages * PAGE_SIZE) - BlockHeader::size()
+/// with the given page size.
+///
+/// The size of the [`BlockHeader`] is already subtracted, so the result can be passed
+/// to [`TupleFile::allocate_block`] directly.
+/// The size of the block including its header is also a multiple of the block alignment,
+/// so that the data of the next block is aligned without skipping any space.
+pub fn page_aligned_capacity(capacity: usize, page_size: usize, alignment: usize) -> usize {
+    // Both sizes are powers of two, so the larger one is a multiple of the smaller one
+    let block_size = (capacity + BlockHeader::size()).next_multiple_of(page_size.max(alignment));
+    block_size - BlockHeader::size()
 }
 
-pub trait TupleFile<B>: Send + Sync
-where
-    B: Send + Sync,
-{
+/// File that stores blocks of serialized values of type `B`.
+///
+/// Each block is identified by the ID returned when allocating it.
+pub trait TupleFile<B> {
     /// Allocate a new block with the given capacity to hold this many bytes of data.
     ///
     /// In case there is any internal block header or other meta data,
@@ -37,26 +93,298 @@ where
     /// Get a block with the given id give ownership of the result to the caller.
     fn get_owned(&self, block_id: usize) -> Result<B>;
 
+    /// Get a shared reference to the block with the given id, which might be held by a cache.
     fn get(&self, block_id: usize) -> Result<Arc<B>>;
 
+    /// Get the raw serialized bytes of the block with the given id.
+    fn get_raw(&self, block_id: usize) -> Result<&[u8]>;
+
+    /// Get the serialized bytes of the block with the given id, which are decompressed if necessary.
+    fn get_serialized(&self, block_id: usize) -> Result<Cow<'_, [u8]>> {
+        Ok(Cow::Borrowed(self.get_raw(block_id)?))
+    }
+
     /// Set the content of a block with the given id.
     ///
     /// If the block needs more space than was originally allocated, a new block is allocated
-    /// and the redirection is saved in an in-memory hash map.
+    /// and the header of the old block points to the new one.
     /// The old block will remain empty. So try to avoid writing any
     /// blocks with a larger size than originally allocated.
+    /// How much space the new block reserves is determined by the [`RelocationPolicy`].
     fn put(&mut self, block_id: usize, block: &B) -> Result<()>;
 
     /// Get the number of bytes necessary to store the given block.
     fn serialized_size(&self, block: &B) -> Result<u64>;
+
+    /// Get the format in which the blocks are serialized.
+    fn codec(&self) -> &dyn Codec<B>;
+
+    /// Get the number of bytes used by the file in memory.
+    fn memory_usage(&self) -> FileMemoryUsage;
+
+    /// Get the number of bytes occupied by the allocated blocks, including freed and
+    /// relocated blocks whose space has not been reclaimed.
+    ///
+    /// Files that don't track this report the whole size of their storage.
+    fn used_bytes(&self) -> usize {
+        self.memory_usage().mapped
+    }
+
+    /// Get the number of relocated blocks and the space they left unused.
+    ///
+    /// Files that never relocate blocks return empty statistics.
+    fn relocation_stats(&self) -> RelocationStats {
+        RelocationStats::default()
+    }
+
+    /// Returns whether the block ID points to an allocated block of the file.
+    fn contains_block(&self, block_id: usize) -> bool;
+
+    /// Returns whether the blocks are stored encrypted, so that their raw bytes can't be used
+    /// without decrypting them.
+    fn is_encrypted(&self) -> bool {
+        false
+    }
+
+    /// Remove all blocks from the file.
+    ///
+    /// The underlying storage keeps its size, so it can be reused for new blocks.
+    fn clear(&mut self);
+
+    /// Shrink the underlying storage to the space used by the allocated blocks.
+    fn shrink_to_fit(&mut self) -> Result<()>;
+
+    /// Grow the underlying storage up front, so that blocks with the given number of additional
+    /// bytes (including their headers) can be allocated without growing it again.
+    fn try_reserve(&mut self, additional_bytes: usize) -> Result<()>;
+
+    /// Returns the ID of the block that currently holds the data of the given block.
+    ///
+    /// This is a different block if the block has been relocated, and the new ID can be used
+    /// instead of the original one to avoid following the forwarding pointer of the original block.
+    fn resolve_block(&self, block_id: usize) -> Result<usize>;
+
+    /// Mark the block as unused, so its space can be reused for new blocks.
+    ///
+    /// The block must not be accessed afterwards.
+    /// Files that can't reuse the space of single blocks ignore this.
+    fn free_block(&mut self, _block_id: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Get the generation of the block, which changes each time the block is
+    /// [freed](Self::free_block), so that references to a freed or reused block can be detected.
+    ///
+    /// Files that never free blocks always return 0.
+    fn block_generation(&self, _block_id: usize) -> Result<u64> {
+        Ok(0)
+    }
+
+    /// Mark the block as not needed anymore, so the memory of its pages can be released.
+    ///
+    /// Unlike with [`Self::free_block`], the space is never reused, so this is meant for files
+    /// that are consumed and not changed anymore.
+    /// The block must not be accessed afterwards.
+    /// Files that can't release the memory of single pages ignore this.
+    fn discard_block(&mut self, _block_id: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Allocate a new block that can hold the given block and store it.
+    ///
+    /// Returns the ID of the new block.
+    fn insert(&mut self, block: &B) -> Result<usize> {
+        let capacity: usize = self.serialized_size(block)?.try_into()?;
+        let block_id = self.allocate_block(capacity)?;
+        self.put(block_id, block)?;
+        Ok(block_id)
+    }
+
+    /// Allocate a new block for a block that is already serialized.
+    ///
+    /// The serialized block consists of the `header` and `len` bytes read from the `reader`.
+    /// Per default, the whole block is read into memory and deserialized before storing it.
+    ///
+    /// Returns the ID of the new block.
+    fn insert_from_reader(
+        &mut self,
+        header: &[u8],
+        len: usize,
+        reader: &mut dyn Read,
+    ) -> Result<usize>
+    where
+        B: DeserializeOwned,
+    {
+        let mut serialized = header.to_vec();
+        serialized.resize(header.len() + len, 0);
+        reader.read_exact(&mut serialized[header.len()..])?;
+        let block: B = self.codec().deserialize(&serialized)?;
+        self.insert(&block)
+    }
+}
+
+/// Number of bytes used by a tuple file, as reported by [`TupleFile::memory_usage`].
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FileMemoryUsage {
+    /// Size of the memory mapped file.
+    pub mapped: usize,
+    /// Approximate size of the blocks held in the block cache.
+    pub cache: usize,
+}
+
+/// Counts the relocations of the blocks of a file, as reported by [`TupleFile::relocation_stats`].
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct RelocationStats {
+    /// Number of times a block has been moved to a new block because it needed more space.
+    pub relocations: usize,
+    /// Number of relocations of blocks that have already been relocated before.
+    pub repeated_relocations: usize,
+    /// Bytes of the blocks that have been left behind by relocations and are not used anymore.
+    ///
+    /// The header of the original block is still needed to find the relocated block,
+    /// so it is not included.
+    pub abandoned_bytes: usize,
+}
+
+/// Determines how much space is reserved when a block is relocated because it needs more space.
+///
+/// The new block can hold the needed size multiplied with [`Self::factor`].
+/// Blocks which grow repeatedly would be relocated again and again, so once the same block has
+/// been relocated [`Self::repeated_after`] times, it is over-allocated with [`Self::repeated_factor`]
+/// instead.
+/// The capacity is always rounded up to whole memory pages.
+///
+/// # Example
+///
+/// ```rust
+/// use transient_btree_index::RelocationPolicy;
+///
+/// // Double the size on the first two relocations and quadruple it afterwards
+/// let policy = RelocationPolicy {
+///     factor: 2,
+///     repeated_after: 2,
+///     repeated_factor: 4,
+/// };
+/// assert_eq!(2, policy.factor_for(1));
+/// assert_eq!(4, policy.factor_for(3));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RelocationPolicy {
+    /// Multiple of the needed size that is reserved by a relocated block, at least 1.
+    pub factor: usize,
+    /// Number of previous relocations of the same block after which the repeated factor is used.
+    pub repeated_after: usize,
+    /// Multiple of the needed size that is reserved by blocks which have been relocated
+    /// at least [`Self::repeated_after`] times before, at least 1.
+    pub repeated_factor: usize,
+}
+
+impl Default for RelocationPolicy {
+    /// Always reserve double the needed size.
+    fn default() -> Self {
+        RelocationPolicy {
+            factor: 2,
+            repeated_after: 1,
+            repeated_factor: 2,
+        }
+    }
 }
 
+impl RelocationPolicy {
+    /// The factor for the given relocation of the same block, counting from 1.
+    pub fn factor_for(&self, relocation: usize) -> usize {
+        if relocation > self.repeated_after {
+            self.repeated_factor.max(1)
+        } else {
+            self.factor.max(1)
+        }
+    }
+}
+
+/// Allocation state of a file, to which the file can be rolled back to remove all blocks
+/// that were allocated afterwards.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FileCheckpoint {
+    free_space_offset: usize,
+    free_blocks: BTreeMap<usize, Vec<usize>>,
+}
+
+impl FileCheckpoint {
+    /// Blocks with an ID below this offset were allocated before the checkpoint.
+    pub(crate) fn free_space_offset(&self) -> usize {
+        self.free_space_offset
+    }
+}
+
+/// Counts the discarded bytes of each memory page of a storage,
+/// so that a page is released as soon as all blocks in it have been discarded.
+#[derive(Clone, Default)]
+pub(crate) struct DiscardedPages {
+    bytes: Vec<u32>,
+}
+
+impl DiscardedPages {
+    /// Marks the bytes from `start` to `end` (exclusive) as discarded and releases the pages
+    /// that are discarded completely afterwards.
+    ///
+    /// Each byte must only be discarded once, and the page size must not change.
+    pub fn discard(
+        &mut self,
+        file: &'static str,
+        storage: &mut dyn StorageBackend,
+        start: usize,
+        end: usize,
+        page_size: usize,
+    ) -> Result<()> {
+        let mut offset = start;
+        while offset < end {
+            let page = offset / page_size;
+            let page_start = page * page_size;
+            let page_end = (page_start + page_size).min(end);
+            if self.bytes.len() <= page {
+                self.bytes.resize(page + 1, 0);
+            }
+            self.bytes[page] += u32::try_from(page_end - offset)?;
+            if self.bytes[page] as usize >= page_size {
+                discard_pages(file, storage, page_start, page_start + page_size, page_size)?;
+            }
+            offset = page_end;
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+    }
+}
+
+/// Marks a relocated block in the used size of its header.
+///
+/// The bits below the number of relocations contain the ID of the block the data has been moved to.
+const RELOCATED_TAG: u64 = 1 << 63;
+
+/// Position of the number of relocations of a relocated block in the used size of its header.
+const RELOCATIONS_SHIFT: u32 = 56;
+
+/// Maximum number of relocations that is counted for a block.
+const MAX_RELOCATIONS: u64 = 0x7f;
+
+/// Position of the generation of a block in the capacity of its header.
+///
+/// The capacity of a block is always smaller than the bits below it.
+const GENERATION_SHIFT: u32 = 48;
+
+/// Mask of the bits of the generation after shifting it.
+const GENERATION_MASK: u64 = 0xffff;
+
 /// Representation of a header at the start of each block.
 ///
 /// When allocating new blocks, the size of this header is not included.
 pub struct BlockHeader {
     capacity: u64,
     used: u64,
+    /// Incremented each time the block is freed, and wraps around.
+    generation: u64,
 }
 
 impl BlockHeader {
@@ -65,8 +393,9 @@ impl BlockHeader {
         let block_size = u64::from_le_bytes(buffer[0..8].try_into()?);
         let used_size = u64::from_le_bytes(buffer[8..16].try_into()?);
         Ok(BlockHeader {
-            capacity: block_size,
+            capacity: block_size & ((1 << GENERATION_SHIFT) - 1),
             used: used_size,
+            generation: block_size >> GENERATION_SHIFT,
         })
     }
 
@@ -75,11 +404,39 @@ impl BlockHeader {
     where
         W: Write,
     {
-        buffer.write_all(&self.capacity.to_le_bytes())?;
+        let block_size = self.capacity | (self.generation << GENERATION_SHIFT);
+        buffer.write_all(&block_size.to_le_bytes())?;
         buffer.write_all(&self.used.to_le_bytes())?;
         Ok(())
     }
 
+    /// The ID of the block the data has been moved to, if this block has been relocated.
+    fn relocated_block_id(&self) -> Option<u64> {
+        (self.used & RELOCATED_TAG != 0).then_some(self.used & ((1 << RELOCATIONS_SHIFT) - 1))
+    }
+
+    /// How often the block has been relocated, up to [`MAX_RELOCATIONS`].
+    fn relocations(&self) -> u64 {
+        if self.used & RELOCATED_TAG != 0 {
+            (self.used & !RELOCATED_TAG) >> RELOCATIONS_SHIFT
+        } else {
+            0
+        }
+    }
+
+    /// Marks the block as relocated to the given block.
+    fn set_relocated(&mut self, block_id: usize, relocations: u64) -> Result<()> {
+        let block_id = u64::try_from(block_id)?;
+        if block_id >> RELOCATIONS_SHIFT != 0 {
+            return Err(Error::InvalidCapacity {
+                capacity: block_id.try_into()?,
+            });
+        }
+        self.used =
+            RELOCATED_TAG | (relocations.min(MAX_RELOCATIONS) << RELOCATIONS_SHIFT) | block_id;
+        Ok(())
+    }
+
     /// The number of bytes needed to serialize the block header.
     ///
     /// Should be used as an offset. Also, when you want to allocate
@@ -92,36 +449,66 @@ impl BlockHeader {
 /// Represents a temporary memory mapped file that can store and retrieve blocks of type `B`.
 ///
 /// Blocks will be (de-) serializable with the Serde crate.
-pub struct VariableSizeTupleFile<B>
-where
-    B: Sync,
-{
+pub struct VariableSizeTupleFile<B> {
+    /// Describes the content of the file in storage errors.
+    name: &'static str,
     free_space_offset: usize,
-    mmap: MmapMut,
-    relocated_blocks: HashMap<usize, usize>,
-    serializer: bincode::DefaultOptions,
-    cache: Arc<Mutex<LinkedHashMap<usize, Arc<B>>>>,
-    block_cache_size: usize,
+    storage: Box<dyn StorageBackend>,
+    growth: GrowthPolicy,
+    metrics: Metrics,
+    /// Unused blocks that can be reused, by their capacity.
+    free_blocks: BTreeMap<usize, Vec<usize>>,
+    discarded: DiscardedPages,
+    page_size: usize,
+    codec: Arc<dyn Codec<B>>,
+    block_alignment: usize,
+    relocation_policy: RelocationPolicy,
+    relocation_stats: RelocationStats,
+    cache: Arc<Mutex<BlockCache<B>>>,
+    #[cfg(feature = "compression")]
+    compression_threshold: Option<usize>,
+    #[cfg(feature = "encryption")]
+    cipher: Option<encryption::BlockCipher>,
 }
 
 impl<B> TupleFile<B> for VariableSizeTupleFile<B>
 where
-    B: Send + Sync + Serialize + DeserializeOwned + Clone,
+    B: Serialize + DeserializeOwned + Clone,
 {
     fn allocate_block(&mut self, capacity: usize) -> Result<usize> {
+        if let Some(block_id) = self.reuse_free_block(capacity)? {
+            return Ok(block_id);
+        }
+
+        // Skip some free space if needed, so the data after the header is aligned
+        let data_start =
+            (self.free_space_offset + BlockHeader::size()).next_multiple_of(self.block_alignment);
+
+        // Add the space up to the next aligned block to the capacity instead of skipping it later
+        let new_offset = (data_start + capacity + BlockHeader::size())
+            .next_multiple_of(self.block_alignment)
+            - BlockHeader::size();
+        let capacity = new_offset - data_start;
+
         // Make sure we still have enough space left
-        let new_offset = self.free_space_offset + BlockHeader::size() + capacity;
         self.grow(new_offset)?;
 
-        // Return the old start of free space as block index
-        let result = self.free_space_offset;
+        // The block starts with its header before the data
+        let result = data_start - BlockHeader::size();
 
         // Write the block header to the file
+        let capacity: u64 = capacity.try_into()?;
+        if capacity >> GENERATION_SHIFT != 0 {
+            return Err(Error::InvalidCapacity {
+                capacity: capacity.try_into()?,
+            });
+        }
         let header = BlockHeader {
-            capacity: capacity.try_into()?,
+            capacity,
             used: 0,
+            generation: 0,
         };
-        header.write(&mut self.mmap[result..(result + BlockHeader::size())])?;
+        header.write(&mut self.storage.as_mut_slice()[result..(result + BlockHeader::size())])?;
 
         // The next free block can be added after this block
         self.free_space_offset = new_offset;
@@ -129,86 +516,269 @@ where
     }
 
     fn get_owned(&self, block_id: usize) -> Result<B> {
-        let block_id = *self.relocated_blocks.get(&block_id).unwrap_or(&block_id);
+        let block_id = self.resolve_block(block_id)?;
 
-        if let Some(b) = self.get_cached_entry(block_id) {
+        if let Some(b) = self.get_cached_entry(block_id)? {
             Ok(b.as_ref().clone())
         } else {
-            let result = self.read_block(block_id)?;
+            let (result, _) = self.read_block(block_id)?;
             Ok(result)
         }
     }
 
     fn get(&self, block_id: usize) -> Result<Arc<B>> {
-        let block_id = *self.relocated_blocks.get(&block_id).unwrap_or(&block_id);
+        let block_id = self.resolve_block(block_id)?;
 
-        if let Some(b) = self.get_cached_entry(block_id) {
+        if let Some(b) = self.get_cached_entry(block_id)? {
             Ok(b)
         } else {
-            let result = self.read_block(block_id)?;
+            let (result, size) = self.read_block(block_id)?;
             let result = Arc::new(result);
-            if let Ok(mut cache) = self.cache.try_lock() {
-                cache.insert(block_id, result.clone());
-                // Remove the oldest entry when capacity is reached
-                if cache.len() > self.block_cache_size {
-                    cache.pop_front();
-                }
+            if let Some(mut cache) = self.try_lock_cache()? {
+                cache.insert(block_id, result.clone(), size);
             }
             Ok(result)
         }
     }
 
-    fn put(&mut self, block_id: usize, block: &B) -> Result<()> {
-        let relocated_block_id = *self.relocated_blocks.get(&block_id).unwrap_or(&block_id);
+    /// Compressed or encrypted blocks are returned as they are stored.
+    fn get_raw(&self, block_id: usize) -> Result<&[u8]> {
+        let block_id = self.resolve_block(block_id)?;
+        let header = self.block_header(block_id)?;
+        let used_size: usize = header.used.try_into()?;
+        storage_slice(
+            self.storage.as_slice(),
+            block_id,
+            block_id + BlockHeader::size(),
+            used_size,
+        )
+    }
 
-        // Check there is still enough space in the block
-        let (update_fits, new_used_size) = self.can_update(relocated_block_id, block)?;
-        let block_id = if update_fits {
-            relocated_block_id
-        } else {
-            // Relocate (possible again) to a new block with double the size
-            let new_used_size: usize = new_used_size.try_into()?;
-            let new_block_id = self.allocate_block(page_aligned_capacity(new_used_size * 2))?;
-            self.relocated_blocks.insert(block_id, new_block_id);
-            new_block_id
-        };
+    #[cfg(any(feature = "compression", feature = "encryption"))]
+    fn get_serialized(&self, block_id: usize) -> Result<Cow<'_, [u8]>> {
+        self.decode(self.get_raw(block_id)?)
+    }
 
-        // Update the header with the new size
-        let mut header = self.block_header(block_id)?;
-        header.used = new_used_size;
-        header.write(&mut self.mmap[block_id..(block_id + BlockHeader::size())])?;
+    fn put(&mut self, block_id: usize, block: &B) -> Result<()> {
+        #[cfg(any(feature = "compression", feature = "encryption"))]
+        if let Some((data, size)) = self.encode(block)? {
+            let block_id = self.reserve(block_id, data.len().try_into()?)?;
+            let block_start = block_id + BlockHeader::size();
+            self.storage.as_mut_slice()[block_start..(block_start + data.len())]
+                .copy_from_slice(&data);
+            self.cache_block(block_id, block, size)?;
+            return Ok(());
+        }
+
+        let new_used_size = self.serialized_size(block)?;
+        let block_id = self.reserve(block_id, new_used_size)?;
 
         // Serialize the block and write it at the proper location in the file
-        let block_size: usize = header.capacity.try_into()?;
+        let block_size: usize = new_used_size.try_into()?;
         let block_start = block_id + BlockHeader::size();
         let block_end = block_start + block_size;
-        self.serializer
-            .serialize_into(&mut self.mmap[block_start..block_end], &block)?;
+        self.codec.serialize_into(
+            &mut self.storage.as_mut_slice()[block_start..block_end],
+            block,
+        )?;
 
-        if let Ok(mut cache) = self.cache.lock() {
-            cache.insert(block_id, Arc::new(block.clone()));
-            // Remove the oldest entry when capacity is reached
-            if cache.len() > self.block_cache_size {
-                cache.pop_front();
-            }
-        }
+        self.cache_block(block_id, block, block_size)?;
 
         Ok(())
     }
 
     fn serialized_size(&self, block: &B) -> Result<u64> {
-        let new_size = self.serializer.serialized_size(&block)?;
+        let new_size = self.codec.serialized_size(block)?;
+        // Compressed blocks might be smaller, but there is an additional marker byte
+        #[cfg(feature = "compression")]
+        let new_size = new_size + u64::from(self.compression_threshold.is_some());
+        #[cfg(feature = "encryption")]
+        let new_size = if self.cipher.is_some() {
+            new_size + u64::try_from(encryption::ENCRYPTION_OVERHEAD)?
+        } else {
+            new_size
+        };
         Ok(new_size)
     }
+
+    fn codec(&self) -> &dyn Codec<B> {
+        self.codec.as_ref()
+    }
+
+    fn contains_block(&self, block_id: usize) -> bool {
+        block_id + BlockHeader::size() <= self.free_space_offset
+            && self
+                .resolve_block(block_id)
+                .is_ok_and(|block_id| block_id + BlockHeader::size() <= self.free_space_offset)
+    }
+
+    /// A cache that is unusable because a thread panicked while using it is cleared, too,
+    /// and can be used again.
+    fn clear(&mut self) {
+        self.free_space_offset = 0;
+        self.free_blocks.clear();
+        self.discarded.clear();
+        self.relocation_stats = RelocationStats::default();
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        self.cache.clear_poison();
+    }
+
+    fn shrink_to_fit(&mut self) -> Result<()> {
+        shrink_to_fit(
+            self.name,
+            self.storage.as_mut(),
+            self.free_space_offset,
+            self.page_size,
+        )
+    }
+
+    fn try_reserve(&mut self, additional_bytes: usize) -> Result<()> {
+        self.grow(self.free_space_offset.saturating_add(additional_bytes))
+    }
+
+    /// Relocated blocks contain a forwarding pointer to the new block in their header.
+    fn resolve_block(&self, block_id: usize) -> Result<usize> {
+        match self.block_header(block_id)?.relocated_block_id() {
+            Some(relocated_block_id) => Ok(relocated_block_id.try_into()?),
+            None => Ok(block_id),
+        }
+    }
+
+    /// The block is reused by the next allocation of a block with at most its capacity.
+    /// If the block has been relocated, both the original block with the forwarding pointer
+    /// and the block holding the data are freed.
+    fn free_block(&mut self, block_id: usize) -> Result<()> {
+        let relocated_block_id = self.resolve_block(block_id)?;
+        if relocated_block_id != block_id {
+            self.release_block(relocated_block_id)?;
+            // The space of the original block is not abandoned anymore
+            let capacity: usize = self.block_header(block_id)?.capacity.try_into()?;
+            self.relocation_stats.abandoned_bytes = self
+                .relocation_stats
+                .abandoned_bytes
+                .saturating_sub(capacity);
+        }
+        self.release_block(block_id)
+    }
+
+    fn block_generation(&self, block_id: usize) -> Result<u64> {
+        Ok(self.block_header(block_id)?.generation)
+    }
+
+    /// A page is released when all blocks that overlap it have been discarded.
+    fn discard_block(&mut self, block_id: usize) -> Result<()> {
+        let relocated_block_id = self.resolve_block(block_id)?;
+        if relocated_block_id != block_id {
+            // The original block only holds the forwarding pointer since the block was relocated
+            self.discard_allocated_block(relocated_block_id)?;
+        }
+        self.discard_allocated_block(block_id)
+    }
+
+    fn memory_usage(&self) -> FileMemoryUsage {
+        // The estimated size is still available if a thread panicked while using the cache
+        let cache = self
+            .cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .memory_usage();
+        FileMemoryUsage {
+            mapped: self.storage.len(),
+            cache,
+        }
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.free_space_offset
+    }
+
+    fn relocation_stats(&self) -> RelocationStats {
+        self.relocation_stats
+    }
+
+    #[cfg(feature = "encryption")]
+    fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    fn insert(&mut self, block: &B) -> Result<usize> {
+        #[cfg(any(feature = "compression", feature = "encryption"))]
+        if let Some((data, size)) = self.encode(block)? {
+            // Only compress or encrypt the block once and allocate exactly the needed size
+            let block_id = self.allocate_block(data.len())?;
+            self.reserve(block_id, data.len().try_into()?)?;
+            let block_start = block_id + BlockHeader::size();
+            self.storage.as_mut_slice()[block_start..(block_start + data.len())]
+                .copy_from_slice(&data);
+            self.cache_block(block_id, block, size)?;
+            return Ok(block_id);
+        }
+
+        let capacity: usize = self.serialized_size(block)?.try_into()?;
+        let block_id = self.allocate_block(capacity)?;
+        self.put(block_id, block)?;
+        Ok(block_id)
+    }
+
+    /// The data is copied from the reader directly into the file and is never compressed.
+    /// Encrypted files read the data into memory first to encrypt it.
+    fn insert_from_reader(
+        &mut self,
+        header: &[u8],
+        len: usize,
+        reader: &mut dyn Read,
+    ) -> Result<usize> {
+        // Compressed blocks start with a marker byte, which is 0 for uncompressed data
+        #[cfg(feature = "compression")]
+        let marker: &[u8] = if self.compression_threshold.is_some() {
+            &[0]
+        } else {
+            &[]
+        };
+        #[cfg(not(feature = "compression"))]
+        let marker: &[u8] = &[];
+
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = &mut self.cipher {
+            let mut data = Vec::with_capacity(marker.len() + header.len() + len);
+            data.extend_from_slice(marker);
+            data.extend_from_slice(header);
+            data.resize(marker.len() + header.len() + len, 0);
+            reader.read_exact(&mut data[(marker.len() + header.len())..])?;
+            let data = cipher.encrypt(&data)?;
+            let block_id = self.allocate_block(data.len())?;
+            self.reserve(block_id, data.len().try_into()?)?;
+            let block_start = block_id + BlockHeader::size();
+            self.storage.as_mut_slice()[block_start..(block_start + data.len())]
+                .copy_from_slice(&data);
+            return Ok(block_id);
+        }
+
+        let size = marker.len() + header.len() + len;
+        let block_id = self.allocate_block(size)?;
+        self.reserve(block_id, size.try_into()?)?;
+
+        let block_start = block_id + BlockHeader::size();
+        let block = &mut self.storage.as_mut_slice()[block_start..(block_start + size)];
+        let (prefix, data) = block.split_at_mut(marker.len() + header.len());
+        prefix[..marker.len()].copy_from_slice(marker);
+        prefix[marker.len()..].copy_from_slice(header);
+        reader.read_exact(data)?;
+        Ok(block_id)
+    }
 }
 
 impl<B> VariableSizeTupleFile<B>
 where
-    B: Serialize + DeserializeOwned + Clone + Sync + Send + Sync,
+    B: Serialize + DeserializeOwned + Clone,
 {
-    /// Create a new file with the given capacity in bytes.
+    /// Create a new file with the given capacity in bytes, which caches up to `block_cache_size`
+    /// deserialized blocks.
     ///
-    /// New blocks can be allocated with [`Self::allocate_block()`].
+    /// New blocks can be allocated with [`TupleFile::allocate_block()`].
     /// While the file will automatically grow when block are allocated and the capacity is reached,
     /// you cannot change the capacity of a single block after allocating it.
     pub fn with_capacity(
@@ -216,49 +786,406 @@ where
         block_cache_size: usize,
     ) -> Result<VariableSizeTupleFile<B>> {
         // Create an anonymous memory mapped file with the capacity as size
-        let capacity = capacity.max(1);
-        let mmap = create_mmap(capacity)?;
+        let storage = crate::storage::create_default_storage(capacity.max(1))?;
+        Ok(Self::with_storage(storage, block_cache_size))
+    }
 
-        Ok(VariableSizeTupleFile {
-            mmap,
+    /// Create a new file that uses the given storage.
+    pub fn with_storage(
+        storage: Box<dyn StorageBackend>,
+        block_cache_size: usize,
+    ) -> VariableSizeTupleFile<B> {
+        VariableSizeTupleFile {
+            name: DEFAULT_FILE_NAME,
+            storage,
+            growth: GrowthPolicy::default(),
+            metrics: Metrics::default(),
             free_space_offset: 0,
-            relocated_blocks: HashMap::default(),
-            serializer: bincode::DefaultOptions::new(),
-            cache: Arc::new(Mutex::new(LinkedHashMap::with_capacity(block_cache_size))),
-            block_cache_size,
-        })
+            free_blocks: BTreeMap::default(),
+            discarded: DiscardedPages::default(),
+            page_size: system_page_size(),
+            codec: Arc::new(ValueCodec::default()),
+            block_alignment: DEFAULT_BLOCK_ALIGNMENT,
+            relocation_policy: RelocationPolicy::default(),
+            relocation_stats: RelocationStats::default(),
+            cache: Arc::new(Mutex::new(BlockCache::with_max_entries(block_cache_size))),
+            #[cfg(feature = "compression")]
+            compression_threshold: None,
+            #[cfg(feature = "encryption")]
+            cipher: None,
+        }
     }
 
-    fn read_block(&self, block_id: usize) -> Result<B> {
+    /// Limit the cache by the approximate size of the cached blocks instead of their number.
+    ///
+    /// The size of a block is estimated by its serialized size.
+    pub fn with_cache_byte_budget(mut self, max_bytes: usize) -> Self {
+        let mut cache = BlockCache::with_max_bytes(max_bytes);
+        cache.set_metrics(self.metrics.clone());
+        self.cache = Arc::new(Mutex::new(cache));
+        self
+    }
+
+    /// Describe the content of the file with the given name in storage errors.
+    pub(crate) fn with_name(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Use the given growth strategy and maximum size when the file needs more space.
+    pub(crate) fn with_growth_policy(mut self, growth: GrowthPolicy) -> Self {
+        self.growth = growth;
+        self
+    }
+
+    /// Align relocated blocks and shrink the file to memory pages with the given size in bytes,
+    /// instead of the page size of the system.
+    pub(crate) fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Use eviction policies from the given factory for the cache.
+    pub(crate) fn with_eviction_policy(self, create_policy: EvictionPolicyFactory) -> Self {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.set_eviction_policy(create_policy);
+        }
+        self
+    }
+
+    /// Report growing the file, relocated blocks and evicted cache entries to the metrics.
+    pub(crate) fn with_metrics(mut self, metrics: Metrics) -> Self {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.set_metrics(metrics.clone());
+        }
+        self.metrics = metrics;
+        self
+    }
+
+    /// Returns the allocation state of the file, so that the blocks allocated afterwards
+    /// can be removed again with [`Self::rollback`].
+    ///
+    /// The blocks that already exist must not be changed until then.
+    pub(crate) fn checkpoint(&self) -> FileCheckpoint {
+        FileCheckpoint {
+            free_space_offset: self.free_space_offset,
+            free_blocks: self.free_blocks.clone(),
+        }
+    }
+
+    /// Removes all blocks that were allocated after the checkpoint was taken.
+    pub(crate) fn rollback(&mut self, checkpoint: FileCheckpoint) -> Result<()> {
+        // The removed blocks might be cached
+        self.lock_cache()?.clear();
+        self.free_space_offset = checkpoint.free_space_offset;
+        self.free_blocks = checkpoint.free_blocks;
+        Ok(())
+    }
+
+    /// Serialize the blocks in the given format instead of bincode.
+    ///
+    /// This must be configured before any blocks are allocated.
+    pub(crate) fn with_codec(mut self, codec: Arc<dyn Codec<B>>) -> Self {
+        self.set_codec(codec);
+        self
+    }
+
+    /// Serialize the blocks in the given format, like [`Self::with_codec`].
+    pub(crate) fn set_codec(&mut self, codec: Arc<dyn Codec<B>>) {
+        self.codec = codec;
+    }
+
+    /// Get the format in which the blocks are serialized, to use it for another file.
+    pub(crate) fn shared_codec(&self) -> Arc<dyn Codec<B>> {
+        self.codec.clone()
+    }
+
+    /// Align the start of the data of each block (after the header) to the given number of bytes,
+    /// relative to the start of the storage. The alignment must be a power of two
+    /// and is 8 bytes per default.
+    ///
+    /// This must be configured before any blocks are allocated.
+    pub(crate) fn with_block_alignment(mut self, alignment: usize) -> Self {
+        self.block_alignment = alignment;
+        self
+    }
+
+    /// Use the given policy to determine the capacity of relocated blocks.
+    pub fn with_relocation_policy(mut self, policy: RelocationPolicy) -> Self {
+        self.relocation_policy = policy;
+        self
+    }
+
+    /// Compress all blocks that have at least the given serialized size in bytes.
+    ///
+    /// This must be configured before any blocks are allocated.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, threshold: usize) -> Self {
+        self.compression_threshold = Some(threshold);
+        self
+    }
+
+    /// Encrypt all blocks with AES-256-GCM and the given key.
+    ///
+    /// The blocks are decrypted when they are read, and blocks that have been changed in
+    /// the storage fail with [`Error::Encryption`].
+    /// Each block needs 28 additional bytes for the nonce and the authentication tag.
+    /// If compression is enabled, too, the blocks are compressed before they are encrypted.
+    ///
+    /// This must be configured before any blocks are allocated.
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, key: &[u8; 32]) -> Self {
+        self.cipher = Some(encryption::BlockCipher::new(key));
+        self
+    }
+
+    /// Reads and deserializes the block.
+    ///
+    /// Returns the block and its (uncompressed) serialized size.
+    fn read_block(&self, block_id: usize) -> Result<(B, usize)> {
         // Read the size of the stored block
         let header = self.block_header(block_id)?;
         let used_size: usize = header.used.try_into()?;
+        let data = storage_slice(
+            self.storage.as_slice(),
+            block_id,
+            block_id + BlockHeader::size(),
+            used_size,
+        )?;
+
         // Deserialize and return
-        let block_start = block_id + BlockHeader::size();
-        let block_end = block_start + used_size;
-        let result: B = self
-            .serializer
-            .deserialize(&self.mmap[block_start..block_end])?;
-        Ok(result)
+        #[cfg(any(feature = "compression", feature = "encryption"))]
+        {
+            let serialized = self.decode(data)?;
+            let result: B = self.codec.deserialize(&serialized)?;
+            Ok((result, serialized.len()))
+        }
+        #[cfg(not(any(feature = "compression", feature = "encryption")))]
+        {
+            let result: B = self.codec.deserialize(data)?;
+            Ok((result, used_size))
+        }
+    }
+
+    /// Takes the smallest unused block that can hold the given number of bytes, if there is any.
+    ///
+    /// Returns the ID of the block, which is empty afterwards.
+    fn reuse_free_block(&mut self, capacity: usize) -> Result<Option<usize>> {
+        let Some((&block_capacity, blocks)) = self.free_blocks.range_mut(capacity..).next() else {
+            return Ok(None);
+        };
+        let block_id = blocks.pop();
+        if blocks.is_empty() {
+            self.free_blocks.remove(&block_capacity);
+        }
+        let Some(block_id) = block_id else {
+            return Ok(None);
+        };
+        let mut header = self.block_header(block_id)?;
+        header.used = 0;
+        header
+            .write(&mut self.storage.as_mut_slice()[block_id..(block_id + BlockHeader::size())])?;
+        Ok(Some(block_id))
+    }
+
+    /// Adds the block to the free blocks and increments its generation.
+    ///
+    /// A forwarding pointer is removed, so the header only describes the block itself.
+    fn release_block(&mut self, block_id: usize) -> Result<()> {
+        let mut header = self.block_header(block_id)?;
+        header.used = 0;
+        header.generation = (header.generation + 1) & GENERATION_MASK;
+        header
+            .write(&mut self.storage.as_mut_slice()[block_id..(block_id + BlockHeader::size())])?;
+        self.lock_cache()?.remove(block_id);
+        let capacity: usize = header.capacity.try_into()?;
+        self.free_blocks.entry(capacity).or_default().push(block_id);
+        Ok(())
+    }
+
+    /// Discards the header and the whole capacity of the block.
+    fn discard_allocated_block(&mut self, block_id: usize) -> Result<()> {
+        let capacity: usize = self.block_header(block_id)?.capacity.try_into()?;
+        self.lock_cache()?.remove(block_id);
+        let block_end = block_id + BlockHeader::size() + capacity;
+        self.discarded.discard(
+            self.name,
+            self.storage.as_mut(),
+            block_id,
+            block_end,
+            self.page_size,
+        )
+    }
+
+    /// Makes sure the block can hold the needed number of bytes and updates its header.
+    ///
+    /// If the block is too small, it is relocated to a new block.
+    /// Returns the ID of the block the data should be written to.
+    fn reserve(&mut self, block_id: usize, needed_size: u64) -> Result<usize> {
+        let relocated_block_id = self.resolve_block(block_id)?;
+
+        // Check there is still enough space in the block
+        let header = self.block_header(relocated_block_id)?;
+        let block_id = if needed_size <= header.capacity {
+            relocated_block_id
+        } else {
+            // Relocate (possible again) to a new block that leaves room for growing further
+            let mut original_header = self.block_header(block_id)?;
+            let relocations = original_header.relocations() + 1;
+            let factor = self.relocation_policy.factor_for(relocations.try_into()?);
+            let needed_size: usize = needed_size.try_into()?;
+            let new_capacity = page_aligned_capacity(
+                needed_size.saturating_mul(factor),
+                self.page_size,
+                self.block_alignment,
+            );
+            let new_block_id = self.allocate_block(new_capacity)?;
+            self.metrics
+                .relocate(block_id, header.capacity.try_into()?, new_capacity);
+
+            let old_capacity: usize = header.capacity.try_into()?;
+            self.relocation_stats.relocations += 1;
+            if relocated_block_id == block_id {
+                // The header of the original block is kept for the forwarding pointer
+                self.relocation_stats.abandoned_bytes += old_capacity;
+            } else {
+                self.relocation_stats.repeated_relocations += 1;
+                self.relocation_stats.abandoned_bytes += BlockHeader::size() + old_capacity;
+            }
+
+            // Only the original ID is referenced, so a block that has been relocated before
+            // is abandoned and the original block forwards to the new block instead
+            original_header.set_relocated(new_block_id, relocations)?;
+            original_header.write(
+                &mut self.storage.as_mut_slice()[block_id..(block_id + BlockHeader::size())],
+            )?;
+            new_block_id
+        };
+
+        // Update the header with the new size
+        let mut header = self.block_header(block_id)?;
+        header.used = needed_size;
+        header
+            .write(&mut self.storage.as_mut_slice()[block_id..(block_id + BlockHeader::size())])?;
+        Ok(block_id)
+    }
+
+    fn cache_block(&self, block_id: usize, block: &B, size: usize) -> Result<()> {
+        self.lock_cache()?
+            .insert(block_id, Arc::new(block.clone()), size);
+        Ok(())
+    }
+
+    /// Locks the cache, which fails if a thread panicked while using it.
+    fn lock_cache(&self) -> Result<MutexGuard<'_, BlockCache<B>>> {
+        self.cache.lock().map_err(|_| Error::PoisonedCache)
     }
 
-    fn get_cached_entry(&self, block_id: usize) -> Option<Arc<B>> {
-        if let Ok(mut cache) = self.cache.try_lock() {
-            if let Some(b) = cache.remove(&block_id) {
-                // Mark the block as recently used by re-inserting it
-                cache.insert(block_id, b.clone());
-                return Some(b);
+    /// Locks the cache, unless it is in use by another thread.
+    fn try_lock_cache(&self) -> Result<Option<MutexGuard<'_, BlockCache<B>>>> {
+        match self.cache.try_lock() {
+            Ok(cache) => Ok(Some(cache)),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Poisoned(_)) => Err(Error::PoisonedCache),
+        }
+    }
+
+    /// Serializes the block and compresses it if it is large enough.
+    ///
+    /// The first byte of the result marks whether the data is compressed.
+    /// Also returns the uncompressed serialized size.
+    #[cfg(feature = "compression")]
+    fn compress(&self, block: &B, threshold: usize) -> Result<(Vec<u8>, usize)> {
+        let serialized = self.codec.serialize(block)?;
+        let size = serialized.len();
+        if size >= threshold {
+            let compressed = lz4_flex::compress_prepend_size(&serialized);
+            if compressed.len() < size {
+                let mut result = Vec::with_capacity(compressed.len() + 1);
+                result.push(1);
+                result.extend(compressed);
+                return Ok((result, size));
             }
         }
-        None
+        let mut result = Vec::with_capacity(size + 1);
+        result.push(0);
+        result.extend(serialized);
+        Ok((result, size))
+    }
+
+    /// Serializes the block and compresses and encrypts it, if this is enabled.
+    ///
+    /// Returns `None` if the serialized block is stored as it is.
+    /// Also returns the uncompressed serialized size.
+    #[cfg(any(feature = "compression", feature = "encryption"))]
+    fn encode(&mut self, block: &B) -> Result<Option<(Vec<u8>, usize)>> {
+        #[cfg(feature = "compression")]
+        let encoded = match self.compression_threshold {
+            Some(threshold) => Some(self.compress(block, threshold)?),
+            None => None,
+        };
+        #[cfg(not(feature = "compression"))]
+        let encoded: Option<(Vec<u8>, usize)> = None;
+
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = &mut self.cipher {
+            let (data, size) = match encoded {
+                Some(encoded) => encoded,
+                None => {
+                    let serialized = self.codec.serialize(block)?;
+                    let size = serialized.len();
+                    (serialized, size)
+                }
+            };
+            return Ok(Some((cipher.encrypt(&data)?, size)));
+        }
+        Ok(encoded)
+    }
+
+    /// Decrypts and decompresses the stored data of a block to its serialized bytes,
+    /// if this is enabled.
+    #[cfg(any(feature = "compression", feature = "encryption"))]
+    fn decode<'a>(&self, data: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        #[cfg(feature = "encryption")]
+        let data: Cow<'a, [u8]> = match &self.cipher {
+            Some(cipher) => Cow::Owned(cipher.decrypt(data)?),
+            None => Cow::Borrowed(data),
+        };
+        #[cfg(not(feature = "encryption"))]
+        let data = Cow::Borrowed(data);
+
+        #[cfg(feature = "compression")]
+        if self.compression_threshold.is_some() {
+            return match data.split_first() {
+                Some((1, compressed)) => {
+                    Ok(Cow::Owned(lz4_flex::decompress_size_prepended(compressed)?))
+                }
+                Some(_) => Ok(match data {
+                    Cow::Borrowed(data) => Cow::Borrowed(&data[1..]),
+                    Cow::Owned(mut data) => {
+                        data.remove(0);
+                        Cow::Owned(data)
+                    }
+                }),
+                None => Err(Error::DeserializeBlock("empty block".to_string())),
+            };
+        }
+        Ok(data)
+    }
+
+    fn get_cached_entry(&self, block_id: usize) -> Result<Option<Arc<B>>> {
+        Ok(self
+            .try_lock_cache()?
+            .and_then(|mut cache| cache.get(block_id)))
     }
 
     /// Determines wether a given block would still fit in the originally allocated space.
     ///
     /// Returns a tuple with the first value beeing true when the update fits.
     /// The second value is the needed size for this block.
+    #[cfg(test)]
     pub fn can_update(&self, block_id: usize, block: &B) -> Result<(bool, u64)> {
-        let block_id = *self.relocated_blocks.get(&block_id).unwrap_or(&block_id);
+        let block_id = self.resolve_block(block_id)?;
         // Get the allocated size of this block
         let header = self.block_header(block_id)?;
 
@@ -274,50 +1201,85 @@ where
 
     /// Parses the header of the block.
     fn block_header(&self, block_id: usize) -> Result<BlockHeader> {
-        let header =
-            BlockHeader::read(self.mmap[block_id..(block_id + BlockHeader::size())].try_into()?)?;
+        let header = BlockHeader::read(
+            storage_slice(
+                self.storage.as_slice(),
+                block_id,
+                block_id,
+                BlockHeader::size(),
+            )?
+            .try_into()?,
+        )?;
         Ok(header)
     }
 
     /// Grows the file to contain at least the requested number of bytes.
-    /// This needs to copy all content into a new temporary file.
+    /// This might need to copy all content into a new temporary file.
     /// To avoid this costly operation, the file size is at least doubled.
     fn grow(&mut self, requested_size: usize) -> Result<()> {
-        if requested_size <= self.mmap.len() {
-            // Still enough space, no action required
-            return Ok(());
-        }
-
-        // Create a new anonymous memory mapped the content is copied to.
-        // Allocate at least twice the old file size so we don't need to grow too often
-        let new_size = requested_size.max(self.mmap.len() * 2);
-        let mut new_mmap = create_mmap(new_size)?;
+        self.growth.grow(
+            self.name,
+            self.storage.as_mut(),
+            requested_size,
+            &self.metrics,
+        )
+    }
+}
 
-        // Copy all content from the old file into the new file
-        new_mmap[0..self.mmap.len()].copy_from_slice(&self.mmap);
+/// Returns `len` bytes of the storage from `offset` on, or an error for the block if they are
+/// outside of the storage, e.g. because the block ID is corrupted.
+fn storage_slice(storage: &[u8], block_id: usize, offset: usize, len: usize) -> Result<&[u8]> {
+    storage
+        .get(offset..)
+        .and_then(|data| data.get(..len))
+        .ok_or(Error::BlockOutOfBounds { block_id })
+}
 
-        self.mmap = new_mmap;
+/// Checks that the value is serialized with bincode with fixed integer encoding
+/// to exactly the expected number of bytes.
+pub(crate) fn check_fixed_size<T>(value: &T, expected: usize) -> Result<()>
+where
+    T: Serialize + ?Sized,
+{
+    let actual: usize = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .serialized_size(value)?
+        .try_into()?;
+    if actual == expected {
         Ok(())
+    } else {
+        Err(Error::FixedSizeMismatch { expected, actual })
     }
 }
 
-pub struct FixedSizeTupleFile<B>
-where
-    B: Sync + Serialize + DeserializeOwned,
-{
+/// Represents a temporary memory mapped file that stores blocks of type `B`,
+/// which always have the same serialized size.
+///
+/// The blocks are serialized with bincode with fixed integer encoding and stored
+/// without a header, so the ID of a block is its offset in the file.
+pub struct FixedSizeTupleFile<B> {
+    /// Describes the content of the file in storage errors.
+    name: &'static str,
     free_space_offset: usize,
-    mmap: MmapMut,
+    storage: Box<dyn StorageBackend>,
+    growth: GrowthPolicy,
+    metrics: Metrics,
     fixed_tuple_size: usize,
+    discarded: DiscardedPages,
+    page_size: usize,
     phantom: PhantomData<B>,
 }
 
 impl<B> TupleFile<B> for FixedSizeTupleFile<B>
 where
-    B: Serialize + DeserializeOwned + Clone + Send + Sync,
+    B: Serialize + DeserializeOwned + Clone,
 {
     fn allocate_block(&mut self, capacity: usize) -> Result<usize> {
         if capacity != self.fixed_tuple_size {
-            return Err(Error::InvalidCapacity { capacity });
+            return Err(Error::FixedSizeMismatch {
+                expected: self.fixed_tuple_size,
+                actual: capacity,
+            });
         }
 
         // Make sure we still have enough space left in the file
@@ -342,74 +1304,196 @@ where
         Ok(Arc::new(result))
     }
 
+    fn get_raw(&self, block_id: usize) -> Result<&[u8]> {
+        storage_slice(
+            self.storage.as_slice(),
+            block_id,
+            block_id,
+            self.fixed_tuple_size,
+        )
+    }
+
+    /// Fails with [`Error::FixedSizeMismatch`] if the serialized block does not have exactly
+    /// the fixed size.
     fn put(&mut self, block_id: usize, block: &B) -> Result<()> {
+        check_fixed_size(block, self.fixed_tuple_size)?;
+
         // Serialize the block and write it at the proper location in the file
         let block_start = block_id;
         let block_end = block_start + self.fixed_tuple_size;
 
         let serializer = bincode::DefaultOptions::new().with_fixint_encoding();
-        serializer.serialize_into(&mut self.mmap[block_start..block_end], &block)?;
+        serializer.serialize_into(
+            &mut self.storage.as_mut_slice()[block_start..block_end],
+            &block,
+        )?;
         Ok(())
     }
 
-    fn serialized_size(&self, _block: &B) -> Result<u64> {
-        Ok(self.fixed_tuple_size.try_into()?)
+    /// The actual serialized size, so that allocating a block for a block with
+    /// a different size fails before anything is written.
+    fn serialized_size(&self, block: &B) -> Result<u64> {
+        let size = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .serialized_size(block)?;
+        Ok(size)
+    }
+
+    fn codec(&self) -> &dyn Codec<B> {
+        &ValueCodec::BincodeFixint
+    }
+
+    fn memory_usage(&self) -> FileMemoryUsage {
+        FileMemoryUsage {
+            mapped: self.storage.len(),
+            ..Default::default()
+        }
+    }
+
+    fn used_bytes(&self) -> usize {
+        self.free_space_offset
+    }
+
+    fn contains_block(&self, block_id: usize) -> bool {
+        block_id < self.free_space_offset && block_id.is_multiple_of(self.fixed_tuple_size)
+    }
+
+    fn clear(&mut self) {
+        self.free_space_offset = 0;
+        self.discarded.clear();
+    }
+
+    fn shrink_to_fit(&mut self) -> Result<()> {
+        shrink_to_fit(
+            self.name,
+            self.storage.as_mut(),
+            self.free_space_offset,
+            self.page_size,
+        )
+    }
+
+    fn try_reserve(&mut self, additional_bytes: usize) -> Result<()> {
+        self.grow(self.free_space_offset.saturating_add(additional_bytes))
+    }
+
+    fn resolve_block(&self, block_id: usize) -> Result<usize> {
+        // Fixed size blocks are never relocated
+        Ok(block_id)
+    }
+
+    /// A page is released when all blocks that overlap it have been discarded.
+    fn discard_block(&mut self, block_id: usize) -> Result<()> {
+        self.discarded.discard(
+            self.name,
+            self.storage.as_mut(),
+            block_id,
+            block_id + self.fixed_tuple_size,
+            self.page_size,
+        )
     }
 }
 
 impl<B> FixedSizeTupleFile<B>
 where
-    B: Serialize + DeserializeOwned + Sync,
+    B: Serialize + DeserializeOwned,
 {
-    /// Create a new file with the given capacity in bytes.
+    /// Create a new file with the given capacity in bytes for blocks
+    /// with exactly `fixed_tuple_size` bytes.
     ///
-    /// New blocks can be allocated with [`Self::allocate_block()`].
+    /// New blocks can be allocated with [`TupleFile::allocate_block()`].
     /// The file will automatically grow when block are allocated and the capacity is reached
     pub fn with_capacity(
         capacity: usize,
         fixed_tuple_size: usize,
     ) -> Result<FixedSizeTupleFile<B>> {
         // Create an anonymous memory mapped file with the capacity as size
-        let capacity = capacity.max(1);
-        let mmap = create_mmap(capacity)?;
-        Ok(FixedSizeTupleFile {
-            mmap,
+        let storage = crate::storage::create_default_storage(capacity.max(1))?;
+        Ok(Self::with_storage(storage, fixed_tuple_size))
+    }
+
+    /// Create a new file that uses the given storage.
+    pub fn with_storage(
+        storage: Box<dyn StorageBackend>,
+        fixed_tuple_size: usize,
+    ) -> FixedSizeTupleFile<B> {
+        FixedSizeTupleFile {
+            name: DEFAULT_FILE_NAME,
+            storage,
+            growth: GrowthPolicy::default(),
+            metrics: Metrics::default(),
             fixed_tuple_size,
             free_space_offset: 0,
+            discarded: DiscardedPages::default(),
+            page_size: system_page_size(),
             phantom: PhantomData,
-        })
+        }
     }
 
-    /// Grows the file to contain at least the requested number of bytes.
-    /// This needs to copy all content into a new temporary file.
-    /// To avoid this costly operation, the file size is at least doubled.
-    fn grow(&mut self, requested_size: usize) -> Result<()> {
-        if requested_size <= self.mmap.len() {
-            // Still enough space, no action required
-            return Ok(());
-        }
+    /// The number of bytes of each block.
+    pub(crate) fn fixed_tuple_size(&self) -> usize {
+        self.fixed_tuple_size
+    }
 
-        // Create a new anonymous memory mapped the content is copied to.
-        // Allocate at least twice the old file size so we don't need to grow too often
-        let new_size = requested_size.max(self.mmap.len() * 2);
-        let mut new_mmap = create_mmap(new_size)?;
+    /// Describe the content of the file with the given name in storage errors.
+    pub(crate) fn with_name(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
 
-        // Copy all content from the old file into the new file
-        new_mmap[0..self.mmap.len()].copy_from_slice(&self.mmap);
+    /// Use the given growth strategy and maximum size when the file needs more space.
+    pub(crate) fn with_growth_policy(mut self, growth: GrowthPolicy) -> Self {
+        self.growth = growth;
+        self
+    }
 
-        self.mmap = new_mmap;
-        Ok(())
+    /// Shrink the file to memory pages with the given size in bytes,
+    /// instead of the page size of the system.
+    pub(crate) fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
     }
 
-    fn read_block(&self, block_id: usize) -> Result<B> {
-        // Deserialize and return
-        let block_start = block_id;
-        let block_end = block_start + self.fixed_tuple_size;
+    /// Report growing the file to the metrics.
+    pub(crate) fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
 
-        let serializer = bincode::DefaultOptions::new().with_fixint_encoding();
+    /// Returns the allocation state of the file, so that the blocks allocated afterwards
+    /// can be removed again with [`Self::rollback`].
+    pub(crate) fn checkpoint(&self) -> FileCheckpoint {
+        FileCheckpoint {
+            free_space_offset: self.free_space_offset,
+            free_blocks: BTreeMap::new(),
+        }
+    }
 
-        let result: B = serializer.deserialize(&self.mmap[block_start..block_end])?;
+    /// Removes all blocks that were allocated after the checkpoint was taken.
+    pub(crate) fn rollback(&mut self, checkpoint: FileCheckpoint) {
+        self.free_space_offset = checkpoint.free_space_offset;
+    }
 
+    /// Grows the file to contain at least the requested number of bytes.
+    /// This might need to copy all content into a new temporary file.
+    /// To avoid this costly operation, the file size is at least doubled.
+    fn grow(&mut self, requested_size: usize) -> Result<()> {
+        self.growth.grow(
+            self.name,
+            self.storage.as_mut(),
+            requested_size,
+            &self.metrics,
+        )
+    }
+
+    fn read_block(&self, block_id: usize) -> Result<B> {
+        // Deserialize and return
+        let serializer = bincode::DefaultOptions::new().with_fixint_encoding();
+        let result: B = serializer.deserialize(storage_slice(
+            self.storage.as_slice(),
+            block_id,
+            block_id,
+            self.fixed_tuple_size,
+        )?)?;
         Ok(result)
     }
 }
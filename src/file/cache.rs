@@ -0,0 +1,265 @@
+use std::{collections::HashMap, mem::size_of, sync::Arc};
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::metrics::Metrics;
+
+/// Decides which block is removed from a block cache when it is full.
+///
+/// A policy can be configured with [`BtreeConfig::cache_eviction_policy`](crate::BtreeConfig::cache_eviction_policy).
+/// The cache reports every change of its blocks, so the policy only needs to track the block IDs.
+/// Per default, the least recently used block is removed ([`LruPolicy`]).
+pub trait EvictionPolicy: Send {
+    /// A block that was not cached before has been added to the cache.
+    fn on_insert(&mut self, block_id: usize);
+
+    /// A cached block has been accessed.
+    fn on_access(&mut self, block_id: usize);
+
+    /// A block has been removed from the cache without being chosen by [`Self::evict`].
+    fn on_remove(&mut self, block_id: usize);
+
+    /// Choose the block to remove from the cache and stop tracking it.
+    ///
+    /// Returns `None` if no block is tracked.
+    fn evict(&mut self) -> Option<usize>;
+
+    /// Stop tracking all blocks.
+    fn clear(&mut self);
+}
+
+/// Function that creates a new eviction policy for each cache.
+pub(crate) type EvictionPolicyFactory = Arc<dyn Fn() -> Box<dyn EvictionPolicy> + Send + Sync>;
+
+/// Factory for the default [`LruPolicy`].
+pub(crate) fn default_eviction_policy() -> EvictionPolicyFactory {
+    Arc::new(|| Box::<LruPolicy>::default())
+}
+
+/// Removes the least recently used block.
+#[derive(Debug, Default)]
+pub struct LruPolicy {
+    blocks: LinkedHashMap<usize, ()>,
+}
+
+impl EvictionPolicy for LruPolicy {
+    fn on_insert(&mut self, block_id: usize) {
+        self.blocks.insert(block_id, ());
+    }
+
+    fn on_access(&mut self, block_id: usize) {
+        self.blocks.get_refresh(&block_id);
+    }
+
+    fn on_remove(&mut self, block_id: usize) {
+        self.blocks.remove(&block_id);
+    }
+
+    fn evict(&mut self) -> Option<usize> {
+        self.blocks.pop_front().map(|(block_id, _)| block_id)
+    }
+
+    fn clear(&mut self) {
+        self.blocks.clear();
+    }
+}
+
+/// Segmented LRU policy, which protects blocks that have been accessed more than once.
+///
+/// New blocks are added to a probation segment, and only move to the protected segment
+/// when they are accessed again while cached. Blocks are evicted from the probation segment first,
+/// so scanning many blocks once does not remove the blocks which are accessed repeatedly.
+/// When the protected segment holds more than its share of the maximum number of cached blocks,
+/// its least recently used blocks move back to the probation segment.
+#[derive(Debug)]
+pub struct SegmentedLruPolicy {
+    probation: LinkedHashMap<usize, ()>,
+    protected: LinkedHashMap<usize, ()>,
+    protected_share: f64,
+    /// Largest number of blocks that have been cached at the same time,
+    /// which approximates the capacity of the cache.
+    max_blocks: usize,
+}
+
+impl SegmentedLruPolicy {
+    /// Create a policy where at most the given share (between 0 and 1)
+    /// of the cached blocks is protected.
+    pub fn with_protected_share(protected_share: f64) -> SegmentedLruPolicy {
+        SegmentedLruPolicy {
+            probation: LinkedHashMap::new(),
+            protected: LinkedHashMap::new(),
+            protected_share: protected_share.clamp(0.0, 1.0),
+            max_blocks: 0,
+        }
+    }
+}
+
+impl Default for SegmentedLruPolicy {
+    /// Create a policy which protects at most 80% of the cached blocks.
+    fn default() -> Self {
+        SegmentedLruPolicy::with_protected_share(0.8)
+    }
+}
+
+impl EvictionPolicy for SegmentedLruPolicy {
+    fn on_insert(&mut self, block_id: usize) {
+        self.probation.insert(block_id, ());
+        self.max_blocks = self
+            .max_blocks
+            .max(self.probation.len() + self.protected.len());
+    }
+
+    fn on_access(&mut self, block_id: usize) {
+        if self.protected.get_refresh(&block_id).is_some() {
+            return;
+        }
+        if self.probation.remove(&block_id).is_some() {
+            self.protected.insert(block_id, ());
+            let max_protected = self.protected_share * self.max_blocks as f64;
+            while self.protected.len() as f64 > max_protected {
+                if let Some((demoted, _)) = self.protected.pop_front() {
+                    self.probation.insert(demoted, ());
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn on_remove(&mut self, block_id: usize) {
+        self.probation.remove(&block_id);
+        self.protected.remove(&block_id);
+    }
+
+    fn evict(&mut self) -> Option<usize> {
+        self.probation
+            .pop_front()
+            .or_else(|| self.protected.pop_front())
+            .map(|(block_id, _)| block_id)
+    }
+
+    fn clear(&mut self) {
+        self.probation.clear();
+        self.protected.clear();
+        self.max_blocks = 0;
+    }
+}
+
+/// Cache for deserialized blocks.
+///
+/// The cache tracks the approximate size of its blocks,
+/// so it can be limited by the number of blocks or by the number of bytes.
+/// Which blocks are removed when the cache is full is decided by an [`EvictionPolicy`].
+pub struct BlockCache<B> {
+    entries: HashMap<usize, (Arc<B>, usize)>,
+    policy: Box<dyn EvictionPolicy>,
+    create_policy: EvictionPolicyFactory,
+    max_entries: usize,
+    max_bytes: Option<usize>,
+    size_in_bytes: usize,
+    metrics: Metrics,
+}
+
+impl<B> BlockCache<B> {
+    /// Create a cache that holds at most `max_entries` blocks.
+    pub fn with_max_entries(max_entries: usize) -> BlockCache<B> {
+        let create_policy = default_eviction_policy();
+        BlockCache {
+            entries: HashMap::with_capacity(max_entries),
+            policy: create_policy(),
+            create_policy,
+            max_entries,
+            max_bytes: None,
+            size_in_bytes: 0,
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Create a cache that holds blocks with a total size of at most `max_bytes`.
+    pub fn with_max_bytes(max_bytes: usize) -> BlockCache<B> {
+        let create_policy = default_eviction_policy();
+        BlockCache {
+            entries: HashMap::new(),
+            policy: create_policy(),
+            create_policy,
+            max_entries: usize::MAX,
+            max_bytes: Some(max_bytes),
+            size_in_bytes: 0,
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Get the block and report the access to the eviction policy.
+    pub fn get(&mut self, block_id: usize) -> Option<Arc<B>> {
+        let (block, _) = self.entries.get(&block_id)?;
+        self.policy.on_access(block_id);
+        Some(block.clone())
+    }
+
+    /// Add a block with the given (approximate) size in bytes.
+    ///
+    /// Blocks chosen by the eviction policy are removed when the cache is full.
+    pub fn insert(&mut self, block_id: usize, block: Arc<B>, size: usize) {
+        if let Some((_, old_size)) = self.entries.remove(&block_id) {
+            self.size_in_bytes -= old_size;
+            self.policy.on_remove(block_id);
+        }
+        if self.max_entries == 0 || self.max_bytes.is_some_and(|max_bytes| size > max_bytes) {
+            // The block would never fit
+            return;
+        }
+        self.entries.insert(block_id, (block, size));
+        self.policy.on_insert(block_id);
+        self.size_in_bytes += size;
+
+        while self.entries.len() > self.max_entries
+            || self
+                .max_bytes
+                .is_some_and(|max_bytes| self.size_in_bytes > max_bytes)
+        {
+            let Some(removed_id) = self.policy.evict() else {
+                break;
+            };
+            if let Some((_, removed_size)) = self.entries.remove(&removed_id) {
+                self.metrics.cache_eviction(removed_id, removed_size);
+                self.size_in_bytes -= removed_size;
+            }
+        }
+    }
+
+    /// Remove the block from the cache, e.g. because it is not valid anymore.
+    pub fn remove(&mut self, block_id: usize) {
+        if let Some((_, size)) = self.entries.remove(&block_id) {
+            self.size_in_bytes -= size;
+            self.policy.on_remove(block_id);
+        }
+    }
+
+    /// Use a new eviction policy from the given factory, which also applies to copies of this cache.
+    ///
+    /// The cached blocks are kept and reported to the new policy.
+    pub fn set_eviction_policy(&mut self, create_policy: EvictionPolicyFactory) {
+        self.policy = create_policy();
+        self.create_policy = create_policy;
+        for block_id in self.entries.keys() {
+            self.policy.on_insert(*block_id);
+        }
+    }
+
+    /// Report evicted blocks to the given metrics.
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = metrics;
+    }
+
+    /// Remove all blocks from the cache.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.policy.clear();
+        self.size_in_bytes = 0;
+    }
+
+    /// Approximate number of bytes held by the cache, including its bookkeeping.
+    pub fn memory_usage(&self) -> usize {
+        self.size_in_bytes + self.entries.len() * size_of::<(usize, Arc<B>, usize)>()
+    }
+}
@@ -0,0 +1,67 @@
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+
+use crate::{error::Result, Error};
+
+/// Size of the nonce that is stored before the encrypted data of each block.
+const NONCE_SIZE: usize = 12;
+
+/// Number of bytes an encrypted block needs in addition to its data,
+/// for the nonce and the authentication tag.
+pub(super) const ENCRYPTION_OVERHEAD: usize = NONCE_SIZE + 16;
+
+/// Encrypts and decrypts the data of blocks with AES-256-GCM.
+///
+/// Each encryption uses a new nonce, which is counted up from a random start value,
+/// so a nonce is never used twice for the same key without drawing random numbers for each block.
+pub(super) struct BlockCipher {
+    cipher: Aes256Gcm,
+    next_nonce: u128,
+}
+
+impl BlockCipher {
+    pub(super) fn new(key: &[u8; 32]) -> BlockCipher {
+        BlockCipher {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+            next_nonce: random_nonce(),
+        }
+    }
+
+    /// Returns the nonce followed by the encrypted data and the authentication tag.
+    pub(super) fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let nonce = self.next_nonce.to_le_bytes();
+        let nonce = Nonce::from_slice(&nonce[0..NONCE_SIZE]);
+        self.next_nonce = (self.next_nonce + 1) & NONCE_MASK;
+
+        let encrypted = self
+            .cipher
+            .encrypt(nonce, data)
+            .map_err(|_| Error::Encryption)?;
+        let mut result = Vec::with_capacity(NONCE_SIZE + encrypted.len());
+        result.extend_from_slice(nonce);
+        result.extend(encrypted);
+        Ok(result)
+    }
+
+    /// Decrypts data returned by [`Self::encrypt`] and fails if it has been changed.
+    pub(super) fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < ENCRYPTION_OVERHEAD {
+            return Err(Error::Encryption);
+        }
+        let (nonce, encrypted) = data.split_at(NONCE_SIZE);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), encrypted)
+            .map_err(|_| Error::Encryption)
+    }
+}
+
+/// The nonces are 96 bit numbers.
+const NONCE_MASK: u128 = (1 << (8 * NONCE_SIZE)) - 1;
+
+fn random_nonce() -> u128 {
+    let mut nonce = [0; 16];
+    OsRng.fill_bytes(&mut nonce[0..NONCE_SIZE]);
+    u128::from_le_bytes(nonce)
+}
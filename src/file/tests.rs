@@ -1,66 +1,96 @@
-use super::VariableSizeTupleFile;
+use super::{
+    page_aligned_capacity, BlockCache, BlockHeader, RelocationPolicy, RelocationStats,
+    SegmentedLruPolicy, VariableSizeTupleFile,
+};
 use crate::file::{FixedSizeTupleFile, TupleFile};
+use crate::Error;
+use std::sync::Arc;
 
 #[test]
 fn grow_mmap_from_zero_capacity() {
     // Create file with empty capacity
     let mut m = VariableSizeTupleFile::<u64>::with_capacity(0, 0).unwrap();
     // The capacity must be at least one
-    assert_eq!(1, m.mmap.len());
+    assert_eq!(1, m.storage.len());
 
     // Needs to grow
     m.grow(128).unwrap();
-    assert_eq!(128, m.mmap.len());
+    assert_eq!(128, m.storage.len());
     m.grow(4096).unwrap();
-    assert_eq!(4096, m.mmap.len());
+    assert_eq!(4096, m.storage.len());
 
     // No growing necessar
     m.grow(1024).unwrap();
-    assert_eq!(4096, m.mmap.len());
+    assert_eq!(4096, m.storage.len());
 
     // Grow with double size
     m.grow(8192).unwrap();
-    assert_eq!(8192, m.mmap.len());
+    assert_eq!(8192, m.storage.len());
 
     // Grow with less than the double size still creates the double size
     m.grow(9000).unwrap();
-    assert_eq!(16384, m.mmap.len());
+    assert_eq!(16384, m.storage.len());
 }
 
 #[test]
 fn grow_mmap_with_capacity() {
     let mut m = VariableSizeTupleFile::<u64>::with_capacity(4096, 0).unwrap();
-    assert_eq!(4096, m.mmap.len());
+    assert_eq!(4096, m.storage.len());
 
     // Don't grow if not necessary
     m.grow(128).unwrap();
-    assert_eq!(4096, m.mmap.len());
+    assert_eq!(4096, m.storage.len());
     m.grow(4096).unwrap();
-    assert_eq!(4096, m.mmap.len());
+    assert_eq!(4096, m.storage.len());
 
     // Grow with double size
     m.grow(8192).unwrap();
-    assert_eq!(8192, m.mmap.len());
+    assert_eq!(8192, m.storage.len());
 
     // Grow with less than the double size still creates the double size
     m.grow(9000).unwrap();
-    assert_eq!(16384, m.mmap.len());
+    assert_eq!(16384, m.storage.len());
+}
+
+#[test]
+fn poisoned_cache() {
+    let mut m = VariableSizeTupleFile::<u64>::with_capacity(128, 10).unwrap();
+    let idx = m.allocate_block(8).unwrap();
+    m.put(idx, &42).unwrap();
+
+    // Panic while holding the lock of the cache
+    let cache = m.cache.clone();
+    std::thread::spawn(move || {
+        let _cache = cache.lock().unwrap();
+        panic!("poison the cache");
+    })
+    .join()
+    .unwrap_err();
+
+    assert!(matches!(m.get(idx), Err(Error::PoisonedCache)));
+    assert!(matches!(m.put(idx, &43), Err(Error::PoisonedCache)));
+
+    // Clearing the file makes the cache usable again
+    m.clear();
+    let idx = m.allocate_block(8).unwrap();
+    m.put(idx, &44).unwrap();
+    assert_eq!(44, m.get_owned(idx).unwrap());
 }
 
 #[test]
 fn block_insert_get_update() {
     let mut m = VariableSizeTupleFile::<Vec<u64>>::with_capacity(128, 0).unwrap();
-    assert_eq!(128, m.mmap.len());
+    assert_eq!(128, m.storage.len());
 
-    let mut b: Vec<u64> = std::iter::repeat(42).take(10).collect();
+    let mut b: Vec<u64> = std::iter::repeat_n(42, 10).collect();
     let idx = m
         .allocate_block(256 - crate::file::BlockHeader::size())
         .unwrap();
     // The block needs space for the data, but also for the header
-    assert_eq!(256, m.mmap.len());
+    assert_eq!(256, m.storage.len());
 
     // Insert the block as it is
-    assert_eq!(true, m.can_update(idx, &b).is_ok());
+    assert!(m.can_update(idx, &b).is_ok());
     m.put(idx, &b).unwrap();
 
     // Get and check it is still equal
@@ -71,7 +101,7 @@ fn block_insert_get_update() {
     for i in 1..20 {
         b.push(i);
     }
-    assert_eq!(true, m.can_update(idx, &b).is_ok());
+    assert!(m.can_update(idx, &b).is_ok());
     m.put(idx, &b).unwrap();
     let retrieved_block = m.get_owned(idx).unwrap();
     assert_eq!(b, retrieved_block);
@@ -81,19 +111,81 @@ fn block_insert_get_update() {
     for i in 1..300 {
         large_block.push(i);
     }
-    assert_eq!(false, m.can_update(idx, &large_block).unwrap().0);
-    // Check that we can still insert the block, but that the relocation table is updated
+    assert!(!m.can_update(idx, &large_block).unwrap().0);
+    // Check that we can still insert the block, but that the original block forwards to a new one
     m.put(idx, &large_block).unwrap();
-    assert_eq!(1, m.relocated_blocks.len());
-    assert_eq!(true, m.relocated_blocks.contains_key(&idx));
+    let new_idx = m.resolve_block(idx).unwrap();
+    assert_ne!(idx, new_idx);
+    assert_eq!(new_idx, m.resolve_block(new_idx).unwrap());
     // Get the block and check the new value is returned
     assert_eq!(large_block, m.get_owned(idx).unwrap());
 }
 
+#[test]
+fn relocated_block_forwarding() {
+    let mut m = VariableSizeTupleFile::<Vec<u64>>::with_capacity(128, 0).unwrap();
+    let idx = m.insert(&vec![1]).unwrap();
+
+    // Relocate the same block twice
+    let larger: Vec<u64> = (0..100).collect();
+    m.put(idx, &larger).unwrap();
+    let first_relocation = m.resolve_block(idx).unwrap();
+    let largest: Vec<u64> = (0..10_000).collect();
+    m.put(idx, &largest).unwrap();
+    let second_relocation = m.resolve_block(idx).unwrap();
+    assert_ne!(idx, second_relocation);
+    assert_ne!(first_relocation, second_relocation);
+    assert_eq!(largest, m.get_owned(idx).unwrap());
+}
+
+#[test]
+fn relocation_policy_and_stats() {
+    let mut m = VariableSizeTupleFile::<Vec<u8>>::with_capacity(128, 0)
+        .unwrap()
+        .with_page_size(8)
+        .with_relocation_policy(RelocationPolicy {
+            factor: 2,
+            repeated_after: 1,
+            repeated_factor: 4,
+        });
+    let idx = m.insert(&vec![0; 8]).unwrap();
+    let original_capacity = m.block_header(idx).unwrap().capacity as usize;
+    assert_eq!(RelocationStats::default(), m.relocation_stats());
+
+    // The first relocation doubles the needed size
+    m.put(idx, &vec![1; 16]).unwrap();
+    let first = m.resolve_block(idx).unwrap();
+    let first_capacity = m.block_header(first).unwrap().capacity as usize;
+    assert!((2 * 17..4 * 17).contains(&first_capacity));
+
+    // The second relocation of the same block reserves four times the needed size
+    m.put(idx, &vec![2; 64]).unwrap();
+    let second = m.resolve_block(idx).unwrap();
+    assert!(m.block_header(second).unwrap().capacity >= 4 * 65);
+    assert_eq!(vec![2; 64], m.get_owned(idx).unwrap());
+
+    assert_eq!(
+        RelocationStats {
+            relocations: 2,
+            repeated_relocations: 1,
+            abandoned_bytes: original_capacity + BlockHeader::size() + first_capacity,
+        },
+        m.relocation_stats()
+    );
+
+    // Growing within the capacity does not relocate the block again
+    m.put(idx, &vec![3; 200]).unwrap();
+    assert_eq!(second, m.resolve_block(idx).unwrap());
+    assert_eq!(2, m.relocation_stats().relocations);
+
+    m.clear();
+    assert_eq!(RelocationStats::default(), m.relocation_stats());
+}
+
 #[test]
 fn block_insert_get_update_fixed_size() {
     let mut m = FixedSizeTupleFile::<u64>::with_capacity(128, 8).unwrap();
-    assert_eq!(128, m.mmap.len());
+    assert_eq!(128, m.storage.len());
 
     // Check that we can't allocate block with a size different to 8
     assert!(m.allocate_block(4).is_err());
@@ -108,3 +200,275 @@ fn block_insert_get_update_fixed_size() {
     // Get the block and check the new value is returned
     assert_eq!(b, m.get_owned(idx).unwrap());
 }
+
+#[test]
+fn fixed_size_mismatch() {
+    let mut m = FixedSizeTupleFile::<(u32, u32)>::with_capacity(128, 4).unwrap();
+    assert!(matches!(
+        m.allocate_block(8),
+        Err(Error::FixedSizeMismatch {
+            expected: 4,
+            actual: 8
+        })
+    ));
+    // Inserting fails before a block is allocated
+    assert!(matches!(
+        m.insert(&(1, 2)),
+        Err(Error::FixedSizeMismatch {
+            expected: 4,
+            actual: 8
+        })
+    ));
+    assert!(!m.contains_block(0));
+
+    let mut m = FixedSizeTupleFile::<u16>::with_capacity(128, 4).unwrap();
+    let idx = m.allocate_block(4).unwrap();
+    assert!(matches!(
+        m.put(idx, &1),
+        Err(Error::FixedSizeMismatch {
+            expected: 4,
+            actual: 2
+        })
+    ));
+}
+
+#[test]
+fn cache_byte_budget() {
+    let mut cache = BlockCache::with_max_bytes(100);
+    cache.insert(0, Arc::new("a"), 40);
+    cache.insert(1, Arc::new("b"), 40);
+    assert_eq!(Some(Arc::new("a")), cache.get(0));
+
+    // Evicts the least recently used block to stay within the budget
+    cache.insert(2, Arc::new("c"), 40);
+    assert_eq!(None, cache.get(1));
+    assert_eq!(Some(Arc::new("a")), cache.get(0));
+    assert_eq!(Some(Arc::new("c")), cache.get(2));
+
+    // Replacing a block updates its size
+    cache.insert(2, Arc::new("d"), 10);
+    assert_eq!(Some(Arc::new("d")), cache.get(2));
+    assert!(cache.memory_usage() >= 50);
+    assert!(cache.memory_usage() < 100);
+
+    // Blocks larger than the budget are not cached at all
+    cache.insert(3, Arc::new("e"), 101);
+    assert_eq!(None, cache.get(3));
+    assert_eq!(Some(Arc::new("a")), cache.get(0));
+
+    // A large block can evict all other blocks
+    cache.insert(4, Arc::new("f"), 100);
+    assert_eq!(None, cache.get(0));
+    assert_eq!(None, cache.get(2));
+    assert_eq!(Some(Arc::new("f")), cache.get(4));
+}
+
+#[test]
+fn cache_segmented_lru() {
+    let mut cache = BlockCache::with_max_entries(4);
+    cache.set_eviction_policy(Arc::new(|| {
+        Box::new(SegmentedLruPolicy::with_protected_share(0.5))
+    }));
+    cache.insert(0, Arc::new("a"), 1);
+    cache.insert(1, Arc::new("b"), 1);
+    cache.insert(2, Arc::new("c"), 1);
+    cache.insert(3, Arc::new("d"), 1);
+    assert_eq!(Some(Arc::new("a")), cache.get(0));
+    assert_eq!(Some(Arc::new("b")), cache.get(1));
+
+    // Blocks that are only accessed once don't evict the protected blocks
+    for block_id in 4..100 {
+        cache.insert(block_id, Arc::new("scan"), 1);
+    }
+    assert_eq!(Some(Arc::new("a")), cache.get(0));
+    assert_eq!(Some(Arc::new("b")), cache.get(1));
+    assert_eq!(None, cache.get(97));
+
+    // Protecting a third block moves the least recently used protected block back to probation
+    assert_eq!(Some(Arc::new("scan")), cache.get(98));
+    cache.insert(100, Arc::new("scan"), 1);
+    cache.insert(101, Arc::new("scan"), 1);
+    assert_eq!(None, cache.get(0));
+    assert_eq!(Some(Arc::new("b")), cache.get(1));
+    assert_eq!(Some(Arc::new("scan")), cache.get(98));
+
+    cache.clear();
+    assert_eq!(None, cache.get(1));
+    cache.insert(0, Arc::new("a"), 1);
+    assert_eq!(Some(Arc::new("a")), cache.get(0));
+}
+
+#[test]
+fn variable_size_file_cache_byte_budget() {
+    let mut m = VariableSizeTupleFile::<String>::with_capacity(1024, 0)
+        .unwrap()
+        .with_cache_byte_budget(1000);
+    let small = m.insert(&"a".repeat(10)).unwrap();
+    let large = m.insert(&"b".repeat(2000)).unwrap();
+
+    assert_eq!("a".repeat(10), m.get_owned(small).unwrap());
+    assert_eq!("b".repeat(2000), m.get_owned(large).unwrap());
+    assert!(m.get_cached_entry(small).unwrap().is_some());
+    assert!(m.get_cached_entry(large).unwrap().is_none());
+    assert!(m.memory_usage().cache < 1000);
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn block_insert_get_update_compressed() {
+    let mut m = VariableSizeTupleFile::<String>::with_capacity(128, 0)
+        .unwrap()
+        .with_compression(16);
+
+    // Small blocks are not compressed
+    let small = "abc".to_string();
+    let small_idx = m.insert(&small).unwrap();
+    assert_eq!(small, m.get_owned(small_idx).unwrap());
+
+    // Repetitive blocks are compressed and need less space than their serialized size
+    let large = "abcdefgh".repeat(100);
+    let large_idx = m.insert(&large).unwrap();
+    assert_eq!(large, m.get_owned(large_idx).unwrap());
+    assert!(m.block_header(large_idx).unwrap().capacity < 100);
+
+    // Updating with a value that does not compress well relocates the block
+    let random: String = (0..200)
+        .map(|i| char::from(33 + (i * 7919 % 90) as u8))
+        .collect();
+    m.put(large_idx, &random).unwrap();
+    assert_ne!(large_idx, m.resolve_block(large_idx).unwrap());
+    assert_eq!(random, m.get_owned(large_idx).unwrap());
+    assert_eq!(small, m.get_owned(small_idx).unwrap());
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn block_insert_get_update_encrypted() {
+    let mut m = VariableSizeTupleFile::<String>::with_capacity(128, 0)
+        .unwrap()
+        .with_encryption(&[7; 32]);
+
+    let secret = "customer-4711".to_string();
+    let idx = m.insert(&secret).unwrap();
+    assert_eq!(secret, m.get_owned(idx).unwrap());
+    assert_eq!(secret, *m.get(idx).unwrap());
+    let stored = m.storage.as_slice();
+    assert!(!stored.windows(secret.len()).any(|w| w == secret.as_bytes()));
+    assert!(m.is_encrypted());
+
+    // Larger blocks are relocated and encrypted again
+    let larger = "customer-4711".repeat(20);
+    m.put(idx, &larger).unwrap();
+    assert_eq!(larger, m.get_owned(idx).unwrap());
+    assert_eq!(
+        bincode::Options::serialize(bincode::DefaultOptions::new(), &larger).unwrap(),
+        m.get_serialized(idx).unwrap().as_ref()
+    );
+
+    // Changed blocks are detected
+    let block_start = m.resolve_block(idx).unwrap() + BlockHeader::size();
+    m.storage.as_mut_slice()[block_start + 20] ^= 1;
+    assert!(matches!(m.get_owned(idx), Err(Error::Encryption)));
+}
+
+#[cfg(all(feature = "encryption", feature = "compression"))]
+#[test]
+fn block_insert_get_compressed_and_encrypted() {
+    let mut m = VariableSizeTupleFile::<String>::with_capacity(128, 0)
+        .unwrap()
+        .with_compression(16)
+        .with_encryption(&[7; 32]);
+
+    let small = "abc".to_string();
+    let small_idx = m.insert(&small).unwrap();
+    let large = "abcdefgh".repeat(100);
+    let large_idx = m.insert(&large).unwrap();
+    assert!(m.block_header(large_idx).unwrap().capacity < 100);
+    assert_eq!(small, m.get_owned(small_idx).unwrap());
+    assert_eq!(large, m.get_owned(large_idx).unwrap());
+
+    let streamed = m.insert_from_reader(&[3], 3, &mut &b"xyz"[..]).unwrap();
+    assert_eq!("xyz", m.get_owned(streamed).unwrap());
+}
+
+#[test]
+fn reuse_free_blocks() {
+    let mut m = VariableSizeTupleFile::<String>::with_capacity(1024, 10).unwrap();
+    let short = m.insert(&"a".repeat(10)).unwrap();
+    let long = m.insert(&"b".repeat(100)).unwrap();
+    let relocated = m.insert(&"c".repeat(10)).unwrap();
+    m.put(relocated, &"c".repeat(50)).unwrap();
+    let used = m.free_space_offset;
+
+    m.free_block(long).unwrap();
+    m.free_block(short).unwrap();
+    m.free_block(relocated).unwrap();
+
+    // The smallest free block with enough capacity is used
+    let block = m.insert(&"d".repeat(20)).unwrap();
+    assert!(![short, relocated].contains(&block));
+    assert_eq!("d".repeat(20), *m.get(block).unwrap());
+    // The original block of the relocated value has been freed, too
+    let first = m.insert(&"e".repeat(5)).unwrap();
+    let second = m.insert(&"e".repeat(6)).unwrap();
+    let mut small_blocks = [first, second];
+    small_blocks.sort();
+    assert_eq!([short, relocated], small_blocks);
+    assert_eq!("e".repeat(5), m.get_owned(first).unwrap());
+    assert_eq!("e".repeat(6), m.get_owned(second).unwrap());
+    let block = m.insert(&"f".repeat(90)).unwrap();
+    assert_eq!("f".repeat(90), *m.get(block).unwrap());
+    assert_eq!(used, m.free_space_offset);
+
+    // Without free blocks, new blocks are added at the end of the file
+    let block = m.insert(&"g".repeat(10)).unwrap();
+    assert!(block >= used);
+}
+
+#[test]
+fn aligned_blocks() {
+    let mut m = VariableSizeTupleFile::<String>::with_capacity(128, 0).unwrap();
+    let mut previous_end = 0;
+    for size in [1, 5, 8, 13] {
+        let block_id = m.allocate_block(size).unwrap();
+        // The data of all blocks is aligned to 8 bytes per default
+        assert_eq!(0, (block_id + BlockHeader::size()) % 8);
+        // The padding is part of the previous block, so no space is skipped
+        assert_eq!(previous_end, block_id);
+        let capacity = m.block_header(block_id).unwrap().capacity as usize;
+        assert_eq!(size.next_multiple_of(8), capacity);
+        previous_end = block_id + BlockHeader::size() + capacity;
+    }
+
+    let mut m = VariableSizeTupleFile::<String>::with_capacity(128, 0)
+        .unwrap()
+        .with_block_alignment(32);
+    for size in [1, 40, 100] {
+        let block_id = m.allocate_block(size).unwrap();
+        assert_eq!(0, (block_id + BlockHeader::size()) % 32);
+        m.put(block_id, &"a".repeat(size / 2)).unwrap();
+        assert_eq!("a".repeat(size / 2), m.get_owned(block_id).unwrap());
+    }
+}
+
+#[test]
+fn page_aligned_capacity_with_alignment() {
+    // The block including the header ends at a page
+    assert_eq!(
+        4096 - BlockHeader::size(),
+        page_aligned_capacity(100, 4096, 8)
+    );
+    assert_eq!(
+        8192 - BlockHeader::size(),
+        page_aligned_capacity(4090, 4096, 8)
+    );
+    assert_eq!(
+        16384 - BlockHeader::size(),
+        page_aligned_capacity(100, 16384, 8)
+    );
+    // Alignments larger than a page extend the block to a multiple of the alignment
+    assert_eq!(
+        3 * 8192 - BlockHeader::size(),
+        page_aligned_capacity(2 * 8192, 4096, 8192)
+    );
+}
@@ -0,0 +1,56 @@
+/// Types that are always serialized with the same number of bytes.
+///
+/// The size assumes that [bincode](https://crates.io/crates/bincode) is used with a fixed integer
+/// encoding, like for [fixed key sizes](crate::BtreeConfig::fixed_key_size).
+/// It is implemented for the primitive integer and floating point types, `bool`,
+/// arrays like `[u8; N]` and tuples of such types.
+/// With the `derive` feature, it can be derived for structs of such types with `#[derive(FixedSize)]`.
+///
+/// Create the index with [`BtreeIndex::with_fixed_size`](crate::BtreeIndex::with_fixed_size)
+/// to configure the key and value sizes from their types.
+pub trait FixedSize {
+    /// Number of bytes of the serialized value.
+    const SIZE: usize;
+}
+
+macro_rules! impl_fixed_size {
+    ($($t:ty),*) => {
+        $(
+            impl FixedSize for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+            }
+        )*
+    };
+}
+
+impl_fixed_size!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+// Sizes are always encoded as 64 bit integers
+impl FixedSize for usize {
+    const SIZE: usize = 8;
+}
+
+impl FixedSize for isize {
+    const SIZE: usize = 8;
+}
+
+impl FixedSize for bool {
+    const SIZE: usize = 1;
+}
+
+impl<T: FixedSize, const N: usize> FixedSize for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+}
+
+macro_rules! impl_fixed_size_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: FixedSize),+> FixedSize for ($($name,)+) {
+            const SIZE: usize = 0 $(+ $name::SIZE)+;
+        }
+    };
+}
+
+impl_fixed_size_tuple!(A);
+impl_fixed_size_tuple!(A, B);
+impl_fixed_size_tuple!(A, B, C);
+impl_fixed_size_tuple!(A, B, C, D);
@@ -0,0 +1,74 @@
+use std::{cmp::Ordering, hash::Hash};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::fixed::FixedSize;
+
+macro_rules! ordered_float {
+    ($name:ident, $t:ty) => {
+        #[doc = concat!("Wrapper for `", stringify!($t), "` with a total order, so it can be used as key.")]
+        ///
+        /// The values are ordered like
+        #[doc = concat!("[`", stringify!($t), "::total_cmp`]:")]
+        /// negative NaN values are smaller than all other values and positive NaN values
+        /// are larger than all other values (including infinity).
+        /// Negative zero is smaller than positive zero, and two values are only equal if they
+        /// have the same bit pattern, so NaN is equal to itself.
+        ///
+        /// The value is serialized like the inner float. When configuring the
+        #[doc = concat!("[fixed key size](crate::BtreeConfig::fixed_key_size) ", stringify!($name), "::SIZE")]
+        /// (e.g. with [`BtreeIndex::with_fixed_size`](crate::BtreeIndex::with_fixed_size)),
+        /// the keys are stored inside the nodes and compared without deserializing them.
+        #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+        #[serde(transparent)]
+        pub struct $name(pub $t);
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other) == Ordering::Equal
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
+
+        impl Hash for $name {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                self.0.to_bits().hash(state);
+            }
+        }
+
+        impl From<$t> for $name {
+            fn from(value: $t) -> Self {
+                $name(value)
+            }
+        }
+
+        impl From<$name> for $t {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl FixedSize for $name {
+            const SIZE: usize = <$t as FixedSize>::SIZE;
+        }
+    };
+}
+
+ordered_float!(OrderedF32, f32);
+ordered_float!(OrderedF64, f64);
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+
+use crate::{BtreeConfig, BtreeIndex};
+
+use super::*;
+
+const VALUES: [f64; 12] = [
+    f64::NEG_INFINITY,
+    f64::MIN,
+    -1.5,
+    -f64::MIN_POSITIVE,
+    -0.0,
+    0.0,
+    f64::MIN_POSITIVE,
+    1.0,
+    1.5,
+    f64::MAX,
+    f64::INFINITY,
+    f64::NAN,
+];
+
+const VALUES_F32: [f32; 12] = [
+    f32::NEG_INFINITY,
+    f32::MIN,
+    -1.5,
+    -f32::MIN_POSITIVE,
+    -0.0,
+    0.0,
+    f32::MIN_POSITIVE,
+    1.0,
+    1.5,
+    f32::MAX,
+    f32::INFINITY,
+    f32::NAN,
+];
+
+#[test]
+fn total_order() {
+    for (i, a) in VALUES.iter().enumerate() {
+        for (j, b) in VALUES.iter().enumerate() {
+            assert_eq!(i.cmp(&j), OrderedF64(*a).cmp(&OrderedF64(*b)));
+            assert_eq!(
+                i.cmp(&j),
+                OrderedF32(VALUES_F32[i]).cmp(&OrderedF32(VALUES_F32[j]))
+            );
+        }
+    }
+    assert!(OrderedF64(-f64::NAN) < OrderedF64(f64::NEG_INFINITY));
+    assert_eq!(OrderedF64(f64::NAN), OrderedF64(f64::NAN));
+}
+
+#[test]
+fn float_keys_in_index() {
+    // Compare the search on the raw bits (fixed size) with the search on deserialized keys
+    let fixed: BtreeIndex<OrderedF64, usize> =
+        BtreeIndex::with_fixed_size(BtreeConfig::default().order(2), 10).unwrap();
+    let variable: BtreeIndex<OrderedF64, usize> =
+        BtreeIndex::with_capacity(BtreeConfig::default().order(2), 10).unwrap();
+    let fixed_f32: BtreeIndex<OrderedF32, usize> =
+        BtreeIndex::with_fixed_size(BtreeConfig::default().order(2), 10).unwrap();
+
+    let mut expected = BTreeMap::new();
+    let mut indexes = (fixed, variable, fixed_f32);
+    for i in 0..500 {
+        let key = VALUES[i % VALUES.len()] * ((i / VALUES.len()) as f64 + 1.0) / 7.0;
+        expected.insert(OrderedF64(key), i);
+        indexes.0.insert(OrderedF64(key), i).unwrap();
+        indexes.1.insert(OrderedF64(key), i).unwrap();
+        indexes.2.insert(OrderedF32(key as f32), i).unwrap();
+    }
+    for (key, value) in expected.iter() {
+        assert_eq!(Some(*value), indexes.0.get(key).unwrap());
+        assert_eq!(Some(*value), indexes.1.get(key).unwrap());
+        assert!(indexes.2.contains_key(&OrderedF32(key.0 as f32)).unwrap());
+    }
+    let range = OrderedF64(-1.0)..OrderedF64(1.0);
+    let found: Vec<_> = indexes
+        .0
+        .range(range.clone())
+        .unwrap()
+        .map(|e| e.unwrap())
+        .collect();
+    let expected: Vec<_> = expected.range(range).map(|(k, v)| (*k, *v)).collect();
+    assert_eq!(expected, found);
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::BTreeMap,
+    ops::{Bound, RangeBounds},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{error::Result, BtreeConfig, BtreeIndex, SortedMap, SortedMapRange, ValueCodec};
+
+/// Sorted map that starts in main memory and moves its entries into a [`BtreeIndex`]
+/// once it gets too large.
+///
+/// Small maps are held in a [`BTreeMap`], so no temporary files need to be created for them.
+/// When the number of entries or the approximate size of the serialized keys and values
+/// exceeds the configured limits, all entries are inserted into a new index in sorted order
+/// and all further operations use the index.
+///
+/// # Example
+///
+/// ```rust
+/// use transient_btree_index::{BtreeConfig, Error, HybridIndex};
+///
+/// fn main() -> std::result::Result<(), Error> {
+///     let mut m = HybridIndex::<u32, u32>::with_limits(BtreeConfig::default(), 100, usize::MAX);
+///     for i in 0..100 {
+///         m.insert(i, i * 2)?;
+///     }
+///     assert_eq!(false, m.is_spilled());
+///
+///     m.insert(100, 200)?;
+///     assert_eq!(true, m.is_spilled());
+///     assert_eq!(Some(10), m.get(&5)?);
+///     assert_eq!(101, m.len());
+///     Ok(())
+/// }
+/// ```
+pub struct HybridIndex<K, V>
+where
+    K: Serialize + DeserializeOwned + PartialOrd + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    storage: HybridStorage<K, V>,
+    config: BtreeConfig,
+    max_entries: usize,
+    max_bytes: usize,
+}
+
+enum HybridStorage<K, V>
+where
+    K: Serialize + DeserializeOwned + PartialOrd + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// Entries in main memory with the approximate size of their serialized keys and values.
+    Memory {
+        map: BTreeMap<K, V>,
+        bytes: usize,
+    },
+    Index(Box<BtreeIndex<K, V>>),
+}
+
+impl<K, V> HybridIndex<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    /// Create an empty map, which creates an index with the given configuration
+    /// when it holds more than `max_entries` entries or more than `max_bytes` bytes of
+    /// serialized keys and values.
+    pub fn with_limits(
+        config: BtreeConfig,
+        max_entries: usize,
+        max_bytes: usize,
+    ) -> HybridIndex<K, V> {
+        HybridIndex {
+            storage: HybridStorage::Memory {
+                map: BTreeMap::new(),
+                bytes: 0,
+            },
+            config,
+            max_entries,
+            max_bytes,
+        }
+    }
+
+    /// Insert a new element and return the previous value of the key if it existed.
+    ///
+    /// If the map exceeds one of the limits, all entries are moved into an index.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        match &mut self.storage {
+            HybridStorage::Memory { map, bytes } => {
+                let value_size = serialized_size(&value)?;
+                let previous = if let Some(previous) = map.get_mut(&key) {
+                    *bytes = (*bytes + value_size).saturating_sub(serialized_size(previous)?);
+                    Some(std::mem::replace(previous, value))
+                } else {
+                    *bytes += serialized_size(&key)? + value_size;
+                    map.insert(key, value);
+                    None
+                };
+                if map.len() > self.max_entries || *bytes > self.max_bytes {
+                    self.spill()?;
+                }
+                Ok(previous)
+            }
+            HybridStorage::Index(index) => index.insert(key, value),
+        }
+    }
+
+    /// Move all entries from main memory into a new index, if this has not happened yet.
+    ///
+    /// The entries are inserted in sorted order.
+    /// If the operation fails, you should assume that the whole map is corrupted.
+    pub fn spill(&mut self) -> Result<()> {
+        if let HybridStorage::Memory { map, .. } = &mut self.storage {
+            let map = std::mem::take(map);
+            let mut index = BtreeIndex::with_capacity(self.config.clone(), map.len())?;
+            for (key, value) in map {
+                index.insert(key, value)?;
+            }
+            self.storage = HybridStorage::Index(Box::new(index));
+        }
+        Ok(())
+    }
+
+    /// Returns whether the entries have been moved into an index.
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.storage, HybridStorage::Index(_))
+    }
+
+    /// Searches for a key and returns a copy of its value if found.
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        match &self.storage {
+            HybridStorage::Memory { map, .. } => Ok(map.get(key).cloned()),
+            HybridStorage::Index(index) => index.get(key),
+        }
+    }
+
+    /// Returns whether the map contains the given key.
+    pub fn contains_key(&self, key: &K) -> Result<bool> {
+        match &self.storage {
+            HybridStorage::Memory { map, .. } => Ok(map.contains_key(key)),
+            HybridStorage::Index(index) => index.contains_key(key),
+        }
+    }
+
+    /// Returns an iterator over a range of keys in key order.
+    pub fn range<R>(&self, range: R) -> Result<SortedMapRange<'_, K, V>>
+    where
+        R: RangeBounds<K>,
+    {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        match &self.storage {
+            HybridStorage::Memory { map, .. } => SortedMap::range(map, range),
+            HybridStorage::Index(index) => SortedMap::range(index.as_ref(), range),
+        }
+    }
+
+    /// Returns true if the map does not contain any elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            HybridStorage::Memory { map, .. } => map.len(),
+            HybridStorage::Index(index) => index.len(),
+        }
+    }
+
+    /// Move the entries into an index if needed and return it.
+    pub fn into_index(mut self) -> Result<BtreeIndex<K, V>> {
+        self.spill()?;
+        match self.storage {
+            HybridStorage::Index(index) => Ok(*index),
+            HybridStorage::Memory { .. } => unreachable!("entries have been moved into an index"),
+        }
+    }
+}
+
+impl<K, V> SortedMap<K, V> for HybridIndex<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    fn get(&self, key: &K) -> Result<Option<V>> {
+        HybridIndex::get(self, key)
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        HybridIndex::insert(self, key, value)
+    }
+
+    fn range(&self, range: (Bound<K>, Bound<K>)) -> Result<SortedMapRange<'_, K, V>> {
+        HybridIndex::range(self, range)
+    }
+
+    fn len(&self) -> usize {
+        HybridIndex::len(self)
+    }
+}
+
+/// Approximate size of a key or value in main memory, which is estimated by its serialized size.
+fn serialized_size<T: Serialize>(value: &T) -> Result<usize> {
+    Ok(ValueCodec::Bincode.serialized_size(value)?.try_into()?)
+}
+
+#[cfg(test)]
+mod tests;
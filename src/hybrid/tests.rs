@@ -0,0 +1,66 @@
+use super::*;
+
+#[test]
+fn spill_by_entries() {
+    let mut m = HybridIndex::<u32, String>::with_limits(BtreeConfig::default(), 50, usize::MAX);
+    for i in (0..50u32).rev() {
+        assert_eq!(None, m.insert(i, i.to_string()).unwrap());
+    }
+    assert!(!m.is_spilled());
+    assert_eq!(
+        Some("7".to_string()),
+        m.insert(7, "seven".to_string()).unwrap()
+    );
+    assert!(!m.is_spilled());
+    assert_eq!(50, m.len());
+
+    m.insert(50, "50".to_string()).unwrap();
+    assert!(m.is_spilled());
+    assert_eq!(51, m.len());
+    assert_eq!(Some("seven".to_string()), m.get(&7).unwrap());
+    assert!(m.contains_key(&0).unwrap());
+    assert!(!m.contains_key(&51).unwrap());
+
+    for i in 51..1000u32 {
+        m.insert(i, i.to_string()).unwrap();
+    }
+    let keys: Vec<u32> = m.range(45..55).unwrap().map(|e| e.unwrap().0).collect();
+    assert_eq!((45..55).collect::<Vec<_>>(), keys);
+
+    let index = m.into_index().unwrap();
+    assert_eq!(1000, index.len());
+    assert_eq!(Some("999".to_string()), index.get(&999).unwrap());
+}
+
+#[test]
+fn spill_by_bytes() {
+    // Each entry needs one byte for the key and 101 bytes for the value with its length
+    let mut m = HybridIndex::<u8, Vec<u8>>::with_limits(BtreeConfig::default(), usize::MAX, 1000);
+    for i in 0..9 {
+        m.insert(i, vec![i; 100]).unwrap();
+    }
+    assert!(!m.is_spilled());
+    // Replacing a value with a smaller one reduces the size
+    m.insert(0, vec![]).unwrap();
+    m.insert(9, vec![9; 100]).unwrap();
+    assert!(!m.is_spilled());
+
+    m.insert(10, vec![10; 100]).unwrap();
+    assert!(m.is_spilled());
+    assert_eq!(11, m.len());
+    assert_eq!(Some(Vec::new()), m.get(&0).unwrap());
+    assert_eq!(Some(vec![10; 100]), m.get(&10).unwrap());
+}
+
+#[test]
+fn small_map_stays_in_memory() {
+    let mut m = HybridIndex::<u32, u32>::with_limits(BtreeConfig::default(), 10, 1000);
+    assert!(m.is_empty());
+    m.insert(1, 1).unwrap();
+    let entries: Vec<(u32, u32)> = m.range(..).unwrap().map(|e| e.unwrap()).collect();
+    assert_eq!(vec![(1, 1)], entries);
+    assert!(!m.is_spilled());
+
+    let index = m.into_index().unwrap();
+    assert_eq!(Some(1), index.get(&1).unwrap());
+}
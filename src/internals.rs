@@ -0,0 +1,43 @@
+//! Building blocks of the index for custom on-disk data structures.
+//!
+//! This module needs the `unstable-internals` feature.
+//! It exposes the [`NodeFile`], which stores fixed size node blocks with keys, `u64` payloads
+//! and child node references in the configured [storage](crate::StorageBackend),
+//! e.g. to build other tree structures like a radix tree with the same storage machinery.
+//! Together with the [tuple files](crate::file) for the values, this is everything the
+//! [`BtreeIndex`](crate::BtreeIndex) is built from.
+//!
+//! **These types are not covered by semantic versioning** and can change in any release.
+//! Accessors do not check the invariants of a B-tree, e.g. that the keys of a node are sorted,
+//! but [`NodeFile::binary_search`] and [`NodeFile::find_range`] rely on them.
+//!
+//! # Example
+//!
+//! ```rust
+//! use transient_btree_index::{internals::{NodeFile, SearchResult}, BtreeConfig, Error};
+//!
+//! fn main() -> std::result::Result<(), Error> {
+//!     let mut nodes = NodeFile::<String>::with_capacity(10, &BtreeConfig::default())?;
+//!
+//!     // A node with two keys, which reference some external data with their payload
+//!     let root = nodes.allocate_new_node()?;
+//!     nodes.set_key_value(root, 0, &"apple".to_string())?;
+//!     nodes.set_payload(root, 0, 10)?;
+//!     nodes.set_key_value(root, 1, &"banana".to_string())?;
+//!     nodes.set_payload(root, 1, 20)?;
+//!
+//!     // Link a child node before the first key
+//!     let child = nodes.allocate_new_node()?;
+//!     nodes.set_child_node(root, 0, child)?;
+//!     assert_eq!(false, nodes.is_leaf(root)?);
+//!     assert_eq!(child, nodes.get_child_node(root, 0)?);
+//!
+//!     match nodes.binary_search(root, &"banana".to_string())? {
+//!         SearchResult::Found(i) => assert_eq!(20, nodes.get_payload(root, i)?),
+//!         SearchResult::NotFound(_) => unreachable!(),
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+pub use crate::btree::node::{NodeFile, NodeLayout, SearchResult, StackEntry};
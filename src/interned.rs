@@ -0,0 +1,312 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    btree::create_variable_size_file,
+    error::{Error, Result},
+    file::{TupleFile, VariableSizeTupleFile},
+    BtreeConfig, BtreeIndex, DEFAULT_PAGE_SIZE,
+};
+
+/// Key that consists of a component which is shared by many keys and the remaining part of the key.
+///
+/// The keys must be ordered like the tuple of their component and remaining part,
+/// which is e.g. the case for a tuple `(A, B)` with `A` as component.
+/// See [`InternedBtreeIndex`] for an index that stores each distinct component only once.
+pub trait InternedKey: Sized {
+    /// Part of the key that is stored only once for all keys.
+    type Component: 'static + Serialize + DeserializeOwned + Ord + Hash + Clone;
+    /// Part of the key that is stored for each key.
+    type Rest: 'static + Serialize + DeserializeOwned + Ord + Clone;
+
+    /// Split the key into its shared component and the remaining part.
+    fn into_parts(self) -> (Self::Component, Self::Rest);
+
+    /// Create a key from its shared component and the remaining part.
+    fn from_parts(component: Self::Component, rest: Self::Rest) -> Self;
+}
+
+impl<A, B> InternedKey for (A, B)
+where
+    A: 'static + Serialize + DeserializeOwned + Ord + Hash + Clone,
+    B: 'static + Serialize + DeserializeOwned + Ord + Clone,
+{
+    type Component = A;
+    type Rest = B;
+
+    fn into_parts(self) -> (A, B) {
+        self
+    }
+
+    fn from_parts(component: A, rest: B) -> Self {
+        (component, rest)
+    }
+}
+
+/// B-tree index for keys where many keys share the same large component, e.g. a long namespace.
+///
+/// Each distinct [component](InternedKey::Component) is stored once in a separate file and the keys
+/// of the index only contain the ID of their component and the [remaining part](InternedKey::Rest).
+/// The components are sorted by their actual value and not by their ID, so range queries return
+/// the keys in the same order as an index with the complete keys.
+/// The IDs of the distinct components are held in main memory, so this is only useful if there
+/// are far fewer distinct components than keys.
+///
+/// # Example
+///
+/// ```rust
+/// use transient_btree_index::{BtreeConfig, Error, InternedBtreeIndex};
+///
+/// fn main() -> std::result::Result<(), Error> {
+///     let mut b = InternedBtreeIndex::<(String, u32), u32>::with_capacity(BtreeConfig::default(), 100)?;
+///     for i in 0..50 {
+///         b.insert(("http://example.com/ns/b".to_string(), i), i)?;
+///         b.insert(("http://example.com/ns/a".to_string(), i), i * 2)?;
+///     }
+///     assert_eq!(2, b.component_count());
+///     assert_eq!(Some(20), b.get(&("http://example.com/ns/a".to_string(), 10))?);
+///
+///     let first = b.range(..)?.next().unwrap()?;
+///     assert_eq!((("http://example.com/ns/a".to_string(), 0), 0), first);
+///     Ok(())
+/// }
+/// ```
+pub struct InternedBtreeIndex<K, V>
+where
+    K: InternedKey,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// The component of a key is `None` only for the bounds of range queries,
+    /// which are ordered before all keys with the same component.
+    index: BtreeIndex<(u64, Option<K::Rest>), V>,
+    components: Interner<K::Component>,
+}
+
+impl<K, V> InternedBtreeIndex<K, V>
+where
+    K: InternedKey + Clone,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    /// Create a new instance with the given configuration and capacity in number of elements.
+    pub fn with_capacity(config: BtreeConfig, capacity: usize) -> Result<InternedBtreeIndex<K, V>> {
+        let components = Interner::new(create_variable_size_file(
+            "interned components",
+            DEFAULT_PAGE_SIZE,
+            &config,
+        )?);
+        Ok(InternedBtreeIndex {
+            index: BtreeIndex::with_capacity(config, capacity)?,
+            components,
+        })
+    }
+
+    /// Insert a new element into the index.
+    ///
+    /// Existing values will be overwritten and returned.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        let (component, rest) = key.into_parts();
+        let id = self.components.intern(component)?;
+        self.index.insert((id, Some(rest)), value)
+    }
+
+    /// Searches for a key in the index and returns the value if found.
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        if let Some(key) = self.stored_key(key)? {
+            self.index.get(&key)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns whether the index contains the given key.
+    pub fn contains_key(&self, key: &K) -> Result<bool> {
+        if let Some(key) = self.stored_key(key)? {
+            self.index.contains_key(&key)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Returns an iterator over a range of keys in key order.
+    pub fn range<R>(&self, range: R) -> Result<impl Iterator<Item = Result<(K, V)>> + '_>
+    where
+        R: RangeBounds<K>,
+    {
+        let start = range.start_bound().cloned().map(InternedKey::into_parts);
+        let end = range.end_bound().cloned().map(InternedKey::into_parts);
+        // Keys with the component of an excluded bound can still be inside the range
+        let component_bound = |bound: &Bound<(K::Component, K::Rest)>| match bound {
+            Bound::Included((c, _)) | Bound::Excluded((c, _)) => Some(c.clone()),
+            Bound::Unbounded => None,
+        };
+        let ids = self
+            .components
+            .ids_in_range(component_bound(&start), component_bound(&end))?;
+
+        let mut ranges = Vec::with_capacity(ids.len());
+        for id in ids {
+            let component = self.components.get(id)?;
+            // The bounds of the remaining part only apply to the component of the bound
+            let start = match &start {
+                Bound::Included((c, rest)) if *c == *component => {
+                    Bound::Included((id, Some(rest.clone())))
+                }
+                Bound::Excluded((c, rest)) if *c == *component => {
+                    Bound::Excluded((id, Some(rest.clone())))
+                }
+                _ => Bound::Included((id, None)),
+            };
+            let end = match &end {
+                Bound::Included((c, rest)) if *c == *component => {
+                    Bound::Included((id, Some(rest.clone())))
+                }
+                Bound::Excluded((c, rest)) if *c == *component => {
+                    Bound::Excluded((id, Some(rest.clone())))
+                }
+                _ => Bound::Excluded((id + 1, None)),
+            };
+            let entries = self.index.range((start, end))?;
+            ranges.push(entries.map(move |e| {
+                let ((_, rest), value) = e?;
+                let rest = rest.ok_or_else(|| {
+                    Error::IntegrityViolation("stored key without remaining part".to_string())
+                })?;
+                Ok((K::from_parts(component.as_ref().clone(), rest), value))
+            }));
+        }
+        Ok(ranges.into_iter().flatten())
+    }
+
+    /// Returns true if the index does not contain any elements.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the length of the index.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns the number of distinct components of the keys.
+    pub fn component_count(&self) -> usize {
+        self.components.sorted.len()
+    }
+
+    /// Get the key as it is stored in the index, or `None` if its component is unknown.
+    fn stored_key(&self, key: &K) -> Result<Option<(u64, Option<K::Rest>)>> {
+        let (component, rest) = key.clone().into_parts();
+        let id = self.components.find(&component)?;
+        Ok(id.map(|id| (id, Some(rest))))
+    }
+}
+
+/// Stores each distinct component once and assigns an ID to it.
+struct Interner<C> {
+    file: VariableSizeTupleFile<C>,
+    /// IDs of the components by the hash of the component.
+    ids: HashMap<u64, Vec<u64>>,
+    /// IDs of all components, sorted by their component.
+    sorted: Vec<u64>,
+}
+
+impl<C> Interner<C>
+where
+    C: Serialize + DeserializeOwned + Ord + Hash + Clone,
+{
+    fn new(file: VariableSizeTupleFile<C>) -> Interner<C> {
+        Interner {
+            file,
+            ids: HashMap::new(),
+            sorted: Vec::new(),
+        }
+    }
+
+    fn get(&self, id: u64) -> Result<Arc<C>> {
+        self.file.get(id.try_into()?)
+    }
+
+    /// Returns the ID of the component if it has been stored before.
+    fn find(&self, component: &C) -> Result<Option<u64>> {
+        if let Some(candidates) = self.ids.get(&component_hash(component)) {
+            for id in candidates {
+                if *self.get(*id)? == *component {
+                    return Ok(Some(*id));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the ID of the component and stores it if it is new.
+    fn intern(&mut self, component: C) -> Result<u64> {
+        if let Some(id) = self.find(&component)? {
+            return Ok(id);
+        }
+        let position = match self.binary_search(&component)? {
+            Ok(i) | Err(i) => i,
+        };
+        let id: u64 = self.file.insert(&component)?.try_into()?;
+        self.sorted.insert(position, id);
+        self.ids
+            .entry(component_hash(&component))
+            .or_default()
+            .push(id);
+        Ok(id)
+    }
+
+    /// Search the component in the sorted list of IDs like [`slice::binary_search`].
+    fn binary_search(&self, component: &C) -> Result<std::result::Result<usize, usize>> {
+        let mut low = 0;
+        let mut high = self.sorted.len();
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.get(self.sorted[mid])?.as_ref().cmp(component) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Ok(Ok(mid)),
+            }
+        }
+        Ok(Err(low))
+    }
+
+    /// Returns the IDs of all components between the inclusive bounds, sorted by their component.
+    ///
+    /// A missing bound is unbounded.
+    fn ids_in_range(&self, start: Option<C>, end: Option<C>) -> Result<Vec<u64>> {
+        let start = if let Some(c) = start {
+            match self.binary_search(&c)? {
+                Ok(i) | Err(i) => i,
+            }
+        } else {
+            0
+        };
+        let end = if let Some(c) = end {
+            match self.binary_search(&c)? {
+                Ok(i) => i + 1,
+                Err(i) => i,
+            }
+        } else {
+            self.sorted.len()
+        };
+        if start < end {
+            Ok(self.sorted[start..end].to_vec())
+        } else {
+            Ok(Vec::new())
+        }
+    }
+}
+
+fn component_hash<C: Hash>(component: &C) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    component.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+use super::*;
+use crate::{test_util::seeded_rng, SortedMap};
+
+fn namespace(i: u32) -> String {
+    format!("http://example.com/some/long/namespace/{i}/")
+}
+
+#[test]
+fn interned_keys() {
+    let mut b = InternedBtreeIndex::<(String, u32), u64>::with_capacity(
+        BtreeConfig::default().max_key_size(16),
+        2000,
+    )
+    .unwrap();
+    let mut expected = BTreeMap::new();
+
+    let mut rng = seeded_rng();
+    for i in 0..2000u64 {
+        let key = (
+            namespace(rand::Rng::gen_range(&mut rng, 0..10u32)),
+            rand::Rng::gen_range(&mut rng, 0..500u32),
+        );
+        assert_eq!(expected.insert(key.clone(), i), b.insert(key, i).unwrap());
+    }
+    assert_eq!(expected.len(), b.len());
+    assert_eq!(10, b.component_count());
+
+    for (key, value) in expected.iter() {
+        assert_eq!(Some(*value), b.get(key).unwrap());
+    }
+    assert!(!b.contains_key(&(namespace(3), 500)).unwrap());
+    assert!(!b.contains_key(&(namespace(10), 0)).unwrap());
+
+    // Ranges are ordered by the components and not by the order they have been inserted in
+    let all: Vec<_> = b.range(..).unwrap().map(|e| e.unwrap()).collect();
+    assert_eq!(expected.clone().into_iter().collect::<Vec<_>>(), all);
+
+    let ranges = [
+        (
+            Bound::Included((namespace(2), 100)),
+            Bound::Excluded((namespace(5), 20)),
+        ),
+        (
+            Bound::Excluded((namespace(2), 100)),
+            Bound::Included((namespace(2), 400)),
+        ),
+        (
+            Bound::Included(("http://a".to_string(), 0)),
+            Bound::Included((namespace(1), 3)),
+        ),
+        (Bound::Excluded((namespace(7), 499)), Bound::Unbounded),
+        (
+            Bound::Included((namespace(5), 0)),
+            Bound::Included((namespace(4), 0)),
+        ),
+    ];
+    for range in ranges {
+        let result: Vec<_> = b
+            .range(range.clone())
+            .unwrap()
+            .map(|e| e.unwrap())
+            .collect();
+        // The map of the standard library panics for ranges that end before they start
+        let expected: Vec<_> = SortedMap::range(&expected, range)
+            .unwrap()
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(expected, result);
+    }
+}
@@ -7,11 +7,15 @@
 //! - want to query entries (get and range queries) while the index is still constructed, e.g. to check existence of a previous entry, and
 //! - need support for all serde-serializable key and value types with varying key-size.
 //!
-//! Because of its intended use case, it is therefore **not possible to**
+//! Because of its intended use case, it is therefore **not possible to** load an existing index file
+//! (you might want to use an immutable map file and this index can act as an "overlay" for all changed entries,
+//! see [`BtreeIndex::overlay_range`]).
 //!
-//! - delete entries once they are inserted (you can use [`Option`] values and set them to [`Option::None`], but this will not reclaim any used space),
-//! - persist the index to a file (you can use other crates like [sstable](https://crates.io/crates/sstable) to create immutable maps), or
-//! - load an existing index file (you might want to use an immutable map file and this index can act as an "overlay" for all changed entries).
+//! Entries can be removed, e.g. with [`BtreeIndex::remove_range`], but their keys are only marked as removed
+//! until the index is [compacted](BtreeIndex::compact).
+//! The index itself is not persisted, but its entries can be written into an immutable sorted table
+//! with `write_sstable` (`export` feature), and all changes can be recorded with `write_ahead_log`
+//! (`wal` feature) to recover the index later.
 //!
 //! # Example
 //!
@@ -36,25 +40,72 @@
 //!     Ok(())
 //! }
 //! ```
+// Allow the derive macros to refer to this crate in its own tests
+#[cfg(all(test, feature = "derive"))]
+extern crate self as transient_btree_index;
+
+#[cfg(feature = "rkyv")]
+mod archive;
 mod btree;
+mod buffered;
+mod cancellation;
+mod codec;
+mod composite;
 mod error;
-mod file;
+pub mod file;
+mod fixed;
+mod float;
+mod hybrid;
+#[cfg(feature = "unstable-internals")]
+pub mod internals;
+mod interned;
+mod map;
+mod metrics;
+mod ordered;
+mod parallel;
+mod prefix;
+mod raw;
+mod storage;
+#[cfg(feature = "test-support")]
+mod test_support;
+#[cfg(test)]
+mod test_util;
 
-pub use btree::{BtreeConfig, BtreeIndex};
+#[cfg(feature = "rkyv")]
+pub use archive::{ArchivedBtreeIndex, ArchivedRef};
+#[cfg(feature = "async")]
+pub use btree::RangeStream;
+pub use btree::{
+    BtreeBuilder, BtreeConfig, BtreeIndex, BtreeIntoIter, Capacity, Drain, FileCapacity,
+    FrozenBtreeIndex, GroupedRange, JoinRange, KeySetIter, LookupContext, MemoryUsage,
+    OverlayRange, PayloadId, Range, Transaction, TypeSize,
+};
+pub use buffered::BufferedBtreeIndex;
+pub use cancellation::CancellationToken;
+pub use codec::{Codec, ValueCodec};
+pub use composite::{CompositeKey, CompositeKeyReader};
 pub use error::Error;
-use memmap2::MmapMut;
+pub use file::{EvictionPolicy, LruPolicy, RelocationPolicy, RelocationStats, SegmentedLruPolicy};
+pub use fixed::FixedSize;
+pub use float::{OrderedF32, OrderedF64};
+pub use hybrid::HybridIndex;
+pub use interned::{InternedBtreeIndex, InternedKey};
+pub use map::{SortedMap, SortedMapRange};
+pub use metrics::BtreeMetricsObserver;
+pub use ordered::OrderedBytes;
+pub use parallel::ParallelBtreeBuilder;
+pub use prefix::PrefixKey;
+pub use raw::RawBtreeIndex;
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+pub use storage::MmapStorage;
+pub use storage::{GrowthStrategy, MemoryStorage, StorageBackend};
+#[cfg(feature = "test-support")]
+pub use test_support::{ModelChecker, ModelOperation};
+#[cfg(feature = "derive")]
+pub use transient_btree_index_derive::FixedSize;
 
 const KB: usize = 1 << 10;
-const PAGE_SIZE: usize = 4 * KB;
-
-/// Create a new memory mapped file with the capacity in bytes.
-fn create_mmap(capacity: usize) -> error::Result<MmapMut> {
-    let file = tempfile::tempfile()?;
-    if capacity > 0 {
-        file.set_len(capacity.try_into()?)?;
-    }
-
-    // Load this file as memory mapped file
-    let mmap = unsafe { MmapMut::map_mut(&file)? };
-    Ok(mmap)
-}
+/// Page size that is used when the page size of the system can't be detected.
+/// This is also the default and minimum size of a node block.
+const DEFAULT_PAGE_SIZE: usize = 4 * KB;
+const HUGE_PAGE_SIZE: usize = 2 * KB * KB;
@@ -0,0 +1,122 @@
+use std::{collections::BTreeMap, ops::Bound};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{error::Result, BtreeIndex};
+
+/// Iterator over the entries of a [`SortedMap`] in key order.
+pub type SortedMapRange<'a, K, V> = Box<dyn Iterator<Item = Result<(K, V)>> + 'a>;
+
+/// Common interface of sorted maps, which allows to choose between an in-memory map
+/// and a [`BtreeIndex`] at runtime.
+///
+/// The trait is implemented by [`BtreeIndex`] and by the [`BTreeMap`] of the standard library.
+/// It can be used as trait object, e.g. to create a small map in memory and an index backed by
+/// temporary files for large inputs, based on the expected number of entries.
+///
+/// # Example
+///
+/// ```rust
+/// use std::{collections::BTreeMap, ops::Bound};
+/// use transient_btree_index::{BtreeConfig, BtreeIndex, Error, SortedMap};
+///
+/// fn main() -> std::result::Result<(), Error> {
+///     let expected_size = 100;
+///     let mut m: Box<dyn SortedMap<u64, String>> = if expected_size > 1_000_000 {
+///         Box::new(BtreeIndex::with_capacity(BtreeConfig::default(), expected_size)?)
+///     } else {
+///         Box::new(BTreeMap::new())
+///     };
+///     m.insert(1, "a".to_string())?;
+///     m.insert(2, "b".to_string())?;
+///
+///     assert_eq!(Some("b".to_string()), m.get(&2)?);
+///     let keys: Vec<u64> = m
+///         .range((Bound::Included(2), Bound::Unbounded))?
+///         .map(|e| e.map(|(k, _)| k))
+///         .collect::<Result<_, _>>()?;
+///     assert_eq!(vec![2], keys);
+///     Ok(())
+/// }
+/// ```
+pub trait SortedMap<K, V> {
+    /// Searches for a key and returns a copy of its value if found.
+    fn get(&self, key: &K) -> Result<Option<V>>;
+
+    /// Insert a new element and return the previous value of the key if it existed.
+    fn insert(&mut self, key: K, value: V) -> Result<Option<V>>;
+
+    /// Returns an iterator over the entries with keys inside the given bounds in key order.
+    fn range(&self, range: (Bound<K>, Bound<K>)) -> Result<SortedMapRange<'_, K, V>>;
+
+    /// Returns the number of elements.
+    fn len(&self) -> usize;
+
+    /// Returns true if the map does not contain any elements.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V> SortedMap<K, V> for BtreeIndex<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    fn get(&self, key: &K) -> Result<Option<V>> {
+        BtreeIndex::get(self, key)
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        BtreeIndex::insert(self, key, value)
+    }
+
+    fn range(&self, range: (Bound<K>, Bound<K>)) -> Result<SortedMapRange<'_, K, V>> {
+        Ok(Box::new(BtreeIndex::range(self, range)?))
+    }
+
+    fn len(&self) -> usize {
+        BtreeIndex::len(self)
+    }
+}
+
+impl<K, V> SortedMap<K, V> for BTreeMap<K, V>
+where
+    K: Clone + Ord,
+    V: Clone,
+{
+    fn get(&self, key: &K) -> Result<Option<V>> {
+        Ok(BTreeMap::get(self, key).cloned())
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        Ok(BTreeMap::insert(self, key, value))
+    }
+
+    fn range(&self, range: (Bound<K>, Bound<K>)) -> Result<SortedMapRange<'_, K, V>> {
+        // In contrast to the index, the map panics for a range that ends before it starts
+        if is_empty_range(&range) {
+            return Ok(Box::new(std::iter::empty()));
+        }
+        let entries = BTreeMap::range(self, range).map(|(k, v)| Ok((k.clone(), v.clone())));
+        Ok(Box::new(entries))
+    }
+
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+}
+
+/// Returns whether no key can be inside the bounds, because the end is before the start.
+fn is_empty_range<K: Ord>(range: &(Bound<K>, Bound<K>)) -> bool {
+    match range {
+        (Bound::Included(start), Bound::Included(end)) => start > end,
+        (Bound::Included(start), Bound::Excluded(end))
+        | (Bound::Excluded(start), Bound::Included(end))
+        | (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+
+use super::*;
+use crate::BtreeConfig;
+
+fn check_sorted_map(m: &mut dyn SortedMap<u32, String>) {
+    assert!(m.is_empty());
+    for i in (0..200u32).rev() {
+        assert_eq!(None, m.insert(i * 2, i.to_string()).unwrap());
+    }
+    assert_eq!(
+        Some("10".to_string()),
+        m.insert(20, "ten".to_string()).unwrap()
+    );
+    assert_eq!(200, m.len());
+    assert!(!m.is_empty());
+
+    assert_eq!(Some("ten".to_string()), m.get(&20).unwrap());
+    assert_eq!(Some("199".to_string()), m.get(&398).unwrap());
+    assert_eq!(None, m.get(&21).unwrap());
+
+    let keys: Vec<u32> = m
+        .range((Bound::Excluded(10), Bound::Included(20)))
+        .unwrap()
+        .map(|e| e.unwrap().0)
+        .collect();
+    assert_eq!(vec![12, 14, 16, 18, 20], keys);
+    assert_eq!(
+        200,
+        m.range((Bound::Unbounded, Bound::Unbounded))
+            .unwrap()
+            .count()
+    );
+
+    // Ranges that end before they start are empty
+    assert_eq!(
+        0,
+        m.range((Bound::Included(20), Bound::Included(10)))
+            .unwrap()
+            .count()
+    );
+    assert_eq!(
+        0,
+        m.range((Bound::Excluded(20), Bound::Excluded(20)))
+            .unwrap()
+            .count()
+    );
+}
+
+#[test]
+fn sorted_map_btree_index() {
+    let mut m = BtreeIndex::with_capacity(BtreeConfig::default().max_key_size(8), 200).unwrap();
+    check_sorted_map(&mut m);
+}
+
+#[test]
+fn sorted_map_std_btree_map() {
+    let mut m = BTreeMap::new();
+    check_sorted_map(&mut m);
+}
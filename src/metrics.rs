@@ -0,0 +1,76 @@
+use std::{fmt, sync::Arc};
+
+/// Receives notifications about internal operations of an index, e.g. to collect metrics.
+///
+/// An observer can be configured with [`BtreeConfig::metrics_observer`](crate::BtreeConfig::metrics_observer).
+/// All methods do nothing by default, so only the relevant ones need to be implemented.
+/// The methods are called while the operation is executed and should return quickly.
+pub trait BtreeMetricsObserver: Send + Sync {
+    /// A full node has been split and half of its keys have been moved into a new node.
+    fn on_split(&self, _node_id: u64, _new_node_id: u64) {}
+
+    /// The storage of one of the files has been grown from the old to the new size in bytes.
+    fn on_grow(&self, _old_size: usize, _new_size: usize) {}
+
+    /// A block needed more space than allocated and has been moved to a new block
+    /// with the given capacity in bytes.
+    fn on_relocate(&self, _block_id: usize, _old_capacity: usize, _new_capacity: usize) {}
+
+    /// A block with the given (approximate) size in bytes has been removed from a block cache.
+    fn on_cache_eviction(&self, _block_id: usize, _size: usize) {}
+}
+
+/// Forwards the internal operations to the configured observer and emits them as `tracing` events.
+#[derive(Clone, Default)]
+pub(crate) struct Metrics(Option<Arc<dyn BtreeMetricsObserver>>);
+
+impl Metrics {
+    pub fn new(observer: Arc<dyn BtreeMetricsObserver>) -> Metrics {
+        Metrics(Some(observer))
+    }
+
+    pub fn split(&self, node_id: u64, new_node_id: u64) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(node = node_id, new_node = new_node_id, "split node");
+        if let Some(observer) = &self.0 {
+            observer.on_split(node_id, new_node_id);
+        }
+    }
+
+    pub fn grow(&self, old_size: usize, new_size: usize) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(old_size, new_size, "grow storage");
+        if let Some(observer) = &self.0 {
+            observer.on_grow(old_size, new_size);
+        }
+    }
+
+    pub fn relocate(&self, block_id: usize, old_capacity: usize, new_capacity: usize) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            block = block_id,
+            old_capacity,
+            new_capacity,
+            "relocate block"
+        );
+        if let Some(observer) = &self.0 {
+            observer.on_relocate(block_id, old_capacity, new_capacity);
+        }
+    }
+
+    pub fn cache_eviction(&self, block_id: usize, size: usize) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(block = block_id, size, "evict cached block");
+        if let Some(observer) = &self.0 {
+            observer.on_cache_eviction(block_id, size);
+        }
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Metrics")
+            .field(&self.0.as_ref().map(|_| "observer"))
+            .finish()
+    }
+}
@@ -0,0 +1,18 @@
+/// Marker for keys where the order of their serialized bytes is the same as the order of the keys.
+///
+/// The bytes are compared lexicographically (like `memcmp`).
+/// This must hold for both the default [bincode](https://crates.io/crates/bincode) encoding
+/// and the fixed integer encoding used for [fixed key sizes](crate::BtreeConfig::fixed_key_size).
+/// It is e.g. true for byte arrays like `[u8; 16]` (which can hold the bytes of a UUID), but not for
+/// multi-byte integers (which are encoded as little-endian) or [`String`]
+/// (which has the length encoded before its bytes).
+///
+/// Create the index with [`BtreeIndex::with_ordered_keys`](crate::BtreeIndex::with_ordered_keys)
+/// to compare the serialized bytes when searching inside a node, instead of deserializing each key.
+pub trait OrderedBytes {}
+
+impl OrderedBytes for u8 {}
+
+impl OrderedBytes for bool {}
+
+impl<const N: usize> OrderedBytes for [u8; N] {}
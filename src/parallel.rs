@@ -0,0 +1,143 @@
+use std::sync::Mutex;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    BtreeConfig, BtreeIndex, CancellationToken,
+};
+
+/// Builds a [`BtreeIndex`] from entries that are inserted by multiple threads at the same time.
+///
+/// The key space is partitioned into ranges by the given split keys, and each range has
+/// its own index (shard) with its own lock. Threads inserting keys of different ranges don't
+/// block each other, so the split keys should divide the keys into ranges of similar size,
+/// e.g. by using quantiles of a sample of the keys.
+/// [`Self::build`] combines the shards into a single index by inserting their entries
+/// in sorted order.
+///
+/// # Example
+///
+/// ```rust
+/// use transient_btree_index::{BtreeConfig, Error, ParallelBtreeBuilder};
+///
+/// fn main() -> std::result::Result<(), Error> {
+///     let builder =
+///         ParallelBtreeBuilder::<u32, u32>::new(BtreeConfig::default(), 1000, vec![250, 500, 750])?;
+///     std::thread::scope(|s| {
+///         for t in 0..4 {
+///             let builder = &builder;
+///             s.spawn(move || {
+///                 for i in (t..1000).step_by(4) {
+///                     builder.insert(i, i * 2).unwrap();
+///                 }
+///             });
+///         }
+///     });
+///     let index = builder.build()?;
+///     assert_eq!(1000, index.len());
+///     assert_eq!(Some(20), index.get(&10)?);
+///     Ok(())
+/// }
+/// ```
+pub struct ParallelBtreeBuilder<K, V>
+where
+    K: Serialize + DeserializeOwned + PartialOrd + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    split_keys: Vec<K>,
+    shards: Vec<Mutex<BtreeIndex<K, V>>>,
+    config: BtreeConfig,
+    capacity: usize,
+}
+
+impl<K, V> ParallelBtreeBuilder<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord,
+    V: 'static + Serialize + DeserializeOwned + Clone,
+{
+    /// Create a builder for an index with the given configuration and capacity in number of elements.
+    ///
+    /// The `split_keys` divide the keys into one more range than there are split keys,
+    /// where each split key is the first key of its range.
+    pub fn new(
+        config: BtreeConfig,
+        capacity: usize,
+        mut split_keys: Vec<K>,
+    ) -> Result<ParallelBtreeBuilder<K, V>> {
+        split_keys.sort();
+        split_keys.dedup();
+        let shard_capacity = capacity / (split_keys.len() + 1);
+        let shards = (0..=split_keys.len())
+            .map(|_| {
+                Ok(Mutex::new(BtreeIndex::with_capacity(
+                    config.clone(),
+                    shard_capacity,
+                )?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ParallelBtreeBuilder {
+            split_keys,
+            shards,
+            config,
+            capacity,
+        })
+    }
+
+    /// Insert a new element into the shard for its key range.
+    ///
+    /// Existing values will be overwritten.
+    /// This blocks while another thread inserts into the same shard.
+    pub fn insert(&self, key: K, value: V) -> Result<()> {
+        let shard = self
+            .split_keys
+            .partition_point(|split_key| split_key <= &key);
+        let mut index = self.shards[shard]
+            .lock()
+            .map_err(|_| Error::PoisonedShard)?;
+        index.insert(key, value)?;
+        Ok(())
+    }
+
+    /// Combine all shards into a single index.
+    pub fn build(self) -> Result<BtreeIndex<K, V>> {
+        self.build_with(None)
+    }
+
+    /// [Combine](Self::build) all shards into a single index, but stop with [`Error::Cancelled`]
+    /// when the token is cancelled.
+    pub fn build_cancellable(self, cancellation: &CancellationToken) -> Result<BtreeIndex<K, V>> {
+        self.build_with(Some(cancellation))
+    }
+
+    fn build_with(self, cancellation: Option<&CancellationToken>) -> Result<BtreeIndex<K, V>> {
+        if let Some(cancellation) = cancellation {
+            cancellation.check()?;
+        }
+        let mut shards = self
+            .shards
+            .into_iter()
+            .map(|shard| shard.into_inner().map_err(|_| Error::PoisonedShard))
+            .collect::<Result<Vec<_>>>()?;
+        if shards.len() == 1 {
+            return Ok(shards.remove(0));
+        }
+        let len: usize = shards.iter().map(BtreeIndex::len).sum();
+        let mut result = BtreeIndex::with_capacity(self.config, len.max(self.capacity))?;
+        // The shards contain disjoint and ordered key ranges, so the keys are inserted in sorted order
+        for shard in shards {
+            let mut entries = shard.into_iter()?;
+            if let Some(cancellation) = cancellation {
+                entries = entries.cancel_on(cancellation.clone());
+            }
+            for entry in entries {
+                let (key, value) = entry?;
+                result.insert(key, value)?;
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+
+use rand::{Rng, SeedableRng};
+
+use super::*;
+use crate::test_util::SEED;
+
+#[test]
+fn parallel_build() {
+    let builder: ParallelBtreeBuilder<u32, u32> = ParallelBtreeBuilder::new(
+        BtreeConfig::default().order(3),
+        4000,
+        vec![3000, 1000, 2000],
+    )
+    .unwrap();
+    let entries: Vec<BTreeMap<u32, u32>> = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let builder = &builder;
+                s.spawn(move || {
+                    let mut rng = rand::rngs::SmallRng::seed_from_u64(SEED + t);
+                    let mut m = BTreeMap::new();
+                    for _ in 0..1000 {
+                        // Each thread uses different keys, so the expected value is unique
+                        let key = rng.gen_range(0..1000) * 4 + t as u32;
+                        let value = rng.gen();
+                        m.insert(key, value);
+                        builder.insert(key, value).unwrap();
+                    }
+                    m
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let expected: BTreeMap<u32, u32> = entries.into_iter().flatten().collect();
+
+    let index = builder.build().unwrap();
+    index.verify_integrity().unwrap();
+    assert_eq!(expected.len(), index.len());
+    let result: Vec<_> = index.range(..).unwrap().map(|e| e.unwrap()).collect();
+    assert_eq!(expected.into_iter().collect::<Vec<_>>(), result);
+}
+
+#[test]
+fn parallel_build_without_split_keys() {
+    let builder: ParallelBtreeBuilder<u32, u32> =
+        ParallelBtreeBuilder::new(BtreeConfig::default(), 10, Vec::new()).unwrap();
+    builder.insert(2, 20).unwrap();
+    builder.insert(1, 10).unwrap();
+    let index = builder.build().unwrap();
+    assert_eq!(2, index.len());
+    assert_eq!(Some(10), index.get(&1).unwrap());
+}
+
+#[test]
+fn cancel_parallel_build() {
+    let builder: ParallelBtreeBuilder<u32, u32> =
+        ParallelBtreeBuilder::new(BtreeConfig::default(), 100, vec![50]).unwrap();
+    for i in 0..100 {
+        builder.insert(i, i).unwrap();
+    }
+    let token = CancellationToken::new();
+    token.cancel();
+    assert!(matches!(
+        builder.build_cancellable(&token),
+        Err(Error::Cancelled)
+    ));
+
+    let builder: ParallelBtreeBuilder<u32, u32> =
+        ParallelBtreeBuilder::new(BtreeConfig::default(), 100, vec![50]).unwrap();
+    builder.insert(1, 10).unwrap();
+    let index = builder
+        .build_cancellable(&CancellationToken::new())
+        .unwrap();
+    assert_eq!(Some(10), index.get(&1).unwrap());
+}
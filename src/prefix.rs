@@ -0,0 +1,62 @@
+use std::ops::Bound;
+
+/// Keys that can be searched by a common prefix with [`BtreeIndex::prefix_range`](crate::BtreeIndex::prefix_range).
+///
+/// The order of the keys must be the lexicographic order of their prefixes.
+pub trait PrefixKey: Sized {
+    /// Type of the prefix, e.g. `[u8]` for byte vectors.
+    type Prefix: ?Sized;
+
+    /// Returns the range of all keys that start with the given prefix.
+    fn prefix_bounds(prefix: &Self::Prefix) -> (Bound<Self>, Bound<Self>);
+}
+
+impl PrefixKey for Vec<u8> {
+    type Prefix = [u8];
+
+    fn prefix_bounds(prefix: &[u8]) -> (Bound<Self>, Bound<Self>) {
+        // All keys with the prefix are smaller than the prefix where the last byte that is not
+        // 0xFF is incremented and all following bytes are removed.
+        let end = match prefix.iter().rposition(|b| *b != u8::MAX) {
+            Some(pos) => {
+                let mut end = prefix[0..=pos].to_vec();
+                end[pos] += 1;
+                Bound::Excluded(end)
+            }
+            // Only 0xFF bytes or empty prefix: all larger keys have the prefix
+            None => Bound::Unbounded,
+        };
+        (Bound::Included(prefix.to_vec()), end)
+    }
+}
+
+impl PrefixKey for String {
+    type Prefix = str;
+
+    fn prefix_bounds(prefix: &str) -> (Bound<Self>, Bound<Self>) {
+        // UTF-8 preserves the order of the code points, so the same approach as for bytes
+        // can be used on the characters
+        let mut end = prefix.to_string();
+        let end = loop {
+            match end.pop() {
+                Some(c) => {
+                    if let Some(next) = next_char(c) {
+                        end.push(next);
+                        break Bound::Excluded(end);
+                    }
+                }
+                None => break Bound::Unbounded,
+            }
+        };
+        (Bound::Included(prefix.to_string()), end)
+    }
+}
+
+/// Returns the next valid character, skipping the surrogate code points.
+fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        char::MAX => None,
+        _ => char::from_u32(c as u32 + 1),
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    borrow::Borrow,
+    fmt,
+    io::{Read, Write},
+    ops::{Bound, RangeBounds},
+};
+
+use bincode::Options;
+
+use serde::{
+    de::{SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    error::{Error, Result},
+    BtreeConfig, BtreeIndex, ValueCodec,
+};
+
+/// Byte slice that is serialized as a single block of bytes.
+///
+/// Serde would serialize each byte of a `Vec<u8>` on its own, while bincode can copy
+/// the whole slice at once if it is declared as bytes.
+/// Keys are compared with the lexicographic byte order (like `memcmp`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct RawBytes(pub(crate) Vec<u8>);
+
+/// Allows searching with byte slices without copying them, since the bytes are compared
+/// in the same order as the slices.
+impl Borrow<[u8]> for RawBytes {
+    fn borrow(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Serialize for RawBytes {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for RawBytes {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_byte_buf(RawBytesVisitor)
+    }
+}
+
+struct RawBytesVisitor;
+
+impl<'de> Visitor<'de> for RawBytesVisitor {
+    type Value = RawBytes;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a byte array")
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> std::result::Result<Self::Value, E> {
+        Ok(RawBytes(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> std::result::Result<Self::Value, E> {
+        Ok(RawBytes(v.to_vec()))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut result = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(b) = seq.next_element()? {
+            result.push(b);
+        }
+        Ok(RawBytes(result))
+    }
+}
+
+/// B-tree index for keys and values that are already serialized as byte slices.
+///
+/// The bytes are copied into the index as they are, without serializing each byte with serde.
+/// Keys are ordered by comparing their bytes lexicographically (like `memcmp`).
+///
+/// # Example
+///
+/// ```rust
+/// use transient_btree_index::{BtreeConfig, Error, RawBtreeIndex};
+///
+/// fn main() -> std::result::Result<(), Error> {
+///     let mut b = RawBtreeIndex::with_capacity(BtreeConfig::default(), 10)?;
+///     b.insert_raw(b"b", b"2")?;
+///     b.insert_raw(b"a", b"1")?;
+///
+///     assert_eq!(Some(b"1".to_vec()), b.get_raw(b"a")?);
+///     let keys: Result<Vec<_>, Error> =
+///         b.range_raw(..)?.map(|e| e.map(|(k, _)| k)).collect();
+///     assert_eq!(vec![b"a".to_vec(), b"b".to_vec()], keys?);
+///     Ok(())
+/// }
+/// ```
+pub struct RawBtreeIndex {
+    index: BtreeIndex<RawBytes, RawBytes>,
+}
+
+impl RawBtreeIndex {
+    /// Create a new instance with the given configuration and capacity in number of elements.
+    ///
+    /// The values are always serialized with bincode, regardless of the configured
+    /// [value codec](BtreeConfig::value_codec).
+    pub fn with_capacity(config: BtreeConfig, capacity: usize) -> Result<RawBtreeIndex> {
+        let config = config.value_codec(ValueCodec::Bincode);
+        Ok(RawBtreeIndex {
+            index: BtreeIndex::with_capacity(config, capacity)?,
+        })
+    }
+
+    /// Insert a new element into the index.
+    ///
+    /// Existing values will be overwritten and returned.
+    pub fn insert_raw(&mut self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        let previous = self
+            .index
+            .insert(RawBytes(key.to_vec()), RawBytes(value.to_vec()))?;
+        Ok(previous.map(|v| v.0))
+    }
+
+    /// Insert a new element with a value of `len` bytes, which are read from the `reader`.
+    ///
+    /// The bytes are copied directly from the reader into the index, so large values never
+    /// need to be held in memory. They are not compressed, even if value compression is configured.
+    /// Existing values will be overwritten.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use transient_btree_index::{BtreeConfig, Error, RawBtreeIndex};
+    ///
+    /// fn main() -> std::result::Result<(), Error> {
+    ///     let mut b = RawBtreeIndex::with_capacity(BtreeConfig::default(), 10)?;
+    ///     let blob = vec![42; 1 << 20];
+    ///     b.insert_from_reader(b"blob", blob.len(), blob.as_slice())?;
+    ///
+    ///     let mut output = Vec::new();
+    ///     assert!(b.get_to_writer(b"blob", &mut output)?);
+    ///     assert_eq!(blob, output);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn insert_from_reader<R>(&mut self, key: &[u8], len: usize, mut reader: R) -> Result<()>
+    where
+        R: Read,
+    {
+        let header = self.index.value_codec().serialize(&u64::try_from(len)?)?;
+        self.index
+            .insert_from_reader(RawBytes(key.to_vec()), &header, len, &mut reader)
+    }
+
+    /// Searches for a key in the index and writes the value to the `writer` if found.
+    ///
+    /// Unless the value is compressed, the bytes are written directly from the index
+    /// without copying them into memory first.
+    /// Returns whether the key was found.
+    pub fn get_to_writer<W>(&self, key: &[u8], mut writer: W) -> Result<bool>
+    where
+        W: Write,
+    {
+        if let Some(serialized) = self.index.get_serialized_value(key)? {
+            let value = strip_length_header(self.index.value_codec(), &serialized)?;
+            writer.write_all(value)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Searches for a key in the index and returns the value if found.
+    pub fn get_raw(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let value = self.index.get(key)?;
+        Ok(value.map(|v| v.0))
+    }
+
+    /// Returns whether the index contains the given key.
+    pub fn contains_key_raw(&self, key: &[u8]) -> Result<bool> {
+        self.index.contains_key(key)
+    }
+
+    /// Return an iterator over a range of keys.
+    pub fn range_raw<'a, R>(
+        &self,
+        range: R,
+    ) -> Result<impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + '_>
+    where
+        R: RangeBounds<&'a [u8]>,
+    {
+        let to_slice = |bound: Bound<&&'a [u8]>| match bound {
+            Bound::Included(b) => Bound::Included(*b),
+            Bound::Excluded(b) => Bound::Excluded(*b),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let range = (to_slice(range.start_bound()), to_slice(range.end_bound()));
+        let result = self
+            .index
+            .range::<[u8], _>(range)?
+            .map(|e| e.map(|(k, v)| (k.0, v.0)));
+        Ok(result)
+    }
+
+    /// Returns true if the index does not contain any elements.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the length of the index.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+}
+
+/// Returns the bytes of a serialized byte slice without the length in front of them.
+fn strip_length_header(codec: ValueCodec, serialized: &[u8]) -> Result<&[u8]> {
+    let len: u64 = match codec {
+        // Values with a fixed size are always serialized with a fixed integer encoding
+        ValueCodec::BincodeFixint => bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .deserialize(serialized)?,
+        _ => bincode::DefaultOptions::new()
+            .allow_trailing_bytes()
+            .deserialize(serialized)?,
+    };
+    let header_len: usize = codec.serialized_size(&len)?.try_into()?;
+    let len: usize = len.try_into()?;
+    serialized
+        .get(header_len..(header_len + len))
+        .ok_or_else(|| Error::DeserializeBlock("value is shorter than its length".to_string()))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,137 @@
+use bincode::Options;
+
+use super::*;
+
+#[test]
+fn raw_bytes_serialization() {
+    // The serialized representation is the same as for a vector of bytes
+    let serializer = bincode::DefaultOptions::new();
+    let bytes = vec![0, 1, 2, 255];
+    let serialized = serializer.serialize(&RawBytes(bytes.clone())).unwrap();
+    assert_eq!(serializer.serialize(&bytes).unwrap(), serialized);
+    let deserialized: RawBytes = serializer.deserialize(&serialized).unwrap();
+    assert_eq!(bytes, deserialized.0);
+}
+
+#[test]
+fn insert_get_range_raw() {
+    let mut b = RawBtreeIndex::with_capacity(BtreeConfig::default().order(2), 10).unwrap();
+    assert!(b.is_empty());
+
+    for i in (0..300u32).rev() {
+        // Big-endian encoding keeps the numeric order of the keys
+        assert_eq!(
+            None,
+            b.insert_raw(&i.to_be_bytes(), &i.to_le_bytes()).unwrap()
+        );
+    }
+    assert_eq!(300, b.len());
+    assert_eq!(
+        Some(7u32.to_le_bytes().to_vec()),
+        b.insert_raw(&7u32.to_be_bytes(), b"seven").unwrap()
+    );
+
+    assert_eq!(
+        Some(b"seven".to_vec()),
+        b.get_raw(&7u32.to_be_bytes()).unwrap()
+    );
+    assert_eq!(
+        Some(42u32.to_le_bytes().to_vec()),
+        b.get_raw(&42u32.to_be_bytes()).unwrap()
+    );
+    assert!(b.contains_key_raw(&299u32.to_be_bytes()).unwrap());
+    assert!(!b.contains_key_raw(&300u32.to_be_bytes()).unwrap());
+    assert_eq!(None, b.get_raw(b"").unwrap());
+
+    let start = 10u32.to_be_bytes();
+    let end = 20u32.to_be_bytes();
+    let keys: Result<Vec<_>> = b
+        .range_raw(&start[..]..&end[..])
+        .unwrap()
+        .map(|e| e.map(|(k, _)| k))
+        .collect();
+    let expected: Vec<Vec<u8>> = (10..20u32).map(|i| i.to_be_bytes().to_vec()).collect();
+    assert_eq!(expected, keys.unwrap());
+
+    let keys: Result<Vec<_>> = b
+        .range_raw(&end[..]..)
+        .unwrap()
+        .map(|e| e.map(|(k, _)| k))
+        .collect();
+    let expected: Vec<Vec<u8>> = (20..300u32).map(|i| i.to_be_bytes().to_vec()).collect();
+    assert_eq!(expected, keys.unwrap());
+}
+
+#[test]
+fn insert_from_reader_get_to_writer() {
+    let mut b = RawBtreeIndex::with_capacity(BtreeConfig::default().order(2), 10).unwrap();
+
+    for i in 0..100u32 {
+        let value: Vec<u8> = (0..(i * 1000)).map(|v| (v % 256) as u8).collect();
+        b.insert_from_reader(&i.to_be_bytes(), value.len(), value.as_slice())
+            .unwrap();
+    }
+    // Mix with small values that are inserted with serde
+    b.insert_raw(b"small", b"abc").unwrap();
+    b.insert_raw(b"large", &[7; 1000]).unwrap();
+    assert_eq!(102, b.len());
+
+    for i in 0..100u32 {
+        let expected: Vec<u8> = (0..(i * 1000)).map(|v| (v % 256) as u8).collect();
+        let mut output = Vec::new();
+        assert!(b.get_to_writer(&i.to_be_bytes(), &mut output).unwrap());
+        assert_eq!(expected, output);
+        assert_eq!(Some(expected), b.get_raw(&i.to_be_bytes()).unwrap());
+    }
+    let mut output = Vec::new();
+    assert!(b.get_to_writer(b"small", &mut output).unwrap());
+    assert_eq!(b"abc".to_vec(), output);
+    let mut output = Vec::new();
+    assert!(b.get_to_writer(b"large", &mut output).unwrap());
+    assert_eq!(vec![7; 1000], output);
+    assert!(!b.get_to_writer(b"missing", &mut output).unwrap());
+
+    // Replace an existing value
+    b.insert_from_reader(b"small", 3, &b"xyz"[..]).unwrap();
+    assert_eq!(Some(b"xyz".to_vec()), b.get_raw(b"small").unwrap());
+    assert_eq!(102, b.len());
+
+    // A reader with less bytes than requested must not add the key
+    assert!(b.insert_from_reader(b"short", 10, &b"abc"[..]).is_err());
+    assert!(!b.contains_key_raw(b"short").unwrap());
+    assert_eq!(102, b.len());
+}
+
+#[test]
+fn insert_from_reader_fixed_value_size() {
+    // The fixed size includes the 8 bytes for the length of the value
+    let config = BtreeConfig::default().fixed_value_size(12);
+    let mut b = RawBtreeIndex::with_capacity(config, 10).unwrap();
+    for i in 0..100u32 {
+        b.insert_from_reader(&i.to_be_bytes(), 4, &i.to_le_bytes()[..])
+            .unwrap();
+    }
+    for i in 0..100u32 {
+        let mut output = Vec::new();
+        assert!(b.get_to_writer(&i.to_be_bytes(), &mut output).unwrap());
+        assert_eq!(i.to_le_bytes().to_vec(), output);
+    }
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn insert_from_reader_compression() {
+    let config = BtreeConfig::default().value_compression(64);
+    let mut b = RawBtreeIndex::with_capacity(config, 10).unwrap();
+    b.insert_raw(b"compressed", &[1; 10000]).unwrap();
+    b.insert_from_reader(b"streamed", 10000, &[2; 10000][..])
+        .unwrap();
+
+    let mut output = Vec::new();
+    assert!(b.get_to_writer(b"compressed", &mut output).unwrap());
+    assert_eq!(vec![1; 10000], output);
+    let mut output = Vec::new();
+    assert!(b.get_to_writer(b"streamed", &mut output).unwrap());
+    assert_eq!(vec![2; 10000], output);
+    assert_eq!(Some(vec![2; 10000]), b.get_raw(b"streamed").unwrap());
+}
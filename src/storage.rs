@@ -0,0 +1,390 @@
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+use std::fs::File;
+use std::sync::{Arc, OnceLock};
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+use memmap2::MmapMut;
+
+use crate::error::{Error, Result};
+use crate::metrics::Metrics;
+use crate::DEFAULT_PAGE_SIZE;
+
+/// Returns the size of a memory page of the system in bytes, which is detected once at runtime.
+///
+/// On systems where it can't be detected, 4 KiB pages are assumed.
+pub(crate) fn system_page_size() -> usize {
+    static PAGE_SIZE: OnceLock<usize> = OnceLock::new();
+    *PAGE_SIZE.get_or_init(|| {
+        #[cfg(unix)]
+        {
+            // Reading a setting of the system with `sysconf` is always safe
+            let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+            if let Ok(page_size) = usize::try_from(page_size) {
+                if page_size.is_power_of_two() {
+                    return page_size;
+                }
+            }
+        }
+        DEFAULT_PAGE_SIZE
+    })
+}
+
+/// Memory that holds the content of the files for the nodes, keys and values of an index.
+///
+/// The files access the storage as a single continuous slice of bytes,
+/// and ask the storage to grow when they need more space.
+/// Implement this trait to provide your own storage, e.g. over a pre-allocated scratch file.
+pub trait StorageBackend: Send + Sync {
+    /// The current size of the storage in bytes.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if the storage has a size of zero bytes.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Grows the storage to the new size in bytes.
+    ///
+    /// The existing content must be kept and the new bytes must be initialized with zero.
+    fn grow(&mut self, new_size: usize) -> Result<()>;
+
+    /// Shrinks the storage to the new size in bytes, which is smaller than the current size.
+    ///
+    /// The content before the new size must be kept.
+    /// Per default, the storage keeps its size, which is allowed for storages that can't release memory.
+    fn shrink(&mut self, _new_size: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Ask the operating system to back the storage with huge pages.
+    ///
+    /// This is only a hint that should also apply when the storage grows.
+    /// Per default, nothing is done.
+    fn use_huge_pages(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Releases the memory of a range whose content is not needed anymore.
+    ///
+    /// The offset and length in bytes are multiples of the
+    /// [configured page size](crate::BtreeConfig::page_size).
+    /// Afterwards, the content of the range is unspecified until it is written again.
+    /// Per default, nothing is released.
+    fn discard(&mut self, _offset: usize, _len: usize) -> Result<()> {
+        Ok(())
+    }
+
+    /// Get the content of the storage for reading.
+    fn as_slice(&self) -> &[u8];
+
+    /// Get the content of the storage for writing.
+    fn as_mut_slice(&mut self) -> &mut [u8];
+}
+
+/// Determines how much a storage grows when a file needs more space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GrowthStrategy {
+    /// Multiply the current size with the given factor, which should be larger than 1.
+    Factor(f64),
+    /// Add the given number of bytes to the current size.
+    Increment(usize),
+}
+
+impl Default for GrowthStrategy {
+    fn default() -> Self {
+        GrowthStrategy::Factor(2.0)
+    }
+}
+
+/// Growth strategy and optional maximum size that is applied to all storages of an index.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct GrowthPolicy {
+    pub strategy: GrowthStrategy,
+    pub max_size: Option<usize>,
+}
+
+impl GrowthPolicy {
+    /// Limits the initial capacity of a storage to the maximum size.
+    pub fn initial_size(&self, capacity: usize) -> usize {
+        capacity.min(self.max_size.unwrap_or(usize::MAX))
+    }
+
+    /// Returns an error if the size in bytes is larger than the maximum size.
+    pub fn check_size(&self, size: usize) -> Result<()> {
+        match self.max_size {
+            Some(maximum) if size > maximum => Err(Error::MaximumFileSizeExceeded {
+                requested: size,
+                maximum,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Grows the storage of the file with the given name so it has at least the requested size in bytes.
+    pub fn grow(
+        &self,
+        file: &'static str,
+        storage: &mut dyn StorageBackend,
+        requested_size: usize,
+        metrics: &Metrics,
+    ) -> Result<()> {
+        if requested_size <= storage.len() {
+            // Still enough space, no action required
+            return Ok(());
+        }
+        self.check_size(requested_size)?;
+
+        // Allocate more than requested so we don't need to grow too often
+        let new_size = match self.strategy {
+            GrowthStrategy::Factor(factor) => (storage.len() as f64 * factor).ceil() as usize,
+            GrowthStrategy::Increment(increment) => storage.len().saturating_add(increment),
+        };
+        let new_size = new_size
+            .max(requested_size)
+            .min(self.max_size.unwrap_or(usize::MAX));
+        metrics.grow(storage.len(), new_size);
+        storage
+            .grow(new_size)
+            .map_err(|e| storage_error(file, "Growing", e))
+    }
+}
+
+/// Adds the name of the file and the operation to an error of its storage backend.
+pub(crate) fn storage_error(file: &'static str, operation: &'static str, source: Error) -> Error {
+    Error::Storage {
+        file,
+        operation,
+        source: Box::new(source),
+    }
+}
+
+/// Function that creates a new storage backend with the given capacity in bytes.
+pub type StorageFactory = Arc<dyn Fn(usize) -> Result<Box<dyn StorageBackend>> + Send + Sync>;
+
+/// Shrinks the storage of a file to the used number of bytes, rounded up to whole memory pages.
+pub(crate) fn shrink_to_fit(
+    file: &'static str,
+    storage: &mut dyn StorageBackend,
+    used_size: usize,
+    page_size: usize,
+) -> Result<()> {
+    let new_size = used_size.max(1).next_multiple_of(page_size);
+    if new_size < storage.len() {
+        storage
+            .shrink(new_size)
+            .map_err(|e| storage_error(file, "Shrinking", e))?;
+    }
+    Ok(())
+}
+
+/// Discards all memory pages that are completely inside the range from `start` to `end` (exclusive).
+pub(crate) fn discard_pages(
+    file: &'static str,
+    storage: &mut dyn StorageBackend,
+    start: usize,
+    end: usize,
+    page_size: usize,
+) -> Result<()> {
+    let start = start.next_multiple_of(page_size);
+    let end = end.min(storage.len()) / page_size * page_size;
+    if start < end {
+        storage
+            .discard(start, end - start)
+            .map_err(|e| storage_error(file, "Discarding pages of", e))?;
+    }
+    Ok(())
+}
+
+/// Writes to each memory page of a new storage, so that all pages are allocated
+/// upfront instead of on their first access.
+pub(crate) fn prefault(storage: &mut dyn StorageBackend, page_size: usize) {
+    // New storages only contain zeros, so the content is not changed
+    for b in storage.as_mut_slice().iter_mut().step_by(page_size) {
+        *b = 0;
+    }
+}
+
+/// Storage backed by an anonymous temporary memory mapped file.
+///
+/// This is the default storage and allows indexes larger than the main memory.
+/// It is only available with the `mmap` feature (enabled by default) and not on WebAssembly.
+///
+/// The file is mapped as a plain shared mapping without any additional flags.
+/// Since modified pages are written back to the file, no swap space is reserved for the mapping,
+/// so a flag like `MAP_NORESERVE` is not needed even for huge capacities.
+/// Other mapping options can be used by implementing a [`StorageBackend`]
+/// and configuring it with [`BtreeConfig::storage_backend`](crate::BtreeConfig::storage_backend).
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+pub struct MmapStorage {
+    file: File,
+    mmap: MmapMut,
+    huge_pages: bool,
+}
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+impl MmapStorage {
+    /// Create a new temporary file with the capacity in bytes.
+    pub fn with_capacity(capacity: usize) -> Result<MmapStorage> {
+        let file = tempfile::tempfile().map_err(io_error("Creating the temporary file"))?;
+        if capacity > 0 {
+            file.set_len(capacity.try_into()?)
+                .map_err(io_error("Resizing the temporary file"))?;
+        }
+
+        // Load this file as memory mapped file
+        let mmap =
+            unsafe { MmapMut::map_mut(&file) }.map_err(io_error("Mapping the temporary file"))?;
+        Ok(MmapStorage {
+            file,
+            mmap,
+            huge_pages: false,
+        })
+    }
+
+    /// Map the file again after its size has been changed.
+    fn remap(&mut self) -> Result<()> {
+        self.mmap = unsafe { MmapMut::map_mut(&self.file) }
+            .map_err(io_error("Mapping the temporary file"))?;
+        #[cfg(target_os = "linux")]
+        if self.huge_pages {
+            self.mmap
+                .advise(memmap2::Advice::HugePage)
+                .map_err(io_error("Advising huge pages for the mapping"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds the failed operation to an I/O error of a storage backend.
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+fn io_error(operation: &'static str) -> impl FnOnce(std::io::Error) -> Error {
+    move |source| Error::StorageIo { operation, source }
+}
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+impl StorageBackend for MmapStorage {
+    fn len(&self) -> usize {
+        self.mmap.len()
+    }
+
+    fn grow(&mut self, new_size: usize) -> Result<()> {
+        // Extend the existing file and map it again. The old content stays in the file,
+        // so it does not need to be copied and growing does not depend on the file size.
+        self.file
+            .set_len(new_size.try_into()?)
+            .map_err(io_error("Resizing the temporary file"))?;
+        self.remap()
+    }
+
+    fn shrink(&mut self, new_size: usize) -> Result<()> {
+        // The truncated part of the old mapping is not accessed before it is replaced
+        self.file
+            .set_len(new_size.try_into()?)
+            .map_err(io_error("Resizing the temporary file"))?;
+        self.remap()
+    }
+
+    /// Advises the kernel to use transparent huge pages for the mapping (`MADV_HUGEPAGE`).
+    ///
+    /// This is only supported on Linux and depends on the transparent huge page settings of the kernel.
+    /// On other platforms, nothing is done.
+    fn use_huge_pages(&mut self) -> Result<()> {
+        self.huge_pages = true;
+        self.remap()
+    }
+
+    /// Removes the pages from the memory and frees their space in the temporary file (`MADV_REMOVE`).
+    ///
+    /// If the file system does not support this, the pages are only removed from the memory
+    /// (`MADV_DONTNEED`) and written back to the file.
+    /// If the configured page size is smaller than the one of the system,
+    /// only the whole pages of the system inside the range are removed.
+    /// This is only supported on Linux. On other platforms, nothing is done.
+    fn discard(&mut self, offset: usize, len: usize) -> Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            let page_size = system_page_size();
+            let start = offset.next_multiple_of(page_size);
+            let end = (offset + len) / page_size * page_size;
+            if start < end
+                && self
+                    .mmap
+                    .advise_range(memmap2::Advice::Remove, start, end - start)
+                    .is_err()
+            {
+                self.mmap
+                    .advise_range(memmap2::Advice::DontNeed, start, end - start)
+                    .map_err(io_error("Removing pages from the mapping"))?;
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (offset, len);
+        Ok(())
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.mmap
+    }
+}
+
+/// Storage that holds all content in main memory.
+///
+/// This is the default storage on platforms where memory mapped files are not available,
+/// like WebAssembly, or when the `mmap` feature is disabled.
+#[derive(Default)]
+pub struct MemoryStorage {
+    data: Vec<u8>,
+}
+
+impl MemoryStorage {
+    /// Create a new buffer with the capacity in bytes.
+    pub fn with_capacity(capacity: usize) -> MemoryStorage {
+        MemoryStorage {
+            data: vec![0; capacity],
+        }
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn grow(&mut self, new_size: usize) -> Result<()> {
+        self.data.resize(new_size, 0);
+        Ok(())
+    }
+
+    fn shrink(&mut self, new_size: usize) -> Result<()> {
+        self.data.truncate(new_size);
+        self.data.shrink_to_fit();
+        Ok(())
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+#[cfg(test)]
+mod tests;
+
+/// Create the default storage, which uses memory mapped files if available.
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+pub fn create_default_storage(capacity: usize) -> Result<Box<dyn StorageBackend>> {
+    Ok(Box::new(MmapStorage::with_capacity(capacity)?))
+}
+
+/// Create the default storage, which keeps everything in main memory.
+#[cfg(not(all(feature = "mmap", not(target_arch = "wasm32"))))]
+pub fn create_default_storage(capacity: usize) -> Result<Box<dyn StorageBackend>> {
+    Ok(Box::new(MemoryStorage::with_capacity(capacity)))
+}
@@ -0,0 +1,114 @@
+use super::*;
+
+fn grow_keeps_content(storage: &mut dyn StorageBackend) {
+    let initial_size = storage.len();
+    for (i, b) in storage.as_mut_slice().iter_mut().enumerate() {
+        *b = (i % 251) as u8;
+    }
+
+    storage.grow(initial_size * 3).unwrap();
+    assert_eq!(initial_size * 3, storage.len());
+    for (i, b) in storage.as_slice()[0..initial_size].iter().enumerate() {
+        assert_eq!((i % 251) as u8, *b);
+    }
+    assert!(storage.as_slice()[initial_size..].iter().all(|b| *b == 0));
+
+    // The new space must be writable
+    storage.as_mut_slice()[initial_size * 3 - 1] = 42;
+    assert_eq!(42, storage.as_slice()[initial_size * 3 - 1]);
+}
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+#[test]
+fn mmap_storage_grow() {
+    let mut storage = MmapStorage::with_capacity(10_000).unwrap();
+    grow_keeps_content(&mut storage);
+}
+
+#[test]
+fn memory_storage_grow() {
+    let mut storage = MemoryStorage::with_capacity(10_000);
+    grow_keeps_content(&mut storage);
+}
+
+fn shrink_keeps_content(storage: &mut dyn StorageBackend) {
+    for (i, b) in storage.as_mut_slice().iter_mut().enumerate() {
+        *b = (i % 251) as u8;
+    }
+
+    shrink_to_fit("blocks", storage, 5000, DEFAULT_PAGE_SIZE).unwrap();
+    assert_eq!(2 * DEFAULT_PAGE_SIZE, storage.len());
+    for (i, b) in storage.as_slice().iter().enumerate() {
+        assert_eq!((i % 251) as u8, *b);
+    }
+
+    // A storage is never grown when shrinking
+    shrink_to_fit("blocks", storage, 100_000, DEFAULT_PAGE_SIZE).unwrap();
+    assert_eq!(2 * DEFAULT_PAGE_SIZE, storage.len());
+}
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+#[test]
+fn mmap_storage_shrink() {
+    let mut storage = MmapStorage::with_capacity(10 * DEFAULT_PAGE_SIZE).unwrap();
+    shrink_keeps_content(&mut storage);
+}
+
+#[test]
+fn memory_storage_shrink() {
+    let mut storage = MemoryStorage::with_capacity(10 * DEFAULT_PAGE_SIZE);
+    shrink_keeps_content(&mut storage);
+}
+
+#[test]
+fn prefault_keeps_content() {
+    let mut storage = MemoryStorage::with_capacity(10 * DEFAULT_PAGE_SIZE + 1);
+    prefault(&mut storage, DEFAULT_PAGE_SIZE);
+    assert_eq!(10 * DEFAULT_PAGE_SIZE + 1, storage.len());
+    assert!(storage.as_slice().iter().all(|b| *b == 0));
+}
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+#[test]
+fn mmap_storage_huge_pages() {
+    let mut storage = MmapStorage::with_capacity(crate::HUGE_PAGE_SIZE).unwrap();
+    storage.use_huge_pages().unwrap();
+    grow_keeps_content(&mut storage);
+}
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+#[test]
+fn mmap_storage_discard_pages() {
+    let mut storage = MmapStorage::with_capacity(4 * DEFAULT_PAGE_SIZE).unwrap();
+    storage.as_mut_slice().fill(42);
+
+    // Only the second and third page are completely inside the range
+    discard_pages(
+        "blocks",
+        &mut storage,
+        DEFAULT_PAGE_SIZE - 1,
+        3 * DEFAULT_PAGE_SIZE + 1,
+        DEFAULT_PAGE_SIZE,
+    )
+    .unwrap();
+    assert_eq!(4 * DEFAULT_PAGE_SIZE, storage.len());
+    assert!(storage.as_slice()[..DEFAULT_PAGE_SIZE]
+        .iter()
+        .all(|b| *b == 42));
+    assert!(storage.as_slice()[3 * DEFAULT_PAGE_SIZE..]
+        .iter()
+        .all(|b| *b == 42));
+
+    // Discarded pages can be written again
+    storage.as_mut_slice()[DEFAULT_PAGE_SIZE..3 * DEFAULT_PAGE_SIZE].fill(1);
+    assert!(storage.as_slice()[DEFAULT_PAGE_SIZE..3 * DEFAULT_PAGE_SIZE]
+        .iter()
+        .all(|b| *b == 1));
+}
+
+#[test]
+fn detect_system_page_size() {
+    let page_size = system_page_size();
+    assert!(page_size.is_power_of_two());
+    assert!(page_size >= DEFAULT_PAGE_SIZE);
+}
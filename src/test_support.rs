@@ -0,0 +1,309 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    ops::{Bound, RangeBounds},
+};
+
+use rand::Rng;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{error::Result, BtreeConfig, BtreeIndex};
+
+/// An operation that [`ModelChecker::apply`] executes on both the index and the model.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ModelOperation<K, V> {
+    /// Insert or replace an entry and compare the previous values.
+    Insert(K, V),
+    /// Compare the value for a key.
+    Get(K),
+    /// Compare all entries in a range.
+    Range(Bound<K>, Bound<K>),
+    /// Remove all entries in a range and compare the number of removed entries.
+    RemoveRange(Bound<K>, Bound<K>),
+    /// Remove the entry with the smallest key and compare it.
+    PopFirst,
+    /// Remove the entry with the largest key and compare it.
+    PopLast,
+    /// Remove all entries.
+    Clear,
+}
+
+impl<K, V> ModelOperation<K, V> {
+    /// Create a random operation with keys and values from the given generators.
+    ///
+    /// Most operations are inserts, so the index grows over a sequence of operations,
+    /// and clearing the index is rare.
+    pub fn random<R, FK, FV>(rng: &mut R, mut key: FK, mut value: FV) -> ModelOperation<K, V>
+    where
+        R: Rng + ?Sized,
+        FK: FnMut(&mut R) -> K,
+        FV: FnMut(&mut R) -> V,
+    {
+        match rng.gen_range(0..100) {
+            0..=49 => {
+                let k = key(rng);
+                ModelOperation::Insert(k, value(rng))
+            }
+            50..=64 => ModelOperation::Get(key(rng)),
+            65..=79 => {
+                let (start, end) = random_bounds(rng, &mut key);
+                ModelOperation::Range(start, end)
+            }
+            80..=89 => {
+                let (start, end) = random_bounds(rng, &mut key);
+                ModelOperation::RemoveRange(start, end)
+            }
+            90..=94 => ModelOperation::PopFirst,
+            95..=98 => ModelOperation::PopLast,
+            _ => ModelOperation::Clear,
+        }
+    }
+}
+
+fn random_bounds<R, K, FK>(rng: &mut R, key: &mut FK) -> (Bound<K>, Bound<K>)
+where
+    R: Rng + ?Sized,
+    FK: FnMut(&mut R) -> K,
+{
+    let mut bound = |rng: &mut R| match rng.gen_range(0..3) {
+        0 => Bound::Unbounded,
+        1 => Bound::Included(key(rng)),
+        _ => Bound::Excluded(key(rng)),
+    };
+    let start = bound(rng);
+    (start, bound(rng))
+}
+
+/// Applies the same operations to a [`BtreeIndex`] and a [`BTreeMap`] and panics as soon as their
+/// results differ, so it can be used as an oracle in property tests and fuzz targets.
+///
+/// Errors of the index are returned instead of causing a panic.
+///
+/// # Example
+///
+/// ```rust
+/// use rand::{rngs::SmallRng, Rng, SeedableRng};
+/// use transient_btree_index::{BtreeConfig, Error, ModelChecker, ModelOperation};
+///
+/// fn main() -> std::result::Result<(), Error> {
+///     let mut checker = ModelChecker::<u32, u64>::new(BtreeConfig::default().order(3))?;
+///     checker.apply(ModelOperation::Insert(1, 10))?;
+///     checker.apply(ModelOperation::Get(1))?;
+///
+///     let mut rng = SmallRng::seed_from_u64(42);
+///     checker.apply_random(&mut rng, 1000, |rng| rng.gen_range(0..200), |rng| rng.gen())?;
+///     checker.check()?;
+///     Ok(())
+/// }
+/// ```
+pub struct ModelChecker<K, V>
+where
+    K: Serialize + DeserializeOwned + PartialOrd + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    index: BtreeIndex<K, V>,
+    model: BTreeMap<K, V>,
+}
+
+impl<K, V> ModelChecker<K, V>
+where
+    K: 'static + Serialize + DeserializeOwned + PartialOrd + Clone + Ord + Debug,
+    V: 'static + Serialize + DeserializeOwned + Clone + PartialEq + Debug,
+{
+    /// Create an empty index with the given configuration and an empty model.
+    pub fn new(config: BtreeConfig) -> Result<ModelChecker<K, V>> {
+        Ok(ModelChecker {
+            index: BtreeIndex::with_capacity(config, 0)?,
+            model: BTreeMap::new(),
+        })
+    }
+
+    /// Check an existing index, e.g. one with a custom storage backend.
+    ///
+    /// The model is initialized with all entries of the index.
+    pub fn with_index(index: BtreeIndex<K, V>) -> Result<ModelChecker<K, V>> {
+        let mut model = BTreeMap::new();
+        for entry in index.range(..)? {
+            let (key, value) = entry?;
+            model.insert(key, value);
+        }
+        Ok(ModelChecker { index, model })
+    }
+
+    /// The index that is checked.
+    pub fn index(&self) -> &BtreeIndex<K, V> {
+        &self.index
+    }
+
+    /// The model the index is compared with.
+    pub fn model(&self) -> &BTreeMap<K, V> {
+        &self.model
+    }
+
+    /// Returns the index that has been checked.
+    pub fn into_index(self) -> BtreeIndex<K, V> {
+        self.index
+    }
+
+    /// Apply an operation to the index and the model and compare the results.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the results or the number of entries differ.
+    pub fn apply(&mut self, operation: ModelOperation<K, V>) -> Result<()> {
+        match &operation {
+            ModelOperation::Insert(key, value) => {
+                let actual = self.index.insert(key.clone(), value.clone())?;
+                let expected = self.model.insert(key.clone(), value.clone());
+                assert_eq!(
+                    expected, actual,
+                    "previous value differs for {:?}",
+                    operation
+                );
+            }
+            ModelOperation::Get(key) => {
+                assert_eq!(
+                    self.model.get(key),
+                    self.index.get(key)?.as_ref(),
+                    "value differs for {:?}",
+                    operation
+                );
+            }
+            ModelOperation::Range(start, end) => {
+                let range = (start.clone(), end.clone());
+                let actual = self
+                    .index
+                    .range(range.clone())?
+                    .collect::<Result<Vec<_>>>()?;
+                assert_eq!(
+                    self.model_range(&range),
+                    actual,
+                    "entries differ for {:?}",
+                    operation
+                );
+            }
+            ModelOperation::RemoveRange(start, end) => {
+                let range = (start.clone(), end.clone());
+                let removed = self.index.remove_range(range.clone())?;
+                let expected = self.model_range(&range);
+                for (key, _) in expected.iter() {
+                    self.model.remove(key);
+                }
+                assert_eq!(
+                    expected.len(),
+                    removed,
+                    "number of removed entries differs for {:?}",
+                    operation
+                );
+            }
+            ModelOperation::PopFirst => {
+                let actual = self.index.pop_first()?;
+                assert_eq!(
+                    self.model.pop_first(),
+                    actual,
+                    "first entry differs for {:?}",
+                    operation
+                );
+            }
+            ModelOperation::PopLast => {
+                let actual = self.index.pop_last()?;
+                assert_eq!(
+                    self.model.pop_last(),
+                    actual,
+                    "last entry differs for {:?}",
+                    operation
+                );
+            }
+            ModelOperation::Clear => {
+                self.index.clear()?;
+                self.model.clear();
+            }
+        }
+        assert_eq!(
+            self.model.len(),
+            self.index.len(),
+            "number of entries differs after {:?}",
+            operation
+        );
+        Ok(())
+    }
+
+    /// Apply a sequence of operations, e.g. one generated by a fuzzer.
+    pub fn apply_all<I>(&mut self, operations: I) -> Result<()>
+    where
+        I: IntoIterator<Item = ModelOperation<K, V>>,
+    {
+        for operation in operations {
+            self.apply(operation)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the given number of [random operations](ModelOperation::random).
+    pub fn apply_random<R, FK, FV>(
+        &mut self,
+        rng: &mut R,
+        operations: usize,
+        mut key: FK,
+        mut value: FV,
+    ) -> Result<()>
+    where
+        R: Rng + ?Sized,
+        FK: FnMut(&mut R) -> K,
+        FV: FnMut(&mut R) -> V,
+    {
+        for _ in 0..operations {
+            let operation = ModelOperation::random(rng, &mut key, &mut value);
+            self.apply(operation)?;
+        }
+        Ok(())
+    }
+
+    /// Compare all entries of the index and the model and verify the integrity of the index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the entries or the number of entries differ.
+    pub fn check(&self) -> Result<()> {
+        assert_eq!(
+            self.model.len(),
+            self.index.len(),
+            "number of entries differs"
+        );
+        for (key, value) in self.model.iter() {
+            assert_eq!(
+                Some(value),
+                self.index.get(key)?.as_ref(),
+                "value differs for key {:?}",
+                key
+            );
+        }
+        let actual = self.index.range(..)?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(self.model_range(&(..)), actual, "entries differ");
+        self.index.verify_integrity()
+    }
+
+    fn model_range<R>(&self, range: &R) -> Vec<(K, V)>
+    where
+        R: RangeBounds<K>,
+    {
+        // BTreeMap::range panics for ranges where the start is after the end
+        let empty = match (range.start_bound(), range.end_bound()) {
+            (Bound::Included(start), Bound::Included(end)) => start > end,
+            (Bound::Included(start), Bound::Excluded(end))
+            | (Bound::Excluded(start), Bound::Included(end))
+            | (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+            _ => false,
+        };
+        if empty {
+            return Vec::new();
+        }
+        self.model
+            .range((range.start_bound(), range.end_bound()))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,64 @@
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use super::*;
+use crate::test_util::SEED;
+
+#[test]
+fn random_operations() {
+    for order in [2, 3, 42] {
+        let mut checker =
+            ModelChecker::<u32, String>::new(BtreeConfig::default().order(order)).unwrap();
+        let mut rng = SmallRng::seed_from_u64(SEED + order as u64);
+        checker
+            .apply_random(
+                &mut rng,
+                5000,
+                |rng| rng.gen_range(0..500),
+                |rng| "x".repeat(rng.gen_range(0..20)),
+            )
+            .unwrap();
+        checker.check().unwrap();
+    }
+}
+
+#[test]
+fn empty_and_reversed_ranges() {
+    let mut checker = ModelChecker::<u8, u8>::new(BtreeConfig::default()).unwrap();
+    checker
+        .apply_all((0..100).map(|i| ModelOperation::Insert(i, i)))
+        .unwrap();
+    checker
+        .apply_all([
+            ModelOperation::Range(Bound::Included(50), Bound::Included(10)),
+            ModelOperation::Range(Bound::Excluded(10), Bound::Excluded(10)),
+            ModelOperation::Range(Bound::Excluded(10), Bound::Included(10)),
+            ModelOperation::RemoveRange(Bound::Included(50), Bound::Excluded(10)),
+            ModelOperation::RemoveRange(Bound::Excluded(10), Bound::Excluded(11)),
+        ])
+        .unwrap();
+    assert_eq!(100, checker.index().len());
+    checker.check().unwrap();
+}
+
+#[test]
+fn with_existing_index() {
+    let mut index = BtreeIndex::<u16, u16>::with_capacity(BtreeConfig::default(), 10).unwrap();
+    index.insert(1, 2).unwrap();
+    index.insert(3, 4).unwrap();
+
+    let mut checker = ModelChecker::with_index(index).unwrap();
+    assert_eq!(2, checker.model().len());
+    checker.apply(ModelOperation::PopLast).unwrap();
+    checker.apply(ModelOperation::Get(1)).unwrap();
+    assert_eq!(1, checker.into_index().len());
+}
+
+#[test]
+#[should_panic(expected = "value differs")]
+fn detects_differences() {
+    let mut index = BtreeIndex::<u16, u16>::with_capacity(BtreeConfig::default(), 10).unwrap();
+    index.insert(1, 2).unwrap();
+    let mut checker = ModelChecker::with_index(index).unwrap();
+    checker.model.insert(1, 3);
+    checker.apply(ModelOperation::Get(1)).unwrap();
+}
@@ -0,0 +1,39 @@
+//! Helpers that are shared by the tests of several modules.
+
+use std::collections::BTreeMap;
+
+use rand::{rngs::SmallRng, SeedableRng};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::BtreeIndex;
+
+/// Seed of the random number generators, so that failing tests can be reproduced.
+pub(crate) const SEED: u64 = 1971428643569665;
+
+/// Random number generator with the fixed [`SEED`].
+pub(crate) fn seeded_rng() -> SmallRng {
+    SmallRng::seed_from_u64(SEED)
+}
+
+/// Inserts `n` entries with random keys into the index, some of them multiple times.
+///
+/// The value of each entry is the number of its insertion.
+/// Returns a map with the same entries to compare the index with.
+pub(crate) fn insert_random<V, F>(
+    t: &mut BtreeIndex<u32, V>,
+    rng: &mut SmallRng,
+    n: u32,
+    mut random_key: F,
+) -> BTreeMap<u32, V>
+where
+    V: 'static + Serialize + DeserializeOwned + Clone + From<u32>,
+    F: FnMut(&mut SmallRng) -> u32,
+{
+    let mut expected = BTreeMap::new();
+    for i in 0..n {
+        let key = random_key(rng);
+        t.insert(key, V::from(i)).unwrap();
+        expected.insert(key, V::from(i));
+    }
+    expected
+}
@@ -0,0 +1,41 @@
+//! Derive macros for the [transient-btree-index](https://crates.io/crates/transient-btree-index) crate.
+//!
+//! Enable the `derive` feature of the main crate to use them.
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Error};
+
+/// Implements the `FixedSize` trait for a struct whose fields all implement `FixedSize`.
+///
+/// The size of the struct is the sum of the sizes of its fields,
+/// which is the size of the serialized struct when deriving `Serialize` with serde.
+#[proc_macro_derive(FixedSize)]
+pub fn derive_fixed_size(input: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Error::new_spanned(&input.ident, "FixedSize can only be derived for structs")
+                .to_compile_error()
+                .into();
+        }
+    };
+    let field_types: Vec<_> = fields.iter().map(|f| &f.ty).collect();
+
+    // All generic type parameters must have a fixed size, too
+    for param in input.generics.type_params_mut() {
+        param
+            .bounds
+            .push(parse_quote!(::transient_btree_index::FixedSize));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let result = quote! {
+        impl #impl_generics ::transient_btree_index::FixedSize for #name #ty_generics #where_clause {
+            const SIZE: usize = 0 #(+ <#field_types as ::transient_btree_index::FixedSize>::SIZE)*;
+        }
+    };
+    result.into()
+}